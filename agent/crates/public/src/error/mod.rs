@@ -28,6 +28,9 @@ pub enum Error {
     #[cfg(target_os = "linux")]
     #[error("create raw socket error")]
     CreateRawSocketError(#[from] std::io::Error),
+    #[cfg(target_os = "linux")]
+    #[error("pcap file error {0}")]
+    PcapFileError(String),
     #[cfg(target_os = "windows")]
     #[error("winpcap error {0}")]
     WinpcapError(String),
@@ -29,26 +29,35 @@ pub enum L7Protocol {
     Http2 = 21,
     Http1TLS = 22,
     Http2TLS = 23,
+    WebSocket = 24,
 
     // RPC
     Dubbo = 40,
     Grpc = 41,
     ProtobufRPC = 42,
     SofaRPC = 43,
+    Thrift = 44,
 
     // SQL
     MySQL = 60,
     PostgreSQL = 61,
+    TDS = 62,
 
     // NoSQL
     Redis = 80,
+    MongoDB = 81,
+    Memcached = 82,
+    Cassandra = 83,
 
     // MQ
     Kafka = 100,
     MQTT = 101,
+    AMQP = 102,
 
     // INFRA
     DNS = 120,
+    TLS = 121,
+    QUIC = 122,
 
     Max = 255,
 }
@@ -58,6 +67,10 @@ pub enum L7Protocol {
 #[repr(u64)]
 pub enum ProtobufRpcProtocol {
     Krpc = 1,
+    // bRPC baidu_std
+    Brpc = 2,
+    // Tencent tRPC
+    Trpc = 3,
 }
 
 #[derive(Clone, Copy, Debug)]
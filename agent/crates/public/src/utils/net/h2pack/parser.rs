@@ -14,11 +14,14 @@
  * limitations under the License.
  */
 
+use std::fmt;
+
 use bitflags::bitflags;
-use hpack::decoder::Decoder;
+use hpack::decoder::{Decoder, DecoderError};
 
 const STATIC_INDEX_MIN: usize = 1;
-const STATIC_INDEX_MAX: usize = 61;
+// 动态表大小的上限，超过时忽略对端的动态表大小更新
+const DYNAMIC_TABLE_SIZE_MAX: usize = 65536;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ParseError {
@@ -42,10 +45,31 @@ bitflags! {
     }
 }
 
+// Parser 在同一个连接方向上的多个头部块之间保留 HPACK 动态表。
+// 丢包会使动态表与对端不一致，发现无法解析的动态表索引或者头部块解码失败时清空动态表，
+// 之后新加入的条目与对端保持一致，引用更早条目的字段被跳过。
+// ================================================================================
+// Parser keeps the HPACK dynamic table across header blocks of the same connection direction.
+// Packet loss makes the dynamic table differ from the peer's, so the dynamic table is cleared
+// when an unresolvable dynamic table index is found or a header block fails to decode, the
+// entries added later are consistent with the peer and fields referencing earlier entries are
+// skipped.
 pub struct Parser<'a> {
     decoder: Decoder<'a>,
 }
 
+impl Default for Parser<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Parser<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parser").finish_non_exhaustive()
+    }
+}
+
 fn parse_int(buf: &[u8], prefix: u8) -> Result<(usize, usize), ParseError> {
     if prefix < 1 || prefix > 8 {
         return Err(ParseError::InvalidIntger);
@@ -97,6 +121,23 @@ impl Parser<'_> {
         }
     }
 
+    // 清空动态表
+    pub fn reset(&mut self) {
+        self.decoder = Decoder::new();
+    }
+
+    fn decode(&mut self, buf: &[u8]) -> Result<Option<Vec<(Vec<u8>, Vec<u8>)>>, ParseError> {
+        match self.decoder.decode(buf) {
+            Ok(rst) => Ok(Some(rst)),
+            // 引用的动态表条目不存在，跳过该字段并清空动态表
+            Err(DecoderError::HeaderIndexOutOfBounds) => {
+                self.reset();
+                Ok(None)
+            }
+            Err(_) => Err(ParseError::InvalidHuffmanCode),
+        }
+    }
+
     fn parse_kv_pair(
         &mut self,
         buf: &[u8],
@@ -111,14 +152,11 @@ impl Parser<'_> {
 
         if index != 0 {
             // Indexed
-            let (str_len, len) = parse_int(&buf[index_len..], 7)?;
-            val_len = val_len + str_len + len;
             // RFC7541附录A(https://datatracker.ietf.org/doc/html/rfc7541#appendix-A)规定：
             // 静态表index从1到61，共60项。如果index大于61, 意味着这是一个dynamic table的
-            // index，我们无法解出index对应的value，应该跳过对应的字节继续解析。
-            if index > STATIC_INDEX_MAX {
-                return Ok((None, val_len));
-            }
+            // index，由动态表解出index对应的name。
+            let (str_len, len) = parse_int(&buf[index_len..], 7)?;
+            val_len = val_len + str_len + len;
         } else {
             // New Name
             let (name_len, len) = parse_int(&buf[1..], 7)?;
@@ -136,10 +174,7 @@ impl Parser<'_> {
             return Err(ParseError::InvalidInput);
         }
 
-        match self.decoder.decode(&buf[..val_len]) {
-            Ok(rst) => Ok((Some(rst), val_len)),
-            Err(_) => Err(ParseError::InvalidHuffmanCode),
-        }
+        Ok((self.decode(&buf[..val_len])?, val_len))
     }
 
     fn parse_indexed(
@@ -152,11 +187,8 @@ impl Parser<'_> {
             return Err(ParseError::InvalidInput);
         }
 
-        if index >= STATIC_INDEX_MIN && index <= STATIC_INDEX_MAX {
-            match self.decoder.decode(&buf[..index_len]) {
-                Ok(rst) => Ok((Some(rst), index_len)),
-                Err(_) => Err(ParseError::InvalidHuffmanCode),
-            }
+        if index >= STATIC_INDEX_MIN {
+            Ok((self.decode(&buf[..index_len])?, index_len))
         } else {
             Ok((None, index_len))
         }
@@ -170,7 +202,10 @@ impl Parser<'_> {
     }
 
     fn parse_sizeup(&mut self, buf: &[u8]) -> Result<usize, ParseError> {
-        let (_, consumed) = parse_int(buf, 5)?;
+        let (size, consumed) = parse_int(buf, 5)?;
+        if size <= DYNAMIC_TABLE_SIZE_MAX {
+            self.decode(&buf[..consumed])?;
+        }
         Ok(consumed)
     }
 
@@ -233,7 +268,14 @@ impl Parser<'_> {
         let mut offset = 0;
 
         while offset < input.len() {
-            offset += self.parse_one_field(input, offset, &mut header_list)?;
+            match self.parse_one_field(input, offset, &mut header_list) {
+                Ok(consumed) => offset += consumed,
+                // 头部块中已解码的字段可能修改了动态表，剩余字段的修改无从得知
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
         }
 
         Ok(header_list)
@@ -261,6 +303,48 @@ mod tests {
         assert_eq!(0, r2.len());
     }
 
+    #[test]
+    fn parse_field_dynamic_table() {
+        let mut parser = Parser::new();
+
+        // literal with incremental indexing, new name "foo: bar"
+        let buffer1 = [0x40, 0x03, b'f', b'o', b'o', 0x03, b'b', b'a', b'r'];
+        let r1 = parser.parse(&buffer1).unwrap();
+        assert_eq!(b"foo", r1[0].0.as_slice());
+
+        let buffer2 = [0xbe]; // dynamic table index 62
+        let r2 = parser.parse(&buffer2).unwrap();
+        assert_eq!(b"foo", r2[0].0.as_slice());
+        assert_eq!(b"bar", r2[0].1.as_slice());
+
+        // index out of the dynamic table, the field is skipped and the table is cleared
+        let buffer3 = [0xbf, 0x82];
+        let r3 = parser.parse(&buffer3).unwrap();
+        assert_eq!(1, r3.len());
+        assert_eq!(b":method", r3[0].0.as_slice());
+        assert!(parser.parse(&buffer2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_field_decode_failure() {
+        let mut parser = Parser::new();
+
+        let buffer1 = [0x40, 0x03, b'f', b'o', b'o', 0x03, b'b', b'a', b'r'];
+        parser.parse(&buffer1).unwrap();
+
+        // the rest of the header block is lost, the table is cleared
+        let buffer2 = [0x40, 0x03, b'b', b'a', b'z', 0x01, b'q', 0x41, 0x03, b'q'];
+        assert_eq!(parser.parse(&buffer2), Err(ParseError::InvalidInput));
+        let buffer3 = [0xbe];
+        assert!(parser.parse(&buffer3).unwrap().is_empty());
+
+        // entries added after the failure are referenced again
+        parser.parse(&buffer1).unwrap();
+        let r4 = parser.parse(&buffer3).unwrap();
+        assert_eq!(b"foo", r4[0].0.as_slice());
+        assert_eq!(b"bar", r4[0].1.as_slice());
+    }
+
     #[test]
     fn parse_field_incremental_indexing() {
         let mut parser = Parser::new();
@@ -420,10 +504,14 @@ mod tests {
 
         let r2 = parser.parse(&buffer2).unwrap();
 
-        assert_eq!(4, r2.len());
+        // dynamic table entries added by buffer1 are referenced
+        assert_eq!(7, r2.len());
         assert_eq!(b":method", r2[0].0.as_slice());
         assert_eq!(b"PUT", r2[0].1.as_slice());
-        assert_eq!(b"content-length", r2[3].0.as_slice());
-        assert_eq!(b"46", r2[3].1.as_slice());
+        assert_eq!(b"accept-language", r2[3].0.as_slice());
+        assert_eq!(b"accept-encoding", r2[4].0.as_slice());
+        assert_eq!(b"content-length", r2[5].0.as_slice());
+        assert_eq!(b"46", r2[5].1.as_slice());
+        assert_eq!(b"upgrade-insecure-requests", r2[6].0.as_slice());
     }
 }
@@ -19,8 +19,9 @@ use serde::Serialize;
 
 use crate::flow_generator::{
     protocol_logs::{
-        pb_adapter::L7ProtocolSendLog, DnsInfo, DubboInfo, HttpInfo, KafkaInfo, MqttInfo,
-        MysqlInfo, PostgreInfo, ProtobufRpcInfo, RedisInfo, SofaRpcInfo,
+        pb_adapter::L7ProtocolSendLog, AmqpInfo, CassandraInfo, DnsInfo, DubboInfo, HttpInfo,
+        KafkaInfo, MemcachedInfo, MongoDBInfo, MqttInfo, MysqlInfo, PostgreInfo, ProtobufRpcInfo,
+        QuicInfo, RedisInfo, SofaRpcInfo, TdsInfo, ThriftInfo, TlsInfo, WebSocketInfo,
     },
     AppProtoHead, Result,
 };
@@ -76,6 +77,15 @@ all_protocol_info!(
     PostgreInfo(PostgreInfo),
     ProtobufRpcInfo(ProtobufRpcInfo),
     SofaRpcInfo(SofaRpcInfo),
+    AmqpInfo(AmqpInfo),
+    MongoDBInfo(MongoDBInfo),
+    MemcachedInfo(MemcachedInfo),
+    CassandraInfo(CassandraInfo),
+    TdsInfo(TdsInfo),
+    ThriftInfo(ThriftInfo),
+    TlsInfo(TlsInfo),
+    QuicInfo(QuicInfo),
+    WebSocketInfo(WebSocketInfo),
 );
 
 #[enum_dispatch(L7ProtocolInfo)]
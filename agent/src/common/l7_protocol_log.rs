@@ -26,8 +26,9 @@ use super::MetaPacket;
 
 use crate::config::handler::LogParserConfig;
 use crate::flow_generator::protocol_logs::{
-    get_protobuf_rpc_parser, DnsLog, DubboLog, HttpLog, KafkaLog, MqttLog, MysqlLog, PostgresqlLog,
-    ProtobufRpcWrapLog, RedisLog, SofaRpcLog,
+    get_protobuf_rpc_parser, AmqpLog, CassandraLog, DnsLog, DubboLog, HttpLog, KafkaLog,
+    MemcachedLog, MongoDBLog, MqttLog, MysqlLog, PostgresqlLog, ProtobufRpcWrapLog, QuicLog,
+    RedisLog, SofaRpcLog, TdsLog, ThriftLog, TlsLog, WebSocketLog,
 };
 use crate::flow_generator::Result;
 
@@ -346,6 +347,15 @@ all_protocol!(
     Dubbo,DubboParser,DubboLog::default;
     MQTT,MqttParser,MqttLog::default;
     // add protocol below
+    AMQP,AmqpParser,AmqpLog::new;
+    MongoDB,MongoDBParser,MongoDBLog::new;
+    Memcached,MemcachedParser,MemcachedLog::new;
+    Cassandra,CassandraParser,CassandraLog::new;
+    TDS,TdsParser,TdsLog::new;
+    Thrift,ThriftParser,ThriftLog::new;
+    TLS,TlsParser,TlsLog::new;
+    QUIC,QuicParser,QuicLog::new;
+    WebSocket,WebSocketParser,WebSocketLog::new;
 );
 
 #[enum_dispatch(L7ProtocolParser)]
@@ -385,6 +395,31 @@ pub trait L7ProtocolParserInterface {
     fn parsable_on_udp(&self) -> bool {
         true
     }
+    // 解析日志时是否需要完整的载荷，例如需要校验 AEAD 标签的 QUIC Initial 报文，或需要跨报文
+    // 跟踪消息边界、HPACK 动态表的连接。超出 ParseParam.log_packet_size 的部分只用于维护连接状态，
+    // 不应解析为日志内容
+    // ==================================================================================================
+    // whether the complete payload is needed when parsing logs, e.g. QUIC Initial packets whose
+    // AEAD tag must be verified, or connections tracking message boundaries or the HPACK dynamic
+    // table across packets. Bytes beyond ParseParam.log_packet_size are only used to keep the
+    // connection state and should not be decoded into log content
+    fn need_complete_payload(&self) -> bool {
+        false
+    }
+    // 协议升级后接管连接的解析器，例如 HTTP 101 Switching Protocols 之后的 WebSocket
+    // ==========================================================================
+    // the parser taking over the connection after a protocol upgrade,
+    // e.g. WebSocket after HTTP 101 Switching Protocols
+    fn upgraded_parser(&mut self) -> Option<L7ProtocolParser> {
+        None
+    }
+    // 流结束或超时时仍未上报的日志，例如没有收到 Close 帧的 WebSocket 会话
+    // ===================================================================
+    // logs not yet reported when the flow ends or times out,
+    // e.g. WebSocket sessions without a Close frame
+    fn flow_end(&mut self, _time: u64) -> Vec<L7ProtocolInfo> {
+        vec![]
+    }
 
     // is parse default? use for config init.
     fn parse_default(&self) -> bool {
@@ -409,14 +444,14 @@ pub struct EbpfParam {
         当响应的 cap_seq 减去请求的 cap_seq 不等于1，就认为乱序无法聚合，直接发送请求和响应。
         其中，mysql 和 postgresql 由于 预编译请求 和 执行结果之间有数个报文的间隔，所以不能通过序号差判断是否乱序。
         所以现在只有 http1，redis 能使用这个方法处理乱序。
-        FIXME: http1 在 pipeline 模型下依然会有乱序的情况，目前不解决。
+        http1 和 redis 在 pipeline 模型下按连接内请求的先后顺序与响应配对，配对后不再检查序号差。
         ====================================================================
         The eBPF program maintains a cap_seq sequence number for each socket, which is incremented by 1 for each read/write syscall call.
         Currently only used to deal with ebpf out-of-order problems, and can only be used for protocol without request_id and request-response serialization.
         When the cap_seq of the response subtract the cap_seq of the request is not equal to 1, it is considered that the out-of-order cannot be aggregated, and the request and response are sent directly without merge.
         MySQL and postgreSQL cannot judge whether the order is out of order due to the interval of several messages between the precompiled request and the execution result.
         So now only http1, redis can use this method to deal with out-of-order.
-        FIXME: http1 will still be out of order under the pipeline model, which is not resolved at present.
+        Under the pipeline model, http1 and redis pair responses with requests in the order they were sent on the connection, and the sequence difference is not checked once paired.
     */
     pub cap_seq: u64,
 }
@@ -439,6 +474,17 @@ pub struct ParseParam<'a> {
     pub time: u64,
 
     pub parse_config: Option<&'a LogParserConfig>,
+    // 载荷中可以解析为日志内容的长度，即 l7_log_packet_size
+    // =====================================================
+    // length of the payload which may be decoded into log content, i.e. l7_log_packet_size
+    pub log_packet_size: usize,
+    // TCP 报文的序列号、确认号和载荷的实际长度，用于发现丢包，只对采集的报文有效
+    // ========================================================================
+    // sequence number, acknowledgment number and actual payload length of the TCP packet,
+    // used to detect packet loss, only valid for captured packets
+    pub tcp_seq: u32,
+    pub tcp_ack: u32,
+    pub payload_len: u16,
 }
 
 impl From<&MetaPacket<'_>> for ParseParam<'_> {
@@ -455,6 +501,10 @@ impl From<&MetaPacket<'_>> for ParseParam<'_> {
             ebpf_param: None,
             time: packet.lookup_key.timestamp.as_micros() as u64,
             parse_config: None,
+            log_packet_size: usize::MAX,
+            tcp_seq: packet.tcp_data.seq,
+            tcp_ack: packet.tcp_data.ack,
+            payload_len: packet.payload_len,
         };
         if packet.ebpf_type != EbpfType::None {
             let is_tls = match packet.ebpf_type {
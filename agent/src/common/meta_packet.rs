@@ -96,9 +96,12 @@ pub struct MetaPacket<'a> {
     pub queue_hash: u8,
 
     /********** for xFlow (NetFlow/sFlow/NetStream) **********/
-    // TODO support xFlow
-    // pub packet_count: u64,
-    // pub packet_bytes: u64,
+    // 一个采样报文代表的原始报文数和字节数，为 0 时按单个报文统计
+    // ============================================================
+    // number of packets and bytes represented by one sampled packet, 0 means a single packet
+    pub packet_count: u64,
+    pub packet_bytes: u64,
+    // TODO support NetFlow
     // pub start_time: Duration,
     // pub end_time: Duration,
     // pub source_ip: u32,
@@ -338,6 +341,22 @@ impl<'a> MetaPacket<'a> {
         (packet[IPV6_PROTO_OFFSET + l2_opt_size], 0)
     }
 
+    pub fn sampled_packet_count(&self) -> u64 {
+        if self.packet_count > 0 {
+            self.packet_count
+        } else {
+            1
+        }
+    }
+
+    pub fn sampled_packet_bytes(&self) -> u64 {
+        if self.packet_bytes > 0 {
+            self.packet_bytes
+        } else {
+            self.packet_len as u64 * self.sampled_packet_count()
+        }
+    }
+
     pub fn get_pkt_size(&self) -> u16 {
         if self.packet_len < u16::MAX as u32 {
             self.packet_len as u16
@@ -61,6 +61,12 @@ pub struct XflowKey {
     tap_idx: u32,
 }
 
+impl XflowKey {
+    pub fn new(ip: Ipv4Addr, tap_idx: u32) -> Self {
+        Self { ip, tap_idx }
+    }
+}
+
 impl Hash for XflowKey {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let key = ((u32::from(self.ip) as u64) << 32) + self.tap_idx as u64;
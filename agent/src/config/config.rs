@@ -321,6 +321,7 @@ pub struct YamlConfig {
     pub dpdk_pmd_core_id: u32,
     pub dpdk_ring_port: String,
     pub xflow_collector: XflowGeneratorConfig,
+    pub pcap_replay: PcapReplayConfig,
     pub vxlan_flags: u8,
     pub collector_sender_queue_size: usize,
     pub collector_sender_queue_count: usize,
@@ -572,6 +573,7 @@ impl Default for YamlConfig {
             dpdk_pmd_core_id: 0,
             dpdk_ring_port: "dpdkr0".into(),
             xflow_collector: Default::default(),
+            pcap_replay: Default::default(),
             vxlan_flags: 0xff,
             // default size changes according to tap_mode
             collector_sender_queue_size: 1 << 16,
@@ -759,6 +761,22 @@ impl Default for XflowGeneratorConfig {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PcapReplayConfig {
+    pub files: Vec<String>,
+    pub speed: u32,
+}
+
+impl Default for PcapReplayConfig {
+    fn default() -> Self {
+        PcapReplayConfig {
+            files: vec![],
+            speed: 1,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct TripleMapConfig {
@@ -15,7 +15,7 @@
  */
 
 use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
 use std::net::IpAddr;
 use std::path::PathBuf;
@@ -530,6 +530,12 @@ pub enum TraceType {
     Sw8,
     TraceParent,
     NewRpcTraceContext,
+    B3,
+    AwsXRay,
+    DatadogTrace,
+    DatadogParent,
+    TraceState,
+    Baggage,
     Customize(String),
 }
 
@@ -540,6 +546,12 @@ const TRACE_TYPE_UBER: &str = "uber-trace-id";
 const TRACE_TYPE_SW6: &str = "sw6";
 const TRACE_TYPE_SW8: &str = "sw8";
 const TRACE_TYPE_TRACE_PARENT: &str = "traceparent";
+const TRACE_TYPE_B3: &str = "b3";
+const TRACE_TYPE_AWS_XRAY: &str = "x-amzn-trace-id";
+const TRACE_TYPE_DATADOG_TRACE: &str = "x-datadog-trace-id";
+const TRACE_TYPE_DATADOG_PARENT: &str = "x-datadog-parent-id";
+const TRACE_TYPE_TRACE_STATE: &str = "tracestate";
+const TRACE_TYPE_BAGGAGE: &str = "baggage";
 
 impl From<&str> for TraceType {
     // 参数支持如下两种格式：
@@ -559,6 +571,12 @@ impl From<&str> for TraceType {
             TRACE_TYPE_SW8 => TraceType::Sw8,
             TRACE_TYPE_TRACE_PARENT => TraceType::TraceParent,
             SOFA_NEW_RPC_TRACE_CTX_KEY => TraceType::NewRpcTraceContext,
+            TRACE_TYPE_B3 => TraceType::B3,
+            TRACE_TYPE_AWS_XRAY => TraceType::AwsXRay,
+            TRACE_TYPE_DATADOG_TRACE => TraceType::DatadogTrace,
+            TRACE_TYPE_DATADOG_PARENT => TraceType::DatadogParent,
+            TRACE_TYPE_TRACE_STATE => TraceType::TraceState,
+            TRACE_TYPE_BAGGAGE => TraceType::Baggage,
             _ if t.len() > 0 => TraceType::Customize(format_t.to_string()),
             _ => TraceType::Disabled,
         }
@@ -590,6 +608,12 @@ impl TraceType {
             TraceType::NewRpcTraceContext => {
                 context.to_ascii_lowercase() == SOFA_NEW_RPC_TRACE_CTX_KEY
             }
+            TraceType::B3 => context.to_ascii_lowercase() == TRACE_TYPE_B3,
+            TraceType::AwsXRay => context.to_ascii_lowercase() == TRACE_TYPE_AWS_XRAY,
+            TraceType::DatadogTrace => context.to_ascii_lowercase() == TRACE_TYPE_DATADOG_TRACE,
+            TraceType::DatadogParent => context.to_ascii_lowercase() == TRACE_TYPE_DATADOG_PARENT,
+            TraceType::TraceState => context.to_ascii_lowercase() == TRACE_TYPE_TRACE_STATE,
+            TraceType::Baggage => context.to_ascii_lowercase() == TRACE_TYPE_BAGGAGE,
             TraceType::Customize(tag) => context.to_ascii_lowercase() == tag.to_ascii_lowercase(),
             _ => false,
         }
@@ -604,6 +628,12 @@ impl TraceType {
             &TraceType::Sw8 => TRACE_TYPE_SW8.into(),
             &TraceType::TraceParent => TRACE_TYPE_TRACE_PARENT.into(),
             &TraceType::NewRpcTraceContext => SOFA_NEW_RPC_TRACE_CTX_KEY.into(),
+            &TraceType::B3 => TRACE_TYPE_B3.into(),
+            &TraceType::AwsXRay => TRACE_TYPE_AWS_XRAY.into(),
+            &TraceType::DatadogTrace => TRACE_TYPE_DATADOG_TRACE.into(),
+            &TraceType::DatadogParent => TRACE_TYPE_DATADOG_PARENT.into(),
+            &TraceType::TraceState => TRACE_TYPE_TRACE_STATE.into(),
+            &TraceType::Baggage => TRACE_TYPE_BAGGAGE.into(),
             &TraceType::Customize(ref tag) => tag.to_ascii_lowercase(),
             _ => "".into(),
         }
@@ -617,6 +647,12 @@ impl TraceType {
             TraceType::Sw6 => TRACE_TYPE_SW6.to_string(),
             TraceType::Sw8 => TRACE_TYPE_SW8.to_string(),
             TraceType::TraceParent => TRACE_TYPE_TRACE_PARENT.to_string(),
+            TraceType::B3 => TRACE_TYPE_B3.to_string(),
+            TraceType::AwsXRay => TRACE_TYPE_AWS_XRAY.to_string(),
+            TraceType::DatadogTrace => TRACE_TYPE_DATADOG_TRACE.to_string(),
+            TraceType::DatadogParent => TRACE_TYPE_DATADOG_PARENT.to_string(),
+            TraceType::TraceState => TRACE_TYPE_TRACE_STATE.to_string(),
+            TraceType::Baggage => TRACE_TYPE_BAGGAGE.to_string(),
             TraceType::Customize(tag) => tag.to_string(),
             _ => "".to_string(),
         }
@@ -636,11 +672,13 @@ pub struct L7LogDynamicConfig {
     // in lowercase
     pub x_request_id: String,
 
+    // 按优先级排列，同一个报文中有多个链路追踪头部时使用靠前的
     pub trace_types: Vec<TraceType>,
     pub span_types: Vec<TraceType>,
 
-    trace_set: HashSet<String>,
-    span_set: HashSet<String>,
+    // 头部名称到优先级的映射
+    trace_set: HashMap<String, usize>,
+    span_set: HashMap<String, usize>,
 }
 
 impl PartialEq for L7LogDynamicConfig {
@@ -664,14 +702,14 @@ impl L7LogDynamicConfig {
         proxy_client.make_ascii_lowercase();
         x_request_id.make_ascii_lowercase();
 
-        let mut trace_set = HashSet::new();
-        for t in trace_types.iter() {
-            trace_set.insert(t.to_checker_string());
+        let mut trace_set = HashMap::new();
+        for (i, t) in trace_types.iter().enumerate() {
+            trace_set.entry(t.to_checker_string()).or_insert(i);
         }
 
-        let mut span_set = HashSet::new();
-        for t in span_types.iter() {
-            span_set.insert(t.to_checker_string());
+        let mut span_set = HashMap::new();
+        for (i, t) in span_types.iter().enumerate() {
+            span_set.entry(t.to_checker_string()).or_insert(i);
         }
 
         Self {
@@ -685,11 +723,20 @@ impl L7LogDynamicConfig {
     }
 
     pub fn is_trace_id(&self, context: &str) -> bool {
-        self.trace_set.contains(context)
+        self.trace_set.contains_key(context)
     }
 
     pub fn is_span_id(&self, context: &str) -> bool {
-        self.span_set.contains(context)
+        self.span_set.contains_key(context)
+    }
+
+    // 返回头部在 trace_types 中的优先级，数值越小越优先
+    pub fn trace_id_priority(&self, context: &str) -> Option<usize> {
+        self.trace_set.get(context).copied()
+    }
+
+    pub fn span_id_priority(&self, context: &str) -> Option<usize> {
+        self.span_set.get(context).copied()
     }
 }
 
@@ -249,6 +249,7 @@ impl BaseDispatcher {
         counter: &PacketCounter,
         ntp_diff: &AtomicI64,
     ) -> Option<(Packet, Duration)> {
+        let is_replay = engine.is_replay();
         let packet = engine.recv();
         if packet.is_err() {
             if let recv_engine::Error::Timeout = packet.unwrap_err() {
@@ -273,7 +274,11 @@ impl BaseDispatcher {
             timestamp -= Duration::from_nanos(-time_diff as u64);
         }
         if timestamp > *prev_timestamp {
-            if timestamp - *prev_timestamp > Duration::from_secs(60) {
+            // 回放不限速或倍速时报文时间会超前于当前时间，不做修正
+            // =================================================
+            // Replayed packets run ahead of present time when the replay is faster
+            // than the original speed, they are not corrected
+            if !is_replay && timestamp - *prev_timestamp > Duration::from_secs(60) {
                 // Correct invalid timestamp under some environments. Root cause unclear.
                 // A large timestamp will lead to discarding of following packets, correct
                 // this by setting it to present time
@@ -406,6 +411,7 @@ impl BaseDispatcher {
         counter: &PacketCounter,
         ntp_diff: &AtomicI64,
     ) -> Option<(Packet<'a>, Duration)> {
+        let is_replay = engine.is_replay();
         let packet = engine.recv();
         if packet.is_err() {
             if let recv_engine::Error::Timeout = packet.unwrap_err() {
@@ -430,7 +436,11 @@ impl BaseDispatcher {
             timestamp -= Duration::from_nanos(-time_diff as u64);
         }
         if timestamp > *prev_timestamp {
-            if timestamp - *prev_timestamp > Duration::from_secs(60) {
+            // 回放不限速或倍速时报文时间会超前于当前时间，不做修正
+            // =================================================
+            // Replayed packets run ahead of present time when the replay is faster
+            // than the original speed, they are not corrected
+            if !is_replay && timestamp - *prev_timestamp > Duration::from_secs(60) {
                 // Correct invalid timestamp under some environments. Root cause unclear.
                 // A large timestamp will lead to discarding of following packets, correct
                 // this by setting it to present time
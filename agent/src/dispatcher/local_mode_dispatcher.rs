@@ -91,6 +91,8 @@ impl LocalModeDispatcher {
             false, // !from_ebpf
         );
 
+        let is_replay = base.engine.is_replay();
+
         while !base.terminated.load(Ordering::Relaxed) {
             if base.reset_whitelist.swap(false, Ordering::Relaxed) {
                 base.tap_interface_whitelist.reset();
@@ -122,6 +124,11 @@ impl LocalModeDispatcher {
                     p.clone()
                 } else if pipelines.is_empty() {
                     continue;
+                } else if is_replay {
+                    // 回放的报文没有采集接口，送到任意一个 pipeline
+                    // ==============================================
+                    // replayed packets have no capture interface, send to any of the pipelines
+                    pipelines.iter().next().unwrap().1.clone()
                 } else {
                     // send to one of the pipelines if packet is LLDP
                     let mut eth_type = read_u16_be(&packet.data[FIELD_OFFSET_ETH_TYPE..]);
@@ -23,6 +23,8 @@ mod analyzer_mode_dispatcher;
 mod local_mode_dispatcher;
 mod mirror_mode_dispatcher;
 
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
 #[cfg(target_os = "windows")]
 use std::process;
 use std::thread::{self, JoinHandle};
@@ -57,6 +59,7 @@ pub use recv_engine::RecvEngine;
 #[cfg(target_os = "linux")]
 pub use recv_engine::{
     af_packet::{self, bpf::*, BpfSyntax, OptTpacketVersion, RawInstruction, Tpacket},
+    pcap_file::{self, PcapFile},
     DEFAULT_BLOCK_SIZE, FRAME_SIZE_MAX, FRAME_SIZE_MIN, POLL_TIMEOUT,
 };
 
@@ -473,6 +476,13 @@ pub struct Options {
     pub af_packet_blocks: usize,
     #[cfg(target_os = "linux")]
     pub af_packet_version: OptTpacketVersion,
+    // 不为空时从 pcap 文件回放，不再从网卡采集
+    // =====================================================
+    // replay from pcap files instead of capturing from NICs when not empty
+    #[cfg(target_os = "linux")]
+    pub pcap_replay_files: Vec<PathBuf>,
+    #[cfg(target_os = "linux")]
+    pub pcap_replay_speed: u32,
     pub snap_len: usize,
     pub tap_mode: TapMode,
     pub dpdk_conf: DpdkRingPortConf,
@@ -1014,6 +1024,17 @@ impl DispatcherBuilder {
     ) -> Result<RecvEngine> {
         let options = options.lock().unwrap();
         match tap_mode {
+            TapMode::Local | TapMode::Mirror if !options.pcap_replay_files.is_empty() => {
+                let opts = pcap_file::Options {
+                    files: options.pcap_replay_files.clone(),
+                    speed: options.pcap_replay_speed,
+                    snap_len: options.snap_len,
+                };
+                info!("PcapFile init with {:?}", opts);
+                Ok(RecvEngine::PcapFile(
+                    PcapFile::new(opts).map_err(|e| Error::ConfigInvalid(e.to_string()))?,
+                ))
+            }
             TapMode::Mirror if options.dpdk_conf.enabled => {
                 #[cfg(target_arch = "s390x")]
                 return Err(Error::ConfigInvalid(
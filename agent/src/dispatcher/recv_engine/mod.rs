@@ -16,6 +16,8 @@
 
 pub mod af_packet;
 pub(crate) mod bpf;
+#[cfg(target_os = "linux")]
+pub mod pcap_file;
 
 #[cfg(target_os = "windows")]
 use std::ffi::CStr;
@@ -24,6 +26,8 @@ use std::time::Duration;
 
 #[cfg(target_os = "linux")]
 use af_packet::{options::Options, tpacket::Tpacket};
+#[cfg(target_os = "linux")]
+use pcap_file::PcapFile;
 pub use public::error::{Error, Result};
 use public::packet;
 
@@ -40,6 +44,8 @@ pub const POLL_TIMEOUT: Duration = Duration::from_millis(100);
 pub enum RecvEngine {
     #[cfg(target_os = "linux")]
     AfPacket(Tpacket),
+    #[cfg(target_os = "linux")]
+    PcapFile(PcapFile),
     Dpdk(),
     #[cfg(target_os = "windows")]
     WinPcap(Option<WinPacket>),
@@ -52,12 +58,25 @@ impl RecvEngine {
         match self {
             #[cfg(target_os = "linux")]
             Self::AfPacket(_) => Ok(()),
+            #[cfg(target_os = "linux")]
+            Self::PcapFile(_) => Ok(()),
             Self::Dpdk() => todo!(),
             #[cfg(target_os = "windows")]
             Self::WinPcap(_) => Ok(()),
         }
     }
 
+    // 是否从 pcap 文件回放，回放的报文没有采集接口
+    // ============================================
+    // whether packets are replayed from pcap files, replayed packets have no capture interface
+    pub fn is_replay(&self) -> bool {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::PcapFile(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn close(&mut self) {
         match self {
             #[cfg(target_os = "windows")]
@@ -75,6 +94,8 @@ impl RecvEngine {
                 Some(p) => Ok(p),
                 None => Err(Error::Timeout),
             },
+            #[cfg(target_os = "linux")]
+            Self::PcapFile(e) => e.read(),
             Self::Dpdk() => todo!(),
             // Enterprise Edition Feature: windows-dispatcher
             #[cfg(target_os = "windows")]
@@ -89,6 +110,10 @@ impl RecvEngine {
     pub fn set_bpf(&mut self, s: Vec<af_packet::RawInstruction>) -> Result<()> {
         match self {
             Self::AfPacket(e) => e.set_bpf(s).map_err(|e| e.into()),
+            // 回放的报文无需过滤 agent 自身的流量
+            // ====================================
+            // replayed packets do not contain traffic of the agent itself, no need to filter
+            Self::PcapFile(_) => Ok(()),
             Self::Dpdk() => todo!(),
         }
     }
@@ -108,6 +133,8 @@ impl RecvEngine {
         match self {
             #[cfg(target_os = "linux")]
             Self::AfPacket(e) => Arc::new(e.get_counter_handle()),
+            #[cfg(target_os = "linux")]
+            Self::PcapFile(e) => e.get_counter_handle(),
             Self::Dpdk() => todo!(),
             #[cfg(target_os = "windows")]
             Self::WinPcap(w) => match w {
@@ -0,0 +1,385 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use pcap::{Capture, Linktype, Offline};
+use public::error::*;
+use public::packet::Packet;
+
+use super::{FRAME_SIZE_MAX, POLL_TIMEOUT};
+
+use crate::rpc::get_timestamp;
+use crate::utils::stats;
+
+const LINKTYPE_ETHERNET: Linktype = Linktype(1);
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub files: Vec<PathBuf>,
+    // 回放倍速，0 表示不做限速，1 表示按原始速度回放
+    // =========================================================================
+    // replay speed multiplier, 0 means as fast as possible, 1 means original speed
+    pub speed: u32,
+    pub snap_len: usize,
+}
+
+#[derive(Default)]
+pub struct PcapFileCounter {
+    files: AtomicU64,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl stats::RefCountable for PcapFileCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        vec![
+            (
+                "pcap_files",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.files.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "pcap_packets",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "pcap_bytes",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "pcap_errors",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.errors.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+struct Pending {
+    // 报文在 pcap 文件中的原始时间
+    // ===============================
+    // packet time recorded in pcap file
+    pcap_time: Duration,
+    capture_length: isize,
+    len: usize,
+}
+
+/*
+    按顺序回放一个或多个 pcap/pcapng 文件，代替网卡作为 dispatcher 的数据源。
+    报文时间会被平移到回放开始的时刻，报文之间的相对时间保持不变，
+    因此 FlowMap、FlowPerf 以及协议解析得到的流、时延与实时采集一致。
+    多个文件依次首尾相接，后一个文件从前一个文件最后一个报文的时间继续。
+    不限速或倍速回放时报文时间会超前于当前时间，dispatcher 不会将其修正为当前时间。
+    ===========================================================================
+    Replay one or more pcap/pcapng files in order as the packet source of a dispatcher.
+    Packet timestamps are shifted to the time replay starts while keeping the relative
+    time between packets, so that flows, RRT and L7 logs are identical to live capture.
+    Files are concatenated, each one continues from the last packet time of the previous.
+    When replaying faster than the original speed timestamps run ahead of present time,
+    the dispatcher does not correct them to present time.
+*/
+pub struct PcapFile {
+    files: VecDeque<PathBuf>,
+    capture: Option<Capture<Offline>>,
+    speed: u32,
+    snap_len: usize,
+
+    buffer: Vec<u8>,
+    pending: Option<Pending>,
+
+    // (first packet time in pcap, replay time of the first packet, wall clock of the first packet)
+    file_base: Option<(Duration, Duration, Instant)>,
+    last_timestamp: Duration,
+    finished: bool,
+
+    counter: Arc<PcapFileCounter>,
+}
+
+impl PcapFile {
+    pub fn new(opts: Options) -> Result<Self> {
+        if opts.files.is_empty() {
+            return Err(Error::PcapFileError("no pcap file to replay".into()));
+        }
+        for f in opts.files.iter() {
+            if !f.is_file() {
+                return Err(Error::PcapFileError(format!(
+                    "pcap file {} not found",
+                    f.display()
+                )));
+            }
+        }
+        let snap_len = if opts.snap_len == 0 || opts.snap_len > FRAME_SIZE_MAX {
+            FRAME_SIZE_MAX
+        } else {
+            opts.snap_len
+        };
+        Ok(Self {
+            files: opts.files.into(),
+            capture: None,
+            speed: opts.speed,
+            snap_len,
+            buffer: vec![0; snap_len],
+            pending: None,
+            file_base: None,
+            last_timestamp: Duration::ZERO,
+            finished: false,
+            counter: Default::default(),
+        })
+    }
+
+    pub fn get_counter_handle(&self) -> Arc<PcapFileCounter> {
+        self.counter.clone()
+    }
+
+    fn open_next(&mut self) -> bool {
+        while let Some(path) = self.files.pop_front() {
+            let capture = match Capture::from_file(&path) {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("open pcap file {} failed: {}", path.display(), e);
+                    self.counter.errors.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            if capture.get_datalink() != LINKTYPE_ETHERNET {
+                warn!(
+                    "pcap file {} skipped, only ethernet link type is supported",
+                    path.display()
+                );
+                self.counter.errors.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            info!("replaying pcap file {}", path.display());
+            self.counter.files.fetch_add(1, Ordering::Relaxed);
+            self.capture = Some(capture);
+            self.file_base = None;
+            return true;
+        }
+        false
+    }
+
+    // 读取下一个报文到 buffer 中
+    // ==========================
+    // read next packet into buffer
+    fn next_packet(&mut self) -> Option<Pending> {
+        loop {
+            if self.capture.is_none() && !self.open_next() {
+                return None;
+            }
+            let capture = self.capture.as_mut().unwrap();
+            match capture.next() {
+                Ok(packet) => {
+                    let len = packet.data.len().min(self.snap_len);
+                    self.buffer[..len].copy_from_slice(&packet.data[..len]);
+                    let ts = packet.header.ts;
+                    return Some(Pending {
+                        pcap_time: Duration::new(ts.tv_sec as u64, ts.tv_usec as u32 * 1000),
+                        capture_length: packet.header.len as isize,
+                        len,
+                    });
+                }
+                Err(pcap::Error::NoMorePackets) => {
+                    self.capture = None;
+                }
+                Err(e) => {
+                    warn!("read pcap file failed: {}", e);
+                    self.counter.errors.fetch_add(1, Ordering::Relaxed);
+                    self.capture = None;
+                }
+            }
+        }
+    }
+
+    pub fn read(&mut self) -> Result<Packet> {
+        if self.pending.is_none() {
+            self.pending = self.next_packet();
+        }
+        let Some(pending) = self.pending.as_ref() else {
+            if !self.finished {
+                info!("pcap replay finished");
+                self.finished = true;
+            }
+            thread::sleep(POLL_TIMEOUT);
+            return Err(Error::Timeout);
+        };
+
+        let (first_pcap_time, first_timestamp, first_instant) =
+            *self.file_base.get_or_insert_with(|| {
+                let timestamp = if self.last_timestamp.is_zero() {
+                    get_timestamp(0)
+                } else {
+                    self.last_timestamp
+                };
+                (pending.pcap_time, timestamp, Instant::now())
+            });
+        // pcap 中的时间可能回退，此时当作与上一个报文同时
+        // =================================================
+        // time in pcap may go backwards, treat it as the same time as the previous packet
+        let offset = pending.pcap_time.saturating_sub(first_pcap_time);
+
+        if self.speed > 0 {
+            let due = first_instant + offset / self.speed;
+            let now = Instant::now();
+            if due > now {
+                let wait = due - now;
+                if wait > POLL_TIMEOUT {
+                    thread::sleep(POLL_TIMEOUT);
+                    return Err(Error::Timeout);
+                }
+                thread::sleep(wait);
+            }
+        }
+
+        let pending = self.pending.take().unwrap();
+        let timestamp = (first_timestamp + offset).max(self.last_timestamp);
+        self.last_timestamp = timestamp;
+        self.counter.packets.fetch_add(1, Ordering::Relaxed);
+        self.counter
+            .bytes
+            .fetch_add(pending.len as u64, Ordering::Relaxed);
+
+        Ok(Packet {
+            timestamp,
+            if_index: 0,
+            capture_length: pending.capture_length,
+            data: &mut self.buffer[..pending.len],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+
+    // 按指定字节序写入经典 pcap 文件，truncate 为最后一个记录截掉的字节数
+    fn write_pcap(path: &Path, big_endian: bool, packets: &[(u32, u32, &[u8])], truncate: usize) {
+        let u32_bytes = |v: u32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u16_bytes = |v: u16| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut bs = vec![];
+        bs.extend_from_slice(&u32_bytes(PCAP_MAGIC));
+        bs.extend_from_slice(&u16_bytes(2));
+        bs.extend_from_slice(&u16_bytes(4));
+        bs.extend_from_slice(&u32_bytes(0)); // thiszone
+        bs.extend_from_slice(&u32_bytes(0)); // sigfigs
+        bs.extend_from_slice(&u32_bytes(65535)); // snaplen
+        bs.extend_from_slice(&u32_bytes(LINKTYPE_ETHERNET.0 as u32));
+        for (sec, usec, data) in packets {
+            bs.extend_from_slice(&u32_bytes(*sec));
+            bs.extend_from_slice(&u32_bytes(*usec));
+            bs.extend_from_slice(&u32_bytes(data.len() as u32));
+            bs.extend_from_slice(&u32_bytes(data.len() as u32));
+            bs.extend_from_slice(data);
+        }
+        bs.truncate(bs.len() - truncate);
+        fs::write(path, bs).unwrap();
+    }
+
+    fn read_all(pcap: &mut PcapFile) -> Vec<(Duration, isize, Vec<u8>)> {
+        let mut packets = vec![];
+        loop {
+            match pcap.read() {
+                Ok(p) => packets.push((p.timestamp, p.capture_length, p.data.to_vec())),
+                Err(Error::Timeout) => return packets,
+                Err(e) => panic!("unexpected error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn replay_both_byte_orders() {
+        let dir = tempfile::tempdir().unwrap();
+        let le = dir.path().join("le.pcap");
+        let be = dir.path().join("be.pcap");
+        let frame = [0xaau8; 60];
+        write_pcap(
+            &le,
+            false,
+            &[(100, 0, &frame[..]), (100, 500_000, &frame[..20])],
+            0,
+        );
+        write_pcap(&be, true, &[(5, 0, &frame[..30]), (7, 0, &frame[..])], 0);
+
+        let mut pcap = PcapFile::new(Options {
+            files: vec![le, be],
+            speed: 0,
+            snap_len: 40,
+        })
+        .unwrap();
+        let packets = read_all(&mut pcap);
+        assert_eq!(packets.len(), 4);
+        // 超过 snap_len 的部分被截断，capture_length 保留原始长度
+        assert_eq!(packets[0].1, 60);
+        assert_eq!(packets[0].2.len(), 40);
+        assert_eq!(packets[1].1, 20);
+        assert_eq!(packets[2].2, &frame[..30]);
+        // 文件内保持相对时间，下一个文件从上一个文件最后一个报文的时间继续
+        assert_eq!(packets[1].0 - packets[0].0, Duration::from_millis(500));
+        assert_eq!(packets[2].0, packets[1].0);
+        assert_eq!(packets[3].0 - packets[2].0, Duration::from_secs(2));
+
+        assert_eq!(pcap.counter.files.load(Ordering::Relaxed), 2);
+        assert_eq!(pcap.counter.packets.load(Ordering::Relaxed), 4);
+        assert_eq!(pcap.counter.errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn replay_truncated_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("truncated.pcap");
+        let frame = [0x55u8; 60];
+        write_pcap(&path, false, &[(1, 0, &frame[..]), (2, 0, &frame[..])], 10);
+
+        let mut pcap = PcapFile::new(Options {
+            files: vec![path],
+            speed: 0,
+            snap_len: 0,
+        })
+        .unwrap();
+        let packets = read_all(&mut pcap);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].2, frame);
+        assert_eq!(pcap.counter.errors.load(Ordering::Relaxed), 1);
+    }
+}
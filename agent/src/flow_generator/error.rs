@@ -47,6 +47,24 @@ pub enum Error {
     MqttLogParseFailed,
     #[error("mqtt perf parse failed")]
     MqttPerfParseFailed,
+    #[error("amqp log parse failed")]
+    AmqpLogParseFailed,
+    #[error("mongodb log parse failed")]
+    MongoDBLogParseFailed,
+    #[error("memcached log parse failed")]
+    MemcachedLogParseFailed,
+    #[error("cassandra log parse failed")]
+    CassandraLogParseFailed,
+    #[error("tds log parse failed")]
+    TdsLogParseFailed,
+    #[error("thrift log parse failed")]
+    ThriftLogParseFailed,
+    #[error("tls log parse failed")]
+    TlsLogParseFailed,
+    #[error("quic log parse failed")]
+    QuicLogParseFailed,
+    #[error("websocket log parse failed")]
+    WebSocketLogParseFailed,
     #[error("redis log parse failed")]
     RedisLogParseFailed,
     #[error("redis perf parse failed")]
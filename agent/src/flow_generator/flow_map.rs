@@ -876,12 +876,14 @@ impl FlowMap {
             // 统计量
             flow_metrics_peers: [
                 FlowMetricsPeer {
-                    total_packet_count: 1,
-                    packet_count: 1,
-                    total_byte_count: meta_packet.packet_len as u64,
-                    byte_count: meta_packet.packet_len as u64,
-                    l3_byte_count: meta_packet.l3_payload_len() as u64,
-                    l4_byte_count: meta_packet.l4_payload_len() as u64,
+                    total_packet_count: meta_packet.sampled_packet_count(),
+                    packet_count: meta_packet.sampled_packet_count(),
+                    total_byte_count: meta_packet.sampled_packet_bytes(),
+                    byte_count: meta_packet.sampled_packet_bytes(),
+                    l3_byte_count: meta_packet.l3_payload_len() as u64
+                        * meta_packet.sampled_packet_count(),
+                    l4_byte_count: meta_packet.l4_payload_len() as u64
+                        * meta_packet.sampled_packet_count(),
                     first: lookup_key.timestamp,
                     last: lookup_key.timestamp,
                     tcp_flags: meta_packet.tcp_data.flags,
@@ -1078,12 +1080,14 @@ impl FlowMap {
 
         let flow_metrics_peer =
             &mut flow.flow_metrics_peers[meta_packet.lookup_key.direction as usize];
-        flow_metrics_peer.packet_count += 1;
-        flow_metrics_peer.total_packet_count += 1;
-        flow_metrics_peer.byte_count += meta_packet.packet_len as u64;
-        flow_metrics_peer.l3_byte_count += meta_packet.l3_payload_len() as u64;
-        flow_metrics_peer.l4_byte_count += meta_packet.l4_payload_len() as u64;
-        flow_metrics_peer.total_byte_count += meta_packet.packet_len as u64;
+        let packet_count = meta_packet.sampled_packet_count();
+        let packet_bytes = meta_packet.sampled_packet_bytes();
+        flow_metrics_peer.packet_count += packet_count;
+        flow_metrics_peer.total_packet_count += packet_count;
+        flow_metrics_peer.byte_count += packet_bytes;
+        flow_metrics_peer.l3_byte_count += meta_packet.l3_payload_len() as u64 * packet_count;
+        flow_metrics_peer.l4_byte_count += meta_packet.l4_payload_len() as u64 * packet_count;
+        flow_metrics_peer.total_byte_count += packet_bytes;
         flow_metrics_peer.last = pkt_timestamp;
         if flow_metrics_peer.first.is_zero() {
             flow_metrics_peer.first = pkt_timestamp;
@@ -1368,6 +1372,20 @@ impl FlowMap {
         // 统计数据输出前矫正流方向
         self.update_flow_direction(&mut node, meta_packet);
 
+        // 流结束时输出仍未上报的日志
+        // ==========================
+        // report logs still pending when the flow ends
+        let pending_logs = match node.meta_flow_perf.as_mut() {
+            Some(perf) if config.collector_enabled => perf.flow_end(timeout.as_micros() as u64),
+            _ => vec![],
+        };
+        if !pending_logs.is_empty() {
+            let packet = Self::flow_end_packet(&node.tagged_flow.flow, timeout);
+            for info in pending_logs {
+                self.write_to_app_proto_log(config, &mut node, &packet, info, 0);
+            }
+        }
+
         let flow = &mut node.tagged_flow.flow;
         if flow.signal_source == SignalSource::EBPF {
             // the flow which from eBPF, it's close_type always be CloseType::Timeout
@@ -1483,6 +1501,26 @@ impl FlowMap {
         }
     }
 
+    // 流结束时可能没有报文，用流信息构造日志需要的报文字段
+    // ===================================================
+    // there may be no packet when the flow ends, build the packet fields
+    // needed by the log from the flow
+    fn flow_end_packet<'a>(flow: &Flow, timestamp: Duration) -> MetaPacket<'a> {
+        let mut packet = MetaPacket::default();
+        packet.lookup_key.timestamp = timestamp;
+        packet.lookup_key.proto = flow.flow_key.proto;
+        packet.lookup_key.eth_type = if flow.flow_key.ip_src.is_ipv6() {
+            EthernetType::Ipv6
+        } else {
+            EthernetType::Ipv4
+        };
+        packet.lookup_key.l2_end_0 = flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC].is_l2_end;
+        packet.lookup_key.l2_end_1 = flow.flow_metrics_peers[FLOW_METRICS_PEER_DST].is_l2_end;
+        packet.lookup_key.direction = PacketDirection::ServerToClient;
+        packet.signal_source = flow.signal_source;
+        packet
+    }
+
     fn flush_app_protolog(&mut self) {
         if self.protolog_buffer.len() > 0 {
             if let Err(_) = self.out_log_queue.send_all(&mut self.protolog_buffer) {
@@ -1992,6 +2030,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sampled_packet() {
+        let (mut flow_map, output_queue_receiver) =
+            _new_flow_map_and_receiver(TridentType::TtProcess);
+        let mut packet0 = _new_meta_packet();
+        packet0.signal_source = SignalSource::XFlow;
+        packet0.packet_count = 100;
+        let packet_len = packet0.packet_len as u64;
+        let flush_timestamp = packet0.lookup_key.timestamp;
+        flow_map.inject_meta_packet(&mut packet0);
+
+        let mut packet1 = _new_meta_packet();
+        packet1.signal_source = SignalSource::XFlow;
+        packet1.packet_count = 100;
+        packet1.tcp_data.flags = TcpFlags::PSH_ACK;
+        flow_map.inject_meta_packet(&mut packet1);
+
+        flow_map.inject_flush_ticker(flush_timestamp);
+        flow_map.inject_flush_ticker(flush_timestamp + Duration::from_secs(600));
+
+        if let Ok(tagged_flow) = output_queue_receiver.recv(Some(TIME_UNIT)) {
+            let peer_src = &tagged_flow.flow.flow_metrics_peers[FLOW_METRICS_PEER_SRC];
+            assert_eq!(peer_src.total_packet_count, 200);
+            assert_eq!(peer_src.total_byte_count, packet_len * 200);
+        }
+    }
+
     #[test]
     fn platform_data() {
         let (mut flow_map, output_queue_receiver) =
@@ -43,13 +43,13 @@ use std::time::Duration;
 
 const FLOW_METRICS_PEER_SRC: usize = 0;
 const FLOW_METRICS_PEER_DST: usize = 1;
-const TIME_UNIT: Duration = Duration::from_secs(1);
+pub(crate) const TIME_UNIT: Duration = Duration::from_secs(1);
 const QUEUE_BATCH_SIZE: usize = 1024;
 const STATISTICAL_INTERVAL: Duration = Duration::from_secs(1);
 const DEFAULT_L7_LOG_PACKET_SIZE: u32 = 256;
-const THREAD_FLOW_ID_MASK: u64 = 0xFF;
-const TIMER_FLOW_ID_MASK: u64 = 0xFFFFFFFF;
-const COUNTER_FLOW_ID_MASK: u64 = 0xFFFFFF;
+pub(crate) const THREAD_FLOW_ID_MASK: u64 = 0xFF;
+pub(crate) const TIMER_FLOW_ID_MASK: u64 = 0xFFFFFFFF;
+pub(crate) const COUNTER_FLOW_ID_MASK: u64 = 0xFFFFFF;
 // 暂定的Ipv4 ServiceTable LRU cache 容量
 const SERVICE_TABLE_IPV4_CAPACITY: usize = 2048;
 // 暂定的Ipv6 ServiceTable LRU cache 容量
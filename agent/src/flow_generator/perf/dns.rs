@@ -96,15 +96,26 @@ impl L7FlowPerf for DnsPerfData {
                     return Err(Error::DNSPerfParseFailed("dns payload length error"));
                 }
 
-                let size = read_u16_be(payload) as usize;
-                if size < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    return Err(Error::DNSPerfParseFailed("dns payload length error"));
+                // 一个报文段中可能有多个带长度前缀的消息
+                // ====================================
+                // a segment may contain multiple length-prefixed messages
+                let mut offset = 0;
+                while payload.len() > offset + DNS_TCP_PAYLOAD_OFFSET {
+                    let size = read_u16_be(&payload[offset..]) as usize;
+                    let start = offset + DNS_TCP_PAYLOAD_OFFSET;
+                    let end = (start + size).min(payload.len());
+                    let ret = self.decode_payload(
+                        &payload[start..end],
+                        packet.lookup_key.timestamp,
+                        flow_id,
+                    );
+                    if offset == 0 {
+                        ret?;
+                    } else if ret.is_err() {
+                        break;
+                    }
+                    offset = start + size;
                 }
-                self.decode_payload(
-                    &payload[DNS_TCP_PAYLOAD_OFFSET..],
-                    packet.lookup_key.timestamp,
-                    flow_id,
-                )?;
             }
             _ => return Err(Error::DNSPerfParseFailed("dns translation type error")),
         }
@@ -25,16 +25,15 @@ use crate::{
         l7_protocol_log::{L7ProtocolParser, L7ProtocolParserInterface, ParseParam},
         meta_packet::MetaPacket,
     },
-    config::handler::LogParserConfig,
+    config::handler::{L7LogDynamicConfig, LogParserConfig},
     flow_generator::{
         error::{Error, Result},
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
         protocol_logs::{
-            check_http_method, consts::*, get_http_request_info, get_http_request_version,
-            get_http_resp_info, is_http_v1_payload, parse_v1_headers, AppProtoHead, Httpv2Headers,
-            L7ResponseStatus, LogMessageType,
+            consts::*, grpc_response_status, AppProtoHead, Httpv2Headers, L7ResponseStatus,
+            LogMessageType,
         },
         HttpLog,
     },
@@ -44,6 +43,13 @@ use public::utils::net::h2pack;
 struct HttpSessionData {
     // HTTPv2 Header
     httpv2_headers: Httpv2Headers,
+    // HTTP/1 与日志使用相同的 pipeline 配对请求与响应
+    http1: HttpLog,
+    // 两个方向的 HPACK 动态表 <client, server>
+    hpack: (
+        h2pack::parser::Parser<'static>,
+        h2pack::parser::Parser<'static>,
+    ),
 
     pub status: L7ResponseStatus,
     pub status_code: u16,
@@ -113,16 +119,18 @@ impl L7FlowPerf for HttpPerfData {
             return Err(Error::NoParseConfig);
         };
         let payload = meta.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+        let param = ParseParam::from(meta);
 
-        if ParseParam::from(meta).ebpf_type == EbpfType::GoHttp2Uprobe {
+        if param.ebpf_type == EbpfType::GoHttp2Uprobe {
             return self.parse_go_http2_uprobe(payload, &ParseParam::from((meta, config)));
         }
 
         if self
             .parse_http_v1(
+                &config.l7_log_dynamic,
                 payload,
+                &param,
                 meta.lookup_key.timestamp,
-                meta.lookup_key.direction,
                 flow_id,
             )
             .is_ok()
@@ -211,6 +219,8 @@ impl HttpPerfData {
     pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
         let session_data = HttpSessionData {
             httpv2_headers: Httpv2Headers::default(),
+            http1: HttpLog::new_v1(),
+            hpack: Default::default(),
             status_code: 0,
             status: L7ResponseStatus::default(),
             has_log_data: false,
@@ -225,58 +235,59 @@ impl HttpPerfData {
         }
     }
 
+    // 报文中可能有多个 pipeline 的请求或响应，每个都计数，响应按顺序与请求配对计算 RRT
+    // ================================================================================
+    // A packet may carry several pipelined requests or responses, each of them is counted
+    // and responses are paired with requests in order to calculate RRT
     fn parse_http_v1(
         &mut self,
+        config: &L7LogDynamicConfig,
         payload: &[u8],
+        param: &ParseParam,
         timestamp: Duration,
-        direction: PacketDirection,
         flow_id: u64,
     ) -> Result<()> {
-        if !is_http_v1_payload(payload) {
-            return Err(Error::HttpHeaderParseFailed);
-        }
-
-        let mut headers = parse_v1_headers(payload);
-        let Some(first_line) = headers.next() else {
+        let infos = self
+            .session_data
+            .http1
+            .parse_http_v1_messages(config, payload, param);
+        self.session_data.http1.reset();
+        let infos = infos?;
+        let Some(last) = infos.last() else {
             return Err(Error::HttpHeaderParseFailed);
         };
+        self.session_data.msg_type = last.msg_type;
+        if last.msg_type == LogMessageType::Response {
+            self.session_data.status_code = last.status_code.unwrap_or_default() as u16;
+            self.session_data.status = Self::response_status(self.session_data.status_code, None);
+        }
 
-        if direction == PacketDirection::ServerToClient {
-            // HTTP响应行：HTTP/1.1 404 Not Found.
-            let (_, status_code) = get_http_resp_info(first_line)?;
-            self.session_data.msg_type = LogMessageType::Response;
-
-            let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
-            self.session_data.status_code = status_code as u16;
-            match status_code {
-                HTTP_STATUS_CLIENT_ERROR_MIN..=HTTP_STATUS_CLIENT_ERROR_MAX => {
-                    perf_stats.req_err_count += 1;
-                    self.session_data.status = L7ResponseStatus::ClientError;
-                }
-                HTTP_STATUS_SERVER_ERROR_MIN..=HTTP_STATUS_SERVER_ERROR_MAX => {
-                    perf_stats.resp_err_count += 1;
-                    self.session_data.status = L7ResponseStatus::ServerError;
-                }
-                _ => {
-                    self.session_data.status = L7ResponseStatus::Ok;
-                }
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        perf_stats.rrt_last = Duration::ZERO;
+        let mut rrt_cache = self.session_data.rrt_cache.borrow_mut();
+        for info in infos.iter() {
+            if info.msg_type == LogMessageType::Request {
+                perf_stats.req_count += 1;
+                rrt_cache.add_req_time(flow_id, info.seq, timestamp);
+                continue;
             }
 
             perf_stats.resp_count += 1;
-            perf_stats.rrt_last = Duration::ZERO;
-
-            let req_timestamp = match self
-                .session_data
-                .rrt_cache
-                .borrow_mut()
-                .get_and_remove_l7_req_time(flow_id, None)
-            {
-                Some(t) => t,
-                None => return Ok(()),
+            match Self::response_status(info.status_code.unwrap_or_default() as u16, None) {
+                L7ResponseStatus::ClientError => perf_stats.req_err_count += 1,
+                L7ResponseStatus::ServerError => perf_stats.resp_err_count += 1,
+                _ => {}
+            }
+            // 没有配对的请求时不计算 RRT
+            if info.seq.is_none() {
+                continue;
+            }
+            let Some(req_timestamp) = rrt_cache.get_and_remove_l7_req_time(flow_id, info.seq)
+            else {
+                continue;
             };
-
             if timestamp < req_timestamp {
-                return Ok(());
+                continue;
             }
 
             let rrt = timestamp - req_timestamp;
@@ -286,23 +297,6 @@ impl HttpPerfData {
             perf_stats.rrt_last = rrt;
             perf_stats.rrt_sum += rrt;
             perf_stats.rrt_count += 1;
-        } else {
-            // HTTP请求行：GET /background.png HTTP/1.0
-            let Ok((method, _, version)) = get_http_request_info(first_line) else {
-                return Err(Error::HttpHeaderParseFailed);
-            };
-            check_http_method(method)?;
-            get_http_request_version(version)?;
-
-            self.session_data.msg_type = LogMessageType::Request;
-
-            let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
-            perf_stats.req_count += 1;
-            perf_stats.rrt_last = Duration::ZERO;
-            self.session_data
-                .rrt_cache
-                .borrow_mut()
-                .add_req_time(flow_id, None, timestamp);
         }
         Ok(())
     }
@@ -319,7 +313,12 @@ impl HttpPerfData {
     // +---------------------------------------------------------------+
     // |                           Padding (*)                       ...
     // +---------------------------------------------------------------+
-    fn parse_headers_frame_payload(&mut self, payload: &[u8]) -> Result<u16> {
+    // 返回 (状态码, grpc-status)，请求和只有 trailers 的头部块状态码为 0
+    fn parse_headers_frame_payload(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<(u16, Option<i32>)> {
         let mut l_offset = 0;
         let mut end_index = 0;
 
@@ -347,8 +346,10 @@ impl HttpPerfData {
 
         let frame_payload = &payload[l_offset..end_index];
 
-        let mut parser = h2pack::parser::Parser::new();
-
+        let parser = match direction {
+            PacketDirection::ClientToServer => &mut self.session_data.hpack.0,
+            PacketDirection::ServerToClient => &mut self.session_data.hpack.1,
+        };
         let parse_rst = parser.parse(frame_payload);
 
         if let Err(_) = parse_rst {
@@ -356,17 +357,25 @@ impl HttpPerfData {
         }
 
         let header_list = parse_rst.unwrap();
-        let mut ret = Err(Error::HttpHeaderParseFailed);
+        let mut status_code = None;
+        let mut grpc_status = None;
         for header in header_list.iter() {
             match header.0.as_slice() {
                 b":method" => {
-                    ret = Ok(0);
+                    status_code = Some(0);
                 }
                 b":status" => {
-                    ret = Ok(std::str::from_utf8(header.1.as_slice())
-                        .unwrap_or_default()
-                        .parse::<u16>()
-                        .unwrap_or_default())
+                    status_code = Some(
+                        std::str::from_utf8(header.1.as_slice())
+                            .unwrap_or_default()
+                            .parse::<u16>()
+                            .unwrap_or_default(),
+                    )
+                }
+                b"grpc-status" => {
+                    grpc_status = std::str::from_utf8(header.1.as_slice())
+                        .ok()
+                        .and_then(|v| v.parse().ok())
                 }
                 b"content-type" => {
                     if header.1.starts_with(b"application/grpc") {
@@ -377,7 +386,10 @@ impl HttpPerfData {
                 _ => {}
             }
         }
-        ret
+        if status_code.is_none() && grpc_status.is_none() {
+            return Err(Error::HttpHeaderParseFailed);
+        }
+        Ok((status_code.unwrap_or_default(), grpc_status))
     }
 
     fn has_magic(payload: &[u8]) -> bool {
@@ -391,38 +403,81 @@ impl HttpPerfData {
         }
     }
 
-    fn parse_frame(&mut self, payload: &[u8]) -> Result<u16> {
+    // 返回第一个头部块的状态码，以及报文中所有头部块（包括 trailers）里最后的 grpc-status
+    // ================================================================================
+    // Returns the status code of the first header block, and the last grpc-status in all
+    // header blocks of the packet, trailers included
+    fn parse_frame(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<(u16, Option<i32>)> {
         let mut frame_payload = payload;
+        let mut first_headers = None;
+        let mut grpc_status = None;
         while frame_payload.len() > H2C_HEADER_SIZE {
             if Self::has_magic(frame_payload) {
                 frame_payload = &frame_payload[HTTPV2_MAGIC_LENGTH..];
                 continue;
             }
-            self.session_data
+            if self
+                .session_data
                 .httpv2_headers
-                .parse_headers_frame(frame_payload)?;
+                .parse_headers_frame(frame_payload)
+                .is_err()
+            {
+                break;
+            }
 
             // 值得注意的是，关于H2存在发送端主动通过Settings帧发起WindowUpdate请求时或发送方测量最小往返时间（PING）时，
             // 接收端如果支持配置会在其发送第一个请求时携带上述帧，可能会影响H2-HEADERS帧的位置，将HEADERS帧前的其它帧跳过。
             // 参考：https://tools.ietf.org/html/rfc7540#section-6.5
             if self.session_data.httpv2_headers.frame_type == FRAME_HEADERS {
                 if self.session_data.httpv2_headers.stream_id == 0 {
-                    return Err(Error::HttpHeaderParseFailed);
+                    break;
                 }
 
-                // TODO 调用第三库解析有时会导致panic, 先默认返回成功
-                // return Ok(200);
-                frame_payload = &frame_payload[H2C_HEADER_SIZE..];
-                return self.parse_headers_frame_payload(frame_payload);
+                // gRPC 响应的 trailers 是响应头、DATA 帧之后的另一个 HEADERS 帧
+                if let Ok((status_code, status)) =
+                    self.parse_headers_frame_payload(&frame_payload[H2C_HEADER_SIZE..], direction)
+                {
+                    grpc_status = status.or(grpc_status);
+                    if first_headers.is_none() {
+                        first_headers =
+                            Some((self.session_data.httpv2_headers.clone(), status_code));
+                    }
+                }
             }
             let offset = self.session_data.httpv2_headers.frame_length as usize + H2C_HEADER_SIZE;
 
             if frame_payload.len() <= offset {
-                return Err(Error::HttpHeaderParseFailed);
+                break;
             }
             frame_payload = &frame_payload[offset..];
         }
-        Err(Error::HttpHeaderParseFailed)
+        let Some((headers, status_code)) = first_headers else {
+            return Err(Error::HttpHeaderParseFailed);
+        };
+        self.session_data.httpv2_headers = headers;
+        Ok((status_code, grpc_status))
+    }
+
+    // gRPC 的错误由 grpc-status 表示，此时 HTTP 状态码通常为 200
+    // ================================================================================
+    // gRPC errors are indicated by grpc-status, the HTTP status code is usually 200 then
+    fn response_status(status_code: u16, grpc_status: Option<i32>) -> L7ResponseStatus {
+        if let Some(code) = grpc_status {
+            return grpc_response_status(code);
+        }
+        match status_code {
+            HTTP_STATUS_CLIENT_ERROR_MIN..=HTTP_STATUS_CLIENT_ERROR_MAX => {
+                L7ResponseStatus::ClientError
+            }
+            HTTP_STATUS_SERVER_ERROR_MIN..=HTTP_STATUS_SERVER_ERROR_MAX => {
+                L7ResponseStatus::ServerError
+            }
+            _ => L7ResponseStatus::Ok,
+        }
     }
 
     // HTTPv2协议参考:https://tools.ietf.org/html/rfc7540
@@ -433,26 +488,24 @@ impl HttpPerfData {
         direction: PacketDirection,
         flow_id: u64,
     ) -> Result<()> {
-        let status_code = self.parse_frame(payload)?;
+        let (status_code, grpc_status) = self.parse_frame(payload, direction)?;
         if direction == PacketDirection::ServerToClient {
             self.session_data.msg_type = LogMessageType::Response;
 
             let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
-            self.session_data.status_code = status_code as u16;
-            match status_code {
-                HTTP_STATUS_CLIENT_ERROR_MIN..=HTTP_STATUS_CLIENT_ERROR_MAX => {
-                    perf_stats.req_err_count += 1;
-                    self.session_data.status = L7ResponseStatus::ClientError;
-                }
-                HTTP_STATUS_SERVER_ERROR_MIN..=HTTP_STATUS_SERVER_ERROR_MAX => {
-                    perf_stats.resp_err_count += 1;
-                    self.session_data.status = L7ResponseStatus::ServerError;
-                }
-                _ => {
-                    self.session_data.status = L7ResponseStatus::Ok;
-                }
+            let status = Self::response_status(status_code, grpc_status);
+            match status {
+                L7ResponseStatus::ClientError => perf_stats.req_err_count += 1,
+                L7ResponseStatus::ServerError => perf_stats.resp_err_count += 1,
+                _ => {}
             }
+            self.session_data.status = status;
             perf_stats.rrt_last = Duration::ZERO;
+            // 只有 trailers 的报文，响应已经在响应头所在的报文中统计
+            if status_code == 0 {
+                return Ok(());
+            }
+            self.session_data.status_code = status_code;
 
             let req_timestamp = match self
                 .session_data
@@ -498,14 +551,11 @@ impl HttpPerfData {
         if let L7ProtocolInfo::HttpInfo(h) = log.parse_payload(payload, param)?.get(0).unwrap() {
             self.session_data.httpv2_headers.stream_id = h.stream_id.unwrap_or_default();
             self.session_data.l7_proto = h.get_l7_protocol_with_tls();
-            if let Some(code) = h.status_code {
-                match code as u16 {
-                    HTTP_STATUS_CLIENT_ERROR_MIN..=HTTP_STATUS_CLIENT_ERROR_MAX => {
-                        perf_stats.req_err_count += 1;
-                    }
-                    HTTP_STATUS_SERVER_ERROR_MIN..=HTTP_STATUS_SERVER_ERROR_MAX => {
-                        perf_stats.resp_err_count += 1;
-                    }
+            if h.status_code.is_some() || h.grpc_status.is_some() {
+                let code = h.status_code.unwrap_or_default() as u16;
+                match Self::response_status(code, h.grpc_status) {
+                    L7ResponseStatus::ClientError => perf_stats.req_err_count += 1,
+                    L7ResponseStatus::ServerError => perf_stats.resp_err_count += 1,
                     _ => {}
                 }
             }
@@ -523,6 +573,7 @@ impl HttpPerfData {
 
 #[cfg(test)]
 mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
     use std::path::Path;
 
     use super::*;
@@ -561,6 +612,140 @@ mod tests {
         http_perf_data
     }
 
+    #[test]
+    fn http_v1_pipeline() {
+        let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
+        let mut perf = HttpPerfData::new(rrt_cache);
+        let config = L7LogDynamicConfig::default();
+        let mut parse =
+            |payload: &[u8], direction: PacketDirection, seq: u32, ack: u32, ms: u64| {
+                let param = ParseParam {
+                    l4_protocol: IpProtocol::Tcp,
+                    ip_src: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    ip_dst: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    port_src: 0,
+                    port_dst: 0,
+                    direction,
+                    ebpf_type: EbpfType::None,
+                    ebpf_param: None,
+                    time: ms * 1000,
+                    parse_config: None,
+                    log_packet_size: usize::MAX,
+                    tcp_seq: seq,
+                    tcp_ack: ack,
+                    payload_len: payload.len() as u16,
+                };
+                perf.parse_http_v1(&config, payload, &param, Duration::from_millis(ms), 1)
+                    .unwrap();
+            };
+
+        let requests: &[u8] = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n\
+            GET /b HTTP/1.1\r\nHost: x\r\n\r\n\
+            GET /c HTTP/1.1\r\nHost: x\r\n\r\n";
+        let ack = 1000 + requests.len() as u32;
+        parse(requests, PacketDirection::ClientToServer, 1000, 5000, 10);
+        let responses: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok\
+            HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        parse(responses, PacketDirection::ServerToClient, 5000, ack, 20);
+        let seq = 5000 + responses.len() as u32;
+        let responses: &[u8] = b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n";
+        parse(responses, PacketDirection::ServerToClient, seq, ack, 40);
+
+        assert_eq!(
+            perf.perf_stats,
+            Some(PerfStats {
+                req_count: 3,
+                resp_count: 3,
+                req_err_count: 1,
+                resp_err_count: 1,
+                rrt_count: 3,
+                rrt_max: Duration::from_millis(30),
+                rrt_last: Duration::from_millis(30),
+                rrt_sum: Duration::from_millis(50),
+            })
+        );
+        assert_eq!(perf.session_data.status, L7ResponseStatus::ServerError);
+    }
+
+    fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn grpc_status() {
+        let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
+        let mut perf = HttpPerfData::new(rrt_cache);
+        let (c2s, s2c) = (
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        );
+        // POST /a.B/C, content-type: application/grpc
+        let mut request = vec![0x83, 0x86, 0x44, 0x06];
+        request.extend_from_slice(b"/a.B/C");
+        request.extend_from_slice(&[0x5f, 0x10]);
+        request.extend_from_slice(b"application/grpc");
+        let mut response = frame(FRAME_HEADERS, FLAG_HEADERS_END_HEADERS, 1, &[0x88]);
+        response.extend(frame(HTTPV2_FRAME_DATA_TYPE, 0, 1, &[0; 5]));
+        // grpc-status: 13 (INTERNAL)，加入动态表
+        let mut trailers = vec![0x40, 0x0b];
+        trailers.extend_from_slice(b"grpc-status");
+        trailers.extend_from_slice(&[0x02, b'1', b'3']);
+
+        let payload = frame(FRAME_HEADERS, FLAG_HEADERS_END_HEADERS, 1, &request);
+        perf.parse_http_v2(&payload, Duration::from_millis(10), c2s, 1)
+            .unwrap();
+        perf.parse_http_v2(&response, Duration::from_millis(20), s2c, 1)
+            .unwrap();
+        // trailers 在单独的报文中
+        let payload = frame(
+            FRAME_HEADERS,
+            FLAG_HEADERS_END_HEADERS | FLAG_END_STREAM,
+            1,
+            &trailers,
+        );
+        perf.parse_http_v2(&payload, Duration::from_millis(21), s2c, 1)
+            .unwrap();
+        assert_eq!(perf.session_data.l7_proto, L7Protocol::Grpc);
+        assert_eq!(perf.session_data.status, L7ResponseStatus::ServerError);
+        assert_eq!(
+            perf.perf_stats,
+            Some(PerfStats {
+                req_count: 1,
+                resp_count: 1,
+                req_err_count: 0,
+                resp_err_count: 1,
+                rrt_count: 1,
+                rrt_max: Duration::from_millis(10),
+                rrt_last: Duration::ZERO,
+                rrt_sum: Duration::from_millis(10),
+            })
+        );
+
+        // grpc-status: 3 (INVALID_ARGUMENT) 引用动态表中的名称，trailers 与响应头在同一个报文中
+        let payload = frame(FRAME_HEADERS, FLAG_HEADERS_END_HEADERS, 3, &request);
+        perf.parse_http_v2(&payload, Duration::from_millis(30), c2s, 1)
+            .unwrap();
+        let mut response = frame(FRAME_HEADERS, FLAG_HEADERS_END_HEADERS, 3, &[0x88]);
+        response.extend(frame(HTTPV2_FRAME_DATA_TYPE, 0, 3, &[0; 5]));
+        response.extend(frame(
+            FRAME_HEADERS,
+            FLAG_HEADERS_END_HEADERS | FLAG_END_STREAM,
+            3,
+            &[0x7e, 0x01, b'3'],
+        ));
+        perf.parse_http_v2(&response, Duration::from_millis(35), s2c, 1)
+            .unwrap();
+        assert_eq!(perf.session_data.status, L7ResponseStatus::ClientError);
+        let stats = perf.perf_stats.as_ref().unwrap();
+        assert_eq!(stats.resp_count, 2);
+        assert_eq!(stats.req_err_count, 1);
+        assert_eq!(stats.resp_err_count, 1);
+    }
+
     #[test]
     fn check() {
         let expected = vec![
@@ -585,6 +770,8 @@ mod tests {
                         msg_type: LogMessageType::Response,
                         rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                         httpv2_headers: Httpv2Headers::default(),
+                        http1: HttpLog::new_v1(),
+                        hpack: Default::default(),
                     },
                 },
             ),
@@ -609,6 +796,8 @@ mod tests {
                         msg_type: LogMessageType::Response,
                         rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
                         httpv2_headers: Httpv2Headers::default(),
+                        http1: HttpLog::new_v1(),
+                        hpack: Default::default(),
                     },
                 },
             ),
@@ -37,7 +37,10 @@ use public::l7_protocol::L7ProtocolEnum;
 
 use super::app_table::AppTable;
 use super::error::{Error, Result};
-use super::protocol_logs::{AppProtoHead, PostgresqlLog, ProtobufRpcWrapLog, SofaRpcLog};
+use super::protocol_logs::{
+    AmqpLog, AppProtoHead, CassandraLog, MemcachedLog, MongoDBLog, PostgresqlLog,
+    ProtobufRpcWrapLog, QuicLog, SofaRpcLog, TdsLog, ThriftLog, TlsLog, WebSocketLog,
+};
 
 use crate::{
     common::{
@@ -108,6 +111,15 @@ pub enum L7FlowPerfTable {
     PostgresqlLog,
     ProtobufRpcWrapLog,
     SofaRpcLog,
+    AmqpLog,
+    MongoDBLog,
+    MemcachedLog,
+    CassandraLog,
+    TdsLog,
+    ThriftLog,
+    TlsLog,
+    QuicLog,
+    WebSocketLog,
 }
 
 impl L7FlowPerfTable {
@@ -117,6 +129,15 @@ impl L7FlowPerfTable {
             L7FlowPerfTable::ProtobufRpcWrapLog(p) => p.reset(),
             L7FlowPerfTable::PostgresqlLog(p) => p.reset(),
             L7FlowPerfTable::SofaRpcLog(p) => p.reset(),
+            L7FlowPerfTable::AmqpLog(p) => p.reset(),
+            L7FlowPerfTable::MongoDBLog(p) => p.reset(),
+            L7FlowPerfTable::MemcachedLog(p) => p.reset(),
+            L7FlowPerfTable::CassandraLog(p) => p.reset(),
+            L7FlowPerfTable::TdsLog(p) => p.reset(),
+            L7FlowPerfTable::ThriftLog(p) => p.reset(),
+            L7FlowPerfTable::TlsLog(p) => p.reset(),
+            L7FlowPerfTable::QuicLog(p) => p.reset(),
+            L7FlowPerfTable::WebSocketLog(p) => p.reset(),
             _ => {}
         }
     }
@@ -192,6 +213,10 @@ impl<'a> Iterator for L7ProtocolCheckerIterator<'a> {
 pub struct FlowPerf {
     l4: L4FlowPerfTable,
     l7: Option<L7FlowPerfTable>,
+    // 协议升级前的性能统计，在下一次输出时上报
+    // ======================================
+    // perf stats before the protocol upgrade, reported on the next output
+    l7_before_upgrade: Option<L7FlowPerfTable>,
 
     // perf 目前还没有抽象出来,自定义协议需要添加字段区分,以后抽出来后 l7可以去掉.
     l7_protocol_log_parser: Option<L7ProtocolParser>,
@@ -216,14 +241,23 @@ impl FlowPerf {
     fn l7_new(protocol: L7Protocol, rrt_cache: Rc<RefCell<L7RrtCache>>) -> Option<L7FlowPerfTable> {
         match protocol {
             L7Protocol::DNS => Some(L7FlowPerfTable::from(DnsPerfData::new(rrt_cache.clone()))),
+            L7Protocol::TLS => Some(L7FlowPerfTable::from(TlsLog::new())),
+            L7Protocol::QUIC => Some(L7FlowPerfTable::from(QuicLog::new())),
+            L7Protocol::WebSocket => Some(L7FlowPerfTable::from(WebSocketLog::new())),
             L7Protocol::ProtobufRPC => Some(L7FlowPerfTable::from(ProtobufRpcWrapLog::new())),
             L7Protocol::SofaRPC => Some(L7FlowPerfTable::from(SofaRpcLog::new())),
+            L7Protocol::Thrift => Some(L7FlowPerfTable::from(ThriftLog::new())),
             L7Protocol::Dubbo => Some(L7FlowPerfTable::from(DubboPerfData::new(rrt_cache.clone()))),
             L7Protocol::Kafka => Some(L7FlowPerfTable::from(KafkaPerfData::new(rrt_cache.clone()))),
             L7Protocol::MQTT => Some(L7FlowPerfTable::from(MqttPerfData::new(rrt_cache.clone()))),
+            L7Protocol::AMQP => Some(L7FlowPerfTable::from(AmqpLog::new())),
             L7Protocol::MySQL => Some(L7FlowPerfTable::from(MysqlPerfData::new(rrt_cache.clone()))),
             L7Protocol::PostgreSQL => Some(L7FlowPerfTable::from(PostgresqlLog::new())),
+            L7Protocol::TDS => Some(L7FlowPerfTable::from(TdsLog::new())),
             L7Protocol::Redis => Some(L7FlowPerfTable::from(RedisPerfData::new(rrt_cache.clone()))),
+            L7Protocol::MongoDB => Some(L7FlowPerfTable::from(MongoDBLog::new())),
+            L7Protocol::Memcached => Some(L7FlowPerfTable::from(MemcachedLog::new())),
+            L7Protocol::Cassandra => Some(L7FlowPerfTable::from(CassandraLog::new())),
             L7Protocol::Http1
             | L7Protocol::Http1TLS
             | L7Protocol::Http2
@@ -319,18 +353,19 @@ impl FlowPerf {
         if let Some(payload) = packet.get_l4_payload() {
             let parser = self.l7_protocol_log_parser.as_mut().unwrap();
 
-            let ret = parser.parse_payload(
-                {
-                    let pkt_size = flow_config.l7_log_packet_size as usize;
-                    if pkt_size > payload.len() {
-                        payload
-                    } else {
-                        &payload[..pkt_size]
-                    }
-                },
-                parse_param,
-            );
+            let pkt_size = flow_config.l7_log_packet_size as usize;
+            let payload = if pkt_size > payload.len() || parser.need_complete_payload() {
+                payload
+            } else {
+                &payload[..pkt_size]
+            };
+            let parse_param = &ParseParam {
+                log_packet_size: pkt_size,
+                ..*parse_param
+            };
+            let ret = parser.parse_payload(payload, parse_param);
             parser.reset();
+            let upgraded = parser.upgraded_parser();
 
             if !self.is_success {
                 if ret.is_ok() {
@@ -360,12 +395,36 @@ impl FlowPerf {
                     };
                 }
             }
+            if let Some(parser) = upgraded {
+                if flow_config
+                    .l7_protocol_enabled_bitmap
+                    .is_enabled(parser.protocol())
+                {
+                    self.l7_upgrade(parser);
+                }
+            }
             return ret;
         }
 
         return Err(Error::ZeroPayloadLen);
     }
 
+    // app_table 中仍然是升级前的协议，新的连接需要先经过升级请求
+    // =========================================================
+    // the protocol before the upgrade is kept in app_table, new connections
+    // have to go through the upgrade request first
+    fn l7_upgrade(&mut self, parser: L7ProtocolParser) {
+        let protocol = parser.protocol();
+        self.l7_protocol_enum = parser.l7_protocl_enum();
+        self.l7_protocol_log_parser = Some(parser);
+        if self.l7.is_some() {
+            if self.l7_before_upgrade.is_none() {
+                self.l7_before_upgrade = self.l7.take();
+            }
+            self.l7 = Self::l7_new(protocol, self.rrt_cache.clone());
+        }
+    }
+
     fn l7_check(
         &mut self,
         flow_config: &FlowConfig,
@@ -543,6 +602,7 @@ impl FlowPerf {
         Some(Self {
             l4,
             l7: Self::l7_new(l7_protocol_enum.get_l7_protocol(), rrt_cache.clone()),
+            l7_before_upgrade: None,
             l7_protocol_log_parser: l7_parser,
             rrt_cache,
             l7_protocol_enum,
@@ -598,6 +658,16 @@ impl FlowPerf {
         Ok((vec![], 0))
     }
 
+    pub fn flow_end(&mut self, time: u64) -> Vec<L7ProtocolInfo> {
+        if self.is_skip {
+            return vec![];
+        }
+        match self.l7_protocol_log_parser.as_mut() {
+            Some(parser) => parser.flow_end(time),
+            None => vec![],
+        }
+    }
+
     pub fn copy_and_reset_perf_data(
         &mut self,
         flow_reversed: bool,
@@ -610,8 +680,13 @@ impl FlowPerf {
             stats.replace(self.l4.copy_and_reset_data(flow_reversed));
         }
 
-        if l7_performance_enabled && self.l7.is_some() {
-            let self_l7 = self.l7.as_mut().unwrap();
+        // 协议升级前的统计先上报，升级后的统计在下一次输出时上报
+        // =====================================================
+        // stats before the protocol upgrade are reported first, stats after
+        // the upgrade are reported on the next output
+        let mut l7_before_upgrade = self.l7_before_upgrade.take();
+        let self_l7 = l7_before_upgrade.as_mut().or(self.l7.as_mut());
+        if let (true, Some(self_l7)) = (l7_performance_enabled, self_l7) {
             if self_l7.data_updated() || l7_timeout_count > 0 {
                 if let Some(stats) = stats.as_mut() {
                     let FlowPerfStats {
@@ -36,8 +36,8 @@ use crate::{
         perf::L7FlowPerf,
         protocol_logs::{
             mqtt::{
-                mqtt_fixed_header, parse_connack_packet, parse_connect_packet, parse_status_code,
-                PacketKind, QualityOfService,
+                mqtt_fixed_header, parse_connack_packet, parse_connect_packet, parse_reason_code,
+                parse_status_code, PacketKind, QualityOfService,
             },
             AppProtoHead, L7ResponseStatus, LogMessageType,
         },
@@ -176,9 +176,11 @@ impl MqttPerfData {
     }
 
     fn parse_mqtt(&mut self, mut payload: &[u8], timestamp: Duration, flow_id: u64) -> Result<()> {
-        // 现在只支持 MQTT 3.1.1解析
-        if self.proto_version != 0 && self.proto_version != 4 {
-            warn!("cannot parse packet, perf parser only support to parse MQTT V3.1.1 packet");
+        // 现在只支持 MQTT 3.1.1和5.0解析
+        if self.proto_version != 0 && self.proto_version != 4 && self.proto_version != 5 {
+            warn!(
+                "cannot parse packet, perf parser only support to parse MQTT V3.1.1 and V5.0 packet"
+            );
             return Err(Error::MqttPerfParseFailed);
         }
 
@@ -201,7 +203,11 @@ impl MqttPerfData {
                         parse_connack_packet(input).map_err(|_| Error::MqttLogParseFailed)?;
                     self.status_code = return_code;
                     self.msg_type = LogMessageType::Response;
-                    self.status = parse_status_code(return_code);
+                    self.status = if self.proto_version == 5 {
+                        parse_reason_code(return_code)
+                    } else {
+                        parse_status_code(return_code)
+                    };
                     self.calc_response(timestamp, flow_id);
                 }
                 PacketKind::Publish { dup, qos, .. } => {
@@ -238,7 +244,7 @@ impl MqttPerfData {
                     self.msg_type = LogMessageType::Request;
                     self.calc_request(timestamp, flow_id);
                 }
-                PacketKind::Disconnect => {
+                PacketKind::Disconnect | PacketKind::Auth => {
                     self.msg_type = LogMessageType::Session;
                     self.calc_request(timestamp, flow_id);
                 }
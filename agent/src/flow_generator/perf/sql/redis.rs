@@ -31,7 +31,7 @@ use crate::{
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
-        protocol_logs::{decode, AppProtoHead, L7ResponseStatus, LogMessageType},
+        protocol_logs::{AppProtoHead, L7ResponseStatus, LogMessageType, RedisInfo, RedisLog},
     },
 };
 
@@ -45,6 +45,11 @@ pub struct RedisPerfData {
     status: L7ResponseStatus,
     has_log_data: bool,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
+    // 与日志使用相同的方式解析流水线和事务，并按顺序配对请求与应答
+    // =================================================
+    // Pipelines and transactions are parsed and requests are paired with replies in order
+    // the same way as the log
+    log: RedisLog,
 }
 
 impl PartialEq for RedisPerfData {
@@ -94,22 +99,31 @@ impl L7FlowPerf for RedisPerfData {
             return Err(Error::RedisPerfParseFailed);
         }
         // Redis协议通过Redis请求来识别，对于请求报文格式严格检查，回应有分段的情况不会严格检查
-        let (context, _, is_error_resp) = decode(
-            payload,
-            packet.lookup_key.direction == PacketDirection::ClientToServer,
-        )
-        .ok_or(Error::RedisPerfParseFailed)?;
+        let infos = self
+            .log
+            .parse(
+                payload,
+                IpProtocol::Tcp,
+                packet.lookup_key.direction,
+                usize::MAX,
+            )
+            .map_err(|_| Error::RedisPerfParseFailed)?;
         self.l7_proto = L7Protocol::Redis;
+        // MULTI和事务中排队的命令及其应答不单独统计
+        if infos.is_empty() {
+            return Ok(());
+        }
         self.has_log_data = true;
-        if packet.lookup_key.direction == PacketDirection::ClientToServer {
-            self.calc_request(packet.lookup_key.timestamp, flow_id);
-        } else if self.calc_response(
-            packet.lookup_key.timestamp,
-            &context,
-            flow_id,
-            is_error_resp,
-        ) {
-            return Err(Error::L7ReqNotFound(1));
+        let mut req_not_found = 0;
+        for info in infos.iter() {
+            if info.msg_type == LogMessageType::Request {
+                self.calc_request(packet.lookup_key.timestamp, flow_id, info.seq);
+            } else if self.calc_response(packet.lookup_key.timestamp, info, flow_id) {
+                req_not_found += 1;
+            }
+        }
+        if req_not_found > 0 {
+            return Err(Error::L7ReqNotFound(req_not_found));
         }
         Ok(())
     }
@@ -178,10 +192,11 @@ impl RedisPerfData {
             status: L7ResponseStatus::default(),
             has_log_data: false,
             rrt_cache: rrt_cache,
+            log: RedisLog::default(),
         }
     }
 
-    fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
+    fn calc_request(&mut self, timestamp: Duration, flow_id: u64, seq: Option<u32>) {
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.rrt_last = Duration::ZERO;
         stats.req_count += 1;
@@ -189,21 +204,16 @@ impl RedisPerfData {
         self.msg_type = LogMessageType::Request;
         self.rrt_cache
             .borrow_mut()
-            .add_req_time(flow_id, None, timestamp);
+            .add_req_time(flow_id, seq, timestamp);
     }
 
     // 返回是否无法匹配到request
-    fn calc_response(
-        &mut self,
-        timestamp: Duration,
-        context: &Vec<u8>,
-        flow_id: u64,
-        is_error_resp: bool,
-    ) -> bool {
+    fn calc_response(&mut self, timestamp: Duration, info: &RedisInfo, flow_id: u64) -> bool {
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.resp_count += 1;
         self.msg_type = LogMessageType::Response;
-        if context.len() > 1 && context[0] == b'-' && is_error_resp {
+        // 集群重定向MOVED/ASK不作为错误
+        if info.resp_status == L7ResponseStatus::ServerError {
             stats.resp_err_count += 1;
             self.status = L7ResponseStatus::ServerError;
         } else {
@@ -211,13 +221,13 @@ impl RedisPerfData {
         }
         stats.rrt_last = Duration::ZERO;
 
-        if self.active <= 0 {
+        if self.active <= 0 || info.seq.is_none() {
             return true;
         }
         let req_timestamp = match self
             .rrt_cache
             .borrow_mut()
-            .get_and_remove_l7_req_time(flow_id, None)
+            .get_and_remove_l7_req_time(flow_id, info.seq)
         {
             Some(t) => t,
             None => return true,
@@ -253,6 +263,7 @@ mod tests {
 
     use super::*;
 
+    use crate::common::tap_port::TapPort;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/redis";
@@ -301,6 +312,7 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    log: RedisLog::default(),
                 },
             ),
             (
@@ -322,6 +334,7 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    log: RedisLog::default(),
                 },
             ),
             (
@@ -343,6 +356,7 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    log: RedisLog::default(),
                 },
             ),
         ];
@@ -351,4 +365,39 @@ mod tests {
             assert_eq!(item.1, run(item.0), "parse pcap {} unexcepted", item.0);
         }
     }
+
+    #[test]
+    fn pipeline_and_redirect() {
+        let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
+        let mut perf = RedisPerfData::new(rrt_cache);
+        let mut parse = |payload: &[u8], direction: PacketDirection, ms: u64| {
+            let mut packet = MetaPacket::default();
+            packet.lookup_key.proto = IpProtocol::Tcp;
+            packet.lookup_key.direction = direction;
+            packet.lookup_key.timestamp = Duration::from_millis(ms);
+            packet.tap_port = TapPort::from_ebpf(0);
+            packet.raw_from_ebpf = payload.to_vec();
+            perf.parse(None, &packet, 1)
+        };
+
+        let requests = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\n";
+        assert!(parse(requests, PacketDirection::ClientToServer, 10).is_ok());
+        let replies = b"-MOVED 3999 127.0.0.1:6381\r\n$1\r\n1\r\n";
+        assert!(parse(replies, PacketDirection::ServerToClient, 30).is_ok());
+
+        assert_eq!(
+            perf.stats,
+            Some(PerfStats {
+                req_count: 2,
+                resp_count: 2,
+                req_err_count: 0,
+                resp_err_count: 0,
+                rrt_count: 2,
+                rrt_max: Duration::from_millis(20),
+                rrt_last: Duration::from_millis(20),
+                rrt_sum: Duration::from_millis(40),
+            })
+        );
+        assert_eq!(perf.status, L7ResponseStatus::Ok);
+    }
 }
@@ -19,6 +19,8 @@ pub const HTTP_RESP_MAX_LINE: usize = 40;
 pub const H2C_HEADER_SIZE: usize = 9;
 
 pub const FRAME_HEADERS: u8 = 0x1;
+pub const FLAG_END_STREAM: u8 = 0x1;
+pub const FLAG_HEADERS_END_HEADERS: u8 = 0x4;
 pub const FLAG_HEADERS_PADDED: u8 = 0x8;
 pub const FLAG_HEADERS_PRIORITY: u8 = 0x20;
 
@@ -33,6 +35,11 @@ pub const HTTP_STATUS_CLIENT_ERROR_MAX: u16 = 499;
 pub const HTTP_STATUS_SERVER_ERROR_MIN: u16 = 500;
 pub const HTTP_STATUS_SERVER_ERROR_MAX: u16 = 600;
 pub const HTTP_RESP_MIN_LEN: usize = 13; // 响应行："HTTP/1.1 200 "
+pub const HTTP_STATUS_INFORMATIONAL_MAX: u16 = 199;
+pub const HTTP_STATUS_SWITCHING_PROTOCOLS: u16 = 101;
+pub const HTTP_STATUS_NO_CONTENT: u16 = 204;
+pub const HTTP_STATUS_NOT_MODIFIED: u16 = 304;
+pub const HTTP_V1_MAX_PENDING_REQUESTS: usize = 64; // 流水线中等待响应的请求数上限
 
 pub const HTTP_HOST_OFFSET: usize = 6;
 pub const HTTP_CONTENT_LENGTH_OFFSET: usize = 16;
@@ -45,10 +52,17 @@ pub const HTTPV2_MAGIC_PREFIX: &str = "PRI * HTTP";
 
 pub const HTTPV2_FRAME_DATA_TYPE: u8 = 0x00;
 pub const HTTPV2_FRAME_HEADERS_TYPE: u8 = 0x01;
+pub const HTTPV2_FRAME_RST_STREAM_TYPE: u8 = 0x03;
+pub const HTTPV2_FRAME_CONTINUATION_TYPE: u8 = 0x09;
 
 pub const HTTPV2_FRAME_TYPE_MIN: u8 = 0x00;
 pub const HTTPV2_FRAME_TYPE_MAX: u8 = 0x09;
 
+// gRPC 消息前缀：1字节压缩标志和4字节消息长度
+// 参考：https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+pub const GRPC_MESSAGE_PREFIX_LEN: usize = 5;
+pub const GRPC_MAX_STREAMS: usize = 128; // 每个连接上跟踪的 gRPC stream 数上限
+
 pub const TRACE_ID_TYPE: usize = 0;
 pub const SPAN_ID_TYPE: usize = 1;
 
@@ -86,6 +100,38 @@ pub const BC_STRING_SHORT: u8 = 0x30;
 pub const BC_STRING_SHORT_MAX: u8 = 0x33; // STRING_SHORT_MAX >> 8 | BC_STRING_SHORT
 pub const STRING_SHORT_MAX: u16 = 0x3ff;
 
+// Hessian2 序列化格式
+// 参考：http://hessian.caucho.com/doc/hessian-serialization.html
+pub const HESSIAN2_SERIAL_ID: u8 = 2;
+pub const HESSIAN2_NULL: u8 = b'N';
+pub const HESSIAN2_TRUE: u8 = b'T';
+pub const HESSIAN2_FALSE: u8 = b'F';
+pub const HESSIAN2_INT: u8 = b'I';
+pub const HESSIAN2_LONG: u8 = b'L';
+pub const HESSIAN2_LONG_INT: u8 = 0x59;
+pub const HESSIAN2_DOUBLE: u8 = b'D';
+pub const HESSIAN2_DATE: u8 = 0x4a;
+pub const HESSIAN2_DATE_MINUTE: u8 = 0x4b;
+pub const HESSIAN2_STRING: u8 = b'S';
+pub const HESSIAN2_STRING_CHUNK: u8 = b'R';
+pub const HESSIAN2_BINARY: u8 = b'B';
+pub const HESSIAN2_BINARY_CHUNK: u8 = b'A';
+pub const HESSIAN2_CLASS_DEF: u8 = b'C';
+pub const HESSIAN2_OBJECT: u8 = b'O';
+pub const HESSIAN2_REF: u8 = 0x51;
+pub const HESSIAN2_MAP: u8 = b'M';
+pub const HESSIAN2_UNTYPED_MAP: u8 = b'H';
+pub const HESSIAN2_LIST: u8 = 0x55;
+pub const HESSIAN2_FIXED_LIST: u8 = b'V';
+pub const HESSIAN2_UNTYPED_LIST: u8 = 0x57;
+pub const HESSIAN2_FIXED_UNTYPED_LIST: u8 = b'X';
+pub const HESSIAN2_END: u8 = b'Z';
+
+// Dubbo 响应体第一个值表示响应类型
+// 参考：https://github.com/apache/dubbo/blob/3.2/dubbo-rpc/dubbo-rpc-dubbo/src/main/java/org/apache/dubbo/rpc/protocol/dubbo/DubboCodec.java
+pub const RESPONSE_WITH_EXCEPTION: i64 = 0;
+pub const RESPONSE_WITH_EXCEPTION_WITH_ATTACHMENTS: i64 = 3;
+
 // 参考：https://dubbo.apache.org/zh/docs/concepts/rpc-protocol/#protocol-spec
 // Dubbo Request Body
 pub const BODY_PARAM_MIN: u8 = 1;
@@ -93,7 +139,8 @@ pub const BODY_PARAM_DUBBO_VERSION: u8 = 1;
 pub const BODY_PARAM_SERVICE_NAME: u8 = 2;
 pub const BODY_PARAM_SERVICE_VERSION: u8 = 3;
 pub const BODY_PARAM_METHOD_NAME: u8 = 4;
-pub const BODY_PARAM_MAX: u8 = 5;
+pub const BODY_PARAM_PARAMETER_TYPES: u8 = 5;
+pub const BODY_PARAM_MAX: u8 = 6;
 
 // Mysql constants
 pub const PROTOCOL_VERSION: u8 = 10;
@@ -129,6 +176,14 @@ pub const RESPONSE_CODE_OFFSET: usize = 0;
 pub const ERROR_CODE_OFFSET: usize = RESPONSE_CODE_OFFSET + RESPONSE_CODE_LEN;
 pub const AFFECTED_ROWS_OFFSET: usize = RESPONSE_CODE_OFFSET + RESPONSE_CODE_LEN;
 pub const SQL_STATE_OFFSET: usize = ERROR_CODE_OFFSET + ERROR_CODE_LEN;
+pub const STATUS_FLAGS_LEN: usize = 2;
+pub const WARNINGS_LEN: usize = 2;
+
+// Prepared statement
+pub const STATEMENT_ID_LEN: usize = 4;
+pub const STATEMENT_ID_OFFSET: usize = 1;
+pub const PREPARE_OK_WARNINGS_OFFSET: usize = 10;
+pub const STATEMENT_CACHE_SIZE: usize = 64;
 
 // int
 pub const INT_FLAGS_2: u8 = 0xfc;
@@ -185,18 +240,34 @@ pub const DNS_NAME_MAX_SIZE: usize = 255;
 pub const DNS_HEADER_QDCOUNT_OFFSET: usize = 4;
 pub const DNS_HEADER_ANCOUNT_OFFSET: usize = 6;
 pub const DNS_HEADER_NSCOUNT_OFFSET: usize = 8;
+pub const DNS_HEADER_ARCOUNT_OFFSET: usize = 10;
 pub const QUESTION_CLASS_OFFSET: usize = 2;
 pub const QUESTION_CLASS_TYPE_SIZE: usize = 4;
 pub const RR_CLASS_OFFSET: usize = 2;
+pub const RR_TTL_OFFSET: usize = 4;
 pub const RR_DATALENGTH_OFFSET: usize = 8;
 pub const RR_RDATA_OFFSET: usize = 10;
 pub const DNS_TYPE_A: u16 = 1;
 pub const DNS_TYPE_NS: u16 = 2;
+pub const DNS_TYPE_CNAME: u16 = 5;
 pub const DNS_TYPE_SOA: u16 = 6;
 pub const DNS_TYPE_WKS: u16 = 11;
 pub const DNS_TYPE_PTR: u16 = 12;
+pub const DNS_TYPE_MX: u16 = 15;
+pub const DNS_TYPE_TXT: u16 = 16;
 pub const DNS_TYPE_AAAA: u16 = 28;
+pub const DNS_TYPE_SRV: u16 = 33;
 pub const DNS_TYPE_DNAME: u16 = 39;
+pub const DNS_TYPE_OPT: u16 = 41;
+pub const DNS_TYPE_SVCB: u16 = 64;
+pub const DNS_TYPE_HTTPS: u16 = 65;
 pub const DNS_TYPE_WKS_LENGTH: usize = 5;
-pub const DNS_TYPE_PTR_LENGTH: usize = 2;
+pub const DNS_TYPE_SOA_FIELDS_LENGTH: usize = 20;
+pub const DNS_TYPE_MX_MIN_LENGTH: usize = 3;
+pub const DNS_TYPE_SRV_MIN_LENGTH: usize = 7;
+pub const DNS_TYPE_SVCB_MIN_LENGTH: usize = 3;
+pub const DNS_EDNS_DO_MASK: u16 = 0x8000;
+pub const DNS_EDNS_OPTION_CLIENT_SUBNET: u16 = 8;
+pub const DNS_EDNS_FAMILY_IPV4: u16 = 1;
+pub const DNS_EDNS_FAMILY_IPV6: u16 = 2;
 pub const DOMAIN_NAME_SPLIT: char = ';';
@@ -13,9 +13,11 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::fmt;
+
 use serde::Serialize;
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response};
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
 use super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 use crate::{
     common::{
@@ -27,27 +29,101 @@ use crate::{
     },
     flow_generator::error::{Error, Result},
     log_info_merge, parse_common,
-    utils::bytes::read_u16_be,
+    utils::bytes::{read_u16_be, read_u32_be},
 };
 use public::{l7_protocol::L7Protocol, utils::net::parse_ip_slice};
 
+// CNAME 链最多追踪的层数，避免环路
+// ==================================
+// max depth of the followed CNAME chain, avoiding loops
+const DNS_CNAME_CHAIN_MAX_DEPTH: usize = 16;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsSection {
+    Answer,
+    Authority,
+    Additional,
+}
+
+impl DnsSection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DnsSection::Answer => "answer",
+            DnsSection::Authority => "authority",
+            DnsSection::Additional => "additional",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DnsRecord {
+    pub section: DnsSection,
+    pub name: String,
+    pub rr_type: u16,
+    pub ttl: u32,
+    // 以 zone 文件的格式展示，如：
+    // MX: 10 mail.example.com
+    // SRV: 0 5 5060 sip.example.com
+    // 未知类型按 RFC 3597 展示为 \# <长度> <十六进制>
+    // ==============================================
+    // in zone file presentation format, e.g.:
+    // MX: 10 mail.example.com
+    // SRV: 0 5 5060 sip.example.com
+    // unknown types are shown as \# <length> <hex> per RFC 3597
+    pub data: String,
+}
+
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            presentation_name(&self.name),
+            self.ttl,
+            dns_type_name(self.rr_type),
+            self.data
+        )
+    }
+}
+
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct DnsEdns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    // 格式为 地址/源前缀长度/作用域前缀长度，与 dig 输出一致
+    // ===================================================
+    // formatted as address/source prefix/scope prefix, the same as dig
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub client_subnet: String,
+}
+
 #[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
 pub struct DnsInfo {
     #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
     pub trans_id: u16,
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
-    pub query_type: u8,
-    #[serde(skip)]
-    pub domain_type: u16,
+    pub query_type: u16,
 
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub query_name: String,
-    // 根据查询类型的不同而不同，如：
+    // answer 和 authority 中各记录的数据，根据记录类型的不同而不同，如：
     // A: ipv4/ipv6地址
     // NS: name server
-    // SOA: primary name server
+    // CNAME: canonical name
+    // =============================================================
+    // data of the answer and authority records, varies with the record type, e.g.:
+    // A: ipv4/ipv6 address
+    // NS: name server
+    // CNAME: canonical name
     #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
     pub answers: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<DnsRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edns: Option<DnsEdns>,
 
     #[serde(rename = "response_status")]
     pub status: L7ResponseStatus,
@@ -89,6 +165,10 @@ impl L7ProtocolInfoInterface for DnsInfo {
 impl DnsInfo {
     pub fn merge(&mut self, other: Self) {
         self.answers = other.answers;
+        self.records = other.records;
+        if other.edns.is_some() {
+            self.edns = other.edns;
+        }
         if other.status != L7ResponseStatus::default() {
             self.status = other.status;
         }
@@ -100,27 +180,82 @@ impl DnsInfo {
         }
     }
 
-    pub fn get_domain_str(&self) -> &'static str {
-        let typ = [
-            "", "A", "NS", "MD", "MF", "CNAME", "SOA", "MB", "MG", "MR", "NULL", "WKS", "PTR",
-            "HINFO", "MINFO", "MX", "TXT",
-        ];
+    pub fn get_domain_str(&self) -> String {
+        dns_type_name(self.query_type)
+    }
 
-        match self.domain_type {
-            1..=16 => typ[self.domain_type as usize],
-            28 => "AAAA",
-            252 => "AXFR",
-            253 => "MAILB",
-            254 => "MAILA",
-            255 => "ANY",
-            _ => "",
+    // 从查询的域名开始沿 answer 中的 CNAME 记录追踪，如 a.com -> b.com -> c.com
+    // ======================================================================
+    // follows the CNAME records in answers from the queried name, e.g. a.com -> b.com -> c.com
+    pub fn cname_chain(&self) -> Option<String> {
+        let mut chain = vec![self.query_name.as_str()];
+        while chain.len() <= DNS_CNAME_CHAIN_MAX_DEPTH {
+            let last = chain[chain.len() - 1];
+            let next = self.records.iter().find(|r| {
+                r.section == DnsSection::Answer
+                    && r.rr_type == DNS_TYPE_CNAME
+                    && r.name.eq_ignore_ascii_case(last)
+            });
+            match next {
+                Some(r) if !chain.iter().any(|n| n.eq_ignore_ascii_case(&r.data)) => {
+                    chain.push(r.data.as_str())
+                }
+                _ => break,
+            }
+        }
+        if chain.len() > 1 {
+            Some(chain.join(" -> "))
+        } else {
+            None
         }
     }
 }
 
 impl From<DnsInfo> for L7ProtocolSendLog {
     fn from(f: DnsInfo) -> Self {
-        let req_type = String::from(f.get_domain_str());
+        let req_type = f.get_domain_str();
+        let mut attributes = vec![];
+        if let Some(chain) = f.cname_chain() {
+            attributes.push(KeyVal {
+                key: "cname_chain".to_string(),
+                val: chain,
+            });
+        }
+        let mut indexes = [0usize; 3];
+        for record in f.records.iter() {
+            let index = &mut indexes[record.section as usize];
+            attributes.push(KeyVal {
+                key: format!("{}.{}", record.section.as_str(), index),
+                val: record.to_string(),
+            });
+            *index += 1;
+        }
+        if let Some(edns) = f.edns.as_ref() {
+            attributes.push(KeyVal {
+                key: "edns.udp_payload_size".to_string(),
+                val: edns.udp_payload_size.to_string(),
+            });
+            attributes.push(KeyVal {
+                key: "edns.version".to_string(),
+                val: edns.version.to_string(),
+            });
+            attributes.push(KeyVal {
+                key: "edns.dnssec_ok".to_string(),
+                val: edns.dnssec_ok.to_string(),
+            });
+            if edns.extended_rcode != 0 {
+                attributes.push(KeyVal {
+                    key: "edns.extended_rcode".to_string(),
+                    val: edns.extended_rcode.to_string(),
+                });
+            }
+            if !edns.client_subnet.is_empty() {
+                attributes.push(KeyVal {
+                    key: "edns.client_subnet".to_string(),
+                    val: edns.client_subnet.clone(),
+                });
+            }
+        }
         let log = L7ProtocolSendLog {
             req: L7Request {
                 req_type,
@@ -135,6 +270,11 @@ impl From<DnsInfo> for L7ProtocolSendLog {
             },
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.trans_id as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -144,9 +284,125 @@ impl From<DnsInfo> for L7ProtocolSendLog {
     }
 }
 
+pub fn dns_type_name(rr_type: u16) -> String {
+    let typ = [
+        "", "A", "NS", "MD", "MF", "CNAME", "SOA", "MB", "MG", "MR", "NULL", "WKS", "PTR", "HINFO",
+        "MINFO", "MX", "TXT",
+    ];
+
+    let name = match rr_type {
+        1..=16 => typ[rr_type as usize],
+        28 => "AAAA",
+        33 => "SRV",
+        35 => "NAPTR",
+        39 => "DNAME",
+        41 => "OPT",
+        43 => "DS",
+        46 => "RRSIG",
+        47 => "NSEC",
+        48 => "DNSKEY",
+        64 => "SVCB",
+        65 => "HTTPS",
+        252 => "AXFR",
+        253 => "MAILB",
+        254 => "MAILA",
+        255 => "ANY",
+        257 => "CAA",
+        _ => return format!("TYPE{}", rr_type),
+    };
+    name.to_string()
+}
+
+fn presentation_name(name: &str) -> &str {
+    if name.is_empty() {
+        "."
+    } else {
+        name
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// RFC 9460 中 SVCB/HTTPS 记录的参数
+// =================================
+// parameters of SVCB/HTTPS records in RFC 9460
+fn svc_param_key_name(key: u16) -> String {
+    match key {
+        0 => "mandatory".to_string(),
+        1 => "alpn".to_string(),
+        2 => "no-default-alpn".to_string(),
+        3 => "port".to_string(),
+        4 => "ipv4hint".to_string(),
+        5 => "ech".to_string(),
+        6 => "ipv6hint".to_string(),
+        _ => format!("key{}", key),
+    }
+}
+
+fn svc_param(key: u16, value: &[u8]) -> Result<String> {
+    let invalid =
+        || Error::DNSLogParseFailed(format!("svc param {} length {} invalid", key, value.len()));
+    let value = match key {
+        0 => {
+            if value.len() % 2 != 0 {
+                return Err(invalid());
+            }
+            value
+                .chunks(2)
+                .map(|k| svc_param_key_name(read_u16_be(k)))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        1 => {
+            let mut alpn = vec![];
+            let mut index = 0;
+            while index < value.len() {
+                let end = index + 1 + value[index] as usize;
+                if end > value.len() {
+                    return Err(invalid());
+                }
+                alpn.push(String::from_utf8_lossy(&value[index + 1..end]).into_owned());
+                index = end;
+            }
+            alpn.join(",")
+        }
+        2 => return Ok(svc_param_key_name(key)),
+        3 => {
+            if value.len() != 2 {
+                return Err(invalid());
+            }
+            read_u16_be(value).to_string()
+        }
+        4 | 6 => {
+            let size = if key == 4 {
+                IPV4_ADDR_LEN
+            } else {
+                IPV6_ADDR_LEN
+            };
+            if value.is_empty() || value.len() % size != 0 {
+                return Err(invalid());
+            }
+            value
+                .chunks(size)
+                .filter_map(|ip| parse_ip_slice(ip).map(|ip| ip.to_string()))
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        5 => base64::encode(value),
+        _ => to_hex(value),
+    };
+    Ok(format!("{}={}", svc_param_key_name(key), value))
+}
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct DnsLog {
     info: DnsInfo,
+    // TCP 的一个报文段中可能包含多个 DNS 消息，每个消息对应一条日志
+    // ============================================================
+    // a TCP segment may carry multiple DNS messages, one log for each message
+    infos: Vec<DnsInfo>,
     // 是否已经解析过,避免check后重复解析
     parsed: bool,
 }
@@ -162,12 +418,22 @@ impl L7ProtocolParserInterface for DnsLog {
     }
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
-        if self.parsed {
-            return Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())]);
+        if !self.parsed {
+            parse_common!(self, param);
+            // UDP 载荷可能被 l7_log_packet_size 或抓包长度截断，比较收到的载荷长度与 UDP 头部
+            // 给出的长度；TCP 载荷由 decode_tcp_payload 根据 2 字节的长度前缀判断
+            // ==================================================================================
+            // the UDP payload may be truncated by l7_log_packet_size or the capture length,
+            // compare the received length with the length given by the UDP header; for TCP
+            // decode_tcp_payload checks each message against its 2-byte length prefix
+            let truncated = payload.len() < param.payload_len as usize;
+            self.parse(payload, param.l4_protocol, param.direction, truncated)?;
         }
-        parse_common!(self, param);
-        self.parse(payload, param.l4_protocol, param.direction, None, None)?;
-        Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())])
+        Ok(self
+            .infos
+            .iter()
+            .map(|i| L7ProtocolInfo::DnsInfo(i.clone()))
+            .collect())
     }
 
     fn protocol(&self) -> L7Protocol {
@@ -181,16 +447,22 @@ impl L7ProtocolParserInterface for DnsLog {
 
 impl DnsLog {
     fn reset_logs(&mut self) {
-        self.info.trans_id = 0;
-        self.info.query_type = 0;
-        self.info.query_name = String::new();
-        self.info.answers = String::new();
-        self.info.status_code = None;
+        self.info = DnsInfo {
+            start_time: self.info.start_time,
+            end_time: self.info.end_time,
+            is_tls: self.info.is_tls,
+            ..Default::default()
+        };
     }
 
     pub fn dns_check_protocol(&mut self, payload: &[u8], param: &ParseParam) -> bool {
-        let ret = self.parse(payload, param.l4_protocol, param.direction, None, None);
-        self.parsed = ret.is_ok() && self.info.msg_type == LogMessageType::Request;
+        let ret = self.parse(payload, param.l4_protocol, param.direction, false);
+        self.parsed = ret.is_ok()
+            && self
+                .infos
+                .first()
+                .map(|i| i.msg_type == LogMessageType::Request)
+                .unwrap_or_default();
         self.parsed
     }
 
@@ -280,18 +552,21 @@ impl DnsLog {
 
         if self.info.query_name.len() > 0 {
             self.info.query_name.push(DOMAIN_NAME_SPLIT);
+        } else {
+            self.info.query_type = read_u16_be(&payload[offset..]);
         }
         self.info.query_name.push_str(&name);
-        if self.info.query_type == DNS_REQUEST {
-            self.info.domain_type = read_u16_be(&payload[offset..]);
-            self.info.msg_type = LogMessageType::Request;
-        }
 
         Ok(offset + QUESTION_CLASS_TYPE_SIZE)
     }
 
-    fn decode_resource_record(&mut self, payload: &[u8], g_offset: usize) -> Result<usize> {
-        let (_, offset) = self.decode_name(payload, g_offset)?;
+    fn decode_resource_record(
+        &mut self,
+        payload: &[u8],
+        g_offset: usize,
+        section: DnsSection,
+    ) -> Result<usize> {
+        let (name, offset) = self.decode_name(payload, g_offset)?;
 
         if payload.len() <= offset {
             let err_msg = format!("payload length error: {}", payload.len());
@@ -304,84 +579,223 @@ impl DnsLog {
             return Err(Error::DNSLogParseFailed(err_msg));
         }
 
-        self.info.domain_type = read_u16_be(&payload[offset..]);
+        let rr_type = read_u16_be(&payload[offset..]);
         let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
-        if data_length != 0 {
-            self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?;
+        let rdata_offset = offset + RR_RDATA_OFFSET;
+        if rdata_offset + data_length > payload.len() {
+            let err_msg = format!("resource record data length error: {}", data_length);
+            return Err(Error::DNSLogParseFailed(err_msg));
         }
 
-        Ok(offset + RR_RDATA_OFFSET + data_length)
-    }
+        if rr_type == DNS_TYPE_OPT {
+            self.decode_edns(payload, offset, data_length)?;
+            return Ok(rdata_offset + data_length);
+        }
 
-    fn decode_rdata(&mut self, payload: &[u8], g_offset: usize, data_length: usize) -> Result<()> {
-        let answer_name_len = self.info.answers.len();
-        if answer_name_len > 0
-            && self.info.answers[answer_name_len - 1..] != DOMAIN_NAME_SPLIT.to_string()
-        {
-            self.info.answers.push(DOMAIN_NAME_SPLIT);
+        let data = if data_length != 0 {
+            self.decode_rdata(payload, rr_type, rdata_offset, data_length)?
+        } else {
+            String::new()
+        };
+        if section != DnsSection::Additional && !data.is_empty() {
+            if !self.info.answers.is_empty() {
+                self.info.answers.push(DOMAIN_NAME_SPLIT);
+            }
+            self.info.answers.push_str(&data);
         }
+        self.info.records.push(DnsRecord {
+            section,
+            name,
+            rr_type,
+            ttl: read_u32_be(&payload[offset + RR_TTL_OFFSET..]),
+            data,
+        });
+
+        Ok(rdata_offset + data_length)
+    }
 
-        match self.info.domain_type {
+    fn decode_rdata(
+        &self,
+        payload: &[u8],
+        rr_type: u16,
+        g_offset: usize,
+        data_length: usize,
+    ) -> Result<String> {
+        let rdata = &payload[g_offset..g_offset + data_length];
+        let invalid = || {
+            let err_msg = format!(
+                "domain type {} data length {} invalid",
+                rr_type, data_length
+            );
+            Error::DNSLogParseFailed(err_msg)
+        };
+
+        let data = match rr_type {
             DNS_TYPE_A | DNS_TYPE_AAAA => match data_length {
-                IPV4_ADDR_LEN | IPV6_ADDR_LEN => {
-                    if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length])
-                    {
-                        self.info.answers.push_str(&ipaddr.to_string());
-                    }
-                }
-                _ => {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
-                }
+                IPV4_ADDR_LEN | IPV6_ADDR_LEN => parse_ip_slice(rdata)
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_default(),
+                _ => return Err(invalid()),
             },
-            DNS_TYPE_NS | DNS_TYPE_DNAME | DNS_TYPE_SOA => {
+            DNS_TYPE_NS | DNS_TYPE_CNAME | DNS_TYPE_PTR | DNS_TYPE_DNAME => {
                 if data_length > DNS_NAME_MAX_SIZE {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return Err(invalid());
                 }
 
                 let (name, _) = self.decode_name(payload, g_offset)?;
-                self.info.answers.push_str(&name);
+                presentation_name(&name).to_string()
             }
-            DNS_TYPE_WKS => {
-                if data_length < DNS_TYPE_WKS_LENGTH {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+            DNS_TYPE_SOA => {
+                let (mname, offset) = self.decode_name(payload, g_offset)?;
+                let (rname, offset) = self.decode_name(payload, offset)?;
+                if offset + DNS_TYPE_SOA_FIELDS_LENGTH > g_offset + data_length {
+                    return Err(invalid());
                 }
-                if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length]) {
-                    self.info.answers.push_str(&ipaddr.to_string());
+                // serial refresh retry expire minimum
+                let fields = (0..5)
+                    .map(|i| read_u32_be(&payload[offset + i * 4..]).to_string())
+                    .collect::<Vec<_>>();
+                format!(
+                    "{} {} {}",
+                    presentation_name(&mname),
+                    presentation_name(&rname),
+                    fields.join(" ")
+                )
+            }
+            DNS_TYPE_MX => {
+                if data_length < DNS_TYPE_MX_MIN_LENGTH {
+                    return Err(invalid());
                 }
+                let (exchange, _) = self.decode_name(payload, g_offset + 2)?;
+                format!("{} {}", read_u16_be(rdata), presentation_name(&exchange))
             }
-            DNS_TYPE_PTR => {
-                if data_length != DNS_TYPE_PTR_LENGTH {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+            DNS_TYPE_TXT => {
+                let mut strings = vec![];
+                let mut index = 0;
+                while index < data_length {
+                    let end = index + 1 + rdata[index] as usize;
+                    if end > data_length {
+                        return Err(invalid());
+                    }
+                    strings.push(format!(
+                        "{:?}",
+                        String::from_utf8_lossy(&rdata[index + 1..end])
+                    ));
+                    index = end;
                 }
+                strings.join(" ")
             }
-            _ => {
-                let err_msg = format!(
-                    "other domain type {} data length {} invalid",
-                    self.info.domain_type, data_length
-                );
+            DNS_TYPE_SRV => {
+                if data_length < DNS_TYPE_SRV_MIN_LENGTH {
+                    return Err(invalid());
+                }
+                let (target, _) = self.decode_name(payload, g_offset + 6)?;
+                format!(
+                    "{} {} {} {}",
+                    read_u16_be(rdata),
+                    read_u16_be(&rdata[2..]),
+                    read_u16_be(&rdata[4..]),
+                    presentation_name(&target)
+                )
+            }
+            DNS_TYPE_SVCB | DNS_TYPE_HTTPS => {
+                if data_length < DNS_TYPE_SVCB_MIN_LENGTH {
+                    return Err(invalid());
+                }
+                let (target, mut offset) = self.decode_name(payload, g_offset + 2)?;
+                let mut data = format!("{} {}", read_u16_be(rdata), presentation_name(&target));
+                let end = g_offset + data_length;
+                while offset + 4 <= end {
+                    let key = read_u16_be(&payload[offset..]);
+                    let size = read_u16_be(&payload[offset + 2..]) as usize;
+                    offset += 4;
+                    if offset + size > end {
+                        return Err(invalid());
+                    }
+                    data.push(' ');
+                    data.push_str(&svc_param(key, &payload[offset..offset + size])?);
+                    offset += size;
+                }
+                data
+            }
+            DNS_TYPE_WKS => {
+                if data_length < DNS_TYPE_WKS_LENGTH {
+                    return Err(invalid());
+                }
+                parse_ip_slice(&rdata[..IPV4_ADDR_LEN])
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_default()
+            }
+            _ => format!("\\# {} {}", data_length, to_hex(rdata)),
+        };
+        Ok(data)
+    }
+
+    // OPT 伪记录的 CLASS 为 UDP 载荷大小，TTL 为扩展 RCODE、版本和标志位
+    // ================================================================
+    // the CLASS of the OPT pseudo record is the UDP payload size, and the TTL
+    // is made up of the extended RCODE, version and flags
+    fn decode_edns(&mut self, payload: &[u8], offset: usize, data_length: usize) -> Result<()> {
+        let mut edns = DnsEdns {
+            udp_payload_size: read_u16_be(&payload[offset + RR_CLASS_OFFSET..]),
+            extended_rcode: payload[offset + RR_TTL_OFFSET],
+            version: payload[offset + RR_TTL_OFFSET + 1],
+            dnssec_ok: read_u16_be(&payload[offset + RR_TTL_OFFSET + 2..]) & DNS_EDNS_DO_MASK != 0,
+            ..Default::default()
+        };
+
+        let options = &payload[offset + RR_RDATA_OFFSET..offset + RR_RDATA_OFFSET + data_length];
+        let mut index = 0;
+        while index + 4 <= options.len() {
+            let code = read_u16_be(&options[index..]);
+            let size = read_u16_be(&options[index + 2..]) as usize;
+            index += 4;
+            if index + size > options.len() {
+                let err_msg = format!("edns option {} length {} invalid", code, size);
                 return Err(Error::DNSLogParseFailed(err_msg));
             }
+            if code == DNS_EDNS_OPTION_CLIENT_SUBNET {
+                edns.client_subnet = Self::decode_client_subnet(&options[index..index + size])?;
+            }
+            index += size;
         }
+
+        if edns.extended_rcode != 0 {
+            let code = ((edns.extended_rcode as i32) << 4) | self.info.status_code.unwrap_or(0);
+            self.info.status_code = Some(code);
+            self.set_status(code);
+        }
+        self.info.edns = Some(edns);
         Ok(())
     }
 
-    fn set_status(&mut self, status_code: u8) {
+    // RFC 7871 中的 EDNS Client Subnet，地址按源前缀长度截断
+    // ===================================================
+    // EDNS Client Subnet in RFC 7871, the address is truncated to the source prefix
+    fn decode_client_subnet(option: &[u8]) -> Result<String> {
+        if option.len() < 4 {
+            let err_msg = format!("edns client subnet length {} invalid", option.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
+        let mut addr = match read_u16_be(option) {
+            DNS_EDNS_FAMILY_IPV4 => vec![0u8; IPV4_ADDR_LEN],
+            DNS_EDNS_FAMILY_IPV6 => vec![0u8; IPV6_ADDR_LEN],
+            family => {
+                let err_msg = format!("edns client subnet family {} invalid", family);
+                return Err(Error::DNSLogParseFailed(err_msg));
+            }
+        };
+        let address = &option[4..];
+        if address.len() > addr.len() {
+            let err_msg = format!("edns client subnet length {} invalid", option.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
+        addr[..address.len()].copy_from_slice(address);
+        let ip = parse_ip_slice(&addr).unwrap();
+        Ok(format!("{}/{}/{}", ip, option[2], option[3]))
+    }
+
+    fn set_status(&mut self, status_code: i32) {
         if status_code == 0 {
             self.info.status = L7ResponseStatus::Ok;
         } else if status_code == 1 || status_code == 3 {
@@ -391,19 +805,41 @@ impl DnsLog {
         }
     }
 
-    fn decode_payload(&mut self, payload: &[u8]) -> Result<()> {
+    // truncated 表示消息不完整（如跨越多个 TCP 报文段），此时尽量解析已有的记录
+    // ======================================================================
+    // truncated means the message is incomplete (e.g. spans multiple TCP segments),
+    // in which case the available records are parsed as many as possible
+    fn decode_payload(&mut self, payload: &[u8], truncated: bool) -> Result<()> {
+        self.reset_logs();
         if payload.len() <= DNS_HEADER_SIZE {
             let err_msg = format!("dns payload length too short:{}", payload.len());
             return Err(Error::DNSLogParseFailed(err_msg));
         }
         self.info.trans_id = read_u16_be(&payload[..DNS_HEADER_FLAGS_OFFSET]);
-        self.info.query_type = payload[DNS_HEADER_FLAGS_OFFSET] & 0x80;
-        let code = payload[DNS_HEADER_FLAGS_OFFSET + 1] & 0xf;
-        self.info.status_code = Some(code as i32);
+        self.info.msg_type =
+            if payload[DNS_HEADER_FLAGS_OFFSET] & DNS_HEADER_QR_MASK == DNS_RESPONSE {
+                LogMessageType::Response
+            } else {
+                LogMessageType::Request
+            };
+        let code = (payload[DNS_HEADER_FLAGS_OFFSET + 1] & DNS_HEADER_RESPCODE_MASK) as i32;
+        self.info.status_code = Some(code);
         self.set_status(code);
         let qd_count = read_u16_be(&payload[DNS_HEADER_QDCOUNT_OFFSET..]);
-        let an_count = read_u16_be(&payload[DNS_HEADER_ANCOUNT_OFFSET..]);
-        let ns_count = read_u16_be(&payload[DNS_HEADER_NSCOUNT_OFFSET..]);
+        let sections = [
+            (
+                read_u16_be(&payload[DNS_HEADER_ANCOUNT_OFFSET..]),
+                DnsSection::Answer,
+            ),
+            (
+                read_u16_be(&payload[DNS_HEADER_NSCOUNT_OFFSET..]),
+                DnsSection::Authority,
+            ),
+            (
+                read_u16_be(&payload[DNS_HEADER_ARCOUNT_OFFSET..]),
+                DnsSection::Additional,
+            ),
+        ];
 
         let mut g_offset = DNS_HEADER_SIZE;
 
@@ -411,20 +847,49 @@ impl DnsLog {
             g_offset = self.decode_question(payload, g_offset)?;
         }
 
-        if self.info.query_type == DNS_RESPONSE {
-            self.info.query_type = 1;
-
-            for _i in 0..an_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
+        'sections: for (count, section) in sections {
+            for _i in 0..count {
+                match self.decode_resource_record(payload, g_offset, section) {
+                    Ok(offset) => g_offset = offset,
+                    Err(_) if truncated => break 'sections,
+                    Err(e) => return Err(e),
+                }
             }
+        }
 
-            for _i in 0..ns_count {
-                g_offset = self.decode_resource_record(payload, g_offset)?;
-            }
+        self.infos.push(self.info.clone());
+        Ok(())
+    }
 
-            self.info.msg_type = LogMessageType::Response;
+    // DNS over TCP 的每个消息之前有 2 字节的长度字段，一个报文段中可能有多个消息
+    // =======================================================================
+    // each DNS over TCP message is prefixed with a 2-byte length, and a segment
+    // may contain multiple messages
+    fn decode_tcp_payload(&mut self, payload: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while payload.len() > offset + DNS_TCP_PAYLOAD_OFFSET {
+            let size = read_u16_be(&payload[offset..]) as usize;
+            let start = offset + DNS_TCP_PAYLOAD_OFFSET;
+            let end = start + size;
+            let ret = if size <= DNS_HEADER_SIZE {
+                let err_msg = format!("dns payload length error:{}", size);
+                Err(Error::DNSLogParseFailed(err_msg))
+            } else {
+                self.decode_payload(&payload[start..end.min(payload.len())], end > payload.len())
+            };
+            if let Err(e) = ret {
+                if self.infos.is_empty() {
+                    return Err(e);
+                }
+                break;
+            }
+            offset = end;
         }
 
+        if self.infos.is_empty() {
+            let err_msg = format!("dns payload length error:{}", payload.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
         Ok(())
     }
 
@@ -433,24 +898,12 @@ impl DnsLog {
         payload: &[u8],
         proto: IpProtocol,
         _direction: PacketDirection,
-        _is_req_end: Option<bool>,
-        _is_resp_end: Option<bool>,
+        truncated: bool,
     ) -> Result<()> {
-        self.reset_logs();
+        self.infos.clear();
         match proto {
-            IpProtocol::Udp => self.decode_payload(payload),
-            IpProtocol::Tcp => {
-                if payload.len() <= DNS_TCP_PAYLOAD_OFFSET {
-                    let err_msg = format!("dns payload length error:{}", payload.len());
-                    return Err(Error::DNSLogParseFailed(err_msg));
-                }
-                let size = read_u16_be(payload);
-                if (size as usize) < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    let err_msg = format!("dns payload length error:{}", size);
-                    return Err(Error::DNSLogParseFailed(err_msg));
-                }
-                self.decode_payload(&payload[DNS_TCP_PAYLOAD_OFFSET..])
-            }
+            IpProtocol::Udp => self.decode_payload(payload, truncated),
+            IpProtocol::Tcp => self.decode_tcp_payload(payload),
             _ => {
                 let err_msg = format!("dns payload length error:{}", payload.len());
                 Err(Error::DNSLogParseFailed(err_msg))
@@ -473,14 +926,14 @@ mod tests {
 
     const FILE_DIR: &str = "resources/test/flow_generator/dns";
 
-    fn run(name: &str) -> String {
+    fn run(name: &str) -> Vec<(bool, Vec<DnsInfo>)> {
         let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
         let mut packets = capture.as_meta_packets();
         if packets.is_empty() {
-            return "".to_string();
+            return vec![];
         }
 
-        let mut output = String::new();
+        let mut output = vec![];
         let first_dst_port = packets[0].lookup_key.dst_port;
         for packet in packets.iter_mut() {
             packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
@@ -494,15 +947,8 @@ mod tests {
             };
 
             let mut dns = DnsLog::default();
-            let _ = dns.parse(
-                payload,
-                packet.lookup_key.proto,
-                packet.lookup_key.direction,
-                None,
-                None,
-            );
             let is_dns = dns.dns_check_protocol(payload, &ParseParam::from(packet as &MetaPacket));
-            output.push_str(&format!("{:?} is_dns: {}\r\n", dns.info, is_dns));
+            output.push((is_dns, dns.infos));
         }
         output
     }
@@ -516,7 +962,12 @@ mod tests {
 
         for item in files.iter() {
             let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
-            let output = run(item.0);
+            let mut output = String::new();
+            for (is_dns, infos) in run(item.0) {
+                for info in infos {
+                    output.push_str(&format!("{:?} is_dns: {}\r\n", info, is_dns));
+                }
+            }
 
             if output != expected {
                 let output_path = Path::new("actual.txt");
@@ -530,4 +981,167 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn truncated_by_log_packet_size() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("edns.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let response = &mut packets[1];
+        response.lookup_key.direction = PacketDirection::ServerToClient;
+        let mut param = ParseParam::from(&*response);
+        param.log_packet_size = 80;
+        let payload = &response.get_l4_payload().unwrap()[..param.log_packet_size];
+
+        // 截断处之前的记录仍然输出
+        let mut dns = DnsLog::default();
+        let infos = dns.parse_payload(payload, &param).unwrap();
+        let L7ProtocolInfo::DnsInfo(info) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(info.msg_type, LogMessageType::Response);
+        assert_eq!(
+            info.records[0].to_string(),
+            "www.example.com 300 CNAME cdn.example.net"
+        );
+        assert_eq!(info.records.len(), 1);
+    }
+
+    #[test]
+    fn edns() {
+        let output = run("edns.pcap");
+        assert_eq!(output.len(), 6);
+
+        let (is_dns, request) = &output[0];
+        assert!(*is_dns);
+        assert_eq!(request[0].query_type, DNS_TYPE_A);
+        assert_eq!(
+            request[0].edns,
+            Some(DnsEdns {
+                udp_payload_size: 1232,
+                dnssec_ok: true,
+                client_subnet: "192.0.2.0/24/0".to_string(),
+                ..Default::default()
+            })
+        );
+
+        let response = &output[1].1[0];
+        assert_eq!(
+            response.answers,
+            "cdn.example.net;edge.example.net;93.184.216.34;ns1.example.net"
+        );
+        assert_eq!(
+            response.cname_chain().as_deref(),
+            Some("www.example.com -> cdn.example.net -> edge.example.net")
+        );
+        assert_eq!(
+            response.edns.as_ref().map(|e| e.client_subnet.as_str()),
+            Some("192.0.2.0/24/24")
+        );
+        let records = response
+            .records
+            .iter()
+            .map(|r| (r.section, r.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                (
+                    DnsSection::Answer,
+                    "www.example.com 300 CNAME cdn.example.net".to_string()
+                ),
+                (
+                    DnsSection::Answer,
+                    "cdn.example.net 60 CNAME edge.example.net".to_string()
+                ),
+                (
+                    DnsSection::Answer,
+                    "edge.example.net 20 A 93.184.216.34".to_string()
+                ),
+                (
+                    DnsSection::Authority,
+                    "example.net 86400 NS ns1.example.net".to_string()
+                ),
+                (
+                    DnsSection::Additional,
+                    "ns1.example.net 86400 AAAA 2001:db8::53".to_string()
+                ),
+            ]
+        );
+
+        let log = L7ProtocolSendLog::from(response.clone());
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|kv| kv.key == key)
+                .map(|kv| kv.val.as_str())
+        };
+        assert_eq!(
+            attribute("cname_chain"),
+            Some("www.example.com -> cdn.example.net -> edge.example.net")
+        );
+        assert_eq!(
+            attribute("answer.2"),
+            Some("edge.example.net 20 A 93.184.216.34")
+        );
+        assert_eq!(
+            attribute("additional.0"),
+            Some("ns1.example.net 86400 AAAA 2001:db8::53")
+        );
+        assert_eq!(attribute("edns.dnssec_ok"), Some("true"));
+        assert_eq!(attribute("edns.client_subnet"), Some("192.0.2.0/24/24"));
+
+        let txt = &output[3].1[0];
+        assert_eq!(txt.get_domain_str(), "TXT");
+        assert_eq!(txt.answers, "\"v=spf1 -all\" \"hello world\"");
+
+        // BADVERS 由 OPT 中的扩展 RCODE 和头部 RCODE 组成
+        // ==============================================
+        // BADVERS is made up of the extended RCODE in OPT and the header RCODE
+        let badvers = &output[5].1[0];
+        assert_eq!(badvers.status_code, Some(16));
+        assert_eq!(badvers.status, L7ResponseStatus::ServerError);
+        assert_eq!(
+            badvers.records[0].data,
+            "ns1.example.com hostmaster.example.com 2024010101 7200 3600 1209600 300"
+        );
+    }
+
+    #[test]
+    fn tcp_pipeline() {
+        let output = run("tcp-pipeline.pcap");
+        assert_eq!(output.len(), 5);
+
+        let (is_dns, requests) = &output[0];
+        assert!(*is_dns);
+        assert_eq!(
+            requests
+                .iter()
+                .map(|i| (i.trans_id, i.get_domain_str()))
+                .collect::<Vec<_>>(),
+            vec![(0x0101, "MX".to_string()), (0x0102, "HTTPS".to_string())]
+        );
+
+        let responses = &output[1].1;
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[0].answers,
+            "10 mail.example.com;20 backup.example.com"
+        );
+        assert_eq!(
+            responses[1].answers,
+            "1 . alpn=h2,h3 port=443 ipv4hint=192.0.2.1 ech=AAECAw=="
+        );
+
+        // 跨越报文段的应答只解析到完整的记录
+        // ================================
+        // only complete records are parsed in the response spanning segments
+        let srv = &output[3].1;
+        assert_eq!(srv.len(), 1);
+        assert_eq!(srv[0].answers, "0 5 5060 sip.example.com");
+        assert_eq!(srv[0].records.len(), 1);
+
+        assert!(!output[4].0);
+        assert!(output[4].1.is_empty());
+    }
 }
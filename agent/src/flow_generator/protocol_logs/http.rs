@@ -14,14 +14,17 @@
  * limitations under the License.
  */
 
-use std::str;
+use std::{collections::VecDeque, mem, str};
 
+use lru::LruCache;
 use serde::Serialize;
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo};
+use super::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+};
 use super::value_is_default;
 use super::{consts::*, AppProtoHead, L7ResponseStatus};
-use super::{decode_new_rpc_trace_context_with_type, LogMessageType};
+use super::{decode_trace_header, LogMessageType, TraceContext, WebSocketLog};
 
 use crate::{
     common::{
@@ -30,11 +33,11 @@ use crate::{
         flow::L7Protocol,
         flow::PacketDirection,
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        l7_protocol_log::{L7ProtocolParser, L7ProtocolParserInterface, ParseParam},
     },
-    config::handler::{L7LogDynamicConfig, TraceType},
+    config::handler::L7LogDynamicConfig,
     flow_generator::error::{Error, Result},
-    flow_generator::protocol_logs::{decode_base64_to_string, L7ProtoRawDataType},
+    flow_generator::protocol_logs::L7ProtoRawDataType,
     parse_common,
     utils::bytes::{read_u32_be, read_u32_le},
 };
@@ -65,12 +68,17 @@ pub struct HttpInfo {
 
     #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
     pub stream_id: Option<u32>,
+    // http1 流水线中请求在连接内的序号，用于请求和响应配对
+    #[serde(skip)]
+    seq: Option<u32>,
     #[serde(skip_serializing_if = "value_is_default")]
     pub version: String,
     #[serde(skip_serializing_if = "value_is_default")]
     pub trace_id: String,
     #[serde(skip_serializing_if = "value_is_default")]
     pub span_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub parent_span_id: String,
 
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
     pub method: String,
@@ -96,11 +104,26 @@ pub struct HttpInfo {
     pub status_code: Option<i32>,
     #[serde(rename = "response_status")]
     status: L7ResponseStatus,
+
+    // gRPC 的状态在响应结束时的 trailers 中
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grpc_status: Option<i32>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub grpc_message: String,
+    // gRPC stream 中长度前缀消息的个数和字节数
+    #[serde(skip)]
+    req_msg_count: u32,
+    #[serde(skip)]
+    req_msg_bytes: u64,
+    #[serde(skip)]
+    resp_msg_count: u32,
+    #[serde(skip)]
+    resp_msg_bytes: u64,
 }
 
 impl L7ProtocolInfoInterface for HttpInfo {
     fn session_id(&self) -> Option<u32> {
-        self.stream_id
+        self.stream_id.or(self.seq)
     }
 
     fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
@@ -199,6 +222,16 @@ impl HttpInfo {
                 if self.resp_content_length.is_none() {
                     self.resp_content_length = other.resp_content_length;
                 }
+                if other.grpc_status.is_some() {
+                    self.grpc_status = other.grpc_status;
+                    self.grpc_message = other.grpc_message;
+                }
+                if other.req_msg_count > 0 || other.resp_msg_count > 0 {
+                    self.req_msg_count = other.req_msg_count;
+                    self.req_msg_bytes = other.req_msg_bytes;
+                    self.resp_msg_count = other.resp_msg_count;
+                    self.resp_msg_bytes = other.resp_msg_bytes;
+                }
 
                 if other.is_resp_end {
                     self.is_resp_end = true;
@@ -207,7 +240,9 @@ impl HttpInfo {
             _ => {}
         }
 
-        if other_is_grpc {
+        if other.is_triple() {
+            self.proto = L7Protocol::Dubbo;
+        } else if other_is_grpc && !self.is_triple() {
             self.proto = L7Protocol::Grpc;
         }
         if self.trace_id.is_empty() {
@@ -216,6 +251,9 @@ impl HttpInfo {
         if self.span_id.is_empty() {
             self.span_id = other.span_id;
         }
+        if self.parent_span_id.is_empty() {
+            self.parent_span_id = other.parent_span_id;
+        }
         if self.x_request_id.is_empty() {
             self.x_request_id = other.x_request_id.clone();
         }
@@ -235,6 +273,13 @@ impl HttpInfo {
     */
     pub fn can_merge(&self, resp: &Self) -> bool {
         if self.proto == L7Protocol::Http1 || self.proto == L7Protocol::Http1TLS {
+            // pipeline 的请求和响应已按顺序配对，但响应不能早于请求
+            if self.seq.is_some() && self.seq == resp.seq {
+                return match (self.cap_seq, resp.cap_seq) {
+                    (Some(req_seq), Some(resp_seq)) => resp_seq > req_seq,
+                    _ => true,
+                };
+            }
             if let (Some(req_seq), Some(resp_seq)) = (self.cap_seq, resp.cap_seq) {
                 return resp_seq > req_seq && resp_seq - req_seq == 1;
             }
@@ -269,6 +314,7 @@ impl HttpInfo {
             }
 
             L7Protocol::Grpc => L7Protocol::Grpc,
+            L7Protocol::Dubbo => L7Protocol::Dubbo,
             _ => unreachable!(),
         }
     }
@@ -276,6 +322,44 @@ impl HttpInfo {
     fn is_grpc(&self) -> bool {
         self.proto == L7Protocol::Grpc
     }
+
+    // Dubbo 3 的 Triple 协议基于 HTTP/2，按 Dubbo 协议上报
+    fn is_triple(&self) -> bool {
+        self.proto == L7Protocol::Dubbo
+    }
+
+    fn on_grpc_trailers(
+        &mut self,
+        trailers: &[(Vec<u8>, Vec<u8>)],
+        request: &GrpcMessages,
+        response: &GrpcMessages,
+    ) {
+        for (key, val) in trailers {
+            match key.as_slice() {
+                b"grpc-status" => {
+                    self.grpc_status = str::from_utf8(val).ok().and_then(|v| v.parse().ok())
+                }
+                b"grpc-message" => self.grpc_message = decode_grpc_message(val),
+                _ => {}
+            }
+        }
+        if let Some(code) = self.grpc_status {
+            self.status = grpc_response_status(code);
+        }
+        self.req_msg_count = request.count;
+        self.req_msg_bytes = request.bytes;
+        self.resp_msg_count = response.count;
+        self.resp_msg_bytes = response.bytes;
+    }
+    // triple path: /interfaceName/methodName
+    // return interfaceName, methodName
+    fn triple_service_method(&self) -> Option<(&str, &str)> {
+        if !self.is_triple() {
+            return None;
+        }
+        self.path.strip_prefix('/')?.split_once('/')
+    }
+
     // grpc path: /packageName.Servicename/rcpMethodName
     // return packetName, ServiceName
     fn grpc_package_service_name(&self) -> Option<(String, String)> {
@@ -303,15 +387,25 @@ impl HttpInfo {
 impl From<HttpInfo> for L7ProtocolSendLog {
     fn from(f: HttpInfo) -> Self {
         let is_grpc = f.is_grpc();
+        let is_triple = f.is_triple();
         let service_name = if let Some((package, service)) = f.grpc_package_service_name() {
             let svc_name = format!("{}.{}", package, service);
             Some(svc_name)
+        } else if let Some((service, _)) = f.triple_service_method() {
+            Some(service.to_owned())
         } else {
             None
         };
 
-        // grpc protocol special treatment
-        let (req_type, resource, domain, endpoint) = if is_grpc {
+        // grpc protocol special treatment, triple keeps the same fields as dubbo
+        let (req_type, resource, domain, endpoint) = if is_triple {
+            let (service, method) = f
+                .triple_service_method()
+                .map(|(s, m)| (s.to_owned(), m.to_owned()))
+                .unwrap_or_default();
+            let endpoint = format!("{}/{}", service, method);
+            (method, service, f.host, endpoint)
+        } else if is_grpc {
             // server endpoint = req_type
             (
                 String::from("POST"), // grpc method always post, reference https://chromium.googlesource.com/external/github.com/grpc/grpc/+/HEAD/doc/PROTOCOL-HTTP2.md
@@ -323,6 +417,21 @@ impl From<HttpInfo> for L7ProtocolSendLog {
             (f.method, f.path, f.host, String::new())
         };
 
+        let mut attributes = vec![];
+        if (is_grpc || is_triple) && (f.req_msg_count > 0 || f.resp_msg_count > 0) {
+            for (key, val) in [
+                ("grpc_request_messages", f.req_msg_count as u64),
+                ("grpc_request_message_bytes", f.req_msg_bytes),
+                ("grpc_response_messages", f.resp_msg_count as u64),
+                ("grpc_response_message_bytes", f.resp_msg_bytes),
+            ] {
+                attributes.push(KeyVal {
+                    key: key.to_string(),
+                    val: val.to_string(),
+                });
+            }
+        }
+
         L7ProtocolSendLog {
             req_len: f.req_content_length,
             resp_len: f.resp_content_length,
@@ -335,13 +444,15 @@ impl From<HttpInfo> for L7ProtocolSendLog {
             },
             resp: L7Response {
                 status: f.status,
-                code: f.status_code,
+                // gRPC 的响应码使用 grpc-status
+                code: f.grpc_status.or(f.status_code),
+                exception: f.grpc_message,
                 ..Default::default()
             },
             trace_info: Some(TraceInfo {
                 trace_id: Some(f.trace_id),
                 span_id: Some(f.span_id),
-                ..Default::default()
+                parent_span_id: Some(f.parent_span_id),
             }),
             ext_info: Some(ExtendedInfo {
                 request_id: f.stream_id,
@@ -350,6 +461,11 @@ impl From<HttpInfo> for L7ProtocolSendLog {
                 user_agent: f.user_agent,
                 referer: f.referer,
                 rpc_service: service_name,
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -357,13 +473,356 @@ impl From<HttpInfo> for L7ProtocolSendLog {
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
+// http1 消息体在后续报文中的剩余部分
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum BodyState {
+    #[default]
+    None,
+    // Content-Length 剩余的字节数
+    Length(usize),
+    // chunked 编码，等待 chunk-size 行
+    ChunkSize,
+    // chunked 编码，当前 chunk 剩余的字节数，包括结尾的 \r\n
+    ChunkData(usize),
+    // chunked 编码的 last-chunk 之后，跳过 trailer 直到空行
+    ChunkTrailer,
+}
+
+impl BodyState {
+    // 跳过 payload 开头属于消息体的部分，返回跳过的字节数
+    fn consume(&mut self, payload: &[u8]) -> usize {
+        let mut offset = 0;
+        loop {
+            let rest = &payload[offset..];
+            match *self {
+                BodyState::None => return offset,
+                BodyState::Length(remain) | BodyState::ChunkData(remain) => {
+                    let is_chunk = matches!(*self, BodyState::ChunkData(_));
+                    if rest.len() < remain {
+                        let remain = remain - rest.len();
+                        *self = if is_chunk {
+                            BodyState::ChunkData(remain)
+                        } else {
+                            BodyState::Length(remain)
+                        };
+                        return payload.len();
+                    }
+                    offset += remain;
+                    *self = if is_chunk {
+                        BodyState::ChunkSize
+                    } else {
+                        BodyState::None
+                    };
+                }
+                BodyState::ChunkSize | BodyState::ChunkTrailer if rest.is_empty() => return offset,
+                BodyState::ChunkSize => {
+                    // chunk-size [; chunk-ext] CRLF
+                    let Some(line_len) = rest.windows(2).position(|w| w == b"\r\n") else {
+                        // chunk-size 行跨越报文时无法继续跟踪，放弃本消息体
+                        *self = BodyState::None;
+                        return payload.len();
+                    };
+                    let size = str::from_utf8(&rest[..line_len])
+                        .ok()
+                        .and_then(|l| l.split(';').next())
+                        .and_then(|l| usize::from_str_radix(l.trim(), 16).ok());
+                    let Some(size) = size else {
+                        *self = BodyState::None;
+                        return offset;
+                    };
+                    offset += line_len + 2;
+                    *self = if size == 0 {
+                        BodyState::ChunkTrailer
+                    } else {
+                        BodyState::ChunkData(size + 2)
+                    };
+                }
+                BodyState::ChunkTrailer => {
+                    let Some(line_len) = rest.windows(2).position(|w| w == b"\r\n") else {
+                        *self = BodyState::None;
+                        return payload.len();
+                    };
+                    offset += line_len + 2;
+                    if line_len == 0 {
+                        *self = BodyState::None;
+                        return offset;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PendingRequest {
+    seq: u32,
+    // HEAD 请求的响应没有消息体
+    is_head: bool,
+    // 请求头之后的 TCP 序列号，确认号小于它的响应不属于这个请求
+    end_tcp_seq: Option<u32>,
+}
+
+// http1 连接上需要跨报文保留的状态：请求序号、等待响应的请求和未结束的消息体
+// ================================================================================
+// State of a http1 connection kept across packets: request sequence, requests waiting
+// for responses and the bodies not finished yet
+#[derive(Clone, Debug, Default)]
+struct Http1Pipeline {
+    seq: u32,
+    pending: VecDeque<PendingRequest>,
+    req_body: BodyState,
+    resp_body: BodyState,
+    // 最近一个 Upgrade: websocket 请求的 (序号, 路径)
+    websocket: Option<(u32, String)>,
+    // 两个方向下一个报文期望的 TCP 序列号 <client, server>
+    next_tcp_seq: (Option<u32>, Option<u32>),
+    // 当前报文的 (TCP 序列号, TCP 确认号)，eBPF 数据没有
+    tcp: Option<(u32, u32)>,
+}
+
+impl Http1Pipeline {
+    fn body_mut(&mut self, direction: PacketDirection) -> &mut BodyState {
+        match direction {
+            PacketDirection::ClientToServer => &mut self.req_body,
+            PacketDirection::ServerToClient => &mut self.resp_body,
+        }
+    }
+
+    // 丢包后等待响应的请求和未结束的消息体不再可信：
+    // 1. 响应方向丢包时丢失的响应无法与请求配对，清空等待响应的请求
+    // 2. 丢包方向的消息体无法继续跟踪，放弃消息体
+    // ================================================================================
+    // Requests waiting for responses and unfinished bodies can't be trusted after
+    // packet loss:
+    // 1. Lost responses can't be paired with their requests, so the pending requests
+    //    are dropped if responses are lost
+    // 2. The body in the lost direction can't be tracked any more, so it is dropped
+    fn on_tcp_packet(&mut self, direction: PacketDirection, seq: u32, ack: u32, len: u32) {
+        let next = match direction {
+            PacketDirection::ClientToServer => &mut self.next_tcp_seq.0,
+            PacketDirection::ServerToClient => &mut self.next_tcp_seq.1,
+        };
+        let lost = next.map_or(false, |n| seq.wrapping_sub(n) as i32 > 0);
+        let end = seq.wrapping_add(len);
+        // 重传的报文不回退期望的序列号
+        if next.map_or(true, |n| end.wrapping_sub(n) as i32 > 0) {
+            *next = Some(end);
+        }
+        if lost {
+            *self.body_mut(direction) = BodyState::None;
+            if direction == PacketDirection::ServerToClient {
+                self.pending.clear();
+            }
+        }
+        self.tcp = Some((seq, ack));
+    }
+
+    // offset 为请求头结尾在当前报文中的位置
+    fn push_request(&mut self, is_head: bool, offset: usize) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        if self.pending.len() >= HTTP_V1_MAX_PENDING_REQUESTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(PendingRequest {
+            seq: self.seq,
+            is_head,
+            end_tcp_seq: self.tcp.map(|(seq, _)| seq.wrapping_add(offset as u32)),
+        });
+        self.seq
+    }
+
+    // 服务端还没有确认最早的请求时，响应属于丢失或者开始采集之前的请求，不与之配对
+    // ================================================================================
+    // If the server hasn't acknowledged the earliest request yet, the response belongs
+    // to a request lost or sent before the capture started and is not paired with it
+    fn pop_request(&mut self) -> Option<PendingRequest> {
+        let request = self.pending.front()?;
+        if let (Some(end), Some((_, ack))) = (request.end_tcp_seq, self.tcp) {
+            if (ack.wrapping_sub(end) as i32) < 0 {
+                return None;
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+// http1 消息头的解析结果
+struct V1Message {
+    // 消息头（包括结尾的空行）的长度，消息头不完整时为 None
+    header_len: Option<usize>,
+    chunked: bool,
+    // 带有 Upgrade: websocket 头部
+    websocket: bool,
+}
+
+// http2 帧在后续报文中的剩余部分
+#[derive(Clone, Copy, Debug)]
+struct FrameRemain {
+    stream_id: u32,
+    // DATA 帧剩余的数据字节数，其他帧为 0
+    data: usize,
+    // 剩余的填充或需要跳过的字节数
+    padding: usize,
+}
+
+impl FrameRemain {
+    // 依次消费帧的数据部分和填充部分，返回 (数据, 消费的字节数)
+    fn consume<'a>(&mut self, payload: &'a [u8]) -> (&'a [u8], usize) {
+        let data = self.data.min(payload.len());
+        self.data -= data;
+        let padding = self.padding.min(payload.len() - data);
+        self.padding -= padding;
+        (&payload[..data], data + padding)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.data == 0 && self.padding == 0
+    }
+}
+
+// http2 连接一个方向上的解码状态
+#[derive(Debug, Default)]
+struct Http2HeaderDecoder {
+    hpack: h2pack::parser::Parser<'static>,
+    // 等待 CONTINUATION 帧的头部块：(stream_id, END_STREAM, 已收到的头部块片段)
+    header_block: Option<(u32, bool, Vec<u8>)>,
+    // 跨越报文的帧
+    frame_remain: Option<FrameRemain>,
+    // 客户端最近打开的流
+    last_stream_id: u32,
+}
+
+impl Http2HeaderDecoder {
+    // 头部块丢失后动态表不再可信
+    fn reset(&mut self) {
+        self.hpack.reset();
+        self.header_block = None;
+    }
+
+    // 客户端按顺序使用奇数的流 ID 打开新的流，流 ID 跳过时中间的流的 HEADERS 帧已丢失
+    // ================================================================================
+    // The client opens new streams with odd stream ids in order, the HEADERS frames of
+    // the streams in between are lost if stream ids are skipped
+    fn on_client_stream(&mut self, stream_id: u32) {
+        if stream_id & 1 == 0 || stream_id <= self.last_stream_id {
+            return;
+        }
+        if self.last_stream_id != 0 && stream_id > self.last_stream_id + 2 {
+            self.reset();
+        }
+        self.last_stream_id = stream_id;
+    }
+}
+
+// gRPC 长度前缀消息的计数，消息可能跨越多个 DATA 帧和报文
+// +------------------+------------------+---------------------+
+// | Compressed-Flag  | Message-Length   | Message             |
+// | (8)              | (32)             | (Message-Length)... |
+// +------------------+------------------+---------------------+
+#[derive(Debug, Default)]
+struct GrpcMessages {
+    count: u32,
+    bytes: u64,
+    // 当前消息剩余的字节数
+    remain: usize,
+    // 不完整的消息前缀
+    prefix: Vec<u8>,
+}
+
+impl GrpcMessages {
+    fn consume(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remain > 0 {
+                let n = self.remain.min(data.len());
+                self.remain -= n;
+                data = &data[n..];
+                continue;
+            }
+            let n = (GRPC_MESSAGE_PREFIX_LEN - self.prefix.len()).min(data.len());
+            self.prefix.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.prefix.len() < GRPC_MESSAGE_PREFIX_LEN {
+                return;
+            }
+            let length = read_u32_be(&self.prefix[1..]) as usize;
+            self.prefix.clear();
+            self.count += 1;
+            self.bytes += length as u64;
+            self.remain = length;
+        }
+    }
+}
+
+// gRPC 响应的状态在 stream 结束时的 trailers 中，响应头先暂存到 stream 结束
+// ================================================================================
+// The status of a gRPC response is in the trailers at the end of the stream, so the
+// response headers are kept until then
+#[derive(Debug, Default)]
+struct GrpcStream {
+    request: GrpcMessages,
+    response: GrpcMessages,
+    response_info: Option<HttpInfo>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct HttpLog {
     info: HttpInfo,
 
     // check 是否已经解析过，已经解析过parse会跳过
     parsed: bool,
     proto: L7Protocol,
+
+    #[serde(skip)]
+    pipeline: Http1Pipeline,
+    // http2 每个方向的 HPACK 动态表在整个连接中有效
+    #[serde(skip)]
+    client_decoder: Http2HeaderDecoder,
+    #[serde(skip)]
+    server_decoder: Http2HeaderDecoder,
+    // 只有 gRPC 连接才会创建
+    #[serde(skip)]
+    grpc_streams: Option<LruCache<u32, GrpcStream>>,
+    // 当前报文中带 END_STREAM 的头部块：(stream_id, 头部列表)
+    #[serde(skip)]
+    trailers: Vec<(u32, Vec<(Vec<u8>, Vec<u8>)>)>,
+    // 当前报文中的 RST_STREAM 帧：(stream_id, 错误码)
+    #[serde(skip)]
+    reset_streams: Vec<(u32, u32)>,
+    // 未收到 trailers 就被淘汰的 stream 暂存的响应
+    #[serde(skip)]
+    evicted: Vec<HttpInfo>,
+    // 连接上出现过 tri- 开头的头部时认为是 Dubbo 3 的 Triple 协议
+    #[serde(skip)]
+    triple: bool,
+    // 当前消息中链路追踪头部的解析状态
+    #[serde(skip)]
+    trace: TraceContext,
+    // 101 Switching Protocols 之后由 WebSocket 接管连接：(升级请求的路径, 升级时间)
+    // ================================================================================
+    // the connection is taken over by WebSocket after 101 Switching Protocols:
+    // (path of the upgrade request, upgrade time)
+    #[serde(skip)]
+    websocket_upgrade: Option<(String, u64)>,
+}
+
+impl Default for HttpLog {
+    fn default() -> Self {
+        Self {
+            info: HttpInfo::default(),
+            parsed: false,
+            proto: L7Protocol::default(),
+            pipeline: Http1Pipeline::default(),
+            client_decoder: Http2HeaderDecoder::default(),
+            server_decoder: Http2HeaderDecoder::default(),
+            grpc_streams: None,
+            trailers: vec![],
+            reset_streams: vec![],
+            evicted: vec![],
+            triple: false,
+            trace: TraceContext::default(),
+            websocket_upgrade: None,
+        }
+    }
 }
 
 impl L7ProtocolParserInterface for HttpLog {
@@ -404,6 +863,13 @@ impl L7ProtocolParserInterface for HttpLog {
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
         if self.parsed {
+            if param.ebpf_type != EbpfType::GoHttp2Uprobe && self.proto != L7Protocol::Http1 {
+                // check 只解析了头部块，DATA 帧中的 gRPC 消息、trailers 和暂存的响应仍需处理
+                self.count_grpc_frames(payload, param.direction);
+                let template = self.packet_info();
+                let infos = self.output_http_v2(Ok(()), template, param.direction)?;
+                return Ok(infos.into_iter().map(L7ProtocolInfo::HttpInfo).collect());
+            }
             return Ok(vec![L7ProtocolInfo::HttpInfo(self.info.clone())]);
         }
         let Some(config) = param.parse_config else {
@@ -428,16 +894,25 @@ impl L7ProtocolParserInterface for HttpLog {
                 };
             }
             _ => {
-                match self.proto {
+                let infos = match self.proto {
                     L7Protocol::Http1 => {
-                        self.parse_http_v1(&config.l7_log_dynamic, payload, param.direction)?
-                    }
-                    L7Protocol::Http2 | L7Protocol::Grpc => {
-                        self.parse_http_v2(&config.l7_log_dynamic, payload, param.direction)?
+                        let infos =
+                            self.parse_http_v1_messages(&config.l7_log_dynamic, payload, param)?;
+                        // 101 Switching Protocols 所在报文的时间即 WebSocket 会话的开始时间
+                        if let Some((_, time)) = self.websocket_upgrade.as_mut() {
+                            *time = param.time;
+                        }
+                        infos
                     }
+                    L7Protocol::Http2 | L7Protocol::Grpc => self.parse_http_v2_payload(
+                        &config.l7_log_dynamic,
+                        payload,
+                        param.direction,
+                        param.log_packet_size,
+                    )?,
                     _ => unreachable!(),
-                }
-                Ok(vec![L7ProtocolInfo::HttpInfo(self.info.clone())])
+                };
+                Ok(infos.into_iter().map(L7ProtocolInfo::HttpInfo).collect())
             }
         }
     }
@@ -469,15 +944,44 @@ impl L7ProtocolParserInterface for HttpLog {
         false
     }
 
+    // 截断的报文会使跨报文的消息体长度计算出错，也会丢失更新 HPACK 动态表的头部块
+    fn need_complete_payload(&self) -> bool {
+        true
+    }
+
+    fn upgraded_parser(&mut self) -> Option<L7ProtocolParser> {
+        let (path, time) = self.websocket_upgrade.take()?;
+        Some(L7ProtocolParser::WebSocketParser(WebSocketLog::upgraded(
+            path, time,
+        )))
+    }
+
+    // http1 pipeline、HPACK 动态表和 gRPC stream 的状态在整个连接中有效
     fn reset(&mut self) {
-        self.info = HttpInfo::default();
-        let new_log = match self.proto {
-            L7Protocol::Http1 => Self::new_v1(),
-            L7Protocol::Http2 => Self::new_v2(false),
-            L7Protocol::Grpc => Self::new_v2(true),
-            _ => unreachable!(),
+        self.parsed = false;
+        self.info = HttpInfo {
+            proto: if self.triple {
+                L7Protocol::Dubbo
+            } else {
+                self.proto
+            },
+            ..Default::default()
         };
-        *self = new_log
+        self.trailers.clear();
+        self.reset_streams.clear();
+    }
+
+    // 连接结束时没有收到 trailers 的 gRPC 响应直接输出
+    // ==============================================
+    // gRPC responses without trailers are output when the connection ends
+    fn flow_end(&mut self, _: u64) -> Vec<L7ProtocolInfo> {
+        let mut infos = mem::take(&mut self.evicted);
+        if let Some(mut streams) = self.grpc_streams.take() {
+            while let Some((_, stream)) = streams.pop_lru() {
+                infos.extend(stream.response_info);
+            }
+        }
+        infos.into_iter().map(L7ProtocolInfo::HttpInfo).collect()
     }
 }
 
@@ -536,7 +1040,7 @@ impl HttpLog {
             return false;
         }
         self.parsed = self
-            .parse_http_v2(config, payload, PacketDirection::ClientToServer)
+            .parse_http_v2(config, payload, PacketDirection::ClientToServer, usize::MAX)
             .is_ok();
         self.parsed
     }
@@ -596,6 +1100,7 @@ impl HttpLog {
         }
 
         self.info.raw_data_type = L7ProtoRawDataType::GoHttp2Uprobe; // 用于区分是否需要多段merge
+        self.trace = TraceContext::default();
 
         // adjuest msg type
         match direction {
@@ -623,15 +1128,141 @@ impl HttpLog {
         return Ok(());
     }
 
+    // 性能统计同样使用 pipeline 解析报文中的每个请求和响应，并按顺序配对
+    // ================================================================================
+    // Perf stats also use the pipeline to parse every request and response in the packet
+    // and pair them in order
+    pub(crate) fn parse_http_v1_messages(
+        &mut self,
+        config: &L7LogDynamicConfig,
+        payload: &[u8],
+        param: &ParseParam,
+    ) -> Result<Vec<HttpInfo>> {
+        if param.ebpf_type == EbpfType::None && param.l4_protocol == IpProtocol::Tcp {
+            self.pipeline.on_tcp_packet(
+                param.direction,
+                param.tcp_seq,
+                param.tcp_ack,
+                param.payload_len as u32,
+            );
+        }
+        self.parse_http_v1_pipeline(config, payload, param.direction, param.log_packet_size)
+    }
+
+    // 一个报文中可能有多个 pipeline 的请求或响应，消息体也可能跨越多个报文。
+    // 从 log_packet_size 之后开始的消息只用于配对请求与响应、跳过消息体，不输出日志
+    // ================================================================================
+    // A packet may carry several pipelined requests or responses, and a body may span
+    // several packets. Messages starting beyond log_packet_size are only used to pair
+    // requests with responses and skip bodies, no log is output for them
+    fn parse_http_v1_pipeline(
+        &mut self,
+        config: &L7LogDynamicConfig,
+        payload: &[u8],
+        direction: PacketDirection,
+        log_packet_size: usize,
+    ) -> Result<Vec<HttpInfo>> {
+        let template = self.info.clone();
+        let mut offset = self.pipeline.body_mut(direction).consume(payload);
+        let mut infos = vec![];
+        while offset < payload.len() {
+            self.info = template.clone();
+            let logged = offset < log_packet_size;
+            let message = match self.parse_http_v1(config, &payload[offset..], direction, logged) {
+                Ok(m) => m,
+                Err(e) if offset == 0 => return Err(e),
+                Err(_) => break,
+            };
+
+            let mut has_body = true;
+            if direction == PacketDirection::ClientToServer {
+                let header_end = offset + message.header_len.unwrap_or(payload.len() - offset);
+                let seq = self
+                    .pipeline
+                    .push_request(self.info.method == "HEAD", header_end);
+                self.info.seq = Some(seq);
+                if message.websocket {
+                    self.pipeline.websocket = Some((seq, self.info.path.clone()));
+                }
+            } else {
+                let status_code = self.info.status_code.unwrap_or_default() as u16;
+                // 1xx 临时响应之后还有最终响应，101 Switching Protocols 除外
+                if status_code <= HTTP_STATUS_INFORMATIONAL_MAX
+                    && status_code != HTTP_STATUS_SWITCHING_PROTOCOLS
+                {
+                    let Some(header_len) = message.header_len else {
+                        break;
+                    };
+                    offset += header_len;
+                    continue;
+                }
+                if let Some(request) = self.pipeline.pop_request() {
+                    self.info.seq = Some(request.seq);
+                    has_body = !request.is_head;
+                }
+                has_body &= status_code > HTTP_STATUS_INFORMATIONAL_MAX
+                    && status_code != HTTP_STATUS_NO_CONTENT
+                    && status_code != HTTP_STATUS_NOT_MODIFIED;
+                if status_code == HTTP_STATUS_SWITCHING_PROTOCOLS && message.websocket {
+                    // 升级请求丢失时路径为空
+                    let path = self
+                        .pipeline
+                        .websocket
+                        .take()
+                        .filter(|(seq, _)| Some(*seq) == self.info.seq)
+                        .map(|(_, path)| path)
+                        .unwrap_or_default();
+                    self.websocket_upgrade = Some((path, 0));
+                }
+            }
+            if logged {
+                infos.push(self.info.clone());
+            }
+            // 之后的数据是 WebSocket 帧
+            if self.websocket_upgrade.is_some() {
+                break;
+            }
+
+            let Some(header_len) = message.header_len else {
+                break;
+            };
+            offset += header_len;
+            if !has_body {
+                continue;
+            }
+            let content_length = match direction {
+                PacketDirection::ClientToServer => self.info.req_content_length,
+                PacketDirection::ServerToClient => self.info.resp_content_length,
+            };
+            let body = self.pipeline.body_mut(direction);
+            *body = match (message.chunked, content_length) {
+                (true, _) => BodyState::ChunkSize,
+                (false, Some(length)) => BodyState::Length(length as usize),
+                // 没有长度的响应直到连接关闭都是消息体
+                (false, None) if direction == PacketDirection::ServerToClient => break,
+                (false, None) => BodyState::None,
+            };
+            offset += body.consume(&payload[offset..]);
+        }
+        self.info = template;
+        Ok(infos)
+    }
+
+    // decode_headers 为 false 时只解析确定消息边界所需的头部
+    // ================================================================================
+    // Only the headers needed to find the message boundary are parsed if decode_headers
+    // is false
     fn parse_http_v1(
         &mut self,
         config: &L7LogDynamicConfig,
         payload: &[u8],
         direction: PacketDirection,
-    ) -> Result<()> {
+        decode_headers: bool,
+    ) -> Result<V1Message> {
         if !is_http_v1_payload(payload) {
             return Err(Error::HttpHeaderParseFailed);
         }
+        self.trace = TraceContext::default();
 
         let mut headers = parse_v1_headers(payload);
         let Some(first_line) = headers.next() else {
@@ -662,6 +1293,8 @@ impl HttpLog {
         }
 
         let mut content_length: Option<u32> = None;
+        let mut chunked = false;
+        let mut websocket = false;
         for body_line in headers {
             let col_index = body_line.find(':');
             if col_index.is_none() {
@@ -676,14 +1309,20 @@ impl HttpLog {
             let value = &body_line[col_index + 1..];
 
             let lower_key = key.to_ascii_lowercase();
-            self.on_header(
-                config,
-                lower_key.as_bytes(),
-                value.trim().as_bytes(),
-                direction,
-            );
+            if decode_headers {
+                self.on_header(
+                    config,
+                    lower_key.as_bytes(),
+                    value.trim().as_bytes(),
+                    direction,
+                );
+            }
             if &lower_key == "content-length" {
                 content_length = Some(value.trim_start().parse::<u32>().unwrap_or_default());
+            } else if &lower_key == "transfer-encoding" {
+                chunked = value.trim().to_ascii_lowercase().ends_with("chunked");
+            } else if &lower_key == "upgrade" {
+                websocket = value.trim().eq_ignore_ascii_case("websocket");
             }
         }
 
@@ -693,7 +1332,14 @@ impl HttpLog {
         } else {
             self.info.req_content_length = content_length;
         }
-        Ok(())
+        Ok(V1Message {
+            header_len: payload
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|p| p + 4),
+            chunked,
+            websocket,
+        })
     }
 
     fn has_magic(payload: &[u8]) -> bool {
@@ -707,17 +1353,348 @@ impl HttpLog {
         }
     }
 
+    fn header_decoder_mut(&mut self, direction: PacketDirection) -> &mut Http2HeaderDecoder {
+        match direction {
+            PacketDirection::ClientToServer => &mut self.client_decoder,
+            PacketDirection::ServerToClient => &mut self.server_decoder,
+        }
+    }
+
+    // 解码 HEADERS 或 CONTINUATION 帧携带的头部块片段，头部块未结束时返回 None
+    // ================================================================================
+    // Decode the header block fragment in a HEADERS or CONTINUATION frame, returns None
+    // if the header block is not finished
+    fn decode_header_block(
+        &mut self,
+        header: &Httpv2Headers,
+        frame_payload: &[u8],
+        direction: PacketDirection,
+    ) -> Result<Option<Vec<(Vec<u8>, Vec<u8>)>>> {
+        let decoder = self.header_decoder_mut(direction);
+        let mut frame_length = header.frame_length;
+        let mut l_offset = 0;
+        if header.frame_type == HTTPV2_FRAME_HEADERS_TYPE {
+            if direction == PacketDirection::ClientToServer {
+                decoder.on_client_stream(header.stream_id);
+            }
+            if header.flags & FLAG_HEADERS_PADDED != 0 {
+                if frame_payload.is_empty() || frame_length <= frame_payload[0] as u32 {
+                    decoder.reset();
+                    return Err(Error::HttpHeaderParseFailed);
+                }
+                frame_length -= frame_payload[0] as u32;
+                l_offset += 1;
+            }
+            if header.flags & FLAG_HEADERS_PRIORITY != 0 {
+                l_offset += 5;
+            }
+        }
+        if l_offset >= frame_length || frame_length > frame_payload.len() as u32 {
+            decoder.reset();
+            return Err(Error::HttpHeaderParseFailed);
+        }
+        let fragment = &frame_payload[l_offset as usize..frame_length as usize];
+
+        let (end_stream, block) = match header.frame_type {
+            HTTPV2_FRAME_HEADERS_TYPE => {
+                // 前一个头部块缺少 CONTINUATION 帧
+                if decoder.header_block.take().is_some() {
+                    decoder.hpack.reset();
+                }
+                (header.flags & FLAG_END_STREAM != 0, fragment.to_vec())
+            }
+            _ => match decoder.header_block.take() {
+                Some((stream_id, end_stream, mut block)) if stream_id == header.stream_id => {
+                    block.extend_from_slice(fragment);
+                    (end_stream, block)
+                }
+                // 缺少对应的 HEADERS 帧
+                _ => {
+                    decoder.reset();
+                    return Err(Error::HttpHeaderParseFailed);
+                }
+            },
+        };
+        if header.flags & FLAG_HEADERS_END_HEADERS == 0 {
+            decoder.header_block = Some((header.stream_id, end_stream, block));
+            return Ok(None);
+        }
+
+        match decoder.hpack.parse(&block) {
+            Ok(header_list) => {
+                if end_stream {
+                    self.trailers.push((header.stream_id, header_list.clone()));
+                }
+                Ok(Some(header_list))
+            }
+            Err(_) => {
+                decoder.reset();
+                Err(Error::HttpHeaderParseFailed)
+            }
+        }
+    }
+
+    // 日志需要的信息已经得到，剩余帧中的头部块仍需解码以保持 HPACK 动态表与对端一致
+    // ================================================================================
+    // The information needed by the log is already got, but the header blocks in the remaining
+    // frames are still decoded to keep the HPACK dynamic table consistent with the peer
+    fn skip_header_blocks(&mut self, mut frame_payload: &[u8], direction: PacketDirection) {
+        let mut httpv2_header = Httpv2Headers::default();
+        while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
+            if httpv2_header.parse_headers_frame(frame_payload).is_err() {
+                break;
+            }
+            frame_payload = &frame_payload[HTTPV2_FRAME_HEADER_LENGTH..];
+            if httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE
+                || httpv2_header.frame_type == HTTPV2_FRAME_CONTINUATION_TYPE
+            {
+                if self
+                    .decode_header_block(&httpv2_header, frame_payload, direction)
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            if httpv2_header.frame_length >= frame_payload.len() as u32 {
+                break;
+            }
+            frame_payload = &frame_payload[httpv2_header.frame_length as usize..];
+        }
+    }
+
+    // 统计 DATA 帧中的 gRPC 消息，最后一个帧跨越报文时记录其剩余部分
+    // ================================================================================
+    // Count the gRPC messages in DATA frames, and record the rest of the last frame if
+    // it spans packets
+    fn count_grpc_frames(&mut self, mut payload: &[u8], direction: PacketDirection) {
+        let mut header = Httpv2Headers::default();
+        loop {
+            if Self::has_magic(payload) {
+                payload = &payload[HTTPV2_MAGIC_LENGTH..];
+                continue;
+            }
+            if payload.len() < HTTPV2_FRAME_HEADER_LENGTH
+                || header.parse_headers_frame(payload).is_err()
+            {
+                return;
+            }
+            payload = &payload[HTTPV2_FRAME_HEADER_LENGTH..];
+
+            let frame_length = header.frame_length as usize;
+            if header.frame_type == HTTPV2_FRAME_RST_STREAM_TYPE
+                && header.stream_id != 0
+                && self.proto == L7Protocol::Grpc
+                && frame_length == 4
+                && payload.len() >= 4
+            {
+                self.reset_streams
+                    .push((header.stream_id, read_u32_be(payload)));
+            }
+            let mut frame = FrameRemain {
+                stream_id: header.stream_id,
+                data: 0,
+                padding: frame_length,
+            };
+            if header.frame_type == HTTPV2_FRAME_DATA_TYPE && header.stream_id != 0 {
+                if header.flags & FLAG_HEADERS_PADDED == 0 {
+                    frame.data = frame_length;
+                    frame.padding = 0;
+                } else if let Some(&pad_length) = payload.first() {
+                    // Pad Length 不合法时跳过整个帧
+                    if (pad_length as usize) < frame_length {
+                        payload = &payload[1..];
+                        frame.data = frame_length - 1 - pad_length as usize;
+                        frame.padding = pad_length as usize;
+                    }
+                }
+            }
+            let (data, consumed) = frame.consume(payload);
+            self.count_grpc_messages(frame.stream_id, direction, data);
+            if !frame.is_finished() {
+                self.header_decoder_mut(direction).frame_remain = Some(frame);
+                return;
+            }
+            payload = &payload[consumed..];
+        }
+    }
+
+    // 跳过上一个报文中未结束的帧，返回跳过的字节数
+    fn consume_frame_remain(&mut self, payload: &[u8], direction: PacketDirection) -> usize {
+        let decoder = self.header_decoder_mut(direction);
+        let Some(mut frame) = decoder.frame_remain.take() else {
+            return 0;
+        };
+        let (data, consumed) = frame.consume(payload);
+        if !frame.is_finished() {
+            decoder.frame_remain = Some(frame);
+        }
+        self.count_grpc_messages(frame.stream_id, direction, data);
+        consumed
+    }
+
+    fn count_grpc_messages(&mut self, stream_id: u32, direction: PacketDirection, data: &[u8]) {
+        if self.proto != L7Protocol::Grpc || data.is_empty() {
+            return;
+        }
+        let stream = self.grpc_stream_mut(stream_id);
+        match direction {
+            PacketDirection::ClientToServer => stream.request.consume(data),
+            PacketDirection::ServerToClient => stream.response.consume(data),
+        }
+    }
+
+    fn grpc_stream_mut(&mut self, stream_id: u32) -> &mut GrpcStream {
+        let streams = self
+            .grpc_streams
+            .get_or_insert_with(|| LruCache::new(GRPC_MAX_STREAMS));
+        if !streams.contains(&stream_id) {
+            if streams.len() >= streams.cap() {
+                // 没有收到 trailers 的 stream 被淘汰时直接输出暂存的响应
+                if let Some((_, stream)) = streams.pop_lru() {
+                    self.evicted.extend(stream.response_info);
+                }
+            }
+            streams.put(stream_id, GrpcStream::default());
+        }
+        streams.get_mut(&stream_id).unwrap()
+    }
+
+    fn pop_grpc_stream(&mut self, stream_id: u32) -> Option<GrpcStream> {
+        self.grpc_streams.as_mut()?.pop(&stream_id)
+    }
+
+    // 报文级别的信息，响应头所在的报文丢失时用于构造响应
+    // ================================================
+    // packet level information, used to build the response if the packet
+    // carrying the response headers is lost
+    fn packet_info(&self) -> HttpInfo {
+        HttpInfo {
+            cap_seq: self.info.cap_seq,
+            proto: self.info.proto,
+            start_time: self.info.start_time,
+            end_time: self.info.end_time,
+            is_tls: self.info.is_tls,
+            raw_data_type: self.info.raw_data_type,
+            ..Default::default()
+        }
+    }
+
+    // 一个报文中可能有多个 stream 的 trailers，gRPC 响应在 trailers 到达时才输出
+    // ================================================================================
+    // A packet may carry the trailers of several streams, and gRPC responses are output
+    // when their trailers arrive
+    fn parse_http_v2_payload(
+        &mut self,
+        config: &L7LogDynamicConfig,
+        payload: &[u8],
+        direction: PacketDirection,
+        log_packet_size: usize,
+    ) -> Result<Vec<HttpInfo>> {
+        self.trailers.clear();
+        self.reset_streams.clear();
+        let template = self.packet_info();
+        let offset = self.consume_frame_remain(payload, direction);
+        let payload = &payload[offset..];
+        let result = self.parse_http_v2(
+            config,
+            payload,
+            direction,
+            log_packet_size.saturating_sub(offset),
+        );
+        self.count_grpc_frames(payload, direction);
+        self.output_http_v2(result, template, direction)
+    }
+
+    // gRPC 响应暂存到 trailers 或 RST_STREAM 到达时输出，其它消息直接输出
+    // =================================================================
+    // gRPC responses are kept until their trailers or RST_STREAM arrive,
+    // other messages are output directly
+    fn output_http_v2(
+        &mut self,
+        result: Result<()>,
+        template: HttpInfo,
+        direction: PacketDirection,
+    ) -> Result<Vec<HttpInfo>> {
+        let mut infos = mem::take(&mut self.evicted);
+        if direction == PacketDirection::ClientToServer || self.proto != L7Protocol::Grpc {
+            if result.is_ok() {
+                infos.push(self.info.clone());
+            }
+        } else {
+            if result.is_ok() {
+                let stream_id = self.info.stream_id.unwrap_or_default();
+                let info = self.info.clone();
+                self.grpc_stream_mut(stream_id).response_info = Some(info);
+            }
+            for (stream_id, trailers) in mem::take(&mut self.trailers) {
+                if !trailers.iter().any(|(key, _)| key == b"grpc-status") {
+                    continue;
+                }
+                let GrpcStream {
+                    request,
+                    response,
+                    response_info,
+                } = self.pop_grpc_stream(stream_id).unwrap_or_default();
+                let mut info = response_info.unwrap_or_else(|| {
+                    // 响应头所在的报文已丢失
+                    let mut info = template.clone();
+                    info.msg_type = LogMessageType::Response;
+                    info.version = String::from("2");
+                    info.stream_id = Some(stream_id);
+                    info
+                });
+                info.end_time = info.end_time.max(template.end_time);
+                info.on_grpc_trailers(&trailers, &request, &response);
+                infos.push(info);
+            }
+        }
+
+        // 被 RST_STREAM 结束的 stream 没有 trailers，按 gRPC 规定的错误码映射设置状态
+        // ===========================================================================
+        // streams ended by RST_STREAM have no trailers, the status is set by the error
+        // code mapping defined by gRPC
+        for (stream_id, error_code) in mem::take(&mut self.reset_streams) {
+            let Some(stream) = self.pop_grpc_stream(stream_id) else {
+                continue;
+            };
+            let Some(mut info) = stream.response_info else {
+                continue;
+            };
+            let trailers = [(
+                b"grpc-status".to_vec(),
+                grpc_status_of_rst_stream(error_code)
+                    .to_string()
+                    .into_bytes(),
+            )];
+            info.end_time = info.end_time.max(template.end_time);
+            info.on_grpc_trailers(&trailers, &stream.request, &stream.response);
+            infos.push(info);
+        }
+        match result {
+            Err(e) if infos.is_empty() => Err(e),
+            _ => Ok(infos),
+        }
+    }
+
+    // 只有从 log_packet_size 之内开始的头部块输出日志，之后的头部块只用于更新 HPACK 动态表
+    // ================================================================================
+    // Only a header block starting within log_packet_size is logged, the header blocks
+    // after it are only used to update the HPACK dynamic table
     fn parse_http_v2(
         &mut self,
         config: &L7LogDynamicConfig,
         payload: &[u8],
         direction: PacketDirection,
+        log_packet_size: usize,
     ) -> Result<()> {
+        self.trace = TraceContext::default();
         let mut content_length: Option<u32> = None;
         let mut header_frame_parsed = false;
         let mut is_httpv2 = false;
         let mut frame_payload = payload;
         let mut httpv2_header = Httpv2Headers::default();
+        // 提前结束解析时剩余的帧
+        let mut remaining_frames: &[u8] = &[];
 
         while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
             if Self::has_magic(frame_payload) {
@@ -733,59 +1710,49 @@ impl HttpLog {
                 break;
             }
 
+            let logged = payload.len() - frame_payload.len() < log_packet_size;
             frame_payload = &frame_payload[HTTPV2_FRAME_HEADER_LENGTH..];
+            let is_header_block = httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE
+                || httpv2_header.frame_type == HTTPV2_FRAME_CONTINUATION_TYPE;
 
-            if !header_frame_parsed && httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE {
+            if !header_frame_parsed && is_header_block && logged {
                 if httpv2_header.stream_id == 0 {
                     // Headers帧的StreamId不为0
                     // 参考协议：https://tools.ietf.org/html/rfc7540#section-6.2
                     break;
                 }
 
-                let mut l_offset = 0;
-                if httpv2_header.flags & FLAG_HEADERS_PADDED != 0 {
-                    if httpv2_header.frame_length <= frame_payload[0] as u32 {
-                        break;
+                // 头部块由 HEADERS 帧和之后的 CONTINUATION 帧组成，可能跨越多个报文
+                // 参考协议：https://tools.ietf.org/html/rfc7540#section-6.10
+                let header_list =
+                    match self.decode_header_block(&httpv2_header, frame_payload, direction) {
+                        Ok(header_list) => header_list,
+                        Err(e) if httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE => {
+                            return Err(e)
+                        }
+                        // 报文以前一个头部块的 CONTINUATION 帧开始
+                        Err(_) => None,
+                    };
+
+                if let Some(header_list) = header_list {
+                    for (key, val) in header_list.iter() {
+                        self.on_header(config, key, val, direction);
+                        if key == b"content-length" {
+                            content_length = Some(
+                                str::from_utf8(val.as_slice())
+                                    .unwrap_or_default()
+                                    .parse::<u32>()
+                                    .unwrap_or_default(),
+                            )
+                        }
                     }
-                    httpv2_header.frame_length -= frame_payload[0] as u32;
-                    l_offset += 1;
-                }
-                if httpv2_header.flags & FLAG_HEADERS_PRIORITY != 0 {
-                    l_offset += 5;
-                }
-                if l_offset >= httpv2_header.frame_length
-                    || httpv2_header.frame_length > frame_payload.len() as u32
-                {
-                    break;
-                }
-
-                let header_frame_payload =
-                    &frame_payload[l_offset as usize..httpv2_header.frame_length as usize];
-
-                let mut parser = h2pack::parser::Parser::new();
-                let parse_rst = parser.parse(header_frame_payload);
-
-                if let Err(_) = parse_rst {
-                    return Err(Error::HttpHeaderParseFailed);
-                }
-                let header_list = parse_rst.unwrap();
-
-                for (key, val) in header_list.iter() {
-                    self.on_header(config, key, val, direction);
-                    if key == b"content-length" {
-                        content_length = Some(
-                            str::from_utf8(val.as_slice())
-                                .unwrap_or_default()
-                                .parse::<u32>()
-                                .unwrap_or_default(),
-                        )
+                    header_frame_parsed = true;
+                    if content_length.is_some() {
+                        is_httpv2 = true;
+                        remaining_frames = frame_payload;
+                        break;
                     }
                 }
-                header_frame_parsed = true;
-                if content_length.is_some() {
-                    is_httpv2 = true;
-                    break;
-                }
             } else if header_frame_parsed && httpv2_header.frame_type == HTTPV2_FRAME_DATA_TYPE {
                 if httpv2_header.stream_id == 0 {
                     // Data帧的StreamId不为0
@@ -803,7 +1770,10 @@ impl HttpLog {
                             Some(content_length.unwrap_or_default() - frame_payload[0] as u32);
                     }
                 }
+                remaining_frames = frame_payload;
                 break;
+            } else if is_header_block {
+                let _ = self.decode_header_block(&httpv2_header, frame_payload, direction);
             }
 
             if httpv2_header.stream_id > 0 {
@@ -814,6 +1784,12 @@ impl HttpLog {
             }
             frame_payload = &frame_payload[httpv2_header.frame_length as usize..];
         }
+        if (httpv2_header.frame_length as usize) < remaining_frames.len() {
+            self.skip_header_blocks(
+                &remaining_frames[httpv2_header.frame_length as usize..],
+                direction,
+            );
+        }
         // 流量中可能仅存在Headers帧且Headers帧中没有传输实体，“Content-Length”为0
         if header_frame_parsed && !is_httpv2 {
             if !content_length.is_some() {
@@ -886,8 +1862,14 @@ impl HttpLog {
             }
             "user-agent" => self.info.user_agent = Some(String::from_utf8_lossy(val).into_owned()),
             "referer" => self.info.referer = Some(String::from_utf8_lossy(val).into_owned()),
+            // Triple 协议的请求头，例如 tri-service-version、tri-consumer-appname
+            // 参考：https://cn.dubbo.apache.org/zh-cn/overview/reference/protocols/triple-spec/
+            _ if self.proto != L7Protocol::Http1 && key.starts_with("tri-") => self.triple = true,
             _ => {}
         }
+        if self.triple {
+            self.info.proto = L7Protocol::Dubbo;
+        }
 
         if !key.is_ascii() {
             return;
@@ -898,15 +1880,10 @@ impl HttpLog {
             return;
         };
 
-        if config.is_trace_id(key) {
-            if let Some(id) = Self::decode_id(val, key, Self::TRACE_ID) {
-                self.info.trace_id = id;
-            }
-        }
-        if config.is_span_id(key) {
-            if let Some(id) = Self::decode_id(val, key, Self::SPAN_ID) {
-                self.info.span_id = id;
-            }
+        if self.trace.on_header(config, key, val) {
+            self.info.trace_id = self.trace.trace_id.clone();
+            self.info.span_id = self.trace.span_id.clone();
+            self.info.parent_span_id = self.trace.parent_span_id.clone();
         }
         if key == &config.x_request_id {
             self.info.x_request_id = val.to_owned();
@@ -916,76 +1893,11 @@ impl HttpLog {
         }
     }
 
-    // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS
-    // 使用':'分隔，第一个字段为TRACEID，第三个字段为SPANID
-    fn decode_uber_id(value: &str, id_type: u8) -> Option<String> {
-        let segs = value.split(":");
-        let mut i = 0;
-        for seg in segs {
-            if id_type == Self::TRACE_ID && i == 0 {
-                return Some(seg.to_string());
-            }
-            if id_type == Self::SPAN_ID && i == 2 {
-                return Some(seg.to_string());
-            }
-
-            i += 1;
-        }
-        None
-    }
-
-    // sw6: 1-TRACEID-SEGMENTID-3-5-2-IPPORT-ENTRYURI-PARENTURI
-    // sw8: 1-TRACEID-SEGMENTID-3-PARENT_SERVICE-PARENT_INSTANCE-PARENT_ENDPOINT-IPPORT
-    // sw6和sw8的value全部使用'-'分隔，TRACEID前为SAMPLE字段取值范围仅有0或1
-    // 提取`TRACEID`展示为HTTP日志中的`TraceID`字段
-    // 提取`SEGMENTID-SPANID`展示为HTTP日志中的`SpanID`字段
-    fn decode_skywalking_id(value: &str, id_type: u8) -> Option<String> {
-        let segs: Vec<&str> = value.split("-").collect();
-
-        if id_type == Self::TRACE_ID && segs.len() > 2 {
-            return Some(decode_base64_to_string(segs[1]));
-        }
-        if id_type == Self::SPAN_ID && segs.len() > 4 {
-            return Some(format!("{}-{}", decode_base64_to_string(segs[2]), segs[3]));
-        }
-
-        None
-    }
-
-    // OTel HTTP Trace format:
-    // traceparent: 00-TRACEID-SPANID-01
-    fn decode_traceparent(value: &str, id_type: u8) -> Option<String> {
-        let segs = value.split("-");
-        let mut i = 0;
-        for seg in segs {
-            if id_type == Self::TRACE_ID && i == 1 {
-                return Some(seg.to_string());
-            }
-            if id_type == Self::SPAN_ID && i == 2 {
-                return Some(seg.to_string());
-            }
-
-            i += 1;
-        }
-        None
-    }
-
-    fn decode_id(payload: &str, trace_key: &str, id_type: u8) -> Option<String> {
-        let trace_type = TraceType::from(trace_key);
-        match trace_type {
-            TraceType::Disabled | TraceType::XB3 | TraceType::XB3Span | TraceType::Customize(_) => {
-                Some(payload.to_owned())
-            }
-            TraceType::Uber => Self::decode_uber_id(payload, id_type),
-            TraceType::Sw6 | TraceType::Sw8 => Self::decode_skywalking_id(payload, id_type),
-            TraceType::TraceParent => Self::decode_traceparent(payload, id_type),
-            TraceType::NewRpcTraceContext => {
-                /*
-                    referer https://github.com/sofastack/sofa-rpc/blob/7931102255d6ea95ee75676d368aad37c56b57ee/tracer/tracer-opentracing-resteasy/src/main/java/com/alipay/sofa/rpc/tracer/sofatracer/RestTracerAdapter.java#L75
-                    in new version of sofarpc, use new_rpc_trace_context header store trace info
-                */
-                decode_new_rpc_trace_context_with_type(payload.as_bytes(), id_type)
-            }
+    pub(crate) fn decode_id(payload: &str, trace_key: &str, id_type: u8) -> Option<String> {
+        let ids = decode_trace_header(trace_key, payload);
+        match id_type {
+            Self::TRACE_ID => ids.trace_id,
+            _ => ids.span_id,
         }
     }
 
@@ -1008,15 +1920,64 @@ impl HttpLog {
                 self.parse_http2_go_uprobe(&config, payload, direction, is_req_end, is_resp_end)?;
             }
             _ => {
-                self.parse_http_v1(&config, payload, direction)
-                    .or(self.parse_http_v2(&config, payload, direction))?;
+                self.parse_http_v1(&config, payload, direction, true)
+                    .map(|_| ())
+                    .or(self.parse_http_v2(&config, payload, direction, usize::MAX))?;
             }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+// 参考：https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+pub fn grpc_response_status(code: i32) -> L7ResponseStatus {
+    match code {
+        0 => L7ResponseStatus::Ok,
+        // CANCELLED, INVALID_ARGUMENT, NOT_FOUND, ALREADY_EXISTS, PERMISSION_DENIED,
+        // FAILED_PRECONDITION, OUT_OF_RANGE, UNAUTHENTICATED
+        1 | 3 | 5 | 6 | 7 | 9 | 11 | 16 => L7ResponseStatus::ClientError,
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+// 参考：https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#errors
+fn grpc_status_of_rst_stream(error_code: u32) -> i32 {
+    match error_code {
+        // REFUSED_STREAM => UNAVAILABLE
+        0x7 => 14,
+        // CANCEL => CANCELLED
+        0x8 => 1,
+        // ENHANCE_YOUR_CALM => RESOURCE_EXHAUSTED
+        0xb => 8,
+        // INADEQUATE_SECURITY => PERMISSION_DENIED
+        0xc => 7,
+        // 其它错误码 => INTERNAL
+        _ => 13,
+    }
+}
+
+// grpc-message 使用百分号编码
+fn decode_grpc_message(val: &[u8]) -> String {
+    let mut message = Vec::with_capacity(val.len());
+    let mut i = 0;
+    while i < val.len() {
+        if val[i] == b'%' && i + 2 < val.len() {
+            let byte = str::from_utf8(&val[i + 1..i + 3])
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(byte) = byte {
+                message.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        message.push(val[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&message).into_owned()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Httpv2Headers {
     pub frame_length: u32,
     pub frame_type: u8,
@@ -1214,6 +2175,7 @@ pub fn parse_v1_headers(payload: &[u8]) -> V1HeaderIterator<'_> {
 #[cfg(test)]
 mod tests {
     use crate::common::MetaPacket;
+    use crate::config::handler::TraceType;
     use crate::utils::test::Capture;
 
     use std::collections::HashSet;
@@ -1381,6 +2343,623 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_http_v1_pipeline() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v1();
+        let mut parse = |payload: &[u8], direction: PacketDirection| {
+            let infos = http
+                .parse_http_v1_pipeline(&config, payload, direction, usize::MAX)
+                .unwrap();
+            http.reset();
+            infos
+        };
+
+        let requests = parse(
+            b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n\
+              HEAD /b HTTP/1.1\r\nHost: x\r\n\r\n\
+              POST /c HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\n01234",
+            PacketDirection::ClientToServer,
+        );
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].method, "HEAD");
+        assert_eq!(requests[2].path, "/c");
+        let requests = parse(
+            b"56789GET /d HTTP/1.1\r\nHost: x\r\n\r\n",
+            PacketDirection::ClientToServer,
+        );
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/d");
+        assert_eq!(requests[0].session_id(), Some(4));
+
+        // chunked 消息体跨越报文
+        let responses = parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].session_id(), Some(1));
+        // HEAD 请求的响应没有消息体，100 Continue 不与请求配对
+        let responses = parse(
+            b"0\r\n\r\n\
+              HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n\
+              HTTP/1.1 100 Continue\r\n\r\n\
+              HTTP/1.1 404 Not Found\r\nContent-Length: 3\r\n\r\nab",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].session_id(), Some(2));
+        assert_eq!(responses[1].session_id(), Some(3));
+        assert_eq!(responses[1].status_code, Some(404));
+        let responses = parse(
+            b"cHTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].session_id(), Some(4));
+        assert_eq!(responses[0].status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn test_http_v1_pipeline_packet_loss() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v1();
+        let mut parse = |payload: &[u8], direction: PacketDirection, seq: u32, ack: u32| {
+            http.pipeline
+                .on_tcp_packet(direction, seq, ack, payload.len() as u32);
+            let infos = http
+                .parse_http_v1_pipeline(&config, payload, direction, usize::MAX)
+                .unwrap();
+            http.reset();
+            infos
+        };
+        let (c2s, s2c) = (
+            PacketDirection::ClientToServer,
+            PacketDirection::ServerToClient,
+        );
+        let (req_a, req_b, req_c): (&[u8], &[u8], &[u8]) = (
+            b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n",
+            b"GET /b HTTP/1.1\r\nHost: x\r\n\r\n",
+            b"GET /c HTTP/1.1\r\nHost: x\r\n\r\n",
+        );
+        let resp: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let (len_a, len_b, len_c) = (req_a.len() as u32, req_b.len() as u32, req_c.len() as u32);
+        let resp_len = resp.len() as u32;
+
+        // 请求 /b 丢失
+        let requests = parse(req_a, c2s, 1000, 5000);
+        assert_eq!(requests[0].session_id(), Some(1));
+        let requests = parse(req_c, c2s, 1000 + len_a + len_b, 5000);
+        assert_eq!(requests[0].path, "/c");
+        assert_eq!(requests[0].session_id(), Some(2));
+        let responses = parse(resp, s2c, 5000, 1000 + len_a);
+        assert_eq!(responses[0].session_id(), Some(1));
+        // 服务端还没有收到 /c，这是 /b 的响应
+        let responses = parse(resp, s2c, 5000 + resp_len, 1000 + len_a + len_b);
+        assert_eq!(responses[0].session_id(), None);
+        let end_c = 1000 + len_a + len_b + len_c;
+        let responses = parse(resp, s2c, 5000 + resp_len * 2, end_c);
+        assert_eq!(responses[0].session_id(), Some(2));
+
+        // /a 的响应丢失，之后的响应不再与之前的请求配对
+        let requests = parse(&[req_a, req_b].concat(), c2s, end_c, 5000 + resp_len * 3);
+        assert_eq!(requests[1].session_id(), Some(4));
+        let end_b = end_c + len_a + len_b;
+        let responses = parse(resp, s2c, 5000 + resp_len * 4, end_b);
+        assert_eq!(responses[0].session_id(), None);
+        let requests = parse(req_c, c2s, end_b, 5000 + resp_len * 5);
+        assert_eq!(requests[0].session_id(), Some(5));
+        let responses = parse(resp, s2c, 5000 + resp_len * 5, end_b + len_c);
+        assert_eq!(responses[0].session_id(), Some(5));
+    }
+
+    #[test]
+    fn test_http_v1_log_packet_size() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v1();
+
+        // 超出 log_packet_size 的请求不输出日志，但仍占用序号
+        let requests = http
+            .parse_http_v1_pipeline(
+                &config,
+                b"POST /a HTTP/1.1\r\nContent-Length: 20\r\n\r\n01234567890123456789\
+                  GET /b HTTP/1.1\r\nHost: x\r\n\r\n",
+                PacketDirection::ClientToServer,
+                32,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/a");
+
+        let responses = http
+            .parse_http_v1_pipeline(
+                &config,
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n\
+                  HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].session_id(), Some(1));
+        assert_eq!(responses[1].session_id(), Some(2));
+    }
+
+    #[test]
+    fn test_http_v1_websocket_upgrade() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v1();
+
+        let requests = http
+            .parse_http_v1_pipeline(
+                &config,
+                b"GET /chat HTTP/1.1\r\nHost: x\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+                PacketDirection::ClientToServer,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(requests.len(), 1);
+        assert!(http.upgraded_parser().is_none());
+
+        // 101 之后紧跟的 WebSocket 帧不作为 HTTP 解析
+        let responses = http
+            .parse_http_v1_pipeline(
+                &config,
+                b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n\x81\x02hi",
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].session_id(), Some(1));
+        assert_eq!(responses[0].status_code, Some(101));
+
+        let Some(parser) = http.upgraded_parser() else {
+            panic!("connection not handed over to websocket");
+        };
+        assert_eq!(parser.protocol(), L7Protocol::WebSocket);
+        assert!(http.upgraded_parser().is_none());
+    }
+
+    fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    // 不加入动态表的新名称头部
+    fn literal(block: &mut Vec<u8>, key: &str, val: &str) {
+        block.extend_from_slice(&[0x00, key.len() as u8]);
+        block.extend_from_slice(key.as_bytes());
+        block.push(val.len() as u8);
+        block.extend_from_slice(val.as_bytes());
+    }
+
+    #[test]
+    fn test_http_v2_log_packet_size() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+
+        // 第二个头部块在 log_packet_size 之外，不输出日志但仍加入动态表
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &[0x82, 0x86, 0x84],
+        );
+        let mut block = vec![0x83, 0x86, 0x44, 0x06];
+        block.extend_from_slice(b"/a.B/C");
+        payload.extend(frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            3,
+            &block,
+        ));
+        http.parse_http_v2(&config, &payload, PacketDirection::ClientToServer, 12)
+            .unwrap();
+        assert_eq!(http.info.method, "GET");
+        assert_eq!(http.info.path, "/");
+        http.reset();
+
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            5,
+            &[0x83, 0x86, 0xbe],
+        );
+        http.parse_http_v2(&config, &payload, PacketDirection::ClientToServer, 12)
+            .unwrap();
+        assert_eq!(http.info.stream_id, Some(5));
+        assert_eq!(http.info.path, "/a.B/C");
+    }
+
+    #[test]
+    fn test_http_v2_dynamic_table() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+
+        // :method POST, :scheme http, 加入动态表的 :path 和 :authority
+        let mut block = vec![0x83, 0x86, 0x44, 0x06];
+        block.extend_from_slice(b"/a.B/C");
+        block.extend_from_slice(&[0x41, 0x03]);
+        block.extend_from_slice(b"svc");
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(http.info.path, "/a.B/C");
+        http.reset();
+
+        // 头部块由 CONTINUATION 帧结束，并引用之前报文加入动态表的条目
+        let mut payload = frame(HTTPV2_FRAME_HEADERS_TYPE, 0, 3, &[0x83, 0x86]);
+        payload.extend(frame(
+            HTTPV2_FRAME_CONTINUATION_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            3,
+            &[0xbf, 0xbe],
+        ));
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(http.info.stream_id, Some(3));
+        assert_eq!(http.info.method, "POST");
+        assert_eq!(http.info.path, "/a.B/C");
+        assert_eq!(http.info.host, "svc");
+        http.reset();
+
+        // 另一个方向的动态表是独立的
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            5,
+            &[0x88, 0xbe],
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ServerToClient,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(http.info.status_code, Some(200));
+        assert_eq!(http.info.host, "");
+    }
+
+    #[test]
+    fn test_http_v2_stream_id_gap() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+
+        let mut block = vec![0x83, 0x86, 0x44, 0x06];
+        block.extend_from_slice(b"/a.B/C");
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        http.reset();
+
+        // 流 3 的 HEADERS 帧丢失，对端的索引 62 已经是其中加入的 :authority
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            5,
+            &[0x83, 0x86, 0xbe],
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(http.info.stream_id, Some(5));
+        assert_eq!(http.info.method, "POST");
+        assert_eq!(http.info.path, "");
+        http.reset();
+
+        // 之后加入动态表的条目可以被引用
+        let mut block = vec![0x83, 0x86, 0x44, 0x02];
+        block.extend_from_slice(b"/d");
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            7,
+            &block,
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        http.reset();
+        let payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            9,
+            &[0x83, 0x86, 0xbe],
+        );
+        http.parse_http_v2(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!(http.info.path, "/d");
+    }
+
+    #[test]
+    fn test_grpc_trailers() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+
+        // unary 请求，一个 3 字节的消息
+        let mut block = vec![0x83, 0x86, 0x44, 0x06];
+        block.extend_from_slice(b"/a.B/C");
+        literal(&mut block, "content-type", "application/grpc");
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        );
+        payload.extend(frame(
+            HTTPV2_FRAME_DATA_TYPE,
+            FLAG_END_STREAM,
+            1,
+            &[0, 0, 0, 0, 3, b'a', b'b', b'c'],
+        ));
+        let infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ClientToServer,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].proto, L7Protocol::Grpc);
+
+        // server streaming 响应，第二个消息跨越报文，响应在 trailers 到达前不输出
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &[0x88],
+        );
+        let data = frame(
+            HTTPV2_FRAME_DATA_TYPE,
+            0,
+            1,
+            &[0, 0, 0, 0, 2, b'x', b'y', 0, 0, 0, 0, 2, b'z', b'w'],
+        );
+        payload.extend_from_slice(&data[..HTTPV2_FRAME_HEADER_LENGTH + 10]);
+        let infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert!(infos.is_empty());
+
+        let mut block = vec![];
+        literal(&mut block, "grpc-status", "14");
+        literal(&mut block, "grpc-message", "upstream%20down");
+        let mut payload = data[HTTPV2_FRAME_HEADER_LENGTH + 10..].to_vec();
+        payload.extend(frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS | FLAG_END_STREAM,
+            1,
+            &block,
+        ));
+        let infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(infos.len(), 1);
+        let info = &infos[0];
+        assert_eq!(info.status_code, Some(200));
+        assert_eq!(info.grpc_status, Some(14));
+        assert_eq!(info.grpc_message, "upstream down");
+        assert_eq!(info.status, L7ResponseStatus::ServerError);
+        assert_eq!((info.req_msg_count, info.req_msg_bytes), (1, 3));
+        assert_eq!((info.resp_msg_count, info.resp_msg_bytes), (2, 4));
+
+        let log = L7ProtocolSendLog::from(infos[0].clone());
+        assert_eq!(log.resp.code, Some(14));
+        assert_eq!(log.resp.exception, "upstream down");
+    }
+
+    #[test]
+    fn test_grpc_response_without_trailers() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+        assert!(http.grpc_streams.is_none());
+
+        let mut block = vec![0x83, 0x86, 0x44, 0x06];
+        block.extend_from_slice(b"/a.B/C");
+        literal(&mut block, "content-type", "application/grpc");
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        );
+        payload.extend(frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            3,
+            &block,
+        ));
+        http.parse_http_v2_payload(
+            &config,
+            &payload,
+            PacketDirection::ClientToServer,
+            usize::MAX,
+        )
+        .unwrap();
+        http.reset();
+
+        // 两个 stream 的响应头都暂存等待 trailers
+        for stream_id in [1, 3] {
+            let payload = frame(
+                HTTPV2_FRAME_HEADERS_TYPE,
+                FLAG_HEADERS_END_HEADERS,
+                stream_id,
+                &[0x88],
+            );
+            let infos = http
+                .parse_http_v2_payload(
+                    &config,
+                    &payload,
+                    PacketDirection::ServerToClient,
+                    usize::MAX,
+                )
+                .unwrap();
+            http.reset();
+            assert!(infos.is_empty());
+        }
+
+        // 客户端取消 stream 1，暂存的响应按 CANCELLED 输出
+        let payload = frame(HTTPV2_FRAME_RST_STREAM_TYPE, 0, 1, &8u32.to_be_bytes());
+        let infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ClientToServer,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].stream_id, Some(1));
+        assert_eq!(infos[0].grpc_status, Some(1));
+        assert_eq!(infos[0].status, L7ResponseStatus::ClientError);
+
+        // 连接结束时输出 stream 3 暂存的响应
+        let infos = http.flow_end(0);
+        assert_eq!(infos.len(), 1);
+        let L7ProtocolInfo::HttpInfo(info) = &infos[0] else {
+            unreachable!();
+        };
+        assert_eq!(info.stream_id, Some(3));
+        assert_eq!(info.status_code, Some(200));
+        assert_eq!(info.grpc_status, None);
+        assert!(http.flow_end(0).is_empty());
+    }
+
+    #[test]
+    fn test_triple() {
+        let config = L7LogDynamicConfig::default();
+        let mut http = HttpLog::new_v2(false);
+
+        let mut block = vec![0x83, 0x86, 0x44, 0x1a];
+        block.extend_from_slice(b"/org.demo.Greeter/sayHello");
+        literal(&mut block, "content-type", "application/grpc+proto");
+        literal(&mut block, "tri-service-version", "1.0.0");
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        );
+        payload.extend(frame(
+            HTTPV2_FRAME_DATA_TYPE,
+            FLAG_END_STREAM,
+            1,
+            &[0, 0, 0, 0, 1, b'a'],
+        ));
+        let mut infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ClientToServer,
+                usize::MAX,
+            )
+            .unwrap();
+        http.reset();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].get_l7_protocol_with_tls(), L7Protocol::Dubbo);
+        // 解析器仍按 gRPC 解析后续报文
+        assert_eq!(http.protocol(), L7Protocol::Grpc);
+        let mut request = infos.remove(0);
+
+        // 响应中没有 tri- 头部
+        let mut payload = frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &[0x88],
+        );
+        payload.extend(frame(HTTPV2_FRAME_DATA_TYPE, 0, 1, &[0, 0, 0, 0, 1, b'b']));
+        let mut block = vec![];
+        literal(&mut block, "grpc-status", "0");
+        payload.extend(frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS | FLAG_END_STREAM,
+            1,
+            &block,
+        ));
+        let infos = http
+            .parse_http_v2_payload(
+                &config,
+                &payload,
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].proto, L7Protocol::Dubbo);
+        assert_eq!(infos[0].status, L7ResponseStatus::Ok);
+
+        request.merge(infos[0].clone()).unwrap();
+        let log = L7ProtocolSendLog::from(request);
+        assert_eq!(log.req.req_type, "sayHello");
+        assert_eq!(log.req.resource, "org.demo.Greeter");
+        assert_eq!(log.req.endpoint, "org.demo.Greeter/sayHello");
+        assert_eq!(
+            log.ext_info.unwrap().rpc_service,
+            Some("org.demo.Greeter".to_string())
+        );
+    }
+
     #[test]
     fn get_http_v1_header_from_payload() {
         let testcases = vec![
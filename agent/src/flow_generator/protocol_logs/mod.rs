@@ -20,24 +20,34 @@ mod http;
 mod mq;
 mod parser;
 pub mod pb_adapter;
+mod quic;
 mod rpc;
 mod sql;
+mod tls;
+mod trace;
+mod websocket;
 pub use self::http::{
     check_http_method, get_http_request_info, get_http_request_version, get_http_resp_info,
-    is_http_v1_payload, parse_v1_headers, HttpInfo, HttpLog, Httpv2Headers,
+    grpc_response_status, is_http_v1_payload, parse_v1_headers, HttpInfo, HttpLog, Httpv2Headers,
 };
 use self::pb_adapter::L7ProtocolSendLog;
 pub use dns::{DnsInfo, DnsLog};
-pub use mq::{mqtt, KafkaInfo, KafkaLog, MqttInfo, MqttLog};
+pub use mq::{mqtt, AmqpInfo, AmqpLog, KafkaInfo, KafkaLog, MqttInfo, MqttLog};
 pub use parser::{AppProtoLogsParser, MetaAppProto};
+pub use quic::{QuicInfo, QuicLog};
 pub use rpc::{
     decode_new_rpc_trace_context, decode_new_rpc_trace_context_with_type, get_protobuf_rpc_parser,
     DubboHeader, DubboInfo, DubboLog, ProtobufRpcInfo, ProtobufRpcWrapLog, SofaRpcInfo, SofaRpcLog,
-    SOFA_NEW_RPC_TRACE_CTX_KEY,
+    ThriftInfo, ThriftLog, SOFA_NEW_RPC_TRACE_CTX_KEY,
 };
 pub use sql::{
-    decode, MysqlHeader, MysqlInfo, MysqlLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog,
+    decode, CassandraInfo, CassandraLog, MemcachedInfo, MemcachedLog, MongoDBInfo, MongoDBLog,
+    MysqlHeader, MysqlInfo, MysqlLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog, TdsInfo,
+    TdsLog,
 };
+pub use tls::{TlsInfo, TlsLog};
+pub use trace::{decode_trace_header, trace_decoder, TraceContext, TraceDecoder, TraceIds};
+pub use websocket::{WebSocketInfo, WebSocketLog};
 
 use std::{
     fmt,
@@ -379,18 +389,17 @@ impl AppProtoLogsData {
         // |flow_id 高8位| flow_id 低24位|proto 8 位|session 低24位|
 
         // due to grpc is init by http2 and modify during parse, it must reset to http2 when the protocol is grpc.
-        let proto = if self.base_info.head.proto == L7Protocol::Grpc {
-            if let L7ProtocolInfo::HttpInfo(http) = &self.special_info {
+        // dubbo triple is parsed by http2 as well.
+        let proto = match (self.base_info.head.proto, &self.special_info) {
+            (L7Protocol::Grpc | L7Protocol::Dubbo, L7ProtocolInfo::HttpInfo(http)) => {
                 if http.is_tls() {
                     L7Protocol::Http2TLS
                 } else {
                     L7Protocol::Http2
                 }
-            } else {
-                unreachable!()
             }
-        } else {
-            self.base_info.head.proto
+            (L7Protocol::Grpc, _) => unreachable!(),
+            (proto, _) => proto,
         };
 
         let flow_id_part =
@@ -0,0 +1,804 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::{read_u16_be, read_u32_be, read_u64_be},
+};
+
+// https://www.rabbitmq.com/resources/specs/amqp0-9-1.pdf
+const PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+
+const FRAME_HEADER_LEN: usize = 7;
+const FRAME_END: u8 = 0xce;
+
+const FRAME_METHOD: u8 = 1;
+const FRAME_CONTENT_HEADER: u8 = 2;
+const FRAME_CONTENT_BODY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 8;
+
+const CLASS_CONNECTION: u16 = 10;
+const CLASS_CHANNEL: u16 = 20;
+const CLASS_EXCHANGE: u16 = 40;
+const CLASS_QUEUE: u16 = 50;
+const CLASS_BASIC: u16 = 60;
+const CLASS_CONFIRM: u16 = 85;
+const CLASS_TX: u16 = 90;
+
+const REPLY_SUCCESS: u16 = 200;
+const REPLY_CONNECTION_FORCED: u16 = 320;
+
+// (class_id, method_id, name)
+const METHODS: &[(u16, u16, &str)] = &[
+    (CLASS_CONNECTION, 10, "Connection.Start"),
+    (CLASS_CONNECTION, 11, "Connection.StartOk"),
+    (CLASS_CONNECTION, 20, "Connection.Secure"),
+    (CLASS_CONNECTION, 21, "Connection.SecureOk"),
+    (CLASS_CONNECTION, 30, "Connection.Tune"),
+    (CLASS_CONNECTION, 31, "Connection.TuneOk"),
+    (CLASS_CONNECTION, 40, "Connection.Open"),
+    (CLASS_CONNECTION, 41, "Connection.OpenOk"),
+    (CLASS_CONNECTION, 50, "Connection.Close"),
+    (CLASS_CONNECTION, 51, "Connection.CloseOk"),
+    (CLASS_CONNECTION, 60, "Connection.Blocked"),
+    (CLASS_CONNECTION, 61, "Connection.Unblocked"),
+    (CLASS_CONNECTION, 70, "Connection.UpdateSecret"),
+    (CLASS_CONNECTION, 71, "Connection.UpdateSecretOk"),
+    (CLASS_CHANNEL, 10, "Channel.Open"),
+    (CLASS_CHANNEL, 11, "Channel.OpenOk"),
+    (CLASS_CHANNEL, 20, "Channel.Flow"),
+    (CLASS_CHANNEL, 21, "Channel.FlowOk"),
+    (CLASS_CHANNEL, 40, "Channel.Close"),
+    (CLASS_CHANNEL, 41, "Channel.CloseOk"),
+    (CLASS_EXCHANGE, 10, "Exchange.Declare"),
+    (CLASS_EXCHANGE, 11, "Exchange.DeclareOk"),
+    (CLASS_EXCHANGE, 20, "Exchange.Delete"),
+    (CLASS_EXCHANGE, 21, "Exchange.DeleteOk"),
+    (CLASS_EXCHANGE, 30, "Exchange.Bind"),
+    (CLASS_EXCHANGE, 31, "Exchange.BindOk"),
+    (CLASS_EXCHANGE, 40, "Exchange.Unbind"),
+    (CLASS_EXCHANGE, 51, "Exchange.UnbindOk"),
+    (CLASS_QUEUE, 10, "Queue.Declare"),
+    (CLASS_QUEUE, 11, "Queue.DeclareOk"),
+    (CLASS_QUEUE, 20, "Queue.Bind"),
+    (CLASS_QUEUE, 21, "Queue.BindOk"),
+    (CLASS_QUEUE, 30, "Queue.Purge"),
+    (CLASS_QUEUE, 31, "Queue.PurgeOk"),
+    (CLASS_QUEUE, 40, "Queue.Delete"),
+    (CLASS_QUEUE, 41, "Queue.DeleteOk"),
+    (CLASS_QUEUE, 50, "Queue.Unbind"),
+    (CLASS_QUEUE, 51, "Queue.UnbindOk"),
+    (CLASS_BASIC, 10, "Basic.Qos"),
+    (CLASS_BASIC, 11, "Basic.QosOk"),
+    (CLASS_BASIC, 20, "Basic.Consume"),
+    (CLASS_BASIC, 21, "Basic.ConsumeOk"),
+    (CLASS_BASIC, 30, "Basic.Cancel"),
+    (CLASS_BASIC, 31, "Basic.CancelOk"),
+    (CLASS_BASIC, 40, "Basic.Publish"),
+    (CLASS_BASIC, 50, "Basic.Return"),
+    (CLASS_BASIC, 60, "Basic.Deliver"),
+    (CLASS_BASIC, 70, "Basic.Get"),
+    (CLASS_BASIC, 71, "Basic.GetOk"),
+    (CLASS_BASIC, 72, "Basic.GetEmpty"),
+    (CLASS_BASIC, 80, "Basic.Ack"),
+    (CLASS_BASIC, 90, "Basic.Reject"),
+    (CLASS_BASIC, 100, "Basic.RecoverAsync"),
+    (CLASS_BASIC, 110, "Basic.Recover"),
+    (CLASS_BASIC, 111, "Basic.RecoverOk"),
+    (CLASS_BASIC, 120, "Basic.Nack"),
+    (CLASS_CONFIRM, 10, "Confirm.Select"),
+    (CLASS_CONFIRM, 11, "Confirm.SelectOk"),
+    (CLASS_TX, 10, "Tx.Select"),
+    (CLASS_TX, 11, "Tx.SelectOk"),
+    (CLASS_TX, 20, "Tx.Commit"),
+    (CLASS_TX, 21, "Tx.CommitOk"),
+    (CLASS_TX, 30, "Tx.Rollback"),
+    (CLASS_TX, 31, "Tx.RollbackOk"),
+];
+
+fn method_name(class_id: u16, method_id: u16) -> Option<&'static str> {
+    METHODS
+        .iter()
+        .find(|(c, m, _)| *c == class_id && *m == method_id)
+        .map(|(_, _, name)| *name)
+}
+
+/*
+    AMQP 是异步协议，只有同步方法有对应的 -Ok 应答:
+    1. 同步请求（如 Queue.Declare）为 Request，对应的 -Ok 为 Response
+    2. Channel.Close/Connection.Close 由服务端发送时携带错误码，视为对前一个请求的 Response
+    3. 连接协商（Start/Secure/Tune）及 Publish/Deliver/Ack 等异步方法为 Session
+    ===========================================================================
+    AMQP is asynchronous, only synchronous methods have -Ok replies:
+    1. synchronous requests (such as Queue.Declare) are Request, and their -Ok replies are Response
+    2. Channel.Close/Connection.Close sent by server carry the error code, regarded as the
+       Response of the previous request
+    3. connection negotiation (Start/Secure/Tune) and asynchronous methods such as
+       Publish/Deliver/Ack are Session
+*/
+fn method_msg_type(class_id: u16, method_id: u16, direction: PacketDirection) -> LogMessageType {
+    match (class_id, method_id) {
+        (CLASS_CONNECTION, 50) | (CLASS_CHANNEL, 40) => match direction {
+            PacketDirection::ClientToServer => LogMessageType::Request,
+            PacketDirection::ServerToClient => LogMessageType::Response,
+        },
+        (CLASS_CONNECTION, 51) | (CLASS_CHANNEL, 41) => match direction {
+            PacketDirection::ClientToServer => LogMessageType::Session,
+            PacketDirection::ServerToClient => LogMessageType::Response,
+        },
+        (CLASS_CONNECTION, 40) | (CLASS_CONNECTION, 70) => LogMessageType::Request,
+        (CLASS_CONNECTION, 41) | (CLASS_CONNECTION, 71) => LogMessageType::Response,
+        (CLASS_CONNECTION, _) => LogMessageType::Session,
+        (CLASS_BASIC, 10 | 20 | 30 | 70 | 110) => LogMessageType::Request,
+        (CLASS_BASIC, 11 | 21 | 31 | 71 | 72 | 111) => LogMessageType::Response,
+        (CLASS_BASIC, _) => LogMessageType::Session,
+        (CLASS_CHANNEL | CLASS_EXCHANGE | CLASS_QUEUE | CLASS_CONFIRM | CLASS_TX, m)
+            if m % 2 == 0 =>
+        {
+            LogMessageType::Request
+        }
+        _ => LogMessageType::Response,
+    }
+}
+
+// 协议识别时只接受客户端发送的方法，因为识别成功后会将报文方向设置为 ClientToServer
+// ==============================================================================
+// only methods sent by client are accepted on protocol check, because the direction of
+// the packet will be set to ClientToServer after check succeeds
+fn sent_by_client(class_id: u16, method_id: u16) -> bool {
+    match (class_id, method_id) {
+        (CLASS_CONNECTION, 11 | 21 | 31 | 40 | 50 | 51) => true,
+        (CLASS_CONNECTION, _) => false,
+        (CLASS_BASIC, 10 | 20 | 30 | 40 | 70 | 80 | 90 | 100 | 110 | 120) => true,
+        (CLASS_BASIC, _) => false,
+        (CLASS_CHANNEL, 40 | 41) => true,
+        (_, m) => m % 2 == 0,
+    }
+}
+
+fn reply_code_status(code: u16) -> L7ResponseStatus {
+    match code {
+        REPLY_SUCCESS => L7ResponseStatus::Ok,
+        REPLY_CONNECTION_FORCED => L7ResponseStatus::ServerError,
+        300..=499 => L7ResponseStatus::ClientError,
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+struct Frame<'a> {
+    typ: u8,
+    channel: u16,
+    payload: &'a [u8],
+}
+
+// 返回解析出的帧和剩余数据，帧不完整时返回 payload 可用部分
+// =========================================================
+// return the parsed frame and the remaining data, payload is truncated when frame is incomplete
+fn read_frame(data: &[u8], strict: bool) -> Option<(Frame<'_>, &[u8])> {
+    if data.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+    let typ = data[0];
+    match typ {
+        FRAME_METHOD | FRAME_CONTENT_HEADER | FRAME_CONTENT_BODY | FRAME_HEARTBEAT => (),
+        _ => return None,
+    }
+    let channel = read_u16_be(&data[1..]);
+    let size = read_u32_be(&data[3..]) as usize;
+    let data = &data[FRAME_HEADER_LEN..];
+    if data.len() <= size {
+        if strict {
+            return None;
+        }
+        return Some((
+            Frame {
+                typ,
+                channel,
+                payload: data,
+            },
+            &[],
+        ));
+    }
+    if data[size] != FRAME_END {
+        return None;
+    }
+    Some((
+        Frame {
+            typ,
+            channel,
+            payload: &data[..size],
+        },
+        &data[size + 1..],
+    ))
+}
+
+struct Arguments<'a>(&'a [u8]);
+
+impl<'a> Arguments<'a> {
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.0.len() < n {
+            return None;
+        }
+        self.0 = &self.0[n..];
+        Some(())
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        if self.0.len() < 2 {
+            return None;
+        }
+        let v = read_u16_be(self.0);
+        self.0 = &self.0[2..];
+        Some(v)
+    }
+
+    fn short_str(&mut self) -> Option<String> {
+        let len = *self.0.first()? as usize;
+        if self.0.len() < 1 + len {
+            return None;
+        }
+        let s = String::from_utf8_lossy(&self.0[1..1 + len]).into_owned();
+        self.0 = &self.0[1 + len..];
+        Some(s)
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct AmqpInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub channel: u16,
+    #[serde(skip)]
+    pub class_id: u16,
+    #[serde(skip)]
+    pub method_id: u16,
+    #[serde(rename = "request_type")]
+    pub method: &'static str,
+
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub vhost: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub exchange: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub routing_key: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub queue: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<u16>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub reply_text: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for AmqpInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.channel as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, AmqpInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::AMQP,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl AmqpInfo {
+    pub fn merge(&mut self, other: Self) {
+        if self.resp_len.is_none() {
+            self.resp_len = other.resp_len;
+        }
+        if other.reply_code.is_some() {
+            self.reply_code = other.reply_code;
+            self.reply_text = other.reply_text;
+        }
+        if self.status == L7ResponseStatus::Ok {
+            self.status = other.status;
+        }
+        // 匿名队列的名称由服务端在 Queue.DeclareOk 中返回
+        // ===============================================
+        // name of anonymous queue is returned by server in Queue.DeclareOk
+        if self.queue.is_empty() {
+            self.queue = other.queue;
+        }
+    }
+}
+
+impl From<AmqpInfo> for L7ProtocolSendLog {
+    fn from(f: AmqpInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.exchange.is_empty() {
+            attributes.push(KeyVal {
+                key: "exchange".to_string(),
+                val: f.exchange.clone(),
+            });
+        }
+        if !f.routing_key.is_empty() {
+            attributes.push(KeyVal {
+                key: "routing_key".to_string(),
+                val: f.routing_key.clone(),
+            });
+        }
+        let resource = if !f.queue.is_empty() {
+            f.queue
+        } else {
+            f.exchange
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: String::from(f.method),
+                domain: f.vhost,
+                resource,
+                endpoint: f.routing_key,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.reply_code.map(|c| c as i32),
+                exception: f.reply_text,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmqpLog {
+    info: AmqpInfo,
+    perf_stats: Option<PerfStats>,
+    // <channel,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for AmqpLog {
+    fn default() -> Self {
+        Self {
+            info: AmqpInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+        }
+    }
+}
+
+perf_impl!(AmqpLog);
+
+impl L7ProtocolParserInterface for AmqpLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        if payload.starts_with(PROTOCOL_HEADER) {
+            return true;
+        }
+        let Some((frame, _)) = read_frame(payload, true) else {
+            return false;
+        };
+        if frame.typ != FRAME_METHOD || frame.payload.len() < 4 {
+            return false;
+        }
+        let (class_id, method_id) = (read_u16_be(frame.payload), read_u16_be(&frame.payload[2..]));
+        method_name(class_id, method_id).is_some() && sent_by_client(class_id, method_id)
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        if !self.parse(payload, param.direction)? {
+            return Ok(vec![]);
+        }
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::AmqpInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::AMQP
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = AmqpInfo::default();
+    }
+}
+
+impl AmqpLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 只解析报文中的第一个方法帧，返回 false 表示报文中没有方法帧
+    // ==========================================================
+    // only the first method frame of the payload is parsed, false means no method frame in payload
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<bool> {
+        if payload.starts_with(PROTOCOL_HEADER) {
+            return Ok(false);
+        }
+        let mut data = payload;
+        let frame = loop {
+            let Some((frame, rest)) = read_frame(data, false) else {
+                return Err(Error::AmqpLogParseFailed);
+            };
+            if frame.typ == FRAME_METHOD {
+                data = rest;
+                break frame;
+            }
+            if rest.is_empty() {
+                return Ok(false);
+            }
+            data = rest;
+        };
+        if frame.payload.len() < 4 {
+            return Err(Error::AmqpLogParseFailed);
+        }
+        let class_id = read_u16_be(frame.payload);
+        let method_id = read_u16_be(&frame.payload[2..]);
+        let Some(method) = method_name(class_id, method_id) else {
+            return Err(Error::AmqpLogParseFailed);
+        };
+
+        let info = &mut self.info;
+        info.channel = frame.channel;
+        info.class_id = class_id;
+        info.method_id = method_id;
+        info.method = method;
+        info.msg_type = method_msg_type(class_id, method_id, direction);
+
+        // 带内容的方法加上消息体长度
+        // ==========================
+        // add body size for methods with content
+        let mut size = frame.payload.len() as u32;
+        if let Some((header, _)) = read_frame(data, false) {
+            if header.typ == FRAME_CONTENT_HEADER
+                && header.channel == frame.channel
+                && header.payload.len() >= 12
+            {
+                size = size.saturating_add(read_u64_be(&header.payload[4..]) as u32);
+            }
+        }
+        match info.msg_type {
+            LogMessageType::Response => info.resp_len = Some(size),
+            _ => info.req_len = Some(size),
+        }
+
+        // 参数截断时保留已解析的字段
+        // ==========================
+        // keep parsed fields when arguments are truncated
+        let _ = Self::parse_arguments(info, Arguments(&frame.payload[4..]));
+        Ok(true)
+    }
+
+    fn parse_arguments(info: &mut AmqpInfo, mut args: Arguments) -> Option<()> {
+        match (info.class_id, info.method_id) {
+            // Connection.Open: virtual-host
+            (CLASS_CONNECTION, 40) => info.vhost = args.short_str()?,
+            // Connection.Close/Channel.Close: reply-code reply-text class-id method-id
+            (CLASS_CONNECTION, 50) | (CLASS_CHANNEL, 40) => {
+                let code = args.u16()?;
+                info.reply_code = Some(code);
+                info.status = reply_code_status(code);
+                info.reply_text = args.short_str()?;
+            }
+            // Exchange.Declare/Delete: reserved exchange
+            (CLASS_EXCHANGE, 10 | 20) => {
+                args.skip(2)?;
+                info.exchange = args.short_str()?;
+            }
+            // Exchange.Bind/Unbind: reserved destination source routing-key
+            (CLASS_EXCHANGE, 30 | 40) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            // Queue.Declare/Purge/Delete: reserved queue
+            // Basic.Consume/Get: reserved queue
+            (CLASS_QUEUE, 10 | 30 | 40) | (CLASS_BASIC, 20 | 70) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?;
+            }
+            // Queue.DeclareOk: queue message-count consumer-count
+            (CLASS_QUEUE, 11) => info.queue = args.short_str()?,
+            // Queue.Bind/Unbind: reserved queue exchange routing-key
+            (CLASS_QUEUE, 20 | 50) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            // Basic.Publish: reserved exchange routing-key
+            (CLASS_BASIC, 40) => {
+                args.skip(2)?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            // Basic.Return: reply-code reply-text exchange routing-key
+            (CLASS_BASIC, 50) => {
+                let code = args.u16()?;
+                info.reply_code = Some(code);
+                info.status = reply_code_status(code);
+                info.reply_text = args.short_str()?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            // Basic.Deliver: consumer-tag delivery-tag redelivered exchange routing-key
+            (CLASS_BASIC, 60) => {
+                args.short_str()?;
+                args.skip(9)?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            // Basic.GetOk: delivery-tag redelivered exchange routing-key message-count
+            (CLASS_BASIC, 71) => {
+                args.skip(9)?;
+                info.exchange = args.short_str()?;
+                info.routing_key = args.short_str()?;
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+impl L7FlowPerf for AmqpLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::AMQP,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/amqp";
+
+    fn run(name: &str) -> Vec<(bool, Option<AmqpInfo>)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from(&*packet);
+            let is_amqp = AmqpLog::new().check_payload(payload, &param);
+            let mut parser = AmqpLog::new();
+            let info = match parser.parse_payload(payload, &param) {
+                Ok(mut infos) if !infos.is_empty() => match infos.remove(0) {
+                    L7ProtocolInfo::AmqpInfo(i) => Some(i),
+                    _ => unreachable!(),
+                },
+                _ => None,
+            };
+            output.push((is_amqp, info));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let output = run("amqp.pcap");
+        assert_eq!(output.len(), 12);
+
+        // protocol header
+        assert!(output[0].0);
+        assert!(output[0].1.is_none());
+
+        // server methods are not used for protocol check
+        let (is_amqp, start) = &output[1];
+        assert!(!is_amqp);
+        assert_eq!(start.as_ref().unwrap().method, "Connection.Start");
+        assert_eq!(start.as_ref().unwrap().msg_type, LogMessageType::Session);
+
+        let open = output[2].1.as_ref().unwrap();
+        assert!(output[2].0);
+        assert_eq!(open.method, "Connection.Open");
+        assert_eq!(open.vhost, "/");
+        assert_eq!(open.msg_type, LogMessageType::Request);
+
+        let declare = output[6].1.as_ref().unwrap();
+        assert_eq!(declare.method, "Queue.Declare");
+        assert_eq!(declare.channel, 1);
+        assert_eq!(declare.queue, "orders");
+        let declare_ok = output[7].1.as_ref().unwrap();
+        assert_eq!(declare_ok.method, "Queue.DeclareOk");
+        assert_eq!(declare_ok.msg_type, LogMessageType::Response);
+        assert_eq!(declare_ok.queue, "orders");
+
+        let publish = output[8].1.as_ref().unwrap();
+        assert!(output[8].0);
+        assert_eq!(publish.method, "Basic.Publish");
+        assert_eq!(publish.msg_type, LogMessageType::Session);
+        assert_eq!(publish.exchange, "amq.direct");
+        assert_eq!(publish.routing_key, "orders");
+        // method frame arguments and 11 bytes message body
+        assert_eq!(publish.req_len, Some(4 + 2 + 11 + 7 + 1 + 11));
+
+        let get = output[9].1.as_ref().unwrap();
+        assert_eq!(get.method, "Basic.Get");
+        assert_eq!(get.queue, "missing");
+        assert_eq!(get.msg_type, LogMessageType::Request);
+
+        let close = output[10].1.as_ref().unwrap();
+        assert_eq!(close.method, "Channel.Close");
+        assert_eq!(close.msg_type, LogMessageType::Response);
+        assert_eq!(close.reply_code, Some(404));
+        assert_eq!(close.status, L7ResponseStatus::ClientError);
+        assert_eq!(
+            close.reply_text,
+            "NOT_FOUND - no queue 'missing' in vhost '/'"
+        );
+
+        let close_ok = output[11].1.as_ref().unwrap();
+        assert_eq!(close_ok.method, "Channel.CloseOk");
+        assert_eq!(close_ok.msg_type, LogMessageType::Session);
+    }
+
+    #[test]
+    fn merge_close() {
+        let output = run("amqp.pcap");
+        let mut get = output[9].1.clone().unwrap();
+        let close = output[10].1.clone().unwrap();
+        get.merge_log(L7ProtocolInfo::AmqpInfo(close)).unwrap();
+        assert_eq!(get.method, "Basic.Get");
+        assert_eq!(get.reply_code, Some(404));
+        assert_eq!(get.status, L7ResponseStatus::ClientError);
+
+        let log = L7ProtocolSendLog::from(get);
+        assert_eq!(log.req.req_type, "Basic.Get");
+        assert_eq!(log.req.resource, "missing");
+        assert_eq!(log.resp.code, Some(404));
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("amqp.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = AmqpLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        // Connection.Open, Channel.Open, Queue.Declare, Basic.Get
+        assert_eq!(stats.request_count, 4);
+        // Connection.OpenOk, Channel.OpenOk, Queue.DeclareOk, Channel.Close
+        assert_eq!(stats.response_count, 4);
+        assert_eq!(stats.err_client_count, 1);
+        assert_eq!(stats.rrt_count, 4);
+    }
+}
@@ -13,6 +13,7 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use lru::LruCache;
 use serde::Serialize;
 
 use crate::{
@@ -22,20 +23,45 @@ use crate::{
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
         l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
     },
+    config::handler::{L7LogDynamicConfig, LogParserConfig},
     flow_generator::{
         error::{Error, Result},
         protocol_logs::{
             consts::KAFKA_REQ_HEADER_LEN,
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            pb_adapter::{
+                ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+            },
             value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+            TraceContext,
         },
     },
     log_info_merge, parse_common,
     utils::bytes::{read_i16_be, read_u16_be, read_u32_be},
 };
 
+const KAFKA_PRODUCE: u16 = 0;
 const KAFKA_FETCH: u16 = 1;
 
+// 从该版本开始使用 compact 字符串/数组和 tagged fields
+// =================================================
+// compact strings/arrays and tagged fields are used since these versions
+const KAFKA_PRODUCE_FLEXIBLE_VERSION: u16 = 9;
+const KAFKA_FETCH_FLEXIBLE_VERSION: u16 = 12;
+// 从该版本开始用 topic id 代替 topic 名称
+// ====================================
+// topic ids replace topic names since this version
+const KAFKA_TOPIC_ID_VERSION: u16 = 13;
+const KAFKA_TOPIC_ID_LEN: usize = 16;
+
+const KAFKA_RECORD_BATCH_MAGIC_OFFSET: usize = 16;
+const KAFKA_RECORD_BATCH_ATTRIBUTES_OFFSET: usize = 21;
+const KAFKA_RECORD_BATCH_COMPRESSION_MASK: u16 = 0x07;
+const KAFKA_RECORD_BATCH_COUNT_OFFSET: usize = 57;
+const KAFKA_RECORD_BATCH_HEADER_LEN: usize = 61;
+const KAFKA_LOG_OVERHEAD: usize = 12;
+
+const KAFKA_SESSION_CACHE_SIZE: usize = 64;
+
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct KafkaInfo {
     msg_type: LogMessageType,
@@ -56,8 +82,26 @@ pub struct KafkaInfo {
     pub api_version: u16,
     #[serde(rename = "request_type")]
     pub api_key: u16,
-    #[serde(skip)]
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
     pub client_id: String,
+    // 多个 topic 以逗号分隔
+    // ===================
+    // multiple topics are separated by commas
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub topic_name: String,
+    // <topic, partition>
+    #[serde(skip)]
+    pub partitions: Vec<(String, i32)>,
+    #[serde(skip)]
+    pub record_count: Option<u32>,
+    #[serde(skip)]
+    pub acks: Option<i16>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub trace_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub span_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub parent_span_id: String,
 
     // reponse
     #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
@@ -103,6 +147,35 @@ impl KafkaInfo {
         if self.resp_msg_size.is_none() {
             self.resp_msg_size = other.resp_msg_size;
         }
+        if self.req_msg_size.is_none() {
+            self.req_msg_size = other.req_msg_size;
+        }
+        if self.client_id.is_empty() {
+            self.client_id = other.client_id;
+        }
+        if self.topic_name.is_empty() {
+            self.topic_name = other.topic_name;
+        }
+        if self.partitions.is_empty() {
+            self.partitions = other.partitions;
+        }
+        if self.record_count.is_none() {
+            self.record_count = other.record_count;
+        }
+        if self.acks.is_none() {
+            self.acks = other.acks;
+        }
+        if self.trace_id.is_empty() {
+            self.trace_id = other.trace_id;
+        }
+        if self.span_id.is_empty() {
+            self.span_id = other.span_id;
+            self.parent_span_id = other.parent_span_id;
+        }
+        if other.msg_type == LogMessageType::Request {
+            self.api_key = other.api_key;
+            self.api_version = other.api_version;
+        }
         /*
             reference:  https://kafka.apache.org/protocol.html#protocol_messages
 
@@ -114,12 +187,20 @@ impl KafkaInfo {
                 ...
         */
         match other.msg_type {
+            LogMessageType::Response if other.status_code.is_some() => {
+                self.status = other.status;
+                self.status_code = other.status_code;
+            }
             LogMessageType::Response if self.api_key == KAFKA_FETCH && self.api_version >= 7 => {
                 if let Some(d) = other.resp_data {
                     self.set_status_code(read_i16_be(&d[12..]) as i32)
                 }
             }
-            LogMessageType::Request if other.api_key == KAFKA_FETCH && other.api_version >= 7 => {
+            LogMessageType::Request
+                if self.status_code.is_none()
+                    && other.api_key == KAFKA_FETCH
+                    && other.api_version >= 7 =>
+            {
                 if let Some(d) = self.resp_data {
                     self.set_status_code(read_i16_be(&d[12..]) as i32)
                 }
@@ -128,6 +209,25 @@ impl KafkaInfo {
         }
     }
 
+    fn add_partition(&mut self, topic: &str, partition: i32) {
+        if !self.topic_name.split(',').any(|t| t == topic) {
+            if !self.topic_name.is_empty() {
+                self.topic_name.push(',');
+            }
+            self.topic_name.push_str(topic);
+        }
+        self.partitions.push((topic.to_string(), partition));
+    }
+
+    // 记录第一个非零的错误码，全部为零时为零
+    // ====================================
+    // records the first non-zero error code, or zero if all are zero
+    fn add_error_code(&mut self, code: i16) {
+        if self.status_code.unwrap_or_default() == 0 {
+            self.set_status_code(code as i32);
+        }
+    }
+
     pub fn set_status_code(&mut self, code: i32) {
         self.status_code = Some(code);
         if code == 0 {
@@ -221,11 +321,37 @@ impl KafkaInfo {
 impl From<KafkaInfo> for L7ProtocolSendLog {
     fn from(f: KafkaInfo) -> Self {
         let command_str = f.get_command();
+        let mut attributes = vec![];
+        if !f.partitions.is_empty() {
+            attributes.push(KeyVal {
+                key: "partitions".to_string(),
+                val: f
+                    .partitions
+                    .iter()
+                    .map(|(topic, partition)| format!("{}-{}", topic, partition))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            });
+        }
+        if let Some(count) = f.record_count {
+            attributes.push(KeyVal {
+                key: "record_count".to_string(),
+                val: count.to_string(),
+            });
+        }
+        if let Some(acks) = f.acks {
+            attributes.push(KeyVal {
+                key: "acks".to_string(),
+                val: acks.to_string(),
+            });
+        }
         let log = L7ProtocolSendLog {
             req_len: f.req_msg_size,
             resp_len: f.resp_msg_size,
             req: L7Request {
                 req_type: String::from(command_str),
+                resource: f.topic_name,
+                domain: f.client_id,
                 ..Default::default()
             },
             resp: L7Response {
@@ -233,8 +359,19 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
                 code: f.status_code,
                 ..Default::default()
             },
+            trace_info: Some(TraceInfo {
+                trace_id: Some(f.trace_id),
+                span_id: Some(f.span_id),
+                parent_span_id: Some(f.parent_span_id),
+                ..Default::default()
+            }),
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.correlation_id),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -243,9 +380,248 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
+// Kafka 消息的读取，flexible 版本中字符串、数组等使用 compact 编码
+// ==============================================================
+// reader of Kafka messages, strings and arrays are compact encoded in flexible versions
+struct KafkaReader<'a> {
+    payload: &'a [u8],
+    offset: usize,
+    flexible: bool,
+}
+
+impl<'a> KafkaReader<'a> {
+    fn new(payload: &'a [u8], offset: usize, flexible: bool) -> Self {
+        Self {
+            payload,
+            offset,
+            flexible,
+        }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        if self.offset + size > self.payload.len() {
+            return Err(Error::KafkaLogParseFailed);
+        }
+        let data = &self.payload[self.offset..self.offset + size];
+        self.offset += size;
+        Ok(data)
+    }
+
+    fn skip(&mut self, size: usize) -> Result<()> {
+        self.take(size).map(|_| ())
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        self.take(2).map(read_i16_be)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        self.take(4).map(|b| read_u32_be(b) as i32)
+    }
+
+    fn unsigned_varint(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let b = self.take(1)?[0];
+            value |= ((b & 0x7f) as u32) << (i * 7);
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::KafkaLogParseFailed)
+    }
+
+    // zigzag 编码的变长整数，用于 record batch 中的消息
+    // ===============================================
+    // zigzag encoded variable-length integer, used by records in record batches
+    fn varint(&mut self) -> Result<i64> {
+        let mut value = 0u64;
+        for i in 0..10 {
+            let b = self.take(1)?[0];
+            value |= ((b & 0x7f) as u64) << (i * 7);
+            if b & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err(Error::KafkaLogParseFailed)
+    }
+
+    fn varint_bytes(&mut self) -> Result<&'a [u8]> {
+        match self.varint()? {
+            len if len > 0 => self.take(len as usize),
+            _ => Ok(&[]),
+        }
+    }
+
+    // 返回 None 表示 null
+    // ==================
+    // None means null
+    fn length(&mut self) -> Result<Option<usize>> {
+        if self.flexible {
+            Ok(self
+                .unsigned_varint()?
+                .checked_sub(1)
+                .map(|len| len as usize))
+        } else {
+            let len = self.i32()?;
+            Ok((len >= 0).then(|| len as usize))
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = if self.flexible {
+            self.length()?
+        } else {
+            let len = self.i16()?;
+            (len >= 0).then(|| len as usize)
+        };
+        match len {
+            Some(len) => Ok(String::from_utf8_lossy(self.take(len)?).into_owned()),
+            None => Ok(String::new()),
+        }
+    }
+
+    fn array_len(&mut self) -> Result<usize> {
+        self.length().map(|len| len.unwrap_or_default())
+    }
+
+    // 记录可能因为 l7_log_packet_size 被截断，返回已有的部分
+    // ===================================================
+    // records may be truncated by l7_log_packet_size, the available part is returned
+    fn records(&mut self) -> Result<&'a [u8]> {
+        let len = self.length()?.unwrap_or_default();
+        let start = self.offset.min(self.payload.len());
+        let end = (self.offset + len).min(self.payload.len());
+        self.offset += len;
+        Ok(&self.payload[start..end])
+    }
+
+    fn topic(&mut self, version: u16) -> Result<String> {
+        if version >= KAFKA_TOPIC_ID_VERSION {
+            let id = self.take(KAFKA_TOPIC_ID_LEN)?;
+            Ok(base64::encode_config(id, base64::URL_SAFE_NO_PAD))
+        } else {
+            self.string()
+        }
+    }
+
+    fn record_headers(&mut self, headers: &mut Vec<(&'a [u8], &'a [u8])>) -> Result<()> {
+        let length = self.varint()?;
+        let end = self.offset + length.max(0) as usize;
+        // attributes, timestampDelta, offsetDelta
+        self.skip(1)?;
+        self.varint()?;
+        self.varint()?;
+        // key, value
+        self.varint_bytes()?;
+        self.varint_bytes()?;
+        for _ in 0..self.varint()? {
+            let key = self.varint_bytes()?;
+            let value = self.varint_bytes()?;
+            headers.push((key, value));
+        }
+        self.offset = end;
+        Ok(())
+    }
+
+    fn tagged_fields(&mut self) -> Result<()> {
+        if !self.flexible {
+            return Ok(());
+        }
+        for _ in 0..self.unsigned_varint()? {
+            self.unsigned_varint()?;
+            let size = self.unsigned_varint()? as usize;
+            self.skip(size)?;
+        }
+        Ok(())
+    }
+}
+
+// 统计 record batch (magic 2) 或旧版 message set 中的消息数
+// ======================================================
+// counts the messages in record batches (magic 2) or legacy message sets
+fn record_count(records: &[u8]) -> u32 {
+    let mut count = 0;
+    let mut offset = 0;
+    while offset + KAFKA_RECORD_BATCH_MAGIC_OFFSET < records.len() {
+        let size = read_u32_be(&records[offset + 8..]) as usize;
+        if records[offset + KAFKA_RECORD_BATCH_MAGIC_OFFSET] >= 2 {
+            if offset + KAFKA_RECORD_BATCH_HEADER_LEN > records.len() {
+                break;
+            }
+            count += read_u32_be(&records[offset + KAFKA_RECORD_BATCH_COUNT_OFFSET..]);
+        } else {
+            count += 1;
+        }
+        offset += KAFKA_LOG_OVERHEAD + size;
+    }
+    count
+}
+
+/*
+    未压缩的 record batch (magic 2) 中各条消息的 header，压缩的 batch 被跳过
+
+    Record => length attributes timestampDelta offsetDelta keyLength key valueLength value [headers]
+        length => VARINT
+        attributes => INT8
+        timestampDelta => VARLONG
+        offsetDelta => VARINT
+        keyLength, valueLength => VARINT
+        headers => headerKeyLength headerKey headerValueLength Value
+    ==========================================================================================
+    headers of the records in uncompressed record batches (magic 2), compressed batches are skipped
+*/
+fn record_headers(records: &[u8]) -> Vec<Vec<(&[u8], &[u8])>> {
+    let mut headers = vec![];
+    let mut offset = 0;
+    while offset + KAFKA_RECORD_BATCH_HEADER_LEN <= records.len() {
+        let size = read_u32_be(&records[offset + 8..]) as usize;
+        let next = offset + KAFKA_LOG_OVERHEAD + size;
+        if records[offset + KAFKA_RECORD_BATCH_MAGIC_OFFSET] < 2
+            || read_u16_be(&records[offset + KAFKA_RECORD_BATCH_ATTRIBUTES_OFFSET..])
+                & KAFKA_RECORD_BATCH_COMPRESSION_MASK
+                != 0
+        {
+            offset = next;
+            continue;
+        }
+        let count = read_u32_be(&records[offset + KAFKA_RECORD_BATCH_COUNT_OFFSET..]);
+        let mut reader = KafkaReader::new(
+            &records[..next.min(records.len())],
+            offset + KAFKA_RECORD_BATCH_HEADER_LEN,
+            false,
+        );
+        for _ in 0..count {
+            headers.push(vec![]);
+            if reader.record_headers(headers.last_mut().unwrap()).is_err() {
+                // 消息被截断
+                // ==========
+                // the record is truncated
+                return headers;
+            }
+        }
+        offset = next;
+    }
+    headers
+}
+
+#[derive(Debug, Serialize)]
 pub struct KafkaLog {
     info: KafkaInfo,
+    // <correlation_id, (api_key, api_version)>，用于解析应答
+    // ===================================================
+    // <correlation_id, (api_key, api_version)>, used to parse responses
+    #[serde(skip)]
+    sessions: LruCache<u32, (u16, u16)>,
+}
+
+impl Default for KafkaLog {
+    fn default() -> Self {
+        Self {
+            info: KafkaInfo::default(),
+            sessions: LruCache::new(KAFKA_SESSION_CACHE_SIZE),
+        }
+    }
 }
 
 impl L7ProtocolParserInterface for KafkaLog {
@@ -260,6 +636,7 @@ impl L7ProtocolParserInterface for KafkaLog {
         parse_common!(self, param);
         Self::parse(
             self,
+            param.parse_config,
             payload,
             param.l4_protocol,
             param.direction,
@@ -278,7 +655,7 @@ impl L7ProtocolParserInterface for KafkaLog {
     }
 
     fn reset(&mut self) {
-        *self = Self::default();
+        self.info = KafkaInfo::default();
         self.info.status = L7ResponseStatus::NotExist;
     }
 }
@@ -297,6 +674,10 @@ impl KafkaLog {
         self.info.api_version = 0;
         self.info.api_key = 0;
         self.info.client_id = String::new();
+        self.info.topic_name = String::new();
+        self.info.partitions = vec![];
+        self.info.record_count = None;
+        self.info.acks = None;
         self.info.resp_msg_size = None;
         self.info.status = L7ResponseStatus::Ok;
         self.info.status_code = None;
@@ -306,7 +687,12 @@ impl KafkaLog {
     // ================================================================================
     // The protocol identification is strictly checked to avoid misidentification.
     // The log analysis is not strictly checked because there may be length truncation
-    fn request(&mut self, payload: &[u8], strict: bool) -> Result<AppProtoHead> {
+    fn request(
+        &mut self,
+        config: Option<&LogParserConfig>,
+        payload: &[u8],
+        strict: bool,
+    ) -> Result<AppProtoHead> {
         let req_len = read_u32_be(payload);
         self.info.req_msg_size = Some(req_len);
         let client_id_len = read_u16_be(&payload[12..]) as usize;
@@ -329,6 +715,25 @@ impl KafkaLog {
             return Err(Error::KafkaLogParseFailed);
         }
 
+        if !strict {
+            self.sessions.put(
+                self.info.correlation_id,
+                (self.info.api_key, self.info.api_version),
+            );
+            let flexible = self.is_flexible();
+            let mut reader = KafkaReader::new(payload, 14 + client_id_len, flexible);
+            // 请求体可能被截断，忽略错误并保留已解析的部分
+            // ============================================
+            // the body may be truncated, errors are ignored and the parsed part is kept
+            let _ = reader
+                .tagged_fields()
+                .and_then(|_| match self.info.api_key {
+                    KAFKA_PRODUCE => self.produce_request(config, &mut reader),
+                    KAFKA_FETCH => self.fetch_request(&mut reader),
+                    _ => Ok(()),
+                });
+        }
+
         Ok(AppProtoHead {
             proto: L7Protocol::Kafka,
             msg_type: self.info.msg_type,
@@ -337,13 +742,30 @@ impl KafkaLog {
         })
     }
 
-    fn response(&mut self, payload: &[u8]) -> Result<AppProtoHead> {
+    fn response(
+        &mut self,
+        config: Option<&LogParserConfig>,
+        payload: &[u8],
+    ) -> Result<AppProtoHead> {
         self.info.resp_msg_size = Some(read_u32_be(payload));
         self.info.correlation_id = read_u32_be(&payload[4..]);
         self.info.msg_type = LogMessageType::Response;
         if payload.len() >= 14 {
             self.info.resp_data = Some(payload[..14].try_into().unwrap());
         }
+        if let Some((api_key, api_version)) = self.sessions.pop(&self.info.correlation_id) {
+            self.info.api_key = api_key;
+            self.info.api_version = api_version;
+            let flexible = self.is_flexible();
+            let mut reader = KafkaReader::new(payload, 8, flexible);
+            let _ = reader
+                .tagged_fields()
+                .and_then(|_| match self.info.api_key {
+                    KAFKA_PRODUCE => self.produce_response(&mut reader),
+                    KAFKA_FETCH => self.fetch_response(config, &mut reader),
+                    _ => Ok(()),
+                });
+        }
         Ok(AppProtoHead {
             proto: L7Protocol::Kafka,
             msg_type: self.info.msg_type,
@@ -351,6 +773,238 @@ impl KafkaLog {
         })
     }
 
+    fn is_flexible(&self) -> bool {
+        match self.info.api_key {
+            KAFKA_PRODUCE => self.info.api_version >= KAFKA_PRODUCE_FLEXIBLE_VERSION,
+            KAFKA_FETCH => self.info.api_version >= KAFKA_FETCH_FLEXIBLE_VERSION,
+            _ => false,
+        }
+    }
+
+    /*
+        Produce Request (Version: 9) => transactional_id acks timeout_ms [topic_data] TAG_BUFFER
+            transactional_id => COMPACT_NULLABLE_STRING
+            acks => INT16
+            timeout_ms => INT32
+            topic_data => name [partition_data] TAG_BUFFER
+                name => COMPACT_STRING
+                partition_data => index records TAG_BUFFER
+                    index => INT32
+                    records => COMPACT_RECORDS
+    */
+    fn produce_request(
+        &mut self,
+        config: Option<&LogParserConfig>,
+        reader: &mut KafkaReader,
+    ) -> Result<()> {
+        let version = self.info.api_version;
+        if version >= 3 {
+            // transactional_id
+            reader.string()?;
+        }
+        self.info.acks = Some(reader.i16()?);
+        // timeout_ms
+        reader.skip(4)?;
+        for _ in 0..reader.array_len()? {
+            let topic = reader.topic(version)?;
+            for _ in 0..reader.array_len()? {
+                let partition = reader.i32()?;
+                self.info.add_partition(&topic, partition);
+                let records = reader.records()?;
+                *self.info.record_count.get_or_insert(0) += record_count(records);
+                if let Some(config) = config {
+                    self.fill_trace_info(records, &config.l7_log_dynamic);
+                }
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        Ok(())
+    }
+
+    /*
+        Produce Response (Version: 9) => [responses] throttle_time_ms TAG_BUFFER
+            responses => name [partition_responses] TAG_BUFFER
+                name => COMPACT_STRING
+                partition_responses => index error_code base_offset log_append_time_ms log_start_offset [record_errors] error_message TAG_BUFFER
+    */
+    fn produce_response(&mut self, reader: &mut KafkaReader) -> Result<()> {
+        let version = self.info.api_version;
+        for _ in 0..reader.array_len()? {
+            let topic = reader.topic(version)?;
+            for _ in 0..reader.array_len()? {
+                let partition = reader.i32()?;
+                self.info.add_partition(&topic, partition);
+                self.info.add_error_code(reader.i16()?);
+                // base_offset
+                reader.skip(8)?;
+                if version >= 2 {
+                    // log_append_time_ms
+                    reader.skip(8)?;
+                }
+                if version >= 5 {
+                    // log_start_offset
+                    reader.skip(8)?;
+                }
+                if version >= 8 {
+                    for _ in 0..reader.array_len()? {
+                        // batch_index, batch_index_error_message
+                        reader.skip(4)?;
+                        reader.string()?;
+                        reader.tagged_fields()?;
+                    }
+                    // error_message
+                    reader.string()?;
+                }
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        Ok(())
+    }
+
+    /*
+        Fetch Request (Version: 12) => replica_id max_wait_ms min_bytes max_bytes isolation_level session_id session_epoch [topics] [forgotten_topics_data] rack_id TAG_BUFFER
+            topics => topic [partitions] TAG_BUFFER
+                topic => COMPACT_STRING
+                partitions => partition current_leader_epoch fetch_offset last_fetched_epoch log_start_offset partition_max_bytes TAG_BUFFER
+    */
+    fn fetch_request(&mut self, reader: &mut KafkaReader) -> Result<()> {
+        let version = self.info.api_version;
+        if version < 15 {
+            // replica_id
+            reader.skip(4)?;
+        }
+        // max_wait_ms, min_bytes
+        reader.skip(8)?;
+        if version >= 3 {
+            // max_bytes
+            reader.skip(4)?;
+        }
+        if version >= 4 {
+            // isolation_level
+            reader.skip(1)?;
+        }
+        if version >= 7 {
+            // session_id, session_epoch
+            reader.skip(8)?;
+        }
+        for _ in 0..reader.array_len()? {
+            let topic = reader.topic(version)?;
+            for _ in 0..reader.array_len()? {
+                let partition = reader.i32()?;
+                self.info.add_partition(&topic, partition);
+                if version >= 9 {
+                    // current_leader_epoch
+                    reader.skip(4)?;
+                }
+                // fetch_offset
+                reader.skip(8)?;
+                if version >= 12 {
+                    // last_fetched_epoch
+                    reader.skip(4)?;
+                }
+                if version >= 5 {
+                    // log_start_offset
+                    reader.skip(8)?;
+                }
+                // partition_max_bytes
+                reader.skip(4)?;
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        Ok(())
+    }
+
+    /*
+        Fetch Response (Version: 12) => throttle_time_ms error_code session_id [responses] TAG_BUFFER
+            responses => topic [partitions] TAG_BUFFER
+                topic => COMPACT_STRING
+                partitions => partition_index error_code high_watermark last_stable_offset log_start_offset [aborted_transactions] preferred_read_replica records TAG_BUFFER
+    */
+    fn fetch_response(
+        &mut self,
+        config: Option<&LogParserConfig>,
+        reader: &mut KafkaReader,
+    ) -> Result<()> {
+        let version = self.info.api_version;
+        if version >= 1 {
+            // throttle_time_ms
+            reader.skip(4)?;
+        }
+        if version >= 7 {
+            self.info.add_error_code(reader.i16()?);
+            // session_id
+            reader.skip(4)?;
+        }
+        for _ in 0..reader.array_len()? {
+            let topic = reader.topic(version)?;
+            for _ in 0..reader.array_len()? {
+                let partition = reader.i32()?;
+                self.info.add_partition(&topic, partition);
+                self.info.add_error_code(reader.i16()?);
+                // high_watermark
+                reader.skip(8)?;
+                if version >= 4 {
+                    // last_stable_offset
+                    reader.skip(8)?;
+                    if version >= 5 {
+                        // log_start_offset
+                        reader.skip(8)?;
+                    }
+                    for _ in 0..reader.array_len()? {
+                        // producer_id, first_offset
+                        reader.skip(16)?;
+                        reader.tagged_fields()?;
+                    }
+                }
+                if version >= 11 {
+                    // preferred_read_replica
+                    reader.skip(4)?;
+                }
+                let records = reader.records()?;
+                *self.info.record_count.get_or_insert(0) += record_count(records);
+                if let Some(config) = config {
+                    self.fill_trace_info(records, &config.l7_log_dynamic);
+                }
+                reader.tagged_fields()?;
+            }
+            reader.tagged_fields()?;
+        }
+        Ok(())
+    }
+
+    /*
+        trace 信息通过消息的 header 透传，与 HTTP 头相同，按 trace 类型配置的优先级解析，
+        一个 batch 中有多条消息时使用第一个带有 trace 信息的消息
+        ======================================================================
+        trace info is carried in record headers, and decoded in the priority order of the
+        trace type config the same way as HTTP headers, the first record with trace info
+        is used if there are multiple records in a batch
+    */
+    fn fill_trace_info(&mut self, records: &[u8], config: &L7LogDynamicConfig) {
+        if !self.info.trace_id.is_empty() || !self.info.span_id.is_empty() {
+            return;
+        }
+        for headers in record_headers(records) {
+            let mut trace = TraceContext::default();
+            for (key, val) in headers {
+                let (Ok(key), Ok(val)) = (std::str::from_utf8(key), std::str::from_utf8(val))
+                else {
+                    continue;
+                };
+                trace.on_header(config, &key.to_ascii_lowercase(), val);
+            }
+            if !trace.trace_id.is_empty() || !trace.span_id.is_empty() {
+                self.info.trace_id = trace.trace_id;
+                self.info.span_id = trace.span_id;
+                self.info.parent_span_id = trace.parent_span_id;
+                return;
+            }
+        }
+    }
+
     pub fn kafka_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
         if param.l4_protocol != IpProtocol::Tcp {
             return false;
@@ -361,7 +1015,7 @@ impl KafkaLog {
         }
         let mut kafka = KafkaLog::default();
 
-        let ret = kafka.request(payload, true);
+        let ret = kafka.request(None, payload, true);
         if ret.is_err() {
             return false;
         }
@@ -370,6 +1024,7 @@ impl KafkaLog {
 
     fn parse(
         &mut self,
+        config: Option<&LogParserConfig>,
         payload: &[u8],
         proto: IpProtocol,
         direction: PacketDirection,
@@ -383,8 +1038,8 @@ impl KafkaLog {
             return Err(Error::KafkaLogParseFailed);
         }
         match direction {
-            PacketDirection::ClientToServer => self.request(payload, false),
-            PacketDirection::ServerToClient => self.response(payload),
+            PacketDirection::ClientToServer => self.request(config, payload, false),
+            PacketDirection::ServerToClient => self.response(config, payload),
         }?;
         Ok(())
     }
@@ -397,8 +1052,11 @@ mod tests {
 
     use super::*;
 
+    use std::time::Duration;
+
     use crate::{
         common::{flow::PacketDirection, MetaPacket},
+        config::handler::TraceType,
         utils::test::Capture,
     };
 
@@ -426,6 +1084,7 @@ mod tests {
 
             let mut kafka = KafkaLog::default();
             let _ = kafka.parse(
+                None,
                 payload,
                 packet.lookup_key.proto,
                 packet.lookup_key.direction,
@@ -459,4 +1118,231 @@ mod tests {
             }
         }
     }
+
+    fn parse_flow(name: &str, config: Option<&LogParserConfig>) -> Vec<KafkaInfo> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let mut kafka = KafkaLog::new();
+        let mut infos = vec![];
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let payload = match packet.get_l4_payload() {
+                Some(p) => p,
+                None => continue,
+            };
+            kafka
+                .parse(
+                    config,
+                    payload,
+                    packet.lookup_key.proto,
+                    packet.lookup_key.direction,
+                    None,
+                    None,
+                )
+                .unwrap();
+            infos.push(kafka.info.clone());
+            kafka.reset();
+        }
+        infos
+    }
+
+    #[test]
+    fn produce() {
+        let infos = parse_flow("kafka_produce.pcap", None);
+        assert_eq!(infos.len(), 4);
+
+        // flexible 版本
+        // ============
+        // flexible version
+        let (req, resp) = (&infos[0], &infos[1]);
+        assert_eq!(req.client_id, "producer-1");
+        assert_eq!(req.topic_name, "orders");
+        assert_eq!(
+            req.partitions,
+            vec![("orders".to_string(), 0), ("orders".to_string(), 1)]
+        );
+        assert_eq!(req.acks, Some(-1));
+        assert_eq!(req.record_count, Some(5));
+        assert_eq!(resp.status_code, Some(6));
+        assert_eq!(resp.status, L7ResponseStatus::ServerError);
+
+        let mut merged = req.clone();
+        merged.merge(resp.clone());
+        let log = L7ProtocolSendLog::from(merged);
+        assert_eq!(log.req.resource, "orders");
+        assert_eq!(log.req.domain, "producer-1");
+        assert_eq!(log.resp.code, Some(6));
+        let attributes = log
+            .ext_info
+            .unwrap()
+            .attributes
+            .unwrap()
+            .into_iter()
+            .map(|kv| (kv.key, kv.val))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attributes,
+            vec![
+                ("partitions".to_string(), "orders-0,orders-1".to_string()),
+                ("record_count".to_string(), "5".to_string()),
+                ("acks".to_string(), "-1".to_string()),
+            ]
+        );
+
+        let (req, resp) = (&infos[2], &infos[3]);
+        assert_eq!(req.topic_name, "payments");
+        assert_eq!(req.partitions, vec![("payments".to_string(), 3)]);
+        assert_eq!(req.acks, Some(1));
+        assert_eq!(req.record_count, Some(1));
+        assert_eq!(resp.status_code, Some(0));
+        assert_eq!(resp.status, L7ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn fetch() {
+        let infos = parse_flow("kafka_fetch_v11.pcap", None);
+        let (req, resp) = (&infos[0], &infos[1]);
+        assert_eq!(req.client_id, "consumer-1");
+        assert_eq!(req.topic_name, "orders");
+        assert_eq!(
+            req.partitions,
+            vec![("orders".to_string(), 0), ("orders".to_string(), 1)]
+        );
+        assert_eq!(req.acks, None);
+        assert_eq!(resp.record_count, Some(2));
+        assert_eq!(resp.status_code, Some(1));
+        assert_eq!(resp.status, L7ResponseStatus::ServerError);
+
+        // 增量 fetch 会话中没有 topic
+        // ==========================
+        // no topics in incremental fetch sessions
+        let infos = parse_flow("kafka_fetch.pcap", None);
+        assert!(infos[0].topic_name.is_empty());
+        assert_eq!(infos[1].status_code, Some(0));
+    }
+
+    #[test]
+    fn trace_headers() {
+        let config = LogParserConfig {
+            l7_log_collect_nps_threshold: 0,
+            l7_log_session_aggr_timeout: Duration::from_secs(120),
+            l7_log_dynamic: L7LogDynamicConfig::new(
+                "".to_owned(),
+                "".to_owned(),
+                vec![TraceType::TraceParent, TraceType::Sw8],
+                vec![TraceType::TraceParent, TraceType::Sw8],
+            ),
+        };
+
+        let infos = parse_flow("kafka_produce.pcap", Some(&config));
+        assert_eq!(infos[0].trace_id, "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(infos[0].span_id, "b7ad6b7169203331");
+        assert_eq!(infos[2].trace_id, "a1b2c3d4e5f6.1.17000000000000001");
+        assert_eq!(infos[2].span_id, "a1b2c3d4e5f6.1.17000000000000002-3");
+
+        let infos = parse_flow("kafka_fetch_v11.pcap", Some(&config));
+        assert!(infos[0].trace_id.is_empty());
+        let mut merged = infos[0].clone();
+        merged.merge(infos[1].clone());
+        let trace_info = L7ProtocolSendLog::from(merged).trace_info.unwrap();
+        assert_eq!(
+            trace_info.trace_id.as_deref(),
+            Some("0af7651916cd43dd8448eb211c80319c")
+        );
+        assert_eq!(trace_info.span_id.as_deref(), Some("b7ad6b7169203331"));
+
+        // 未配置时不解析
+        // ============
+        // not parsed without config
+        let infos = parse_flow("kafka_produce.pcap", None);
+        assert!(infos[0].trace_id.is_empty());
+    }
+
+    // 构造一个未压缩的 record batch，每条消息带有给定的 header
+    fn record_batch(records: &[&[(&str, &str)]]) -> Vec<u8> {
+        fn varint(v: i64, out: &mut Vec<u8>) {
+            let mut v = ((v << 1) ^ (v >> 63)) as u64;
+            while v >= 0x80 {
+                out.push(v as u8 | 0x80);
+                v >>= 7;
+            }
+            out.push(v as u8);
+        }
+        let mut body = vec![];
+        for headers in records {
+            let mut record = vec![0];
+            varint(0, &mut record);
+            varint(0, &mut record);
+            varint(-1, &mut record);
+            varint(-1, &mut record);
+            varint(headers.len() as i64, &mut record);
+            for (key, val) in headers.iter() {
+                varint(key.len() as i64, &mut record);
+                record.extend_from_slice(key.as_bytes());
+                varint(val.len() as i64, &mut record);
+                record.extend_from_slice(val.as_bytes());
+            }
+            varint(record.len() as i64, &mut body);
+            body.extend_from_slice(&record);
+        }
+        let mut batch = vec![0; KAFKA_RECORD_BATCH_HEADER_LEN];
+        let size = (KAFKA_RECORD_BATCH_HEADER_LEN - KAFKA_LOG_OVERHEAD + body.len()) as u32;
+        batch[8..12].copy_from_slice(&size.to_be_bytes());
+        batch[KAFKA_RECORD_BATCH_MAGIC_OFFSET] = 2;
+        batch[KAFKA_RECORD_BATCH_COUNT_OFFSET..]
+            .copy_from_slice(&(records.len() as u32).to_be_bytes());
+        batch.extend_from_slice(&body);
+        batch
+    }
+
+    #[test]
+    fn trace_header_priority() {
+        let batch = record_batch(&[
+            &[
+                ("X-B3-SpanId", "a2fb4a1d1a96d312"),
+                (
+                    "uber-trace-id",
+                    "5b8aa5a2d2c872e8:196cb4ec8d4d2ab6:3c2a7a9e0d1b4f21:1",
+                ),
+                ("X-B3-ParentSpanId", "0020000000000001"),
+            ],
+            &[(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )],
+        ]);
+
+        let config = L7LogDynamicConfig::new(
+            "".to_owned(),
+            "".to_owned(),
+            vec![TraceType::TraceParent, TraceType::Uber],
+            vec![TraceType::Uber, TraceType::XB3Span],
+        );
+        let mut kafka = KafkaLog::default();
+        kafka.fill_trace_info(&batch, &config);
+        assert_eq!(kafka.info.trace_id, "5b8aa5a2d2c872e8");
+        assert_eq!(kafka.info.span_id, "196cb4ec8d4d2ab6");
+        assert_eq!(kafka.info.parent_span_id, "3c2a7a9e0d1b4f21");
+
+        let config = L7LogDynamicConfig::new(
+            "".to_owned(),
+            "".to_owned(),
+            vec![TraceType::Uber],
+            vec![TraceType::XB3Span, TraceType::Uber],
+        );
+        let mut kafka = KafkaLog::default();
+        kafka.fill_trace_info(&batch, &config);
+        assert_eq!(kafka.info.span_id, "a2fb4a1d1a96d312");
+        assert_eq!(kafka.info.parent_span_id, "0020000000000001");
+        let trace_info = L7ProtocolSendLog::from(kafka.info).trace_info.unwrap();
+        assert_eq!(
+            trace_info.parent_span_id.as_deref(),
+            Some("0020000000000001")
+        );
+    }
 }
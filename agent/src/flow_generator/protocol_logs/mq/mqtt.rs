@@ -14,7 +14,7 @@
  * limitations under the License.
  */
 
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, mem};
 
 use log::{debug, warn};
 use nom::{
@@ -36,7 +36,7 @@ use crate::{
     flow_generator::{
         error::{Error, Result},
         protocol_logs::{
-            pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
             value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
         },
     },
@@ -70,8 +70,18 @@ pub struct MqttInfo {
     #[serde(skip)]
     pub publish_topic: Option<String>,
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
-    pub code: Option<i32>, // connect_ack packet return code
+    pub code: Option<i32>, // connect_ack packet return code, or MQTT 5.0 reason code
     pub status: L7ResponseStatus,
+
+    // MQTT 5.0 properties
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_data: Option<String>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub user_properties: Vec<(String, String)>,
+    #[serde(rename = "response_exception", skip_serializing_if = "Option::is_none")]
+    pub reason_string: Option<String>,
 }
 
 impl L7ProtocolInfoInterface for MqttInfo {
@@ -126,6 +136,10 @@ impl Default for MqttInfo {
             publish_topic: None,
             code: None,
             status: L7ResponseStatus::Ok,
+            response_topic: None,
+            correlation_data: None,
+            user_properties: vec![],
+            reason_string: None,
             start_time: 0,
             end_time: 0,
             msg_type: LogMessageType::Other,
@@ -144,6 +158,16 @@ impl MqttInfo {
         if self.code.is_none() {
             self.code = other.code;
         }
+        if self.response_topic.is_none() {
+            self.response_topic = other.response_topic;
+        }
+        if self.correlation_data.is_none() {
+            self.correlation_data = other.correlation_data;
+        }
+        if self.reason_string.is_none() {
+            self.reason_string = other.reason_string;
+        }
+        self.user_properties.extend(other.user_properties);
         match other.pkt_type {
             PacketKind::Publish { .. } => {
                 self.publish_topic = other.publish_topic;
@@ -155,6 +179,13 @@ impl MqttInfo {
         }
     }
 
+    fn set_properties(&mut self, properties: Properties) {
+        self.response_topic = properties.response_topic;
+        self.correlation_data = properties.correlation_data;
+        self.reason_string = properties.reason_string;
+        self.user_properties = properties.user_properties;
+    }
+
     pub fn get_version_str(&self) -> &'static str {
         match self.version {
             3 => "3.1",
@@ -187,6 +218,22 @@ impl From<MqttInfo> for L7ProtocolSendLog {
             }
             _ => {}
         };
+        let mut attributes = vec![];
+        if let Some(topic) = f.response_topic {
+            attributes.push(KeyVal {
+                key: "response_topic".to_string(),
+                val: topic,
+            });
+        }
+        if let Some(data) = f.correlation_data {
+            attributes.push(KeyVal {
+                key: "correlation_data".to_string(),
+                val: data,
+            });
+        }
+        for (key, val) in f.user_properties {
+            attributes.push(KeyVal { key, val });
+        }
         let log = L7ProtocolSendLog {
             version: version,
             req_len: f.req_msg_size,
@@ -200,8 +247,17 @@ impl From<MqttInfo> for L7ProtocolSendLog {
             resp: L7Response {
                 status: f.status,
                 code: f.code,
+                exception: f.reason_string.unwrap_or_default(),
                 ..Default::default()
             },
+            ext_info: if attributes.is_empty() {
+                None
+            } else {
+                Some(ExtendedInfo {
+                    attributes: Some(attributes),
+                    ..Default::default()
+                })
+            },
             ..Default::default()
         };
         return log;
@@ -215,6 +271,9 @@ pub struct MqttLog {
     status: L7ResponseStatus,
     version: u8,
     client_map: HashMap<u64, String>,
+    // MQTT 5.0 每个方向的主题别名在整个连接中有效
+    client_topic_aliases: HashMap<u16, String>,
+    server_topic_aliases: HashMap<u16, String>,
 
     start_time: u64,
     end_time: u64,
@@ -252,28 +311,37 @@ impl L7ProtocolParserInterface for MqttLog {
     fn reset(&mut self) {
         let mut s = Self::default();
         s.version = self.version;
+        s.client_topic_aliases = mem::take(&mut self.client_topic_aliases);
+        s.server_topic_aliases = mem::take(&mut self.server_topic_aliases);
         *self = s;
     }
 }
 
 impl MqttLog {
-    fn parse_mqtt_info(&mut self, mut payload: &[u8]) -> Result<()> {
-        // 现在只支持MQTT 3.1.1解析，不支持v5.0
-        // Now only supports MQTT 3.1.1 parsing, not support v5.0
-        if self.version != 0 && self.version != 4 {
-            warn!("cannot parse packet, log parser only support to parse MQTT V3.1.1 packet");
+    fn parse_mqtt_info(&mut self, mut payload: &[u8], direction: PacketDirection) -> Result<()> {
+        // 现在只支持MQTT 3.1.1和5.0解析
+        // Now only supports MQTT 3.1.1 and 5.0 parsing
+        if self.version != 0 && self.version != 4 && self.version != 5 {
+            warn!(
+                "cannot parse packet, log parser only support to parse MQTT V3.1.1 and V5.0 packet"
+            );
             return Err(Error::MqttLogParseFailed);
         }
 
         loop {
             let (input, header) =
                 mqtt_fixed_header(payload).map_err(|_| Error::MqttLogParseFailed)?;
+            // 报文可能被截断
+            let body = &input[..input.len().min(header.remaining_length as usize)];
+            let is_v5 = self.version == 5;
             let mut info = MqttInfo::default();
+            // MQTT 5.0 响应中的原因码
+            let mut reason_code = None;
             match header.kind {
                 PacketKind::Connect => {
                     let data = bytes::complete::take(header.remaining_length as u32);
-                    let (_, (version, client_id)) = data
-                        .and_then(parse_connect_packet)
+                    let (_, (version, properties, client_id)) = data
+                        .and_then(parse_connect)
                         .parse(input)
                         .map_err(|_| Error::MqttLogParseFailed)?;
                     info.version = version;
@@ -281,46 +349,79 @@ impl MqttLog {
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = Some(header.remaining_length as u32);
                     info.pkt_type = header.kind;
+                    if let Some(p) = properties {
+                        info.set_properties(p);
+                    }
                     self.version = version;
                 }
                 PacketKind::Connack => {
-                    let (_, return_code) =
-                        parse_connack_packet(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (input, return_code) =
+                        parse_connack_packet(body).map_err(|_| Error::MqttLogParseFailed)?;
                     info.code = Some(return_code as i32);
                     info.version = self.version;
                     self.msg_type = LogMessageType::Response;
                     info.res_msg_size = Some(header.remaining_length as u32);
                     info.pkt_type = header.kind;
-                    self.status = parse_status_code(return_code);
+                    if is_v5 {
+                        if let Ok((_, p)) = mqtt_properties(input) {
+                            info.set_properties(p);
+                        }
+                        self.status = parse_reason_code(return_code);
+                    } else {
+                        self.status = parse_status_code(return_code);
+                    }
                 }
                 PacketKind::Publish { dup, qos, .. } => {
-                    let (_, topic_name) =
-                        mqtt_string(input).map_err(|_| Error::MqttLogParseFailed)?;
+                    let (input, topic_name) =
+                        mqtt_string(body).map_err(|_| Error::MqttLogParseFailed)?;
                     if dup && qos == QualityOfService::AtMostOnce {
                         debug!("mqtt publish packet has invalid dup flags={}", dup);
                         return Err(Error::MqttLogParseFailed);
                     }
                     // QOS=1,2会有报文标识符
                     // QOS=1,2 there will be a message identifier
-                    if qos == QualityOfService::AtLeastOnce || qos == QualityOfService::ExactlyOnce
+                    let input = if qos == QualityOfService::AtLeastOnce
+                        || qos == QualityOfService::ExactlyOnce
                     {
                         self.msg_type = LogMessageType::Request;
                         info.req_msg_size = Some(header.remaining_length as u32);
+                        input.get(2..).unwrap_or_default()
                     } else {
                         self.msg_type = LogMessageType::Response;
                         info.res_msg_size = Some(header.remaining_length as u32);
+                        input
                     };
-                    info.publish_topic.replace(topic_name.to_string());
+                    let mut topic_name = topic_name.to_string();
+                    if is_v5 {
+                        if let Ok((_, p)) = mqtt_properties(input) {
+                            if let Some(alias) = p.topic_alias {
+                                topic_name = self.resolve_topic_alias(direction, alias, topic_name);
+                            }
+                            info.set_properties(p);
+                        }
+                    }
+                    info.publish_topic.replace(topic_name);
                     info.pkt_type = header.kind;
                     info.version = self.version;
                 }
                 PacketKind::Subscribe => {
                     // 跳过解析报文标识符
                     // skip parsing packet identifier
-                    let (_, (_, result)) = mqtt_packet_identifier
-                        .and(mqtt_subscription_requests)
-                        .parse(input)
-                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let result = if is_v5 {
+                        let (_, ((_, p), result)) = mqtt_packet_identifier
+                            .and(mqtt_properties)
+                            .and(mqtt_subscription_requests_v5)
+                            .parse(body)
+                            .map_err(|_| Error::MqttLogParseFailed)?;
+                        info.set_properties(p);
+                        result
+                    } else {
+                        let (_, (_, result)) = mqtt_packet_identifier
+                            .and(mqtt_subscription_requests)
+                            .parse(input)
+                            .map_err(|_| Error::MqttLogParseFailed)?;
+                        result
+                    };
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = Some(header.remaining_length as u32);
                     info.pkt_type = header.kind;
@@ -336,10 +437,21 @@ impl MqttLog {
                     );
                 }
                 PacketKind::Unsubscribe => {
-                    let (_, (_, reqs)) = mqtt_packet_identifier
-                        .and(mqtt_unsubscription_requests)
-                        .parse(input)
-                        .map_err(|_| Error::MqttLogParseFailed)?;
+                    let reqs = if is_v5 {
+                        let (_, ((_, p), reqs)) = mqtt_packet_identifier
+                            .and(mqtt_properties)
+                            .and(mqtt_unsubscription_requests)
+                            .parse(body)
+                            .map_err(|_| Error::MqttLogParseFailed)?;
+                        info.set_properties(p);
+                        reqs
+                    } else {
+                        let (_, (_, reqs)) = mqtt_packet_identifier
+                            .and(mqtt_unsubscription_requests)
+                            .parse(input)
+                            .map_err(|_| Error::MqttLogParseFailed)?;
+                        reqs
+                    };
                     self.msg_type = LogMessageType::Request;
                     info.req_msg_size = Some(header.remaining_length as u32);
                     info.pkt_type = header.kind;
@@ -358,6 +470,9 @@ impl MqttLog {
                     info.version = self.version;
                     info.req_msg_size = Some(header.remaining_length as u32);
                     self.msg_type = LogMessageType::Request;
+                    if is_v5 && header.kind == PacketKind::Pubrel {
+                        reason_code = Self::parse_ack_reason(&mut info, body.get(2..));
+                    }
                 }
                 PacketKind::Suback
                 | PacketKind::Pingresp
@@ -369,16 +484,35 @@ impl MqttLog {
                     info.version = self.version;
                     self.msg_type = LogMessageType::Response;
                     info.res_msg_size = Some(header.remaining_length as u32);
+                    match header.kind {
+                        _ if !is_v5 => {}
+                        PacketKind::Suback | PacketKind::Unsuback => {
+                            reason_code = Self::parse_subscription_reasons(&mut info, body);
+                        }
+                        PacketKind::Pubcomp | PacketKind::Pubrec | PacketKind::Puback => {
+                            reason_code = Self::parse_ack_reason(&mut info, body.get(2..));
+                        }
+                        _ => {}
+                    }
                 }
-                PacketKind::Disconnect => {
+                PacketKind::Disconnect | PacketKind::Auth => {
                     info.pkt_type = header.kind;
                     self.msg_type = LogMessageType::Session;
                     info.res_msg_size = Some(header.remaining_length as u32);
                     info.version = self.version;
+                    if is_v5 {
+                        reason_code = Self::parse_ack_reason(&mut info, Some(body));
+                    }
                 }
             }
 
-            info.status = self.status;
+            info.status = match reason_code {
+                Some(code) => {
+                    info.code = Some(code as i32);
+                    parse_reason_code(code)
+                }
+                None => self.status,
+            };
             self.info.push(info);
 
             if input.len() <= header.remaining_length as usize {
@@ -393,6 +527,48 @@ impl MqttLog {
         Ok(())
     }
 
+    // 主题名为空时使用别名对应的主题名，否则更新别名
+    fn resolve_topic_alias(
+        &mut self,
+        direction: PacketDirection,
+        alias: u16,
+        topic_name: String,
+    ) -> String {
+        let aliases = match direction {
+            PacketDirection::ClientToServer => &mut self.client_topic_aliases,
+            PacketDirection::ServerToClient => &mut self.server_topic_aliases,
+        };
+        if topic_name.is_empty() {
+            aliases.get(&alias).cloned().unwrap_or_default()
+        } else {
+            aliases.insert(alias, topic_name.clone());
+            topic_name
+        }
+    }
+
+    // PUBACK、PUBREC、PUBREL、PUBCOMP、DISCONNECT、AUTH 的原因码和属性，剩余长度为 0 时表示成功
+    fn parse_ack_reason(info: &mut MqttInfo, input: Option<&[u8]>) -> Option<u8> {
+        let (_, (code, properties)) = mqtt_reason_code_and_properties(input?).ok()?;
+        if let Some(p) = properties {
+            info.set_properties(p);
+        }
+        code
+    }
+
+    // SUBACK 和 UNSUBACK 中每个主题过滤器对应一个原因码，有失败时返回第一个失败的原因码
+    fn parse_subscription_reasons(info: &mut MqttInfo, input: &[u8]) -> Option<u8> {
+        let (input, (_, p)) = mqtt_packet_identifier
+            .and(mqtt_properties)
+            .parse(input)
+            .ok()?;
+        info.set_properties(p);
+        input
+            .iter()
+            .find(|code| **code >= 0x80)
+            .or(input.first())
+            .copied()
+    }
+
     /// 尽力而为解析判断是否为mqtt报文, 因为"不依赖端口判断协议实现"要求首个请求包返回true，其他为false，
     /// 所以只判断是不是合法Connect包
     /// pest effort parsing to determine whether it is an mqtt packet, because "judging protocol implementation
@@ -427,7 +603,7 @@ impl MqttLog {
         &mut self,
         payload: &[u8],
         proto: IpProtocol,
-        _direction: PacketDirection,
+        direction: PacketDirection,
         _is_req_end: Option<bool>,
         _is_resp_end: Option<bool>,
     ) -> Result<()> {
@@ -437,7 +613,7 @@ impl MqttLog {
         self.status = L7ResponseStatus::Ok;
         self.info.clear();
 
-        self.parse_mqtt_info(payload).map_err(|e| {
+        self.parse_mqtt_info(payload, direction).map_err(|e| {
             self.status = L7ResponseStatus::Error;
             e
         })
@@ -470,6 +646,7 @@ pub enum PacketKind {
     Pingreq,
     Pingresp,
     Disconnect,
+    Auth,
 }
 
 impl fmt::Display for PacketKind {
@@ -489,6 +666,7 @@ impl fmt::Display for PacketKind {
             Self::Pingreq => write!(f, "PINGREQ"),
             Self::Pingresp => write!(f, "PINGRESP"),
             Self::Disconnect => write!(f, "DISCONNECT"),
+            Self::Auth => write!(f, "AUTH"),
         }
     }
 }
@@ -553,6 +731,7 @@ fn mqtt_packet_kind(input: &[u8]) -> IResult<&[u8], PacketKind> {
         (12, 0b0000) => (input, PacketKind::Pingreq),
         (13, 0b0000) => (input, PacketKind::Pingresp),
         (14, 0b0000) => (input, PacketKind::Disconnect),
+        (15, 0b0000) => (input, PacketKind::Auth),
         (inv_type, _) => {
             debug!(
                 "parse mqtt packet failed because get invalid type={}",
@@ -651,7 +830,109 @@ fn mqtt_string(input: &[u8]) -> IResult<&[u8], &str> {
     .parse(input)
 }
 
-pub fn parse_connect_packet(input: &[u8]) -> IResult<&[u8], (u8, &str)> {
+fn mqtt_binary(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    number::complete::be_u16
+        .flat_map(bytes::complete::take)
+        .parse(input)
+}
+
+// MQTT 5.0 属性，只保留需要展示的部分
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Properties {
+    pub topic_alias: Option<u16>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<String>,
+    pub reason_string: Option<String>,
+    pub user_properties: Vec<(String, String)>,
+}
+
+fn mqtt_properties(input: &[u8]) -> IResult<&[u8], Properties> {
+    let (rest, data) = decode_variable_length
+        .flat_map(bytes::complete::take)
+        .parse(input)?;
+
+    let mut properties = Properties::default();
+    let mut input = data;
+    while !input.is_empty() {
+        let (next, id) = decode_variable_length(input)?;
+        input = match id {
+            // byte
+            0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2a => number::complete::u8(next)?.0,
+            // two byte integer
+            0x13 | 0x21 | 0x22 => number::complete::be_u16(next)?.0,
+            // topic alias
+            0x23 => {
+                let (next, alias) = number::complete::be_u16(next)?;
+                properties.topic_alias = Some(alias);
+                next
+            }
+            // four byte integer
+            0x02 | 0x11 | 0x18 | 0x27 => number::complete::be_u32(next)?.0,
+            // subscription identifier
+            0x0b => decode_variable_length(next)?.0,
+            // response topic
+            0x08 => {
+                let (next, topic) = mqtt_string(next)?;
+                properties.response_topic = Some(topic.to_string());
+                next
+            }
+            // reason string
+            0x1f => {
+                let (next, reason) = mqtt_string(next)?;
+                properties.reason_string = Some(reason.to_string());
+                next
+            }
+            // utf-8 string
+            0x03 | 0x12 | 0x15 | 0x1a | 0x1c => mqtt_string(next)?.0,
+            // correlation data
+            0x09 => {
+                let (next, data) = mqtt_binary(next)?;
+                properties.correlation_data = Some(match std::str::from_utf8(data) {
+                    Ok(s) => s.to_string(),
+                    Err(_) => data.iter().map(|b| format!("{:02x}", b)).collect(),
+                });
+                next
+            }
+            // authentication data
+            0x16 => mqtt_binary(next)?.0,
+            // user property
+            0x26 => {
+                let (next, (key, val)) = mqtt_string.and(mqtt_string).parse(next)?;
+                properties
+                    .user_properties
+                    .push((key.to_string(), val.to_string()));
+                next
+            }
+            _ => {
+                debug!("invalid mqtt property identifier: {}", id);
+                return Err(nom::Err::Error(error::Error::new(
+                    input,
+                    error::ErrorKind::MapRes,
+                )));
+            }
+        };
+    }
+
+    Ok((rest, properties))
+}
+
+// 剩余长度不足时原因码和属性可以省略
+// reason code and properties can be omitted if the remaining length is not enough
+fn mqtt_reason_code_and_properties(
+    input: &[u8],
+) -> IResult<&[u8], (Option<u8>, Option<Properties>)> {
+    if input.is_empty() {
+        return Ok((input, (None, None)));
+    }
+    let (input, code) = number::complete::u8(input)?;
+    if input.is_empty() {
+        return Ok((input, (Some(code), None)));
+    }
+    let (input, properties) = mqtt_properties(input)?;
+    Ok((input, (Some(code), Some(properties))))
+}
+
+fn parse_connect(input: &[u8]) -> IResult<&[u8], (u8, Option<Properties>, &str)> {
     let (input, protocol_name) = mqtt_string(input)?;
     if protocol_name != "MQTT" {
         debug!("invalid protocol name: {}", protocol_name);
@@ -662,9 +943,23 @@ pub fn parse_connect_packet(input: &[u8]) -> IResult<&[u8], (u8, &str)> {
     }
 
     let (input, protocol_level) = number::complete::u8(input)?;
-    let (input, _) = number::complete::be_u16(&input[1..])?;
+    // connect flags and keep alive
+    let (input, _) = number::complete::u8
+        .and(number::complete::be_u16)
+        .parse(input)?;
+    let (input, properties) = if protocol_level == 5 {
+        let (input, properties) = mqtt_properties(input)?;
+        (input, Some(properties))
+    } else {
+        (input, None)
+    };
     // Payload
     let (input, client_id) = mqtt_string(input)?;
+    Ok((input, (protocol_level, properties, client_id)))
+}
+
+pub fn parse_connect_packet(input: &[u8]) -> IResult<&[u8], (u8, &str)> {
+    let (input, (protocol_level, _, client_id)) = parse_connect(input)?;
     Ok((input, (protocol_level, client_id)))
 }
 
@@ -704,6 +999,32 @@ pub fn parse_status_code(code: u8) -> L7ResponseStatus {
     }
 }
 
+pub fn parse_reason_code(code: u8) -> L7ResponseStatus {
+    match code {
+        0x00..=0x7f => L7ResponseStatus::Ok,
+        /*
+        UnspecifiedError = 0x80,
+        ImplementationSpecificError = 0x83,
+        ServerUnavailable = 0x88,
+        ServerBusy = 0x89,
+        ServerShuttingDown = 0x8B,
+        AdministrativeAction = 0x98,
+        RetainNotSupported = 0x9A,
+        QoSNotSupported = 0x9B,
+        UseAnotherServer = 0x9C,
+        ServerMoved = 0x9D,
+        SharedSubscriptionsNotSupported = 0x9E,
+        MaximumConnectTime = 0xA0,
+        SubscriptionIdentifiersNotSupported = 0xA1,
+        WildcardSubscriptionsNotSupported = 0xA2,
+        */
+        0x80 | 0x83 | 0x88 | 0x89 | 0x8b | 0x98 | 0x9a..=0x9e | 0xa0..=0xa2 => {
+            L7ResponseStatus::ServerError
+        }
+        _ => L7ResponseStatus::ClientError,
+    }
+}
+
 fn mqtt_subscription_requests(input: &[u8]) -> IResult<&[u8], Vec<(&str, QualityOfService)>> {
     fn subscription_request(input: &[u8]) -> IResult<&[u8], (&str, QualityOfService)> {
         let (input, topic) = mqtt_string(input)?;
@@ -715,6 +1036,23 @@ fn mqtt_subscription_requests(input: &[u8]) -> IResult<&[u8], Vec<(&str, Quality
     Ok((input, count))
 }
 
+// MQTT 5.0 订阅选项的低两位是 QoS，其余为 No Local、Retain As Published 和 Retain Handling
+fn mqtt_subscription_requests_v5(input: &[u8]) -> IResult<&[u8], Vec<(&str, QualityOfService)>> {
+    fn subscription_request(input: &[u8]) -> IResult<&[u8], (&str, QualityOfService)> {
+        let (input, topic) = mqtt_string(input)?;
+        let (input, qos) = map_res(number::complete::u8, |options| {
+            if options & 0b1100_0000 != 0 {
+                return Err(options);
+            }
+            mqtt_quality_of_service(options & 0b11)
+        })
+        .parse(input)?;
+        Ok((input, (topic, qos)))
+    }
+
+    many1(subscription_request)(input)
+}
+
 fn mqtt_quality_of_service(lower: u8) -> Result<QualityOfService, u8> {
     match lower {
         0b00 => Ok(QualityOfService::AtMostOnce),
@@ -973,6 +1311,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_properties() {
+        let input = &[
+            19, // Properties length
+            0x23, 0, 1, // Topic Alias
+            0x08, 0, 1, b'r', // Response Topic
+            0x09, 0, 2, 0xAB, 0xCD, // Correlation Data
+            0x26, 0, 1, b'k', 0, 1, b'v', // User Property
+            0xFF,
+        ];
+
+        let (rest, properties) = mqtt_properties(input).unwrap();
+        assert_eq!(rest, &[0xFF]);
+        assert_eq!(
+            properties,
+            Properties {
+                topic_alias: Some(1),
+                response_topic: Some("r".to_string()),
+                correlation_data: Some("abcd".to_string()),
+                reason_string: None,
+                user_properties: vec![("k".to_string(), "v".to_string())],
+            }
+        );
+
+        mqtt_properties(&[2, 0x7F, 0]).unwrap_err();
+    }
+
+    #[test]
+    fn check_mqtt5_session() {
+        let packets: [(&[u8], PacketDirection); 5] = [
+            (
+                &[
+                    0x10, 20, 0, 4, b'M', b'Q', b'T', b'T', 5, // Level
+                    0x02, 0, 60, // Connect flags and Keep Alive
+                    5, 0x11, 0, 0, 0, 60, // Session Expiry Interval
+                    0, 2, b'c', b'1', // Client Identifier
+                ][..],
+                PacketDirection::ClientToServer,
+            ),
+            (&[0x20, 3, 0, 0x87, 0][..], PacketDirection::ServerToClient),
+            (
+                &[
+                    0x32, 18, 0, 3, b'a', b'/', b'b', 0, 1, // Topic and Packet Identifier
+                    // Topic Alias and User Property
+                    10, 0x23, 0, 7, 0x26, 0, 1, b'k', 0, 1, b'v',
+                ][..],
+                PacketDirection::ClientToServer,
+            ),
+            (
+                &[0x32, 8, 0, 0, 0, 2, 3, 0x23, 0, 7][..],
+                PacketDirection::ClientToServer,
+            ),
+            (&[0x40, 3, 0, 2, 0x87][..], PacketDirection::ServerToClient),
+        ];
+
+        let mut mqtt = MqttLog::default();
+        let mut infos = vec![];
+        for (payload, direction) in packets {
+            mqtt.parse(payload, IpProtocol::Tcp, direction, None, None)
+                .unwrap();
+            infos.extend(mqtt.info.drain(..));
+            mqtt.reset();
+        }
+
+        assert_eq!(infos[0].version, 5);
+        assert_eq!(infos[0].client_id.as_deref(), Some("c1"));
+        assert_eq!(infos[1].status, L7ResponseStatus::ClientError);
+        assert_eq!(infos[2].publish_topic.as_deref(), Some("a/b"));
+        assert_eq!(
+            infos[2].user_properties,
+            vec![("k".to_string(), "v".to_string())]
+        );
+        assert_eq!(infos[3].publish_topic.as_deref(), Some("a/b"));
+        assert_eq!(infos[4].code, Some(0x87));
+        assert_eq!(infos[4].status, L7ResponseStatus::ClientError);
+
+        assert_eq!(parse_reason_code(0x10), L7ResponseStatus::Ok);
+        assert_eq!(parse_reason_code(0x80), L7ResponseStatus::ServerError);
+        assert_eq!(parse_reason_code(0x97), L7ResponseStatus::ClientError);
+    }
+
     #[test]
     fn check_simple_string() {
         let input = [0x00, 0x05, 0x41, 0xF0, 0xAA, 0x9B, 0x94];
@@ -0,0 +1,1025 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use ring::{
+    aead::{self, quic, Aad, LessSafeKey, Nonce, UnboundKey},
+    hkdf,
+};
+use serde::Serialize;
+
+use super::tls::{
+    alert_description_name, cipher_suite_name, parse_client_hello, parse_server_hello, Reader,
+    HANDSHAKE_CLIENT_HELLO, HANDSHAKE_SERVER_HELLO,
+};
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::read_u32_be,
+};
+
+// https://www.rfc-editor.org/rfc/rfc9000
+// https://www.rfc-editor.org/rfc/rfc9001
+// https://www.rfc-editor.org/rfc/rfc9369
+const QUIC_VERSION_1: u32 = 0x00000001;
+const QUIC_VERSION_2: u32 = 0x6b3343cf;
+const QUIC_DRAFT_29: u32 = 0xff00001d;
+const VERSION_NEGOTIATION: u32 = 0;
+
+const INITIAL_SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const INITIAL_SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+const INITIAL_SALT_DRAFT_29: [u8; 20] = [
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0,
+    0x43, 0x90, 0xa8, 0x99,
+];
+
+const LONG_HEADER_FORM: u8 = 0x80;
+const FIXED_BIT: u8 = 0x40;
+const MAX_CID_LEN: usize = 20;
+// 客户端的 Initial 报文所在的 UDP 报文至少填充到 1200 字节
+// ====================================================
+// UDP datagrams carrying client Initial packets are padded to at least 1200 bytes
+const MIN_INITIAL_DATAGRAM_LEN: usize = 1200;
+const SAMPLE_LEN: usize = 16;
+const MAX_PACKET_NUMBER_LEN: usize = 4;
+// ClientHello 可能跨越多个 Initial 报文，例如携带后量子密钥的 ClientHello
+// ==================================================================
+// ClientHello may span multiple Initial packets, e.g. with post-quantum key shares
+const MAX_CRYPTO_LEN: usize = 16384;
+
+const FRAME_PADDING: u64 = 0x00;
+const FRAME_PING: u64 = 0x01;
+const FRAME_ACK: u64 = 0x02;
+const FRAME_ACK_ECN: u64 = 0x03;
+const FRAME_CRYPTO: u64 = 0x06;
+const FRAME_CONNECTION_CLOSE: u64 = 0x1c;
+const FRAME_CONNECTION_CLOSE_APP: u64 = 0x1d;
+
+const NO_ERROR: u64 = 0x00;
+const INTERNAL_ERROR: u64 = 0x01;
+// CRYPTO_ERROR 为 0x0100 加上 TLS 告警描述
+// =====================================
+// CRYPTO_ERROR is 0x0100 plus the TLS alert description
+const CRYPTO_ERROR_BASE: u64 = 0x0100;
+const CRYPTO_ERROR_MAX: u64 = 0x01ff;
+const TLS_ALERT_INTERNAL_ERROR: u64 = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PacketType {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    Retry,
+    VersionNegotiation,
+}
+
+fn version_name(version: u32) -> String {
+    match version {
+        QUIC_VERSION_1 => String::from("1"),
+        QUIC_VERSION_2 => String::from("2"),
+        QUIC_DRAFT_29 => String::from("draft-29"),
+        _ => format!("0x{:08x}", version),
+    }
+}
+
+fn initial_salt(version: u32) -> Option<&'static [u8]> {
+    match version {
+        QUIC_VERSION_1 => Some(&INITIAL_SALT_V1),
+        QUIC_VERSION_2 => Some(&INITIAL_SALT_V2),
+        QUIC_DRAFT_29 => Some(&INITIAL_SALT_DRAFT_29),
+        _ => None,
+    }
+}
+
+// QUIC v2 修改了长头部的报文类型编码
+// ===============================
+// QUIC v2 changes the encoding of long header packet types
+fn long_packet_type(version: u32, first: u8) -> PacketType {
+    match (version == QUIC_VERSION_2, (first >> 4) & 0x03) {
+        (false, 0) | (true, 1) => PacketType::Initial,
+        (false, 1) | (true, 2) => PacketType::ZeroRtt,
+        (false, 2) | (true, 3) => PacketType::Handshake,
+        _ => PacketType::Retry,
+    }
+}
+
+fn transport_error_name(code: u64) -> &'static str {
+    match code {
+        0x00 => "NO_ERROR",
+        0x01 => "INTERNAL_ERROR",
+        0x02 => "CONNECTION_REFUSED",
+        0x03 => "FLOW_CONTROL_ERROR",
+        0x04 => "STREAM_LIMIT_ERROR",
+        0x05 => "STREAM_STATE_ERROR",
+        0x06 => "FINAL_SIZE_ERROR",
+        0x07 => "FRAME_ENCODING_ERROR",
+        0x08 => "TRANSPORT_PARAMETER_ERROR",
+        0x09 => "CONNECTION_ID_LIMIT_ERROR",
+        0x0a => "PROTOCOL_VIOLATION",
+        0x0b => "INVALID_TOKEN",
+        0x0c => "APPLICATION_ERROR",
+        0x0d => "CRYPTO_BUFFER_EXCEEDED",
+        0x0e => "KEY_UPDATE_ERROR",
+        0x0f => "AEAD_LIMIT_REACHED",
+        0x10 => "NO_VIABLE_PATH",
+        _ => "unknown",
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::new(), |s, c| s + &format!("{:02x}", c))
+}
+
+fn read_varint(r: &mut Reader) -> Option<u64> {
+    let first = r.u8()?;
+    let mut v = (first & 0x3f) as u64;
+    for b in r.take((1 << (first >> 6)) - 1)? {
+        v = v << 8 | *b as u64;
+    }
+    Some(v)
+}
+
+struct LongHeader<'a> {
+    packet_type: PacketType,
+    version: u32,
+    dcid: &'a [u8],
+    scid: &'a [u8],
+    // 受保护的包序号在报文中的偏移
+    // ==========================
+    // offset of the protected packet number in the packet
+    pn_offset: usize,
+    packet: &'a [u8],
+}
+
+/*
+    长头部:
+    | 1 | 1 | type 2b | reserved 2b | pn length 2b | version 4B | dcid len 1B | dcid | scid len 1B | scid |
+    Initial:   | token length (i) | token | length (i) | packet number | payload |
+    0-RTT/Handshake: | length (i) | packet number | payload |
+    Retry:     | retry token | integrity tag 16B |
+    版本号为 0 时是版本协商报文，其后是支持的版本列表
+    一个 UDP 报文中可以合并多个长头部报文，返回当前报文的长度
+    =====================================================================================================
+    long header:
+    | 1 | 1 | type 2b | reserved 2b | pn length 2b | version 4B | dcid len 1B | dcid | scid len 1B | scid |
+    Initial:   | token length (i) | token | length (i) | packet number | payload |
+    0-RTT/Handshake: | length (i) | packet number | payload |
+    Retry:     | retry token | integrity tag 16B |
+    version negotiation packets have version 0, followed by the supported versions
+    multiple long header packets can be coalesced in one UDP datagram, return the length of this packet
+*/
+fn read_long_header(data: &[u8]) -> Option<(LongHeader, usize)> {
+    let mut r = Reader(data);
+    let first = r.u8()?;
+    let version = read_u32_be(r.take(4)?);
+    let dcid = r.vec8().filter(|c| c.len() <= MAX_CID_LEN)?;
+    let scid = r.vec8().filter(|c| c.len() <= MAX_CID_LEN)?;
+    let packet_type = if version == VERSION_NEGOTIATION {
+        PacketType::VersionNegotiation
+    } else {
+        initial_salt(version)?;
+        if first & FIXED_BIT == 0 {
+            return None;
+        }
+        long_packet_type(version, first)
+    };
+    let (pn_offset, length) = match packet_type {
+        PacketType::Initial | PacketType::ZeroRtt | PacketType::Handshake => {
+            if packet_type == PacketType::Initial {
+                let token_len = read_varint(&mut r)? as usize;
+                r.take(token_len)?;
+            }
+            let length = read_varint(&mut r)? as usize;
+            let pn_offset = data.len() - r.0.len();
+            (pn_offset, pn_offset + length)
+        }
+        _ => (data.len() - r.0.len(), data.len()),
+    };
+    let header = LongHeader {
+        packet_type,
+        version,
+        dcid,
+        scid,
+        pn_offset,
+        packet: data.get(..length)?,
+    };
+    Some((header, length))
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+// HKDF-Expand-Label(secret, label, "", length), RFC 8446 7.1
+fn expand_label(prk: &hkdf::Prk, label: &[u8], out: &mut [u8]) -> Option<()> {
+    let length = (out.len() as u16).to_be_bytes();
+    let label_len = [(b"tls13 ".len() + label.len()) as u8];
+    let info: [&[u8]; 5] = [&length, &label_len, b"tls13 ", label, &[0]];
+    prk.expand(&info, Len(out.len())).ok()?.fill(out).ok()
+}
+
+// Initial 报文的密钥由客户端第一个 Initial 报文的目的连接 ID 导出，无需任何秘密
+// =======================================================================
+// keys of Initial packets are derived from the Destination Connection ID of the
+// first client Initial packet, no secret is needed
+struct InitialKeys {
+    key: LessSafeKey,
+    iv: [u8; 12],
+    hp: quic::HeaderProtectionKey,
+}
+
+impl InitialKeys {
+    fn new(version: u32, dcid: &[u8], is_server: bool) -> Option<Self> {
+        let initial_secret =
+            hkdf::Salt::new(hkdf::HKDF_SHA256, initial_salt(version)?).extract(dcid);
+        let mut secret = [0u8; 32];
+        let label: &[u8] = if is_server {
+            b"server in"
+        } else {
+            b"client in"
+        };
+        expand_label(&initial_secret, label, &mut secret)?;
+        let secret = hkdf::Prk::new_less_safe(hkdf::HKDF_SHA256, &secret);
+
+        let (key_label, iv_label, hp_label): (&[u8], &[u8], &[u8]) = if version == QUIC_VERSION_2 {
+            (b"quicv2 key", b"quicv2 iv", b"quicv2 hp")
+        } else {
+            (b"quic key", b"quic iv", b"quic hp")
+        };
+        let mut key = [0u8; 16];
+        let mut iv = [0u8; 12];
+        let mut hp = [0u8; 16];
+        expand_label(&secret, key_label, &mut key)?;
+        expand_label(&secret, iv_label, &mut iv)?;
+        expand_label(&secret, hp_label, &mut hp)?;
+        Some(Self {
+            key: LessSafeKey::new(UnboundKey::new(&aead::AES_128_GCM, &key).ok()?),
+            iv,
+            hp: quic::HeaderProtectionKey::new(&quic::AES_128, &hp).ok()?,
+        })
+    }
+
+    // 去除头部保护后用 AES-128-GCM 解密，报文必须是完整的
+    // ===============================================
+    // decrypt with AES-128-GCM after removing header protection, the packet must be complete
+    fn decrypt(&self, header: &LongHeader) -> Option<Vec<u8>> {
+        let packet = header.packet;
+        let pn_offset = header.pn_offset;
+        let sample_offset = pn_offset + MAX_PACKET_NUMBER_LEN;
+        let mask = self
+            .hp
+            .new_mask(packet.get(sample_offset..sample_offset + SAMPLE_LEN)?)
+            .ok()?;
+
+        let mut aad = packet[..sample_offset].to_vec();
+        aad[0] ^= mask[0] & 0x0f;
+        let pn_len = (aad[0] & 0x03) as usize + 1;
+        aad.truncate(pn_offset + pn_len);
+        let mut packet_number = 0u64;
+        for (b, m) in aad[pn_offset..].iter_mut().zip(&mask[1..]) {
+            *b ^= m;
+            packet_number = packet_number << 8 | *b as u64;
+        }
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(packet_number.to_be_bytes()) {
+            *n ^= p;
+        }
+
+        let mut payload = packet[pn_offset + pn_len..].to_vec();
+        let plain_len = self
+            .key
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&aad[..]),
+                &mut payload,
+            )
+            .ok()?
+            .len();
+        payload.truncate(plain_len);
+        Some(payload)
+    }
+}
+
+struct ConnectionClose {
+    error_code: u64,
+    reason: String,
+}
+
+/*
+    Initial 报文中只允许 PADDING、PING、ACK、CRYPTO 和 CONNECTION_CLOSE 帧
+    ====================================================================
+    only PADDING, PING, ACK, CRYPTO and CONNECTION_CLOSE frames are allowed in Initial packets
+*/
+fn read_frames(
+    payload: &[u8],
+    crypto: &mut CryptoStream,
+    close: &mut Option<ConnectionClose>,
+) -> Option<()> {
+    let mut r = Reader(payload);
+    while !r.0.is_empty() {
+        match read_varint(&mut r)? {
+            FRAME_PADDING | FRAME_PING => {}
+            frame_type @ (FRAME_ACK | FRAME_ACK_ECN) => {
+                read_varint(&mut r)?;
+                read_varint(&mut r)?;
+                let range_count = read_varint(&mut r)?;
+                read_varint(&mut r)?;
+                for _ in 0..range_count * 2 {
+                    read_varint(&mut r)?;
+                }
+                if frame_type == FRAME_ACK_ECN {
+                    for _ in 0..3 {
+                        read_varint(&mut r)?;
+                    }
+                }
+            }
+            FRAME_CRYPTO => {
+                let offset = read_varint(&mut r)?;
+                let length = read_varint(&mut r)?;
+                crypto.insert(offset as usize, r.take(length as usize)?);
+            }
+            frame_type @ (FRAME_CONNECTION_CLOSE | FRAME_CONNECTION_CLOSE_APP) => {
+                let error_code = read_varint(&mut r)?;
+                if frame_type == FRAME_CONNECTION_CLOSE {
+                    read_varint(&mut r)?;
+                }
+                let reason_len = read_varint(&mut r)?;
+                *close = Some(ConnectionClose {
+                    error_code,
+                    reason: String::from_utf8_lossy(r.take(reason_len as usize)?).into_owned(),
+                });
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+// CRYPTO 帧按偏移重组，握手消息完整后取出
+// ====================================
+// CRYPTO frames are reassembled by offset, the handshake message is taken when complete
+#[derive(Debug, Default)]
+struct CryptoStream {
+    frames: Vec<(usize, Vec<u8>)>,
+    done: bool,
+}
+
+impl CryptoStream {
+    fn insert(&mut self, offset: usize, data: &[u8]) {
+        if self.done || offset + data.len() > MAX_CRYPTO_LEN {
+            return;
+        }
+        self.frames.push((offset, data.to_vec()));
+    }
+
+    // 返回第一个握手消息的类型和消息体
+    // =============================
+    // return the type and body of the first handshake message
+    fn take_message(&mut self) -> Option<(u8, Vec<u8>)> {
+        if self.done {
+            return None;
+        }
+        self.frames.sort_by_key(|(offset, _)| *offset);
+        let mut buf = vec![];
+        for (offset, data) in self.frames.iter() {
+            if *offset > buf.len() {
+                break;
+            }
+            if offset + data.len() > buf.len() {
+                buf.extend_from_slice(&data[buf.len() - offset..]);
+            }
+        }
+        let mut r = Reader(&buf);
+        let msg_type = r.u8()?;
+        let body = r.take(r.u24()?)?.to_vec();
+        self.frames.clear();
+        self.done = true;
+        Some((msg_type, body))
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct QuicInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type")]
+    pub packet_type: &'static str,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub server_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub alpn: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cipher_suite: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3: String,
+    // 客户端第一个 Initial 报文的目的连接 ID
+    // ===================================
+    // Destination Connection ID of the first client Initial packet
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub original_dcid: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub client_scid: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub server_scid: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for QuicInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, QuicInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::QUIC,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl QuicInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.cipher_suite = other.cipher_suite;
+        self.server_scid = other.server_scid;
+        self.resp_len = other.resp_len;
+        self.error_code = other.error_code;
+        self.error = other.error;
+        self.status = other.status;
+    }
+}
+
+impl From<QuicInfo> for L7ProtocolSendLog {
+    fn from(f: QuicInfo) -> Self {
+        let attributes = [
+            ("alpn", f.alpn),
+            ("cipher_suite", f.cipher_suite),
+            ("ja3", f.ja3),
+            ("original_dcid", f.original_dcid),
+            ("client_scid", f.client_scid),
+            ("server_scid", f.server_scid),
+        ]
+        .into_iter()
+        .filter(|(_, val)| !val.is_empty())
+        .map(|(key, val)| KeyVal {
+            key: key.to_string(),
+            val,
+        })
+        .collect::<Vec<_>>();
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            version: if f.version.is_empty() {
+                None
+            } else {
+                Some(f.version)
+            },
+            req: L7Request {
+                req_type: String::from(f.packet_type),
+                domain: f.server_name.clone(),
+                resource: f.server_name,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code,
+                exception: f.error,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuicLog {
+    info: QuicInfo,
+    perf_stats: Option<PerfStats>,
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+    #[serde(skip)]
+    version: u32,
+    // 用于导出双方 Initial 密钥的目的连接 ID，Retry 后会改变
+    // ====================================================
+    // Destination Connection ID for deriving the Initial keys of both sides,
+    // changed after Retry
+    #[serde(skip)]
+    initial_dcid: Vec<u8>,
+    // 双方选择的连接 ID，用于识别同一连接的短头部报文，
+    // NEW_CONNECTION_ID 帧是加密的，之后新签发的连接 ID 无法识别
+    // ======================================================
+    // connection IDs chosen by both sides, used for recognizing short header packets
+    // of the connection, connection IDs issued later by the encrypted NEW_CONNECTION_ID
+    // frames can not be recognized
+    #[serde(skip)]
+    client_cid: Vec<u8>,
+    #[serde(skip)]
+    server_cid: Option<Vec<u8>>,
+    #[serde(skip)]
+    client_crypto: CryptoStream,
+    #[serde(skip)]
+    server_crypto: CryptoStream,
+}
+
+impl Default for QuicLog {
+    fn default() -> Self {
+        Self {
+            info: QuicInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+            version: 0,
+            initial_dcid: vec![],
+            client_cid: vec![],
+            server_cid: None,
+            client_crypto: CryptoStream::default(),
+            server_crypto: CryptoStream::default(),
+        }
+    }
+}
+
+perf_impl!(QuicLog);
+
+impl L7ProtocolParserInterface for QuicLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol()
+            || param.l4_protocol != IpProtocol::Udp
+            || payload.len() < MIN_INITIAL_DATAGRAM_LEN
+            || payload[0] & LONG_HEADER_FORM == 0
+        {
+            return false;
+        }
+        // 协议识别时只接受可以解密并且携带 CRYPTO 帧的客户端 Initial 报文
+        // ============================================================
+        // only client Initial packets which can be decrypted and carry CRYPTO frames
+        // are accepted on protocol check
+        let Some((header, _)) = read_long_header(payload) else {
+            return false;
+        };
+        if header.packet_type != PacketType::Initial {
+            return false;
+        }
+        let mut crypto = CryptoStream::default();
+        InitialKeys::new(header.version, header.dcid, false)
+            .and_then(|keys| keys.decrypt(&header))
+            .and_then(|plain| read_frames(&plain, &mut crypto, &mut None))
+            .is_some()
+            && !crypto.frames.is_empty()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        if !self.parse(payload, param.direction)? {
+            return Ok(vec![]);
+        }
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::QuicInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::QUIC
+    }
+
+    fn parsable_on_tcp(&self) -> bool {
+        false
+    }
+
+    fn need_complete_payload(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = QuicInfo::default();
+    }
+}
+
+impl QuicLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 返回 false 表示报文中没有需要上报的内容，例如加密的握手或应用数据
+    // ============================================================
+    // return false if there is nothing to report in the datagram,
+    // e.g. encrypted handshake or application data
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<bool> {
+        let mut data = payload;
+        let mut close = None;
+        let mut version_negotiation = false;
+        while let Some(first) = data.first() {
+            if first & LONG_HEADER_FORM == 0 {
+                // 短头部报文没有连接 ID 长度，使用握手时记录的连接 ID 匹配
+                // ======================================================
+                // short header packets have no connection ID length, match with
+                // the connection ID recorded in the handshake
+                let cid = match direction {
+                    PacketDirection::ClientToServer => self.server_cid.as_deref(),
+                    PacketDirection::ServerToClient => Some(&self.client_cid[..]),
+                };
+                match cid {
+                    Some(cid) if first & FIXED_BIT != 0 && data[1..].starts_with(cid) => break,
+                    _ => return Err(Error::QuicLogParseFailed),
+                }
+            }
+            let Some((header, length)) = read_long_header(data) else {
+                return Err(Error::QuicLogParseFailed);
+            };
+            data = &data[length..];
+            match (header.packet_type, direction) {
+                (PacketType::Initial, PacketDirection::ClientToServer) => {
+                    // 收到服务端的 Initial 报文之前，目的连接 ID 的改变意味着 Retry 后的新连接尝试
+                    // ========================================================================
+                    // before any server Initial packet, a changed Destination Connection ID
+                    // means a new connection attempt after Retry
+                    if self.server_cid.is_none() && self.initial_dcid != header.dcid {
+                        self.initial_dcid = header.dcid.to_vec();
+                        self.client_crypto = CryptoStream::default();
+                    }
+                    self.version = header.version;
+                    self.client_cid = header.scid.to_vec();
+                }
+                (PacketType::Initial, PacketDirection::ServerToClient) => {
+                    if self.initial_dcid.is_empty() {
+                        continue;
+                    }
+                    if self.server_cid.is_none() {
+                        self.server_cid = Some(header.scid.to_vec());
+                    }
+                }
+                (PacketType::VersionNegotiation, PacketDirection::ServerToClient) => {
+                    version_negotiation = !self.initial_dcid.is_empty();
+                    break;
+                }
+                _ => continue,
+            }
+            let is_server = direction == PacketDirection::ServerToClient;
+            let plain = InitialKeys::new(header.version, &self.initial_dcid, is_server)
+                .and_then(|keys| keys.decrypt(&header))
+                .ok_or(Error::QuicLogParseFailed)?;
+            let crypto = if is_server {
+                &mut self.server_crypto
+            } else {
+                &mut self.client_crypto
+            };
+            read_frames(&plain, crypto, &mut close).ok_or(Error::QuicLogParseFailed)?;
+        }
+
+        let mut reported = false;
+        if direction == PacketDirection::ClientToServer {
+            if let Some((msg_type, body)) = self.client_crypto.take_message() {
+                if msg_type != HANDSHAKE_CLIENT_HELLO {
+                    return Err(Error::QuicLogParseFailed);
+                }
+                let hello = parse_client_hello(&body).ok_or(Error::QuicLogParseFailed)?;
+                self.info.msg_type = LogMessageType::Request;
+                self.info.packet_type = "Initial";
+                self.info.version = version_name(self.version);
+                self.info.server_name = hello.server_name;
+                self.info.alpn = hello.alpn.join(",");
+                self.info.ja3 = hello.ja3;
+                self.info.original_dcid = to_hex(&self.initial_dcid);
+                self.info.client_scid = to_hex(&self.client_cid);
+                self.info.req_len = Some(payload.len() as u32);
+                reported = true;
+            }
+        } else if version_negotiation {
+            self.info.msg_type = LogMessageType::Response;
+            self.info.packet_type = "VersionNegotiation";
+            self.info.error = String::from("version negotiation");
+            self.info.status = L7ResponseStatus::ClientError;
+            self.info.resp_len = Some(payload.len() as u32);
+            return Ok(true);
+        } else if let Some((msg_type, body)) = self.server_crypto.take_message() {
+            if msg_type != HANDSHAKE_SERVER_HELLO {
+                return Err(Error::QuicLogParseFailed);
+            }
+            let hello = parse_server_hello(&body).ok_or(Error::QuicLogParseFailed)?;
+            self.info.msg_type = LogMessageType::Response;
+            self.info.packet_type = "Initial";
+            self.info.cipher_suite = cipher_suite_name(hello.cipher_suite);
+            self.info.server_scid = self.server_cid.as_deref().map(to_hex).unwrap_or_default();
+            self.info.resp_len = Some(payload.len() as u32);
+            reported = true;
+        }
+
+        match close {
+            Some(close) if close.error_code != NO_ERROR => {
+                if !reported {
+                    self.info.packet_type = "Initial";
+                    self.info.msg_type = if direction == PacketDirection::ServerToClient
+                        && !self.server_crypto.done
+                    {
+                        self.info.resp_len = Some(payload.len() as u32);
+                        LogMessageType::Response
+                    } else {
+                        LogMessageType::Session
+                    };
+                }
+                let code = close.error_code;
+                self.info.error_code = Some(code as i32);
+                self.info.error = match code {
+                    CRYPTO_ERROR_BASE..=CRYPTO_ERROR_MAX => format!(
+                        "CRYPTO_ERROR({})",
+                        alert_description_name((code - CRYPTO_ERROR_BASE) as u8)
+                    ),
+                    _ => String::from(transport_error_name(code)),
+                };
+                if !close.reason.is_empty() {
+                    self.info.error = format!("{}: {}", self.info.error, close.reason);
+                }
+                // 与 TLS 告警相同，服务端关闭连接计为客户端错误，客户端关闭连接计为服务端错误
+                // =======================================================================
+                // same as TLS alerts, connections closed by the server are counted as
+                // client errors, and those closed by the client as server errors
+                self.info.status = if direction == PacketDirection::ClientToServer
+                    || code == INTERNAL_ERROR
+                    || code == CRYPTO_ERROR_BASE + TLS_ALERT_INTERNAL_ERROR
+                {
+                    L7ResponseStatus::ServerError
+                } else {
+                    L7ResponseStatus::ClientError
+                };
+                Ok(true)
+            }
+            _ => Ok(reported),
+        }
+    }
+}
+
+impl L7FlowPerf for QuicLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::QUIC,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/quic";
+
+    // 同一个流使用同一个解析器，以便重组 CRYPTO 帧和解密服务端的 Initial 报文
+    // ==================================================================
+    // use one parser for the flow to reassemble CRYPTO frames and decrypt the
+    // server Initial packets
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        if packets.is_empty() {
+            return "".to_string();
+        }
+
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = QuicLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from(&*packet);
+            let is_quic = QuicLog::new().check_payload(payload, &param);
+            let reported = parser
+                .parse(payload, packet.lookup_key.direction)
+                .unwrap_or_default();
+            let info = std::mem::take(&mut parser.info);
+            output.push_str(&format!(
+                "{:?} is_quic: {}\r\n",
+                reported.then_some(info),
+                is_quic
+            ));
+        }
+        output
+    }
+
+    // RFC 9001 Appendix A.1 and RFC 9369 Appendix A.1
+    #[test]
+    fn initial_keys() {
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        for (version, is_server, iv) in [
+            (QUIC_VERSION_1, false, "fa044b2f42a3fd3b46fb255c"),
+            (QUIC_VERSION_1, true, "0ac1493ca1905853b0bba03e"),
+            (QUIC_VERSION_2, false, "91f73e2351d8fa91660e909f"),
+            (QUIC_VERSION_2, true, "dd13c276499c0249d3310652"),
+        ] {
+            let keys = InitialKeys::new(version, &dcid, is_server).unwrap();
+            assert_eq!(to_hex(&keys.iv), iv);
+        }
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("quic_v1.pcap", "quic_v1.result"),
+            ("quic_v2_close.pcap", "quic_v2_close.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("quic_v1.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let mut parser = QuicLog::new();
+        let mut infos = vec![];
+        for (i, packet) in packets[..3].iter_mut().enumerate() {
+            packet.lookup_key.direction = if i < 2 {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let payload = packet.get_l4_payload().unwrap();
+            if let Some(L7ProtocolInfo::QuicInfo(info)) = parser
+                .parse_payload(payload, &ParseParam::from(&*packet))
+                .unwrap()
+                .pop()
+            {
+                infos.push(info);
+            }
+            parser.reset();
+        }
+        assert_eq!(infos.len(), 2);
+        let response = infos.pop().unwrap();
+        let mut initial = infos.pop().unwrap();
+
+        initial.merge(response);
+        assert_eq!(initial.server_scid, "5e5e5e5e5e5e5e5e");
+        let log = L7ProtocolSendLog::from(initial);
+        assert_eq!(log.version, Some("1".to_string()));
+        assert_eq!(log.req.domain, "h3.example.com");
+        assert_eq!(log.resp.status, L7ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("quic_v1.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = QuicLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 1);
+        assert_eq!(stats.response_count, 1);
+        assert_eq!(stats.err_client_count, 0);
+        assert_eq!(stats.rrt_count, 1);
+        assert_eq!(stats.rrt_max, 1000);
+    }
+}
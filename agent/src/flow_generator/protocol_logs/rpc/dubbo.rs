@@ -30,15 +30,19 @@ use crate::{
         error::{Error, Result},
         protocol_logs::{
             consts::*,
-            decode_base64_to_string,
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
-            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+            pb_adapter::{
+                ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+            },
+            trace_decoder, value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus,
+            LogMessageType,
         },
     },
     log_info_merge, parse_common,
     utils::bytes::{read_u32_be, read_u64_be},
 };
 
+use super::hessian2::{parameter_count, Hessian2Reader, Value};
+
 const TRACE_ID_MAX_LEN: usize = 1024;
 
 #[derive(Serialize, Debug, Default, Clone)]
@@ -71,9 +75,15 @@ pub struct DubboInfo {
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub method_name: String,
     #[serde(skip_serializing_if = "value_is_default")]
+    pub param_types: String,
+    #[serde(skip_serializing_if = "value_is_default")]
     pub trace_id: String,
     #[serde(skip_serializing_if = "value_is_default")]
     pub span_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub parent_span_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub attachments: Vec<(String, String)>,
 
     // resp
     #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
@@ -82,6 +92,11 @@ pub struct DubboInfo {
     pub resp_status: L7ResponseStatus,
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
     pub status_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
 }
 
 impl DubboInfo {
@@ -95,6 +110,9 @@ impl DubboInfo {
         if self.status_code.is_none() {
             self.status_code = other.status_code;
         }
+        if self.exception.is_empty() {
+            self.exception = other.exception;
+        }
     }
 }
 
@@ -124,6 +142,16 @@ impl L7ProtocolInfoInterface for DubboInfo {
 impl From<DubboInfo> for L7ProtocolSendLog {
     fn from(f: DubboInfo) -> Self {
         let endpoint = format!("{}/{}", f.service_name, f.method_name);
+        let mut attributes = vec![];
+        if !f.param_types.is_empty() {
+            attributes.push(KeyVal {
+                key: "parameter_types".to_string(),
+                val: f.param_types,
+            });
+        }
+        for (key, val) in f.attachments {
+            attributes.push(KeyVal { key, val });
+        }
         L7ProtocolSendLog {
             req_len: f.req_msg_size,
             resp_len: f.resp_msg_size,
@@ -137,16 +165,22 @@ impl From<DubboInfo> for L7ProtocolSendLog {
             resp: L7Response {
                 status: f.resp_status,
                 code: f.status_code,
+                exception: f.exception,
                 ..Default::default()
             },
             trace_info: Some(TraceInfo {
                 trace_id: Some(f.trace_id),
                 span_id: Some(f.span_id),
-                ..Default::default()
+                parent_span_id: Some(f.parent_span_id),
             }),
             ext_info: Some(ExtendedInfo {
                 rpc_service: Some(f.service_name),
                 request_id: Some(f.request_id as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -206,9 +240,12 @@ impl DubboLog {
         self.info.service_name = String::new();
         self.info.service_version = String::new();
         self.info.method_name = String::new();
+        self.info.param_types = String::new();
+        self.info.attachments.clear();
         self.info.resp_msg_size = None;
         self.info.resp_status = L7ResponseStatus::Ok;
         self.info.status_code = None;
+        self.info.exception = String::new();
     }
 
     fn check_char_boundary(payload: &Cow<'_, str>, start: usize, end: usize) -> bool {
@@ -263,23 +300,13 @@ impl DubboLog {
     }
 
     // 注意 dubbo trace id 解析是区分大小写的
-    fn decode_trace_id(payload: &Cow<'_, str>, trace_type: &TraceType, info: &mut DubboInfo) {
-        let tag = match trace_type {
-            TraceType::Sw8 => TraceType::Sw8.to_string(),
-            TraceType::Customize(tag) => tag.to_string(),
-            _ => return,
-        };
-
+    fn find_trace_field(payload: &Cow<'_, str>, tag: &str) -> Option<String> {
         let mut start = 0;
         while start < payload.len() {
             if !payload.is_char_boundary(start) {
                 break;
             }
-            let index = payload[start..].find(tag.as_str());
-            if index.is_none() {
-                break;
-            }
-            let index = index.unwrap();
+            let index = payload[start..].find(tag)?;
             // 注意这里tag长度不会超过256
             if index == 0 || tag.len() != payload.as_bytes()[start + index - 1] as usize {
                 start += index + tag.len();
@@ -293,85 +320,39 @@ impl DubboLog {
                 continue;
             }
 
-            if let Some(trace_id) =
-                Self::decode_field(payload, start + index + tag.len(), last_index)
+            if let Some(value) = Self::decode_field(payload, start + index + tag.len(), last_index)
             {
-                info.trace_id = trace_id;
-                break;
+                return Some(value);
             }
             start += index + tag.len();
         }
+        None
+    }
 
-        match trace_type {
-            TraceType::Sw8 => {
-                if info.trace_id.len() > 2 {
-                    if let Some(index) = info.trace_id[2..].find("-") {
-                        info.trace_id = info.trace_id[2..2 + index].to_string();
-                    }
-                }
-                info.trace_id = decode_base64_to_string(&info.trace_id);
+    fn decode_trace_id(payload: &Cow<'_, str>, trace_type: &TraceType, info: &mut DubboInfo) {
+        let tag = trace_type.to_string();
+        if tag.is_empty() {
+            return;
+        }
+        if let Some(value) = Self::find_trace_field(payload, &tag) {
+            if let Some(trace_id) = trace_decoder(trace_type).decode(&value).trace_id {
+                info.trace_id = trace_id;
             }
-            _ => return,
-        };
+        }
     }
 
     fn decode_span_id(payload: &Cow<'_, str>, trace_type: &TraceType, info: &mut DubboInfo) {
-        let tag = match trace_type {
-            TraceType::Customize(tag) => tag.to_string(),
-            TraceType::Sw8 => TraceType::Sw8.to_string(),
-            _ => return,
-        };
-
-        let mut start = 0;
-        while start < payload.len() {
-            if !payload.is_char_boundary(start) {
-                break;
-            }
-            let index = payload[start..].find(tag.as_str());
-            if index.is_none() {
-                break;
-            }
-            let index = index.unwrap();
-            // 注意这里tag长度不会超过256
-            if index == 0 || tag.len() != payload.as_bytes()[start + index - 1] as usize {
-                start += index + tag.len();
-                continue;
-            }
-            let last_index = payload
-                .len()
-                .min(TRACE_ID_MAX_LEN + start + index + tag.len());
-            if Self::check_char_boundary(&payload, start + index, last_index) {
-                start += index + tag.len();
-                continue;
-            }
-
-            if let Some(span_id) =
-                Self::decode_field(payload, start + index + tag.len(), last_index)
-            {
+        let tag = trace_type.to_string();
+        if tag.is_empty() {
+            return;
+        }
+        if let Some(value) = Self::find_trace_field(payload, &tag) {
+            let ids = trace_decoder(trace_type).decode(&value);
+            if let Some(span_id) = ids.span_id {
                 info.span_id = span_id;
-                break;
+                info.parent_span_id = ids.parent_span_id.unwrap_or_default();
             }
-            start += index + tag.len();
         }
-
-        match trace_type {
-            TraceType::Sw8 => {
-                // Format:
-                // sw8: 1-TRACEID-SEGMENTID-3-PARENT_SERVICE-PARENT_INSTANCE-PARENT_ENDPOINT-IPPORT
-                let mut skip = false;
-                if info.span_id.len() > 2 {
-                    let segs: Vec<&str> = info.span_id.split("-").collect();
-                    if segs.len() > 4 {
-                        info.span_id = format!("{}-{}", decode_base64_to_string(segs[2]), segs[3]);
-                        skip = true;
-                    }
-                }
-                if !skip {
-                    info.span_id = decode_base64_to_string(&info.span_id);
-                }
-            }
-            _ => return,
-        };
     }
 
     // 尽力而为的去解析Dubbo请求中Body各参数
@@ -383,7 +364,10 @@ impl DubboLog {
         while n < BODY_PARAM_MAX && para_index < payload_len {
             let (offset, para_len) = get_req_param_len(&payload[para_index..]);
             para_index += offset;
-            if para_len == 0 || para_len + para_index > payload_len {
+            // 无参数方法的参数类型描述为空字符串
+            if (para_len == 0 && (offset == 0 || n != BODY_PARAM_PARAMETER_TYPES))
+                || para_len + para_index > payload_len
+            {
                 return;
             }
 
@@ -408,6 +392,11 @@ impl DubboLog {
                         String::from_utf8_lossy(&payload[para_index..para_index + para_len])
                             .into_owned();
                 }
+                BODY_PARAM_PARAMETER_TYPES => {
+                    self.info.param_types =
+                        String::from_utf8_lossy(&payload[para_index..para_index + para_len])
+                            .into_owned();
+                }
                 _ => return,
             }
 
@@ -418,6 +407,10 @@ impl DubboLog {
             n += 1;
         }
 
+        if self.info.serial_id == HESSIAN2_SERIAL_ID {
+            self.decode_attachments(&payload[para_index..]);
+        }
+
         if config.trace_types.is_empty() || para_index >= payload.len() {
             return;
         }
@@ -445,6 +438,27 @@ impl DubboLog {
         }
     }
 
+    // 跳过方法参数后解析 attachments，只保留简单类型的值
+    fn decode_attachments(&mut self, payload: &[u8]) {
+        let Some(count) = parameter_count(&self.info.param_types) else {
+            return;
+        };
+        let mut reader = Hessian2Reader::new(payload);
+        for _ in 0..count {
+            if reader.read_value().is_none() {
+                return;
+            }
+        }
+        let Some(Value::Map(entries)) = reader.read_value() else {
+            return;
+        };
+        for (key, value) in entries {
+            if let (Value::String(key), Some(value)) = (key, value.to_attribute()) {
+                self.info.attachments.push((key, value));
+            }
+        }
+    }
+
     fn request(&mut self, config: &L7LogDynamicConfig, payload: &[u8], dubbo_header: &DubboHeader) {
         self.info.msg_type = LogMessageType::Request;
 
@@ -458,21 +472,40 @@ impl DubboLog {
 
     fn set_status(&mut self, status_code: u8) {
         self.info.resp_status = match status_code {
-            20 => L7ResponseStatus::Ok,
-            30 => L7ResponseStatus::ClientError,
-            31 => L7ResponseStatus::ServerError,
-            40 => L7ResponseStatus::ClientError,
-            50 => L7ResponseStatus::ServerError,
-            60 => L7ResponseStatus::ServerError,
-            70 => L7ResponseStatus::ServerError,
-            80 => L7ResponseStatus::ServerError,
-            90 => L7ResponseStatus::ClientError,
-            100 => L7ResponseStatus::ServerError,
-            _ => L7ResponseStatus::Ok,
+            OK => L7ResponseStatus::Ok,
+            CLIENT_TIMEOUT | BAD_REQUEST | CLIENT_ERROR => L7ResponseStatus::ClientError,
+            SERVER_TIMEOUT
+            | BAD_RESPONSE
+            | SERVICE_NOT_FOUND
+            | SERVICE_ERROR
+            | SERVER_ERROR
+            | SERVER_THREADPOOL_EXHAUSTED_ERROR => L7ResponseStatus::ServerError,
+            // 未知的状态码按服务端错误处理，与 perf 统计保持一致
+            _ => L7ResponseStatus::ServerError,
         }
     }
 
-    fn response(&mut self, dubbo_header: &DubboHeader) {
+    // 状态码不是 OK 时消息体是错误信息，状态码是 OK 时服务端方法仍可能抛出异常
+    fn decode_response_body(&mut self, status_code: u8, payload: &[u8]) {
+        let mut reader = Hessian2Reader::new(payload);
+        if status_code != OK {
+            if let Some(message) = reader.read_string() {
+                self.info.exception = message;
+            }
+            return;
+        }
+        match reader.read_int() {
+            Some(RESPONSE_WITH_EXCEPTION) | Some(RESPONSE_WITH_EXCEPTION_WITH_ATTACHMENTS) => {
+                self.info.resp_status = L7ResponseStatus::ServerError;
+                if let Some(exception) = reader.read_value() {
+                    self.info.exception = exception_message(&exception);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn response(&mut self, payload: &[u8], dubbo_header: &DubboHeader) {
         self.info.msg_type = LogMessageType::Response;
 
         self.info.data_type = dubbo_header.data_type;
@@ -481,6 +514,10 @@ impl DubboLog {
         self.info.request_id = dubbo_header.request_id;
         self.info.status_code = Some(dubbo_header.status_code as i32);
         self.set_status(dubbo_header.status_code);
+
+        if dubbo_header.serial_id == HESSIAN2_SERIAL_ID {
+            self.decode_response_body(dubbo_header.status_code, &payload[DUBBO_HEADER_LEN..]);
+        }
     }
 
     pub fn dubbo_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
@@ -520,7 +557,7 @@ impl DubboLog {
                 self.request(&config, payload, &dubbo_header);
             }
             PacketDirection::ServerToClient => {
-                self.response(&dubbo_header);
+                self.response(payload, &dubbo_header);
             }
         }
         Ok(())
@@ -592,6 +629,19 @@ pub fn get_req_param_len(payload: &[u8]) -> (usize, usize) {
     }
 }
 
+// Java 异常序列化为对象，异常信息在 detailMessage 字段中
+fn exception_message(exception: &Value) -> String {
+    match exception {
+        Value::Object { class, .. } => {
+            match exception.field("detailMessage").and_then(Value::as_str) {
+                Some(message) => format!("{}: {}", class, message),
+                None => class.clone(),
+            }
+        }
+        _ => exception.to_attribute().unwrap_or_default(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -679,4 +729,47 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn response_exception() {
+        let config = L7LogDynamicConfig::new("".to_owned(), "".to_owned(), vec![], vec![]);
+        let header = b"\xda\xbb\x02\x14\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00";
+
+        // 服务端方法抛出异常时状态码仍然是 OK
+        let mut payload = header.to_vec();
+        payload.extend_from_slice(
+            b"\x90C\x1ajava.lang.RuntimeException\x92\x0ddetailMessage\x05cause\x60\x04boom\x51\x90",
+        );
+        let mut dubbo = DubboLog::default();
+        dubbo
+            .parse(
+                &config,
+                &payload,
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(dubbo.info.status_code, Some(OK as i32));
+        assert_eq!(dubbo.info.resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(dubbo.info.exception, "java.lang.RuntimeException: boom");
+
+        let mut payload = header.to_vec();
+        payload[3] = BAD_REQUEST;
+        payload.extend_from_slice(b"\x0binvalid arg");
+        let mut dubbo = DubboLog::default();
+        dubbo
+            .parse(
+                &config,
+                &payload,
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(dubbo.info.resp_status, L7ResponseStatus::ClientError);
+        assert_eq!(dubbo.info.exception, "invalid arg");
+    }
 }
@@ -0,0 +1,448 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{
+    flow_generator::protocol_logs::consts::*,
+    utils::bytes::{read_u16_be, read_u32_be, read_u64_be},
+};
+
+// 嵌套过深的值直接放弃解析，避免构造的报文导致栈溢出
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i32),
+    Long(i64),
+    Double(f64),
+    Date(i64),
+    String(String),
+    Binary(usize),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Object {
+        class: String,
+        fields: Vec<(String, Value)>,
+    },
+    Ref(i32),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i as i64),
+            Value::Long(l) => Some(*l),
+            _ => None,
+        }
+    }
+
+    // 仅简单类型可以转换为字符串，用于 attachment 的值
+    pub fn to_attribute(&self) -> Option<String> {
+        match self {
+            Value::Bool(b) => Some(b.to_string()),
+            Value::Int(i) => Some(i.to_string()),
+            Value::Long(l) | Value::Date(l) => Some(l.to_string()),
+            Value::Double(d) => Some(d.to_string()),
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object { fields, .. } => fields.iter().find(|(k, _)| k == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+struct ClassDef {
+    name: String,
+    fields: Vec<String>,
+}
+
+// Hessian2 反序列化，只用于从 Dubbo 消息体中提取日志需要的字段，任何不完整或不合法的数据都返回 None
+// 参考：http://hessian.caucho.com/doc/hessian-serialization.html
+pub struct Hessian2Reader<'a> {
+    payload: &'a [u8],
+    offset: usize,
+    class_defs: Vec<ClassDef>,
+    depth: usize,
+}
+
+impl<'a> Hessian2Reader<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        Self {
+            payload,
+            offset: 0,
+            class_defs: vec![],
+            depth: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.payload.get(self.offset).copied()
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.offset += 1;
+        Some(b)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.offset.checked_add(len)?;
+        let bytes = self.payload.get(self.offset..end)?;
+        self.offset = end;
+        Some(bytes)
+    }
+
+    pub fn read_string(&mut self) -> Option<String> {
+        match self.read_value()? {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn read_int(&mut self) -> Option<i64> {
+        self.read_value()?.as_int()
+    }
+
+    pub fn read_value(&mut self) -> Option<Value> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = self.read_value_inner();
+        self.depth -= 1;
+        value
+    }
+
+    fn read_value_inner(&mut self) -> Option<Value> {
+        let tag = self.read_u8()?;
+        let value = match tag {
+            HESSIAN2_NULL => Value::Null,
+            HESSIAN2_TRUE => Value::Bool(true),
+            HESSIAN2_FALSE => Value::Bool(false),
+
+            // int
+            0x80..=0xbf => Value::Int(tag as i32 - 0x90),
+            0xc0..=0xcf => Value::Int(((tag as i32 - 0xc8) << 8) + self.read_u8()? as i32),
+            0xd0..=0xd7 => {
+                let b = self.read_bytes(2)?;
+                Value::Int(((tag as i32 - 0xd4) << 16) + read_u16_be(b) as i32)
+            }
+            HESSIAN2_INT => Value::Int(read_u32_be(self.read_bytes(4)?) as i32),
+
+            // long
+            0xd8..=0xef => Value::Long(tag as i64 - 0xe0),
+            0xf0..=0xff => Value::Long(((tag as i64 - 0xf8) << 8) + self.read_u8()? as i64),
+            0x38..=0x3f => {
+                let b = self.read_bytes(2)?;
+                Value::Long(((tag as i64 - 0x3c) << 16) + read_u16_be(b) as i64)
+            }
+            HESSIAN2_LONG_INT => Value::Long(read_u32_be(self.read_bytes(4)?) as i32 as i64),
+            HESSIAN2_LONG => Value::Long(read_u64_be(self.read_bytes(8)?) as i64),
+
+            // double
+            0x5b => Value::Double(0.0),
+            0x5c => Value::Double(1.0),
+            0x5d => Value::Double(self.read_u8()? as i8 as f64),
+            0x5e => Value::Double(read_u16_be(self.read_bytes(2)?) as i16 as f64),
+            0x5f => Value::Double(read_u32_be(self.read_bytes(4)?) as i32 as f64 / 1000.0),
+            HESSIAN2_DOUBLE => Value::Double(f64::from_bits(read_u64_be(self.read_bytes(8)?))),
+
+            // date
+            HESSIAN2_DATE => Value::Date(read_u64_be(self.read_bytes(8)?) as i64),
+            HESSIAN2_DATE_MINUTE => {
+                Value::Date(read_u32_be(self.read_bytes(4)?) as i32 as i64 * 60000)
+            }
+
+            // string
+            0x00..=0x1f | 0x30..=0x33 | HESSIAN2_STRING | HESSIAN2_STRING_CHUNK => {
+                Value::String(self.read_string_chunks(tag)?)
+            }
+
+            // binary
+            0x20..=0x2f | 0x34..=0x37 | HESSIAN2_BINARY | HESSIAN2_BINARY_CHUNK => {
+                Value::Binary(self.read_binary_chunks(tag)?)
+            }
+
+            // list
+            HESSIAN2_LIST => {
+                self.read_type()?;
+                Value::List(self.read_values_until_end()?)
+            }
+            HESSIAN2_FIXED_LIST => {
+                self.read_type()?;
+                let len = self.read_int()?;
+                Value::List(self.read_values(len)?)
+            }
+            HESSIAN2_UNTYPED_LIST => Value::List(self.read_values_until_end()?),
+            HESSIAN2_FIXED_UNTYPED_LIST => {
+                let len = self.read_int()?;
+                Value::List(self.read_values(len)?)
+            }
+            0x70..=0x77 => {
+                self.read_type()?;
+                Value::List(self.read_values((tag - 0x70) as i64)?)
+            }
+            0x78..=0x7f => Value::List(self.read_values((tag - 0x78) as i64)?),
+
+            // map
+            HESSIAN2_MAP => {
+                self.read_type()?;
+                Value::Map(self.read_map_entries()?)
+            }
+            HESSIAN2_UNTYPED_MAP => Value::Map(self.read_map_entries()?),
+
+            // object
+            HESSIAN2_CLASS_DEF => {
+                let name = self.read_string()?;
+                let count = self.read_int()?;
+                let mut fields = vec![];
+                for _ in 0..count {
+                    fields.push(self.read_string()?);
+                }
+                self.class_defs.push(ClassDef { name, fields });
+                // 类定义之后紧跟着对象实例，连续的类定义也计入嵌套深度
+                return self.read_value();
+            }
+            HESSIAN2_OBJECT => {
+                let index = self.read_int()?;
+                self.read_object(index)?
+            }
+            0x60..=0x6f => self.read_object((tag - 0x60) as i64)?,
+
+            HESSIAN2_REF => Value::Ref(self.read_int()? as i32),
+
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // 长度以 UTF-16 字符为单位，需要逐个字符计算 UTF-8 编码的字节数
+    fn read_utf8(&mut self, chars: usize, s: &mut Vec<u8>) -> Option<()> {
+        let mut remain = chars;
+        while remain > 0 {
+            // 4 字节的 UTF-8 字符在 Java 中占两个 UTF-16 字符
+            let (len, units) = match self.peek()? {
+                0x00..=0x7f => (1, 1),
+                0xc0..=0xdf => (2, 1),
+                0xe0..=0xef => (3, 1),
+                0xf0..=0xf7 => (4, 2),
+                _ => return None,
+            };
+            s.extend_from_slice(self.read_bytes(len)?);
+            remain = remain.saturating_sub(units);
+        }
+        Some(())
+    }
+
+    fn read_string_chunks(&mut self, mut tag: u8) -> Option<String> {
+        let mut s = vec![];
+        loop {
+            let len = match tag {
+                0x00..=0x1f => tag as usize,
+                0x30..=0x33 => ((tag as usize - 0x30) << 8) + self.read_u8()? as usize,
+                HESSIAN2_STRING | HESSIAN2_STRING_CHUNK => {
+                    read_u16_be(self.read_bytes(2)?) as usize
+                }
+                _ => return None,
+            };
+            self.read_utf8(len, &mut s)?;
+            if tag != HESSIAN2_STRING_CHUNK {
+                break;
+            }
+            tag = self.read_u8()?;
+        }
+        Some(String::from_utf8_lossy(&s).into_owned())
+    }
+
+    fn read_binary_chunks(&mut self, mut tag: u8) -> Option<usize> {
+        let mut total = 0;
+        loop {
+            let len = match tag {
+                0x20..=0x2f => tag as usize - 0x20,
+                0x34..=0x37 => ((tag as usize - 0x34) << 8) + self.read_u8()? as usize,
+                HESSIAN2_BINARY | HESSIAN2_BINARY_CHUNK => {
+                    read_u16_be(self.read_bytes(2)?) as usize
+                }
+                _ => return None,
+            };
+            self.read_bytes(len)?;
+            total += len;
+            if tag != HESSIAN2_BINARY_CHUNK {
+                break;
+            }
+            tag = self.read_u8()?;
+        }
+        Some(total)
+    }
+
+    // 类型是字符串或者类型引用
+    fn read_type(&mut self) -> Option<()> {
+        match self.read_value()? {
+            Value::String(_) | Value::Int(_) => Some(()),
+            _ => None,
+        }
+    }
+
+    fn read_values(&mut self, len: i64) -> Option<Vec<Value>> {
+        if len < 0 {
+            return None;
+        }
+        let mut values = vec![];
+        for _ in 0..len {
+            values.push(self.read_value()?);
+        }
+        Some(values)
+    }
+
+    fn read_values_until_end(&mut self) -> Option<Vec<Value>> {
+        let mut values = vec![];
+        while self.peek()? != HESSIAN2_END {
+            values.push(self.read_value()?);
+        }
+        self.offset += 1;
+        Some(values)
+    }
+
+    fn read_map_entries(&mut self) -> Option<Vec<(Value, Value)>> {
+        let mut entries = vec![];
+        while self.peek()? != HESSIAN2_END {
+            let key = self.read_value()?;
+            let value = self.read_value()?;
+            entries.push((key, value));
+        }
+        self.offset += 1;
+        Some(entries)
+    }
+
+    fn read_object(&mut self, index: i64) -> Option<Value> {
+        let def = self.class_defs.get(usize::try_from(index).ok()?)?;
+        let class = def.name.clone();
+        let names = def.fields.clone();
+        let mut fields = vec![];
+        for name in names {
+            let value = self.read_value()?;
+            fields.push((name, value));
+        }
+        Some(Value::Object { class, fields })
+    }
+}
+
+// 统计 JVM 方法描述符中的参数个数，例如 "Ljava/lang/String;[I" 有两个参数
+pub fn parameter_count(desc: &str) -> Option<usize> {
+    let mut count = 0;
+    let mut bytes = desc.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'[' => continue,
+            b'L' => {
+                bytes.find(|b| *b == b';')?;
+            }
+            b'Z' | b'B' | b'C' | b'S' | b'I' | b'J' | b'F' | b'D' => {}
+            _ => return None,
+        }
+        count += 1;
+    }
+    Some(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_values() {
+        let payload = b"\x90\xc8\x01\xd4\x01\x00I\x7f\xff\xff\xff\xe0\xf8\x01\x3c\x01\x00\
+            \x59\xff\xff\xff\xff\x5d\xfe\x05hello\x52\x00\x02ab\x01cTFN";
+        let mut reader = Hessian2Reader::new(payload);
+        let expected = [
+            Value::Int(0),
+            Value::Int(1),
+            Value::Int(256),
+            Value::Int(i32::MAX),
+            Value::Long(0),
+            Value::Long(1),
+            Value::Long(256),
+            Value::Long(-1),
+            Value::Double(-2.0),
+            Value::String("hello".to_string()),
+            Value::String("abc".to_string()),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Null,
+        ];
+        for e in expected {
+            assert_eq!(reader.read_value(), Some(e));
+        }
+        assert_eq!(reader.read_value(), None);
+    }
+
+    #[test]
+    fn read_object() {
+        let payload = b"C\x0bexample.Car\x92\x05color\x05model\x60\x03red\x08corvette\
+            \x60\x05green\x05civicH\x01a\x91\x01b\x7a\x90\x91Z";
+        let mut reader = Hessian2Reader::new(payload);
+        let car = reader.read_value().unwrap();
+        assert_eq!(car.field("color").and_then(|v| v.as_str()), Some("red"));
+        let car = reader.read_value().unwrap();
+        assert_eq!(car.field("model").and_then(|v| v.as_str()), Some("civic"));
+        assert_eq!(
+            reader.read_value(),
+            Some(Value::Map(vec![
+                (Value::String("a".to_string()), Value::Int(1)),
+                (
+                    Value::String("b".to_string()),
+                    Value::List(vec![Value::Int(0), Value::Int(1)])
+                ),
+            ]))
+        );
+
+        // 截断的数据
+        let mut reader = Hessian2Reader::new(&payload[..20]);
+        assert_eq!(reader.read_value(), None);
+    }
+
+    #[test]
+    fn nested_class_defs() {
+        let payload = b"C\x00\x90".repeat(10000);
+        let mut reader = Hessian2Reader::new(&payload);
+        assert_eq!(reader.read_value(), None);
+        assert!(reader.class_defs.len() <= MAX_DEPTH);
+    }
+
+    #[test]
+    fn count_parameters() {
+        assert_eq!(parameter_count(""), Some(0));
+        assert_eq!(
+            parameter_count("Ljava/lang/String;Ljava/lang/String;"),
+            Some(2)
+        );
+        assert_eq!(parameter_count("[IJ[Ljava/lang/Object;Z"), Some(4));
+        assert_eq!(parameter_count("Ljava/lang/String"), None);
+    }
+}
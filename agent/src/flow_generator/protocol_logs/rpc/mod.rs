@@ -15,8 +15,10 @@
  */
 
 mod dubbo;
+mod hessian2;
 mod protobuf_rpc;
 mod sofa_rpc;
+mod thrift;
 
 pub use dubbo::{DubboHeader, DubboInfo, DubboLog};
 pub use protobuf_rpc::{get_protobuf_rpc_parser, ProtobufRpcInfo, ProtobufRpcWrapLog};
@@ -24,3 +26,4 @@ pub use sofa_rpc::{
     decode_new_rpc_trace_context, decode_new_rpc_trace_context_with_type, SofaRpcInfo, SofaRpcLog,
     SOFA_NEW_RPC_TRACE_CTX_KEY,
 };
+pub use thrift::{ThriftInfo, ThriftLog};
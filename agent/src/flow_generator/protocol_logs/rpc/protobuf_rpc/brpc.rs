@@ -0,0 +1,475 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use prost::Message;
+use public::{
+    bytes::read_u32_be,
+    l7_protocol::{L7Protocol, ProtobufRpcProtocol},
+};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        flow::{FlowPerfStats, L7PerfStats, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
+            L7ResponseStatus,
+        },
+        AppProtoHead, Error, LogMessageType, Result,
+    },
+    perf_impl,
+};
+
+use super::ProtobufRpcInfo;
+use public::proto::protobuf_rpc::BrpcMeta;
+
+const BRPC_MAGIC: &[u8] = b"PRPC";
+const BRPC_FIX_HDR_LEN: usize = 12;
+
+// https://github.com/apache/brpc/blob/master/src/brpc/errno.proto
+const BRPC_ENOSERVICE: i32 = 1001;
+const BRPC_EREQUEST: i32 = 1003;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BrpcInfo {
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+
+    msg_type: LogMessageType,
+    correlation_id: i64,
+    service_name: String,
+    method_name: String,
+    log_id: i64,
+    request_id: String,
+    error_code: i32,
+    error_text: String,
+
+    req_len: u32,
+    resp_len: u32,
+
+    //trace info
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    status: L7ResponseStatus,
+}
+
+impl BrpcInfo {
+    fn fill_from_pb(&mut self, meta: BrpcMeta) -> Result<()> {
+        self.correlation_id = meta.correlation_id;
+        match (meta.request, meta.response) {
+            (Some(req), _) => {
+                self.msg_type = LogMessageType::Request;
+                self.service_name = req.service_name;
+                self.method_name = req.method_name;
+                self.log_id = req.log_id;
+                self.request_id = req.request_id;
+                // bRPC 的 trace id 和 span id 是 64 位整数，0 表示未设置
+                // ====================================================
+                // trace id and span id of bRPC are 64 bit integers, and 0 means not set
+                if req.trace_id != 0 {
+                    self.trace_id = format!("{:x}", req.trace_id);
+                }
+                if req.span_id != 0 {
+                    self.span_id = format!("{:x}", req.span_id);
+                }
+                if req.parent_span_id != 0 {
+                    self.parent_span_id = format!("{:x}", req.parent_span_id);
+                }
+            }
+            (None, resp) => {
+                self.msg_type = LogMessageType::Response;
+                if let Some(resp) = resp {
+                    self.error_code = resp.error_code;
+                    self.error_text = resp.error_text;
+                }
+            }
+        }
+
+        self.status = match self.error_code {
+            0 => L7ResponseStatus::Ok,
+            BRPC_ENOSERVICE..=BRPC_EREQUEST => L7ResponseStatus::ClientError,
+            _ => L7ResponseStatus::ServerError,
+        };
+
+        Ok(())
+    }
+}
+
+impl L7ProtocolInfoInterface for BrpcInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.correlation_id as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::ProtobufRpcInfo(ProtobufRpcInfo::BrpcInfo(b)) = other {
+            self.resp_len = b.resp_len;
+            self.error_code = b.error_code;
+            self.error_text = b.error_text;
+            self.status = b.status;
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::ProtobufRPC,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl From<BrpcInfo> for L7ProtocolSendLog {
+    fn from(b: BrpcInfo) -> Self {
+        let req_id = b.session_id();
+        Self {
+            req_len: Some(b.req_len),
+            resp_len: Some(b.resp_len),
+            req: L7Request {
+                req_type: b.method_name.clone(),
+                resource: b.service_name.clone(),
+                endpoint: format!("{}/{}", b.service_name, b.method_name),
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: b.status,
+                code: Some(b.error_code),
+                exception: b.error_text,
+                ..Default::default()
+            },
+            trace_info: Some(TraceInfo {
+                trace_id: Some(b.trace_id),
+                span_id: Some(b.span_id),
+                parent_span_id: Some(b.parent_span_id),
+                ..Default::default()
+            }),
+            ext_info: Some(ExtendedInfo {
+                rpc_service: Some(b.service_name),
+                request_id: req_id,
+                x_request_id: if b.request_id.is_empty() {
+                    None
+                } else {
+                    Some(b.request_id)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrpcLog {
+    info: BrpcInfo,
+    perf_stats: Option<PerfStats>,
+
+    parsed: bool,
+
+    // <session_id,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for BrpcLog {
+    fn default() -> Self {
+        Self {
+            previous_log_info: LruCache::new(100),
+            info: BrpcInfo::default(),
+            perf_stats: None,
+            parsed: false,
+        }
+    }
+}
+
+perf_impl!(BrpcLog);
+
+impl BrpcLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        baidu_std hdr reference https://github.com/apache/brpc/blob/master/docs/cn/baidu_std.md
+
+        0  .......8........16........24.........32
+        1  |------------------PRPC----------------|
+        2  |---------------body_size--------------|
+        3  |---------------meta_size--------------|
+
+        body 由 meta_size 字节的 RpcMeta 和 body_size - meta_size 字节的数据和附件组成
+        ==============================================================================
+        body consists of RpcMeta of meta_size bytes, and data and attachment of
+        body_size - meta_size bytes
+    */
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        param: &ParseParam,
+        strict: bool,
+    ) -> Result<Vec<L7ProtocolInfo>> {
+        if self.parsed {
+            return Ok(vec![L7ProtocolInfo::ProtobufRpcInfo(
+                ProtobufRpcInfo::BrpcInfo(self.info.clone()),
+            )]);
+        }
+        self.info.start_time = param.time;
+        self.info.end_time = param.time;
+        if payload.len() < BRPC_FIX_HDR_LEN || &payload[..4] != BRPC_MAGIC {
+            return Err(Error::L7ProtocolUnknown);
+        }
+
+        let body_size = read_u32_be(&payload[4..]);
+        let meta_size = read_u32_be(&payload[8..]) as usize;
+        if meta_size > body_size as usize {
+            return Err(Error::L7ProtocolUnknown);
+        }
+
+        let pb_payload = if meta_size + BRPC_FIX_HDR_LEN > payload.len() {
+            // if meta_size + BRPC_FIX_HDR_LEN > payload.len() likely ebpf not read full data from syscall, pb parse to the payload end.
+            if strict {
+                return Err(Error::L7ProtocolUnknown);
+            }
+            &payload[BRPC_FIX_HDR_LEN..]
+        } else {
+            &payload[BRPC_FIX_HDR_LEN..BRPC_FIX_HDR_LEN + meta_size]
+        };
+
+        let mut meta = BrpcMeta::default();
+        if meta.merge(pb_payload).is_err() && strict {
+            return Err(Error::L7ProtocolUnknown);
+        }
+        if strict {
+            match meta.request.as_ref() {
+                Some(req) if !req.service_name.is_empty() && !req.method_name.is_empty() => (),
+                Some(_) => return Err(Error::L7ProtocolUnknown),
+                None if meta.response.is_none() => return Err(Error::L7ProtocolUnknown),
+                None => (),
+            }
+        }
+
+        self.info.fill_from_pb(meta)?;
+
+        match self.info.msg_type {
+            LogMessageType::Request => {
+                self.info.req_len = body_size;
+                self.perf_inc_req(param.time);
+            }
+            LogMessageType::Response => {
+                self.info.resp_len = body_size;
+                self.perf_inc_resp(param.time);
+                match self.info.status {
+                    L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+                    L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+                    _ => {}
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::ProtobufRpcInfo(
+            ProtobufRpcInfo::BrpcInfo(self.info.clone()),
+        )])
+    }
+}
+
+impl L7ProtocolParserInterface for BrpcLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        self.info.start_time = param.time;
+        self.info.end_time = param.time;
+        self.parsed = self.parse(payload, param, true).is_ok();
+        self.parsed && self.info.msg_type == LogMessageType::Request
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        self.parse(payload, param, false)
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::ProtobufRPC
+    }
+
+    fn protobuf_rpc_protocol(&self) -> Option<ProtobufRpcProtocol> {
+        Some(ProtobufRpcProtocol::Brpc)
+    }
+
+    fn reset(&mut self) {
+        self.parsed = false;
+        self.save_info_time();
+        self.info = BrpcInfo::default();
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+}
+
+impl L7FlowPerf for BrpcLog {
+    fn parse(
+        &mut self,
+        _: Option<&LogParserConfig>,
+        _packet: &MetaPacket,
+        _flow_id: u64,
+    ) -> Result<()> {
+        unreachable!()
+    }
+
+    fn data_updated(&self) -> bool {
+        return self.perf_stats.is_some();
+    }
+
+    fn copy_and_reset_data(&mut self, l7_timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::ProtobufRPC,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: l7_timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::common::flow::PacketDirection;
+    use crate::common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface};
+    use crate::common::l7_protocol_log::{L7ProtocolParserInterface, ParseParam};
+
+    use crate::flow_generator::protocol_logs::{L7ResponseStatus, ProtobufRpcInfo};
+    use crate::utils::test::Capture;
+
+    use super::BrpcLog;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/brpc";
+
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        if packets.is_empty() {
+            return "".to_string();
+        }
+
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from(&*packet);
+            let is_brpc = BrpcLog::new().check_payload(payload, &param);
+            let mut parser = BrpcLog::new();
+            let _ = parser.parse_payload(payload, &param);
+            output.push_str(&format!("{:?} is_brpc: {}\r\n", parser.info, is_brpc));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![("brpc.pcap", "brpc.result")];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("brpc.pcap"), None);
+        let mut p = capture.as_meta_packets();
+        p[0].lookup_key.direction = PacketDirection::ClientToServer;
+        p[1].lookup_key.direction = PacketDirection::ServerToClient;
+
+        let mut parser = BrpcLog::new();
+        let req_param = &ParseParam::from(&p[0]);
+        let mut req_info = parser
+            .parse_payload(p[0].get_l4_payload().unwrap(), req_param)
+            .unwrap()
+            .remove(0);
+        parser.reset();
+        let resp_param = &ParseParam::from(&p[1]);
+        let resp_info = parser
+            .parse_payload(p[1].get_l4_payload().unwrap(), resp_param)
+            .unwrap()
+            .remove(0);
+
+        req_info.merge_log(resp_info).unwrap();
+        if let L7ProtocolInfo::ProtobufRpcInfo(ProtobufRpcInfo::BrpcInfo(b)) = &req_info {
+            assert_eq!(b.status, L7ResponseStatus::Ok);
+            assert_eq!(b.method_name, "Echo");
+            assert_eq!(b.resp_len, 11);
+            assert_eq!(b.session_id(), Some(7));
+        } else {
+            unreachable!()
+        }
+    }
+}
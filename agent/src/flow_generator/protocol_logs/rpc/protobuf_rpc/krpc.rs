@@ -108,18 +108,15 @@ impl L7ProtocolInfoInterface for KrpcInfo {
     }
 
     fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
-        if let L7ProtocolInfo::ProtobufRpcInfo(rpc_info) = other {
-            #[allow(irrefutable_let_patterns)]
-            if let ProtobufRpcInfo::KrpcInfo(k) = rpc_info {
-                self.ret_code = k.ret_code;
-                self.status = k.status;
+        if let L7ProtocolInfo::ProtobufRpcInfo(ProtobufRpcInfo::KrpcInfo(k)) = other {
+            self.ret_code = k.ret_code;
+            self.status = k.status;
 
-                if self.trace_id.is_empty() {
-                    self.trace_id = k.trace_id;
-                }
-                if self.span_id.is_empty() {
-                    self.span_id = k.span_id;
-                }
+            if self.trace_id.is_empty() {
+                self.trace_id = k.trace_id;
+            }
+            if self.span_id.is_empty() {
+                self.span_id = k.span_id;
             }
         }
         Ok(())
@@ -14,8 +14,10 @@
  * limitations under the License.
  */
 
+mod brpc;
 mod krpc;
 mod protobuf_rpc;
+mod trpc;
 
 pub use protobuf_rpc::*;
 
@@ -36,19 +38,25 @@ use crate::{
     },
 };
 
+use self::brpc::{BrpcInfo, BrpcLog};
 use self::krpc::{KrpcInfo, KrpcLog};
+use self::trpc::{TrpcInfo, TrpcLog};
 
 // all protobuf rpc info
 #[derive(Serialize, Clone, Debug)]
 #[enum_dispatch(L7ProtocolInfoInterface)]
 pub enum ProtobufRpcInfo {
     KrpcInfo(KrpcInfo),
+    BrpcInfo(BrpcInfo),
+    TrpcInfo(TrpcInfo),
 }
 
 impl Into<L7ProtocolSendLog> for ProtobufRpcInfo {
     fn into(self) -> L7ProtocolSendLog {
         match self {
             ProtobufRpcInfo::KrpcInfo(k) => k.into(),
+            ProtobufRpcInfo::BrpcInfo(b) => b.into(),
+            ProtobufRpcInfo::TrpcInfo(t) => t.into(),
         }
     }
 }
@@ -57,6 +65,8 @@ pub fn get_protobuf_rpc_parser(proto: ProtobufRpcProtocol) -> L7ProtocolParser {
     let mut p = ProtobufRpcWrapLog::default();
     match proto {
         ProtobufRpcProtocol::Krpc => p.set_rpc_parser(ProtobufRpcLog::KrpcLog(KrpcLog::default())),
+        ProtobufRpcProtocol::Brpc => p.set_rpc_parser(ProtobufRpcLog::BrpcLog(BrpcLog::default())),
+        ProtobufRpcProtocol::Trpc => p.set_rpc_parser(ProtobufRpcLog::TrpcLog(TrpcLog::default())),
     }
     L7ProtocolParser::ProtobufRpcParser(p)
 }
@@ -66,8 +76,14 @@ pub fn get_protobuf_rpc_parser(proto: ProtobufRpcProtocol) -> L7ProtocolParser {
 #[enum_dispatch(L7ProtocolParserInterface, L7FlowPerf)]
 pub enum ProtobufRpcLog {
     KrpcLog(KrpcLog),
+    BrpcLog(BrpcLog),
+    TrpcLog(TrpcLog),
 }
 
 fn all_protobuf_rpc_parser() -> Vec<ProtobufRpcLog> {
-    vec![ProtobufRpcLog::KrpcLog(KrpcLog::default())]
+    vec![
+        ProtobufRpcLog::KrpcLog(KrpcLog::default()),
+        ProtobufRpcLog::BrpcLog(BrpcLog::default()),
+        ProtobufRpcLog::TrpcLog(TrpcLog::default()),
+    ]
 }
@@ -0,0 +1,573 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use lru::LruCache;
+use prost::Message;
+use public::{
+    bytes::{read_u16_be, read_u32_be},
+    l7_protocol::{L7Protocol, ProtobufRpcProtocol},
+};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        flow::{FlowPerfStats, L7PerfStats, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
+            L7ResponseStatus,
+        },
+        AppProtoHead, Error, HttpLog, LogMessageType, Result,
+    },
+    perf_impl,
+};
+
+use super::ProtobufRpcInfo;
+use public::proto::protobuf_rpc::{TrpcRequestProtocol, TrpcResponseProtocol};
+
+const TRPC_MAGIC: u16 = 0x0930;
+const TRPC_FIX_HDR_LEN: usize = 16;
+const TRPC_UNARY_FRAME: u8 = 0;
+const TRPC_ONEWAY_CALL: u32 = 1;
+
+// https://github.com/trpc-group/trpc/blob/main/trpc/trpc.proto TrpcRetCode
+const TRPC_SERVER_DECODE_ERR: i32 = 1;
+const TRPC_SERVER_NOSERVICE_ERR: i32 = 11;
+const TRPC_SERVER_NOFUNC_ERR: i32 = 12;
+const TRPC_SERVER_AUTH_ERR: i32 = 41;
+const TRPC_SERVER_VALIDATE_ERR: i32 = 51;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TrpcInfo {
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+
+    msg_type: LogMessageType,
+    request_id: u32,
+    call_type: u32,
+    caller: String,
+    callee: String,
+    func: String,
+    // 框架错误码
+    // ==========
+    // framework error code
+    ret: i32,
+    // 业务错误码
+    // ==========
+    // business error code
+    func_ret: i32,
+    error_msg: String,
+
+    req_len: u32,
+    resp_len: u32,
+
+    //trace info
+    trace_id: String,
+    span_id: String,
+    status: L7ResponseStatus,
+}
+
+impl TrpcInfo {
+    fn fill_from_request(&mut self, req: TrpcRequestProtocol, config: Option<&LogParserConfig>) {
+        self.msg_type = if req.call_type == TRPC_ONEWAY_CALL {
+            LogMessageType::Session
+        } else {
+            LogMessageType::Request
+        };
+        self.request_id = req.request_id;
+        self.call_type = req.call_type;
+        self.caller = String::from_utf8_lossy(&req.caller).into_owned();
+        self.callee = String::from_utf8_lossy(&req.callee).into_owned();
+        self.func = String::from_utf8_lossy(&req.func).into_owned();
+        self.fill_trace_info(&req.trans_info, config);
+    }
+
+    fn fill_from_response(&mut self, resp: TrpcResponseProtocol) {
+        self.msg_type = LogMessageType::Response;
+        self.request_id = resp.request_id;
+        self.call_type = resp.call_type;
+        self.ret = resp.ret;
+        self.func_ret = resp.func_ret;
+        self.error_msg = String::from_utf8_lossy(&resp.error_msg).into_owned();
+        self.status = match (self.ret, self.func_ret) {
+            (0, 0) => L7ResponseStatus::Ok,
+            (
+                TRPC_SERVER_DECODE_ERR
+                | TRPC_SERVER_NOSERVICE_ERR
+                | TRPC_SERVER_NOFUNC_ERR
+                | TRPC_SERVER_AUTH_ERR
+                | TRPC_SERVER_VALIDATE_ERR,
+                _,
+            ) => L7ResponseStatus::ClientError,
+            _ => L7ResponseStatus::ServerError,
+        };
+    }
+
+    /*
+        trace 信息通过 trans_info 透传，使用与 HTTP 头相同的 trace 类型配置解析
+        ====================================================================
+        trace info is carried in trans_info, and parsed with the same trace type config as
+        HTTP headers
+    */
+    fn fill_trace_info(
+        &mut self,
+        trans_info: &HashMap<String, Vec<u8>>,
+        config: Option<&LogParserConfig>,
+    ) {
+        let Some(config) = config else {
+            return;
+        };
+        let config = &config.l7_log_dynamic;
+        for (key, val) in trans_info.iter() {
+            let key = key.to_ascii_lowercase();
+            let Ok(val) = std::str::from_utf8(val) else {
+                continue;
+            };
+            if config.is_trace_id(&key) {
+                if let Some(id) = HttpLog::decode_id(val, &key, HttpLog::TRACE_ID) {
+                    self.trace_id = id;
+                }
+            }
+            if config.is_span_id(&key) {
+                if let Some(id) = HttpLog::decode_id(val, &key, HttpLog::SPAN_ID) {
+                    self.span_id = id;
+                }
+            }
+        }
+    }
+
+    fn method(&self) -> &str {
+        // func 的格式为 /package.Service/Method
+        // ====================================
+        // format of func is /package.Service/Method
+        self.func.rsplit('/').next().unwrap_or_default()
+    }
+
+    fn code(&self) -> i32 {
+        if self.ret != 0 {
+            self.ret
+        } else {
+            self.func_ret
+        }
+    }
+}
+
+impl L7ProtocolInfoInterface for TrpcInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.request_id)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        if let L7ProtocolInfo::ProtobufRpcInfo(ProtobufRpcInfo::TrpcInfo(t)) = other {
+            self.resp_len = t.resp_len;
+            self.ret = t.ret;
+            self.func_ret = t.func_ret;
+            self.error_msg = t.error_msg;
+            self.status = t.status;
+        }
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::ProtobufRPC,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl From<TrpcInfo> for L7ProtocolSendLog {
+    fn from(t: TrpcInfo) -> Self {
+        let req_id = t.session_id();
+        let code = t.code();
+        Self {
+            req_len: Some(t.req_len),
+            resp_len: Some(t.resp_len),
+            req: L7Request {
+                req_type: t.method().to_string(),
+                resource: t.callee.clone(),
+                endpoint: t.func,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: t.status,
+                code: Some(code),
+                exception: t.error_msg,
+                ..Default::default()
+            },
+            trace_info: Some(TraceInfo {
+                trace_id: Some(t.trace_id),
+                span_id: Some(t.span_id),
+                ..Default::default()
+            }),
+            ext_info: Some(ExtendedInfo {
+                service_name: if t.caller.is_empty() {
+                    None
+                } else {
+                    Some(t.caller)
+                },
+                rpc_service: Some(t.callee),
+                request_id: req_id,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrpcLog {
+    info: TrpcInfo,
+    perf_stats: Option<PerfStats>,
+
+    parsed: bool,
+
+    // <session_id,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for TrpcLog {
+    fn default() -> Self {
+        Self {
+            previous_log_info: LruCache::new(100),
+            info: TrpcInfo::default(),
+            perf_stats: None,
+            parsed: false,
+        }
+    }
+}
+
+perf_impl!(TrpcLog);
+
+impl TrpcLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        trpc hdr reference https://github.com/trpc-group/trpc/blob/main/docs/cn/trpc_protocol_design.md
+
+        0  .......8........16........24.........32
+        1  |-----magic------|--type--|stream type|
+        2  |---------------total_size-------------|
+        3  |---header_size--|-------stream_id-----
+        4  ---------------|-------reserved-------|
+
+        帧头后为 header_size 字节的 RequestProtocol 或 ResponseProtocol，帧头中没有请求和响应的标记，
+        请求必须带有 func 字段
+        ================================================================================================
+        RequestProtocol or ResponseProtocol of header_size bytes follows the frame header, there is no
+        flag of request or response in frame header, and request must have func field
+    */
+    fn parse(
+        &mut self,
+        payload: &[u8],
+        param: &ParseParam,
+        strict: bool,
+    ) -> Result<Vec<L7ProtocolInfo>> {
+        if self.parsed {
+            return Ok(vec![L7ProtocolInfo::ProtobufRpcInfo(
+                ProtobufRpcInfo::TrpcInfo(self.info.clone()),
+            )]);
+        }
+        self.info.start_time = param.time;
+        self.info.end_time = param.time;
+        if payload.len() < TRPC_FIX_HDR_LEN || read_u16_be(payload) != TRPC_MAGIC {
+            return Err(Error::L7ProtocolUnknown);
+        }
+        // 流式帧不解析
+        // ============
+        // stream frames are not parsed
+        if payload[2] != TRPC_UNARY_FRAME {
+            return Err(Error::L7ProtocolUnknown);
+        }
+
+        let total_size = read_u32_be(&payload[4..]);
+        let hdr_size = read_u16_be(&payload[8..]) as usize;
+        if hdr_size + TRPC_FIX_HDR_LEN > total_size as usize {
+            return Err(Error::L7ProtocolUnknown);
+        }
+
+        let pb_payload = if hdr_size + TRPC_FIX_HDR_LEN > payload.len() {
+            // if hdr_size + TRPC_FIX_HDR_LEN > payload.len() likely ebpf not read full data from syscall, pb parse to the payload end.
+            if strict {
+                return Err(Error::L7ProtocolUnknown);
+            }
+            &payload[TRPC_FIX_HDR_LEN..]
+        } else {
+            &payload[TRPC_FIX_HDR_LEN..TRPC_FIX_HDR_LEN + hdr_size]
+        };
+
+        match TrpcRequestProtocol::decode(pb_payload) {
+            Ok(req) if !req.func.is_empty() => {
+                self.info.fill_from_request(req, param.parse_config);
+                self.info.req_len = total_size;
+            }
+            _ => {
+                let resp = match TrpcResponseProtocol::decode(pb_payload) {
+                    Ok(resp) => resp,
+                    Err(_) if strict => return Err(Error::L7ProtocolUnknown),
+                    Err(_) => TrpcResponseProtocol::default(),
+                };
+                self.info.fill_from_response(resp);
+                self.info.resp_len = total_size;
+            }
+        }
+
+        match self.info.msg_type {
+            LogMessageType::Request | LogMessageType::Session => self.perf_inc_req(param.time),
+            LogMessageType::Response => {
+                self.perf_inc_resp(param.time);
+                match self.info.status {
+                    L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+                    L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+                    _ => {}
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::ProtobufRpcInfo(
+            ProtobufRpcInfo::TrpcInfo(self.info.clone()),
+        )])
+    }
+}
+
+impl L7ProtocolParserInterface for TrpcLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        self.info.start_time = param.time;
+        self.info.end_time = param.time;
+        self.parsed = self.parse(payload, param, true).is_ok();
+        self.parsed && self.info.msg_type != LogMessageType::Response
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        self.parse(payload, param, false)
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::ProtobufRPC
+    }
+
+    fn protobuf_rpc_protocol(&self) -> Option<ProtobufRpcProtocol> {
+        Some(ProtobufRpcProtocol::Trpc)
+    }
+
+    fn reset(&mut self) {
+        self.parsed = false;
+        self.save_info_time();
+        self.info = TrpcInfo::default();
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+}
+
+impl L7FlowPerf for TrpcLog {
+    fn parse(
+        &mut self,
+        _: Option<&LogParserConfig>,
+        _packet: &MetaPacket,
+        _flow_id: u64,
+    ) -> Result<()> {
+        unreachable!()
+    }
+
+    fn data_updated(&self) -> bool {
+        return self.perf_stats.is_some();
+    }
+
+    fn copy_and_reset_data(&mut self, l7_timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::ProtobufRPC,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: l7_timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use crate::common::flow::PacketDirection;
+    use crate::common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface};
+    use crate::common::l7_protocol_log::{L7ProtocolParserInterface, ParseParam};
+    use crate::config::handler::{L7LogDynamicConfig, LogParserConfig, TraceType};
+    use crate::flow_generator::protocol_logs::{pb_adapter::L7ProtocolSendLog, ProtobufRpcInfo};
+    use crate::utils::test::Capture;
+
+    use super::TrpcLog;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/trpc";
+
+    fn config() -> LogParserConfig {
+        LogParserConfig {
+            l7_log_collect_nps_threshold: 0,
+            l7_log_session_aggr_timeout: Duration::from_secs(120),
+            l7_log_dynamic: L7LogDynamicConfig::new(
+                "".to_owned(),
+                "".to_owned(),
+                vec![TraceType::TraceParent],
+                vec![TraceType::TraceParent],
+            ),
+        }
+    }
+
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        if packets.is_empty() {
+            return "".to_string();
+        }
+
+        let config = config();
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from((&*packet, &config));
+            let is_trpc = TrpcLog::new().check_payload(payload, &param);
+            let mut parser = TrpcLog::new();
+            let _ = parser.parse_payload(payload, &param);
+            output.push_str(&format!("{:?} is_trpc: {}\r\n", parser.info, is_trpc));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![("trpc.pcap", "trpc.result")];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("trpc.pcap"), None);
+        let mut p = capture.as_meta_packets();
+        p[0].lookup_key.direction = PacketDirection::ClientToServer;
+        p[1].lookup_key.direction = PacketDirection::ServerToClient;
+        let config = config();
+
+        let mut parser = TrpcLog::new();
+        let req_param = &ParseParam::from((&p[0], &config));
+        let mut req_info = parser
+            .parse_payload(p[0].get_l4_payload().unwrap(), req_param)
+            .unwrap()
+            .remove(0);
+        parser.reset();
+        let resp_param = &ParseParam::from((&p[1], &config));
+        let resp_info = parser
+            .parse_payload(p[1].get_l4_payload().unwrap(), resp_param)
+            .unwrap()
+            .remove(0);
+
+        req_info.merge_log(resp_info).unwrap();
+        assert_eq!(req_info.session_id(), Some(1));
+        if let L7ProtocolInfo::ProtobufRpcInfo(rpc_info) = req_info {
+            let log: L7ProtocolSendLog = rpc_info.into();
+            assert_eq!(log.req.req_type, "SayHello");
+            assert_eq!(log.req.endpoint, "/trpc.test.helloworld.Greeter/SayHello");
+            assert_eq!(log.resp.code, Some(0));
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn business_error_code() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("trpc.pcap"), None);
+        let mut p = capture.as_meta_packets();
+        p[3].lookup_key.direction = PacketDirection::ServerToClient;
+
+        let mut parser = TrpcLog::new();
+        let param = &ParseParam::from((&p[3], &config()));
+        let resp_info = parser
+            .parse_payload(p[3].get_l4_payload().unwrap(), param)
+            .unwrap()
+            .remove(0);
+        if let L7ProtocolInfo::ProtobufRpcInfo(ProtobufRpcInfo::TrpcInfo(t)) = &resp_info {
+            assert_eq!(t.code(), 1024);
+        } else {
+            unreachable!()
+        }
+    }
+}
@@ -29,12 +29,12 @@ use crate::{
         l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
         MetaPacket,
     },
-    config::handler::LogParserConfig,
+    config::handler::{L7LogDynamicConfig, LogParserConfig},
     flow_generator::{
         perf::{L7FlowPerf, PerfStats},
         protocol_logs::{
             pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo},
-            L7ResponseStatus,
+            L7ResponseStatus, TraceContext,
         },
         AppProtoHead, Error, HttpLog, LogMessageType, Result,
     },
@@ -373,7 +373,7 @@ impl SofaRpcLog {
                 &payload[..hdr_len]
             };
 
-            let sofa_hdr = SofaHdr::from(hdr_payload);
+            let sofa_hdr = SofaHdr::new(hdr_payload, param.parse_config.map(|c| &c.l7_log_dynamic));
             self.info.target_serv = sofa_hdr.service;
             self.info.method = sofa_hdr.method;
             self.info.trace_id = sofa_hdr.trace_id;
@@ -385,6 +385,15 @@ impl SofaRpcLog {
             if !sofa_hdr.new_rpc_trace_context.is_empty() {
                 self.fill_with_trace_ctx(sofa_hdr.new_rpc_trace_context);
             }
+            // 按配置解析的链路追踪头部优先
+            let trace = sofa_hdr.trace;
+            if !trace.trace_id.is_empty() {
+                self.info.trace_id = trace.trace_id;
+            }
+            if !trace.span_id.is_empty() {
+                self.info.span_id = trace.span_id;
+                self.info.parent_span_id = trace.parent_span_id;
+            }
         }
         self.revert_info_time(param.direction, param.time);
         Ok(vec![L7ProtocolInfo::SofaRpcInfo(self.info.clone())])
@@ -451,6 +460,8 @@ struct SofaHdr {
     method: String,
     trace_id: String,
     new_rpc_trace_context: String,
+    // 其他配置的链路追踪头部
+    trace: TraceContext,
 }
 
 /*
@@ -465,13 +476,14 @@ struct SofaHdr {
 
     the const var define in source: https://github.com/sofastack/sofa-rpc/blob/7931102255d6ea95ee75676d368aad37c56b57ee/core/api/src/main/java/com/alipay/sofa/rpc/common/RemotingConstants.java
 */
-impl From<&[u8]> for SofaHdr {
-    fn from(mut payload: &[u8]) -> Self {
+impl SofaHdr {
+    fn new(mut payload: &[u8], config: Option<&L7LogDynamicConfig>) -> Self {
         let mut ret = Self {
             service: "".to_string(),
             method: "".to_string(),
             trace_id: "".to_string(),
             new_rpc_trace_context: "".to_string(),
+            trace: TraceContext::default(),
         };
         while let Some((key, val)) = read_b32_kv(&mut payload) {
             let Ok(key_str) = std::str::from_utf8(key) else{
@@ -484,7 +496,11 @@ impl From<&[u8]> for SofaHdr {
                 SOFA_NEW_RPC_TRACE_CTX_KEY => {
                     ret.new_rpc_trace_context = String::from_utf8_lossy(val).to_string()
                 }
-                _ => {}
+                _ => {
+                    if let (Some(config), Ok(val)) = (config, std::str::from_utf8(val)) {
+                        ret.trace.on_header(config, &key_str.to_ascii_lowercase(), val);
+                    }
+                }
             }
         }
         ret
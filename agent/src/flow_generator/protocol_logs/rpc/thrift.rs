@@ -0,0 +1,792 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use public::{
+    bytes::{read_u16_be, read_u32_be},
+    l7_protocol::L7Protocol,
+};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+};
+
+// https://github.com/apache/thrift/blob/master/doc/specs/thrift-binary-protocol.md
+// https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+const FRAME_HEADER_LEN: usize = 4;
+// TFramedTransport 默认的最大帧长度
+// ================================
+// default max frame length of TFramedTransport
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+const BINARY_VERSION_MASK: u32 = 0xffff0000;
+const BINARY_VERSION_1: u32 = 0x80010000;
+
+const COMPACT_PROTOCOL_ID: u8 = 0x82;
+const COMPACT_VERSION: u8 = 1;
+const COMPACT_VERSION_MASK: u8 = 0x1f;
+const COMPACT_TYPE_SHIFT: u8 = 5;
+
+const MESSAGE_CALL: u8 = 1;
+const MESSAGE_REPLY: u8 = 2;
+const MESSAGE_EXCEPTION: u8 = 3;
+const MESSAGE_ONEWAY: u8 = 4;
+
+const MAX_METHOD_LEN: usize = 256;
+
+// TApplicationException 的字段
+// ===========================
+// fields of TApplicationException
+const EXCEPTION_FIELD_MESSAGE: i16 = 1;
+const EXCEPTION_FIELD_TYPE: i16 = 2;
+
+fn message_type_name(typ: u8) -> Option<&'static str> {
+    match typ {
+        MESSAGE_CALL => Some("CALL"),
+        MESSAGE_REPLY => Some("REPLY"),
+        MESSAGE_EXCEPTION => Some("EXCEPTION"),
+        MESSAGE_ONEWAY => Some("ONEWAY"),
+        _ => None,
+    }
+}
+
+// https://github.com/apache/thrift/blob/master/lib/rs/src/errors.rs
+fn application_exception_name(typ: i32) -> &'static str {
+    match typ {
+        1 => "UNKNOWN_METHOD",
+        2 => "INVALID_MESSAGE_TYPE",
+        3 => "WRONG_METHOD_NAME",
+        4 => "BAD_SEQUENCE_ID",
+        5 => "MISSING_RESULT",
+        6 => "INTERNAL_ERROR",
+        7 => "PROTOCOL_ERROR",
+        8 => "INVALID_TRANSFORM",
+        9 => "INVALID_PROTOCOL",
+        10 => "UNSUPPORTED_CLIENT_TYPE",
+        _ => "UNKNOWN",
+    }
+}
+
+// 方法名只包含标识符字符，TMultiplexedProtocol 使用 "service:method"
+// ================================================================
+// method name only contains identifier characters, and TMultiplexedProtocol uses "service:method"
+fn is_method_name(name: &[u8]) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_METHOD_LEN
+        && name
+            .iter()
+            .all(|c| c.is_ascii_alphanumeric() || b"_.:$-".contains(c))
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ThriftProtocol {
+    #[default]
+    Binary,
+    Compact,
+}
+
+impl ThriftProtocol {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ThriftProtocol::Binary => "binary",
+            ThriftProtocol::Compact => "compact",
+        }
+    }
+}
+
+struct Message<'a> {
+    protocol: ThriftProtocol,
+    message_type: u8,
+    name: &'a [u8],
+    seq_id: i32,
+    body: &'a [u8],
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(v)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(read_u16_be(self.take(2)?) as i16)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(read_u32_be(self.take(4)?) as i32)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            value |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn zigzag(&mut self) -> Option<i64> {
+        let v = self.varint()?;
+        Some((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+}
+
+/*
+    TBinaryProtocol 严格模式:
+    | version 2B (0x8001) | unused 1B | type 1B | name length 4B | name | seqid 4B |
+    非严格模式:
+    | name length 4B | name | type 1B | seqid 4B |
+    ==============================================================================
+    TBinaryProtocol strict mode:
+    | version 2B (0x8001) | unused 1B | type 1B | name length 4B | name | seqid 4B |
+    non-strict mode:
+    | name length 4B | name | type 1B | seqid 4B |
+*/
+fn read_binary_message(payload: &[u8]) -> Option<Message> {
+    let mut r = Reader(payload);
+    let first = r.i32()? as u32;
+    let (message_type, name, seq_id) = if first & BINARY_VERSION_MASK == BINARY_VERSION_1 {
+        let message_type = first as u8;
+        let len = r.i32()?;
+        if len < 0 {
+            return None;
+        }
+        let name = r.take(len as usize)?;
+        (message_type, name, r.i32()?)
+    } else {
+        if first as usize > MAX_METHOD_LEN {
+            return None;
+        }
+        let name = r.take(first as usize)?;
+        (r.u8()?, name, r.i32()?)
+    };
+    if message_type_name(message_type).is_none() || !is_method_name(name) {
+        return None;
+    }
+    Some(Message {
+        protocol: ThriftProtocol::Binary,
+        message_type,
+        name,
+        seq_id,
+        body: r.0,
+    })
+}
+
+/*
+    TCompactProtocol:
+    | protocol id 1B (0x82) | type 3b version 5b | seqid varint | name length varint | name |
+    ========================================================================================
+    TCompactProtocol:
+    | protocol id 1B (0x82) | type 3b version 5b | seqid varint | name length varint | name |
+*/
+fn read_compact_message(payload: &[u8]) -> Option<Message> {
+    let mut r = Reader(payload);
+    if r.u8()? != COMPACT_PROTOCOL_ID {
+        return None;
+    }
+    let b = r.u8()?;
+    if b & COMPACT_VERSION_MASK != COMPACT_VERSION {
+        return None;
+    }
+    let message_type = b >> COMPACT_TYPE_SHIFT;
+    let seq_id = r.varint()? as i32;
+    let len = r.varint()? as usize;
+    if len > MAX_METHOD_LEN {
+        return None;
+    }
+    let name = r.take(len)?;
+    if message_type_name(message_type).is_none() || !is_method_name(name) {
+        return None;
+    }
+    Some(Message {
+        protocol: ThriftProtocol::Compact,
+        message_type,
+        name,
+        seq_id,
+        body: r.0,
+    })
+}
+
+fn read_message(payload: &[u8]) -> Option<Message> {
+    if payload.first() == Some(&COMPACT_PROTOCOL_ID) {
+        read_compact_message(payload)
+    } else {
+        read_binary_message(payload)
+    }
+}
+
+/*
+    TFramedTransport 在消息前增加 4 字节大端长度，优先按帧解析
+    返回值的第二项表示是否使用了 TFramedTransport
+    =========================================================
+    TFramedTransport prepends 4 bytes big endian length to the message, try frame first
+    the second item of return value indicates whether TFramedTransport is used
+*/
+fn read_framed_or_unframed(payload: &[u8]) -> Option<(Message, bool)> {
+    if payload.len() > FRAME_HEADER_LEN {
+        let len = read_u32_be(payload) as usize;
+        if len >= payload.len() - FRAME_HEADER_LEN && len <= MAX_FRAME_LEN {
+            if let Some(message) = read_message(&payload[FRAME_HEADER_LEN..]) {
+                return Some((message, true));
+            }
+        }
+    }
+    read_message(payload).map(|m| (m, false))
+}
+
+/*
+    读取 TApplicationException 的 message 和 type 字段，遇到不认识的字段类型时停止
+    ==============================================================================
+    read message and type fields of TApplicationException, and stop on unknown field types
+*/
+fn read_binary_exception(body: &[u8]) -> (String, Option<i32>) {
+    const T_STOP: u8 = 0;
+    const T_BOOL: u8 = 2;
+    const T_BYTE: u8 = 3;
+    const T_DOUBLE: u8 = 4;
+    const T_I16: u8 = 6;
+    const T_I32: u8 = 8;
+    const T_I64: u8 = 10;
+    const T_STRING: u8 = 11;
+
+    let mut r = Reader(body);
+    let (mut message, mut typ) = (String::new(), None);
+    loop {
+        let Some(field_type) = r.u8() else {
+            break;
+        };
+        if field_type == T_STOP {
+            break;
+        }
+        let Some(id) = r.i16() else {
+            break;
+        };
+        let skipped = match field_type {
+            T_STRING => {
+                let Some(value) = r.i32().and_then(|len| r.take(len.max(0) as usize)) else {
+                    break;
+                };
+                if id == EXCEPTION_FIELD_MESSAGE {
+                    message = String::from_utf8_lossy(value).into_owned();
+                }
+                Some(())
+            }
+            T_I32 => {
+                let Some(value) = r.i32() else {
+                    break;
+                };
+                if id == EXCEPTION_FIELD_TYPE {
+                    typ = Some(value);
+                }
+                Some(())
+            }
+            T_BOOL | T_BYTE => r.take(1).map(|_| ()),
+            T_I16 => r.take(2).map(|_| ()),
+            T_DOUBLE | T_I64 => r.take(8).map(|_| ()),
+            _ => None,
+        };
+        if skipped.is_none() {
+            break;
+        }
+    }
+    (message, typ)
+}
+
+/*
+    compact 字段头: | delta 4b | type 4b |，delta 为 0 时后跟 zigzag varint 的字段 id
+    ================================================================================
+    compact field header: | delta 4b | type 4b |, zigzag varint field id follows when delta is 0
+*/
+fn read_compact_exception(body: &[u8]) -> (String, Option<i32>) {
+    const CT_STOP: u8 = 0;
+    const CT_BOOLEAN_TRUE: u8 = 1;
+    const CT_BOOLEAN_FALSE: u8 = 2;
+    const CT_BYTE: u8 = 3;
+    const CT_I16: u8 = 4;
+    const CT_I32: u8 = 5;
+    const CT_I64: u8 = 6;
+    const CT_DOUBLE: u8 = 7;
+    const CT_BINARY: u8 = 8;
+
+    let mut r = Reader(body);
+    let (mut message, mut typ) = (String::new(), None);
+    let mut last_id = 0i16;
+    loop {
+        let Some(header) = r.u8() else {
+            break;
+        };
+        let field_type = header & 0x0f;
+        if field_type == CT_STOP {
+            break;
+        }
+        let delta = header >> 4;
+        let id = if delta == 0 {
+            let Some(id) = r.zigzag() else {
+                break;
+            };
+            id as i16
+        } else {
+            last_id.wrapping_add(delta as i16)
+        };
+        last_id = id;
+        let skipped = match field_type {
+            CT_BINARY => {
+                let Some(value) = r.varint().and_then(|len| r.take(len as usize)) else {
+                    break;
+                };
+                if id == EXCEPTION_FIELD_MESSAGE {
+                    message = String::from_utf8_lossy(value).into_owned();
+                }
+                Some(())
+            }
+            CT_I32 => {
+                let Some(value) = r.zigzag() else {
+                    break;
+                };
+                if id == EXCEPTION_FIELD_TYPE {
+                    typ = Some(value as i32);
+                }
+                Some(())
+            }
+            CT_BOOLEAN_TRUE | CT_BOOLEAN_FALSE => Some(()),
+            CT_BYTE => r.take(1).map(|_| ()),
+            CT_I16 | CT_I64 => r.zigzag().map(|_| ()),
+            CT_DOUBLE => r.take(8).map(|_| ()),
+            _ => None,
+        };
+        if skipped.is_none() {
+            break;
+        }
+    }
+    (message, typ)
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct ThriftInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    pub protocol: ThriftProtocol,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub framed: bool,
+    #[serde(skip)]
+    pub message_type: u8,
+    #[serde(rename = "request_type")]
+    pub message_type_name: &'static str,
+    pub seq_id: i32,
+    // TMultiplexedProtocol 的服务名
+    // ============================
+    // service name of TMultiplexedProtocol
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub service: String,
+    #[serde(rename = "request_resource")]
+    pub method: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub exception_type: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for ThriftInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.seq_id as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, ThriftInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Thrift,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl ThriftInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.resp_len = other.resp_len;
+        self.exception_type = other.exception_type;
+        self.exception = other.exception;
+        self.status = other.status;
+    }
+}
+
+impl From<ThriftInfo> for L7ProtocolSendLog {
+    fn from(f: ThriftInfo) -> Self {
+        let mut attributes = vec![KeyVal {
+            key: "protocol".to_string(),
+            val: f.protocol.as_str().to_string(),
+        }];
+        if f.framed {
+            attributes.push(KeyVal {
+                key: "transport".to_string(),
+                val: "framed".to_string(),
+            });
+        }
+        let endpoint = if f.service.is_empty() {
+            f.method.clone()
+        } else {
+            format!("{}/{}", f.service, f.method)
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: String::from(f.message_type_name),
+                domain: f.service.clone(),
+                resource: f.method,
+                endpoint,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.exception_type,
+                exception: f.exception,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                rpc_service: if f.service.is_empty() {
+                    None
+                } else {
+                    Some(f.service)
+                },
+                request_id: Some(f.seq_id as u32),
+                attributes: Some(attributes),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThriftLog {
+    info: ThriftInfo,
+    perf_stats: Option<PerfStats>,
+    // <seqid,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for ThriftLog {
+    fn default() -> Self {
+        Self {
+            info: ThriftInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+        }
+    }
+}
+
+perf_impl!(ThriftLog);
+
+impl L7ProtocolParserInterface for ThriftLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        // 协议识别时只接受请求
+        // ====================
+        // only requests are accepted on protocol check
+        match read_framed_or_unframed(payload) {
+            Some((message, _)) => {
+                message.message_type == MESSAGE_CALL || message.message_type == MESSAGE_ONEWAY
+            }
+            None => false,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        self.parse(payload)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        if self.info.status == L7ResponseStatus::ServerError {
+            self.perf_inc_resp_err();
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::ThriftInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Thrift
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = ThriftInfo::default();
+    }
+}
+
+impl ThriftLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        let Some((message, framed)) = read_framed_or_unframed(payload) else {
+            return Err(Error::ThriftLogParseFailed);
+        };
+        let info = &mut self.info;
+        info.protocol = message.protocol;
+        info.framed = framed;
+        info.message_type = message.message_type;
+        // message type was checked in read_message
+        info.message_type_name = message_type_name(message.message_type).unwrap_or_default();
+        info.seq_id = message.seq_id;
+        let name = String::from_utf8_lossy(message.name);
+        match name.split_once(':') {
+            Some((service, method)) => {
+                info.service = service.to_string();
+                info.method = method.to_string();
+            }
+            None => info.method = name.into_owned(),
+        }
+
+        let len = Some(payload.len() as u32);
+        match message.message_type {
+            MESSAGE_CALL => {
+                info.msg_type = LogMessageType::Request;
+                info.req_len = len;
+            }
+            // ONEWAY 请求没有响应
+            // ===================
+            // ONEWAY requests have no response
+            MESSAGE_ONEWAY => {
+                info.msg_type = LogMessageType::Session;
+                info.req_len = len;
+            }
+            MESSAGE_REPLY => {
+                info.msg_type = LogMessageType::Response;
+                info.resp_len = len;
+            }
+            _ => {
+                info.msg_type = LogMessageType::Response;
+                info.resp_len = len;
+                info.status = L7ResponseStatus::ServerError;
+                let (message, typ) = match message.protocol {
+                    ThriftProtocol::Binary => read_binary_exception(message.body),
+                    ThriftProtocol::Compact => read_compact_exception(message.body),
+                };
+                info.exception_type = typ;
+                info.exception = if message.is_empty() {
+                    application_exception_name(typ.unwrap_or_default()).to_string()
+                } else {
+                    message
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+impl L7FlowPerf for ThriftLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::Thrift,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/thrift";
+
+    fn run(name: &str) -> Vec<(bool, ThriftInfo)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let is_thrift = ThriftLog::new().check_payload(payload, &ParseParam::from(&*packet));
+            let mut parser = ThriftLog::new();
+            let _ = parser.parse(payload);
+            output.push((is_thrift, parser.info));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("thrift_binary.pcap", "thrift_binary.result"),
+            ("thrift_compact.pcap", "thrift_compact.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output: String = run(item.0)
+                .iter()
+                .map(|(is_thrift, info)| format!("{:?} is_thrift: {}\r\n", info, is_thrift))
+                .collect();
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merge_and_send_log() {
+        let output = run("thrift_binary.pcap");
+        let mut info = output[4].1.clone();
+        info.merge_log(L7ProtocolInfo::ThriftInfo(output[5].1.clone()))
+            .unwrap();
+        assert!(info.resp_len.is_some());
+        let log = L7ProtocolSendLog::from(info);
+        assert_eq!(log.req.endpoint, "UserService/listUsers");
+        assert_eq!(log.ext_info.unwrap().rpc_service.unwrap(), "UserService");
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("thrift_binary.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = ThriftLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 3);
+        assert_eq!(stats.response_count, 3);
+        assert_eq!(stats.err_server_count, 1);
+        assert_eq!(stats.rrt_count, 3);
+    }
+}
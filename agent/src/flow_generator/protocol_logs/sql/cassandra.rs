@@ -0,0 +1,853 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::{read_u16_be, read_u32_be},
+};
+
+// https://github.com/apache/cassandra/blob/trunk/doc/native_protocol_v4.spec
+// https://github.com/apache/cassandra/blob/trunk/doc/native_protocol_v5.spec
+const FRAME_HEADER_LEN: usize = 9;
+const MIN_VERSION: u8 = 3;
+const MAX_VERSION: u8 = 5;
+const RESPONSE_FLAG: u8 = 0x80;
+// 256MB, the max frame length of native protocol
+const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+const PREPARED_STATEMENTS_MAX: usize = 100;
+
+const FLAG_COMPRESSION: u8 = 0x01;
+const FLAG_TRACING: u8 = 0x02;
+const FLAG_CUSTOM_PAYLOAD: u8 = 0x04;
+const FLAG_WARNING: u8 = 0x08;
+
+const OPCODE_ERROR: u8 = 0x00;
+const OPCODE_STARTUP: u8 = 0x01;
+const OPCODE_OPTIONS: u8 = 0x05;
+const OPCODE_QUERY: u8 = 0x07;
+const OPCODE_RESULT: u8 = 0x08;
+const OPCODE_PREPARE: u8 = 0x09;
+const OPCODE_EXECUTE: u8 = 0x0a;
+const OPCODE_REGISTER: u8 = 0x0b;
+const OPCODE_BATCH: u8 = 0x0d;
+const OPCODE_AUTH_RESPONSE: u8 = 0x0f;
+
+const RESULT_VOID: u32 = 0x0001;
+const RESULT_ROWS: u32 = 0x0002;
+const RESULT_SET_KEYSPACE: u32 = 0x0003;
+const RESULT_PREPARED: u32 = 0x0004;
+const RESULT_SCHEMA_CHANGE: u32 = 0x0005;
+
+const ROWS_FLAG_GLOBAL_TABLES_SPEC: u32 = 0x0001;
+const ROWS_FLAG_HAS_MORE_PAGES: u32 = 0x0002;
+const ROWS_FLAG_NO_METADATA: u32 = 0x0004;
+const ROWS_FLAG_METADATA_CHANGED: u32 = 0x0008;
+
+const BATCH_KIND_PREPARED: u8 = 1;
+
+// v5 帧被封装在 segment 中，segment 头部为 3 字节长度和标记，后跟 3 字节 CRC24
+// ==========================================================================
+// v5 frames are wrapped in segments, the segment header is 3 bytes of length and flag,
+// followed by 3 bytes of CRC24
+const SEGMENT_HEADER_LEN: usize = 6;
+const SEGMENT_PAYLOAD_LEN_MASK: u32 = 0x1ffff;
+const CRC24_INIT: u32 = 0x875060;
+const CRC24_POLY: u32 = 0x1974f0b;
+
+const STREAM_EVENT: i16 = -1;
+
+fn opcode_name(opcode: u8) -> Option<&'static str> {
+    match opcode {
+        OPCODE_ERROR => Some("ERROR"),
+        OPCODE_STARTUP => Some("STARTUP"),
+        0x02 => Some("READY"),
+        0x03 => Some("AUTHENTICATE"),
+        OPCODE_OPTIONS => Some("OPTIONS"),
+        0x06 => Some("SUPPORTED"),
+        OPCODE_QUERY => Some("QUERY"),
+        OPCODE_RESULT => Some("RESULT"),
+        OPCODE_PREPARE => Some("PREPARE"),
+        OPCODE_EXECUTE => Some("EXECUTE"),
+        OPCODE_REGISTER => Some("REGISTER"),
+        0x0c => Some("EVENT"),
+        OPCODE_BATCH => Some("BATCH"),
+        0x0e => Some("AUTH_CHALLENGE"),
+        OPCODE_AUTH_RESPONSE => Some("AUTH_RESPONSE"),
+        0x10 => Some("AUTH_SUCCESS"),
+        _ => None,
+    }
+}
+
+fn is_request_opcode(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        OPCODE_STARTUP
+            | OPCODE_OPTIONS
+            | OPCODE_QUERY
+            | OPCODE_PREPARE
+            | OPCODE_EXECUTE
+            | OPCODE_REGISTER
+            | OPCODE_BATCH
+            | OPCODE_AUTH_RESPONSE
+    )
+}
+
+fn result_kind_name(kind: u32) -> &'static str {
+    match kind {
+        RESULT_VOID => "Void",
+        RESULT_ROWS => "Rows",
+        RESULT_SET_KEYSPACE => "Set_keyspace",
+        RESULT_PREPARED => "Prepared",
+        RESULT_SCHEMA_CHANGE => "Schema_change",
+        _ => "",
+    }
+}
+
+/*
+    错误码:
+    0x000A Protocol_error, 0x0100 Bad_credentials, 0x2xxx 请求错误（语法错误、无权限、
+    非法请求、配置错误、已存在、未预编译）为客户端错误，其余（服务端错误、不可用、过载、
+    超时等）为服务端错误
+    ==================================================================================
+    error code:
+    0x000A Protocol_error, 0x0100 Bad_credentials and 0x2xxx request errors (Syntax_error,
+    Unauthorized, Invalid, Config_error, Already_exists, Unprepared) are client errors, and
+    others (Server_error, Unavailable, Overloaded, timeouts and so on) are server errors
+*/
+fn error_code_status(code: i32) -> L7ResponseStatus {
+    match code {
+        0x000a | 0x0100 | 0x2000..=0x2fff => L7ResponseStatus::ClientError,
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+fn crc24(mut bytes: u64, len: usize) -> u32 {
+    let mut crc = CRC24_INIT;
+    for _ in 0..len {
+        crc ^= ((bytes & 0xff) as u32) << 16;
+        bytes >>= 8;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc
+}
+
+fn read_u24_le(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8 | (data[2] as u32) << 16
+}
+
+// 返回未压缩 segment 的负载，CRC24 校验失败时返回 None
+// ====================================================
+// return payload of uncompressed segment, None if CRC24 check fails
+fn read_segment(data: &[u8]) -> Option<&[u8]> {
+    if data.len() < SEGMENT_HEADER_LEN {
+        return None;
+    }
+    let header = read_u24_le(data);
+    if crc24(header as u64, 3) & 0xffffff != read_u24_le(&data[3..]) {
+        return None;
+    }
+    let len = (header & SEGMENT_PAYLOAD_LEN_MASK) as usize;
+    let payload = &data[SEGMENT_HEADER_LEN..];
+    Some(&payload[..len.min(payload.len())])
+}
+
+struct Frame<'a> {
+    version: u8,
+    flags: u8,
+    stream: i16,
+    opcode: u8,
+    length: u32,
+    body: &'a [u8],
+}
+
+fn read_frame(data: &[u8]) -> Option<Frame<'_>> {
+    if data.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+    let version = data[0] & !RESPONSE_FLAG;
+    if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+        return None;
+    }
+    let length = read_u32_be(&data[5..]);
+    if length > MAX_FRAME_LEN {
+        return None;
+    }
+    opcode_name(data[4])?;
+    let body = &data[FRAME_HEADER_LEN..];
+    Some(Frame {
+        version: data[0],
+        flags: data[1],
+        stream: read_u16_be(&data[2..]) as i16,
+        opcode: data[4],
+        length,
+        body: &body[..(length as usize).min(body.len())],
+    })
+}
+
+struct Body<'a>(&'a [u8]);
+
+impl<'a> Body<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(v)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn short(&mut self) -> Option<u16> {
+        Some(read_u16_be(self.take(2)?))
+    }
+
+    fn int(&mut self) -> Option<i32> {
+        Some(read_u32_be(self.take(4)?) as i32)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.short()? as usize;
+        Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    // 长字符串被截断时返回已有部分
+    // ============================
+    // return the available part when long string is truncated
+    fn long_string(&mut self) -> Option<String> {
+        let len = self.int()?;
+        if len < 0 {
+            return None;
+        }
+        let s = &self.0[..(len as usize).min(self.0.len())];
+        self.0 = &self.0[s.len()..];
+        std::str::from_utf8(s).ok().map(|s| s.to_string())
+    }
+
+    fn short_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.short()? as usize;
+        self.take(len)
+    }
+
+    fn bytes(&mut self) -> Option<()> {
+        let len = self.int()?;
+        if len > 0 {
+            self.take(len as usize)?;
+        }
+        Some(())
+    }
+
+    fn skip_string_list(&mut self) -> Option<()> {
+        for _ in 0..self.short()? {
+            self.string()?;
+        }
+        Some(())
+    }
+
+    fn skip_bytes_map(&mut self) -> Option<()> {
+        for _ in 0..self.short()? {
+            self.string()?;
+            self.bytes()?;
+        }
+        Some(())
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct CassandraInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: u8,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub stream: i16,
+    #[serde(skip)]
+    pub opcode: u8,
+    #[serde(rename = "request_type")]
+    pub opcode_name: &'static str,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub query: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub keyspace: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub prepared_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub batch_size: u16,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub result_kind: &'static str,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for CassandraInfo {
+    fn session_id(&self) -> Option<u32> {
+        Some(self.stream as u16 as u32)
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, CassandraInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Cassandra,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl CassandraInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.resp_len = other.resp_len;
+        self.result_kind = other.result_kind;
+        self.error_code = other.error_code;
+        self.error_message = other.error_message;
+        self.status = other.status;
+        if self.keyspace.is_empty() {
+            self.keyspace = other.keyspace;
+        }
+        if self.prepared_id.is_empty() {
+            self.prepared_id = other.prepared_id;
+        }
+    }
+}
+
+impl From<CassandraInfo> for L7ProtocolSendLog {
+    fn from(f: CassandraInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.prepared_id.is_empty() {
+            attributes.push(KeyVal {
+                key: "prepared_id".to_string(),
+                val: f.prepared_id,
+            });
+        }
+        if f.batch_size > 0 {
+            attributes.push(KeyVal {
+                key: "batch_size".to_string(),
+                val: f.batch_size.to_string(),
+            });
+        }
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: String::from(f.opcode_name),
+                domain: f.keyspace,
+                resource: f.query,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code,
+                exception: f.error_message,
+                result: String::from(f.result_kind),
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.stream as u16 as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CassandraLog {
+    info: CassandraInfo,
+    perf_stats: Option<PerfStats>,
+    // <stream,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+    // 等待 Prepared 结果的 PREPARE 语句，<stream,query>，在第一个 PREPARE 时创建
+    // ===========================================================================
+    // PREPARE statements waiting for the Prepared result, <stream,query>,
+    // created on the first PREPARE
+    #[serde(skip)]
+    pending_prepares: Option<LruCache<i16, String>>,
+    // <prepared id,query>, use for resolving the statement of EXECUTE and BATCH
+    #[serde(skip)]
+    prepared_statements: Option<LruCache<Vec<u8>, String>>,
+}
+
+impl Default for CassandraLog {
+    fn default() -> Self {
+        Self {
+            info: CassandraInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+            pending_prepares: None,
+            prepared_statements: None,
+        }
+    }
+}
+
+perf_impl!(CassandraLog);
+
+impl L7ProtocolParserInterface for CassandraLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        let Some(frame) = read_segment(payload)
+            .and_then(read_frame)
+            .or_else(|| read_frame(payload))
+        else {
+            return false;
+        };
+        // 协议识别时只接受请求
+        // ====================
+        // only requests are accepted on protocol check
+        if frame.version & RESPONSE_FLAG != 0 || !is_request_opcode(frame.opcode) {
+            return false;
+        }
+        match frame.opcode {
+            OPCODE_STARTUP | OPCODE_OPTIONS => frame.body.len() == frame.length as usize,
+            OPCODE_QUERY | OPCODE_PREPARE if frame.flags & FLAG_COMPRESSION == 0 => {
+                let mut body = Body(frame.body);
+                if frame.flags & FLAG_CUSTOM_PAYLOAD != 0 && body.skip_bytes_map().is_none() {
+                    return false;
+                }
+                body.long_string().map(|q| !q.is_empty()).unwrap_or(false)
+            }
+            _ => true,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        self.parse(payload)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::CassandraInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Cassandra
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = CassandraInfo::default();
+    }
+}
+
+impl CassandraLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        帧头部（大端）:
+        | version 1B | flags 1B | stream 2B | opcode 1B | length 4B | body |
+        version 最高位为 1 表示响应
+        ===================================================================
+        frame header (big endian):
+        | version 1B | flags 1B | stream 2B | opcode 1B | length 4B | body |
+        the highest bit of version is set for responses
+    */
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        // segment 头部有 CRC24 校验，优先尝试
+        // =================================
+        // segment header has CRC24 checksum, try it first
+        let Some(frame) = read_segment(payload)
+            .and_then(read_frame)
+            .or_else(|| read_frame(payload))
+        else {
+            return Err(Error::CassandraLogParseFailed);
+        };
+        let info = &mut self.info;
+        info.version = frame.version & !RESPONSE_FLAG;
+        info.stream = frame.stream;
+        info.opcode = frame.opcode;
+        // opcode was checked in read_frame
+        info.opcode_name = opcode_name(frame.opcode).unwrap_or_default();
+        let is_response = frame.version & RESPONSE_FLAG != 0;
+        info.msg_type = if !is_response {
+            LogMessageType::Request
+        } else if frame.stream == STREAM_EVENT {
+            LogMessageType::Session
+        } else {
+            LogMessageType::Response
+        };
+        let len = FRAME_HEADER_LEN as u32 + frame.length;
+        match info.msg_type {
+            LogMessageType::Response => info.resp_len = Some(len),
+            _ => info.req_len = Some(len),
+        }
+
+        // 压缩的帧体（LZ4/Snappy）不解析
+        // ==============================
+        // compressed body (LZ4/Snappy) is not parsed
+        if frame.flags & FLAG_COMPRESSION != 0 && info.version < MAX_VERSION {
+            return Ok(());
+        }
+        let mut body = Body(frame.body);
+        // 帧体被截断时保留已解析的字段
+        // ============================
+        // keep parsed fields when body is truncated
+        let _ = if is_response {
+            self.parse_response(frame.flags, &mut body)
+        } else {
+            self.parse_request(frame.flags, &mut body)
+        };
+        Ok(())
+    }
+
+    fn parse_request(&mut self, flags: u8, body: &mut Body) -> Option<()> {
+        if flags & FLAG_CUSTOM_PAYLOAD != 0 {
+            body.skip_bytes_map()?;
+        }
+        let info = &mut self.info;
+        match info.opcode {
+            // QUERY: <query long string><query_parameters>
+            OPCODE_QUERY => info.query = body.long_string()?,
+            // PREPARE: <query long string>[<flags>]
+            OPCODE_PREPARE => {
+                info.query = body.long_string()?;
+                self.pending_prepares
+                    .get_or_insert_with(|| LruCache::new(PREPARED_STATEMENTS_MAX))
+                    .put(info.stream, info.query.clone());
+            }
+            // EXECUTE: <id short bytes>[<result_metadata_id short bytes>]<query_parameters>
+            OPCODE_EXECUTE => {
+                let id = body.short_bytes()?;
+                info.prepared_id = hex(id);
+                if let Some(query) = self
+                    .prepared_statements
+                    .as_mut()
+                    .and_then(|p| p.get(&id.to_vec()))
+                {
+                    info.query = query.clone();
+                }
+            }
+            /*
+                BATCH: <type 1B><n short><query_1>...<query_n><consistency>...
+                query: <kind 1B><string_or_id><n short><value_1>...<value_n>
+                只解析第一条语句
+                ================
+                only the first statement is parsed
+            */
+            OPCODE_BATCH => {
+                body.u8()?;
+                info.batch_size = body.short()?;
+                if info.batch_size == 0 {
+                    return Some(());
+                }
+                if body.u8()? == BATCH_KIND_PREPARED {
+                    let id = body.short_bytes()?;
+                    info.prepared_id = hex(id);
+                    if let Some(query) = self
+                        .prepared_statements
+                        .as_mut()
+                        .and_then(|p| p.get(&id.to_vec()))
+                    {
+                        info.query = query.clone();
+                    }
+                } else {
+                    info.query = body.long_string()?;
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn parse_response(&mut self, flags: u8, body: &mut Body) -> Option<()> {
+        if flags & FLAG_TRACING != 0 {
+            body.take(16)?;
+        }
+        if flags & FLAG_WARNING != 0 {
+            body.skip_string_list()?;
+        }
+        if flags & FLAG_CUSTOM_PAYLOAD != 0 {
+            body.skip_bytes_map()?;
+        }
+        let info = &mut self.info;
+        match info.opcode {
+            // ERROR: <code int><message string>...
+            OPCODE_ERROR => {
+                let code = body.int()?;
+                info.error_code = Some(code);
+                info.status = error_code_status(code);
+                info.error_message = body.string()?;
+            }
+            OPCODE_RESULT => {
+                let kind = body.int()? as u32;
+                info.result_kind = result_kind_name(kind);
+                match kind {
+                    RESULT_ROWS => {
+                        let flags = body.int()? as u32;
+                        body.int()?;
+                        if flags & ROWS_FLAG_HAS_MORE_PAGES != 0 {
+                            body.bytes()?;
+                        }
+                        if flags & ROWS_FLAG_METADATA_CHANGED != 0 {
+                            body.short_bytes()?;
+                        }
+                        if flags & ROWS_FLAG_NO_METADATA == 0
+                            && flags & ROWS_FLAG_GLOBAL_TABLES_SPEC != 0
+                        {
+                            info.keyspace = body.string()?;
+                        }
+                    }
+                    RESULT_SET_KEYSPACE => info.keyspace = body.string()?,
+                    RESULT_PREPARED => {
+                        let id = body.short_bytes()?;
+                        info.prepared_id = hex(id);
+                        if let Some(query) = self
+                            .pending_prepares
+                            .as_mut()
+                            .and_then(|p| p.pop(&info.stream))
+                        {
+                            self.prepared_statements
+                                .get_or_insert_with(|| LruCache::new(PREPARED_STATEMENTS_MAX))
+                                .put(id.to_vec(), query);
+                        }
+                    }
+                    // Schema_change: <change_type string><target string><keyspace string>...
+                    RESULT_SCHEMA_CHANGE => {
+                        body.string()?;
+                        body.string()?;
+                        info.keyspace = body.string()?;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+impl L7FlowPerf for CassandraLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::Cassandra,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/cassandra";
+
+    // 同一个流使用同一个解析器，以便 EXECUTE 可以查到 PREPARE 的语句
+    // ==============================================================
+    // use one parser for the flow, so that EXECUTE can find the statement of PREPARE
+    fn run(name: &str) -> Vec<(bool, CassandraInfo)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = CassandraLog::new();
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let is_cassandra =
+                CassandraLog::new().check_payload(payload, &ParseParam::from(&*packet));
+            let _ = parser.parse(payload);
+            output.push((is_cassandra, std::mem::take(&mut parser.info)));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("cassandra_v4.pcap", "cassandra_v4.result"),
+            ("cassandra_v5.pcap", "cassandra_v5.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output: String = run(item.0)
+                .iter()
+                .map(|(is_cassandra, info)| {
+                    format!("{:?} is_cassandra: {}\r\n", info, is_cassandra)
+                })
+                .collect();
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let output = run("cassandra_v4.pcap");
+        let mut execute = output[6].1.clone();
+        execute
+            .merge_log(L7ProtocolInfo::CassandraInfo(output[7].1.clone()))
+            .unwrap();
+        let log = L7ProtocolSendLog::from(execute);
+        assert_eq!(log.req.req_type, "EXECUTE");
+        assert_eq!(log.req.resource, "SELECT * FROM users WHERE id = ?");
+        assert_eq!(log.req.domain, "shop");
+        assert_eq!(log.resp.result, "Rows");
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("cassandra_v4.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = CassandraLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 7);
+        assert_eq!(stats.response_count, 7);
+        assert_eq!(stats.err_client_count, 1);
+        assert_eq!(stats.err_server_count, 1);
+        assert_eq!(stats.rrt_count, 7);
+    }
+}
@@ -0,0 +1,782 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::{read_u16_be, read_u32_be},
+};
+
+// https://github.com/memcached/memcached/blob/master/doc/protocol.txt
+const MAX_KEY_LEN: usize = 250;
+
+// https://github.com/memcached/memcached/wiki/BinaryProtocolRevamped
+const BINARY_HEADER_LEN: usize = 24;
+const BINARY_MAGIC_REQUEST: u8 = 0x80;
+const BINARY_MAGIC_RESPONSE: u8 = 0x81;
+
+const BINARY_STATUS_NO_ERROR: u16 = 0x0000;
+const BINARY_STATUS_KEY_NOT_FOUND: u16 = 0x0001;
+
+// (opcode, name, extras length of request)
+const BINARY_COMMANDS: &[(u8, &str, u8)] = &[
+    (0x00, "Get", 0),
+    (0x01, "Set", 8),
+    (0x02, "Add", 8),
+    (0x03, "Replace", 8),
+    (0x04, "Delete", 0),
+    (0x05, "Increment", 20),
+    (0x06, "Decrement", 20),
+    (0x07, "Quit", 0),
+    (0x08, "Flush", 4),
+    (0x09, "GetQ", 0),
+    (0x0a, "No-op", 0),
+    (0x0b, "Version", 0),
+    (0x0c, "GetK", 0),
+    (0x0d, "GetKQ", 0),
+    (0x0e, "Append", 0),
+    (0x0f, "Prepend", 0),
+    (0x10, "Stat", 0),
+    (0x11, "SetQ", 8),
+    (0x12, "AddQ", 8),
+    (0x13, "ReplaceQ", 8),
+    (0x14, "DeleteQ", 0),
+    (0x15, "IncrementQ", 20),
+    (0x16, "DecrementQ", 20),
+    (0x17, "QuitQ", 0),
+    (0x18, "FlushQ", 4),
+    (0x19, "AppendQ", 0),
+    (0x1a, "PrependQ", 0),
+    (0x1b, "Verbosity", 4),
+    (0x1c, "Touch", 4),
+    (0x1d, "GAT", 4),
+    (0x1e, "GATQ", 4),
+    (0x20, "SASL List Mechs", 0),
+    (0x21, "SASL Auth", 0),
+    (0x22, "SASL Step", 0),
+];
+
+fn binary_command(opcode: u8) -> Option<(&'static str, u8)> {
+    BINARY_COMMANDS
+        .iter()
+        .find(|(op, _, _)| *op == opcode)
+        .map(|(_, name, extras)| (*name, *extras))
+}
+
+// Get, GetQ, GetK, GetKQ, GAT, GATQ
+fn binary_is_retrieval(opcode: u8) -> bool {
+    matches!(opcode, 0x00 | 0x09 | 0x0c | 0x0d | 0x1d | 0x1e)
+}
+
+// 安静命令只在出错（或 Get 命中）时有响应
+// ======================================
+// quiet commands only have response on error (or on hit for Get)
+fn binary_is_quiet(opcode: u8) -> bool {
+    matches!(opcode, 0x09 | 0x0d | 0x11..=0x1a | 0x1e)
+}
+
+fn binary_status(status: u16) -> L7ResponseStatus {
+    match status {
+        // no error, key not found, key exists, item not stored, authentication continue
+        0x00 | 0x01 | 0x02 | 0x05 | 0x21 => L7ResponseStatus::Ok,
+        // value too large, invalid arguments, incr/decr on non-numeric value,
+        // vbucket belongs to another server, authentication error, unknown command,
+        // not supported
+        0x03 | 0x04 | 0x06 | 0x07 | 0x20 | 0x81 | 0x83 => L7ResponseStatus::ClientError,
+        // out of memory, internal error, busy, temporary failure
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+// 读取以 \r\n 结尾的一行，返回该行和剩余数据
+// ===========================================
+// read a line ending with \r\n, return the line and the remaining data
+fn read_line(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.windows(2).position(|w| w == b"\r\n")?;
+    Some((&data[..end], &data[end + 2..]))
+}
+
+fn is_number(s: &str) -> bool {
+    let s = s.strip_prefix('-').unwrap_or(s);
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b > b' ' && b != 0x7f)
+}
+
+/*
+    文本协议请求命令，返回命令中的键，None 表示不是合法的命令
+    其中 mg/ms/md/ma/mn 为 meta 命令
+    ==========================================================
+    text protocol request commands, return keys of the command, None means invalid command
+    mg/ms/md/ma/mn are meta commands
+*/
+fn text_request_keys<'a>(command: &str, args: &'a [&'a str]) -> Option<&'a [&'a str]> {
+    let (keys, numbers) = match command {
+        // get <key>*
+        "get" | "gets" if !args.is_empty() => (args, &args[..0]),
+        // gat <exptime> <key>*
+        "gat" | "gats" if args.len() >= 2 => (&args[1..], &args[..1]),
+        // set <key> <flags> <exptime> <bytes> [noreply]
+        "set" | "add" | "replace" | "append" | "prepend" if args.len() >= 4 => {
+            (&args[..1], &args[1..4])
+        }
+        // cas <key> <flags> <exptime> <bytes> <cas unique> [noreply]
+        "cas" if args.len() >= 5 => (&args[..1], &args[1..5]),
+        // incr <key> <value> [noreply]
+        // touch <key> <exptime> [noreply]
+        "incr" | "decr" | "touch" if args.len() >= 2 => (&args[..1], &args[1..2]),
+        // ms <key> <datalen> <flags>*
+        "ms" if args.len() >= 2 => (&args[..1], &args[1..2]),
+        // delete <key> [noreply]
+        // mg/md/ma <key> <flags>*
+        "delete" | "mg" | "md" | "ma" if !args.is_empty() => (&args[..1], &args[..0]),
+        "flush_all" | "version" | "stats" | "verbosity" | "quit" | "mn" => (&args[..0], &args[..0]),
+        _ => return None,
+    };
+    if !keys.iter().all(|k| is_valid_key(k)) || !numbers.iter().all(|n| is_number(n)) {
+        return None;
+    }
+    Some(keys)
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct MemcachedInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    // 只有二进制协议有 opaque
+    // ======================
+    // only binary protocol has opaque
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opaque: Option<u32>,
+    #[serde(skip)]
+    pub opcode: Option<u8>,
+
+    #[serde(rename = "request_type")]
+    pub command: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub key: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub key_count: u32,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    // 文本协议响应的首个单词，如 STORED/END/VALUE
+    // ==========================================
+    // the first word of text protocol response, such as STORED/END/VALUE
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub response: String,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<u16>,
+    // 读取命令是否命中缓存，其他命令为 None
+    // ====================================
+    // whether the retrieval command hits the cache, None for other commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_hit: Option<bool>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub hit_count: u32,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for MemcachedInfo {
+    fn session_id(&self) -> Option<u32> {
+        self.opaque
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, MemcachedInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Memcached,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl MemcachedInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.resp_len = other.resp_len;
+        self.response = other.response;
+        self.status_code = other.status_code;
+        self.cache_hit = other.cache_hit;
+        self.hit_count = other.hit_count;
+        self.error_message = other.error_message;
+        self.status = other.status;
+        // meta get 未请求值时，命中返回 HD
+        // ===============================
+        // meta get returns HD on hit when the value is not requested
+        if self.command == "mg" && self.response == "HD" {
+            self.cache_hit = Some(true);
+            self.hit_count = 1;
+        }
+    }
+
+    // 多键读取只命中部分键时为 partial_hit
+    // ===================================
+    // partial_hit means only part of the keys are hit in multi-key retrieval
+    pub fn cache_result(&self) -> Option<&'static str> {
+        match self.cache_hit? {
+            false => Some("miss"),
+            true if self.key_count > 1 && self.hit_count < self.key_count => Some("partial_hit"),
+            true => Some("hit"),
+        }
+    }
+}
+
+impl From<MemcachedInfo> for L7ProtocolSendLog {
+    fn from(f: MemcachedInfo) -> Self {
+        let result = match f.cache_result() {
+            Some(r) => r.to_string(),
+            None => f.response.clone(),
+        };
+        let mut attributes = vec![];
+        if f.key_count > 1 {
+            attributes.push(KeyVal {
+                key: "key_count".to_string(),
+                val: f.key_count.to_string(),
+            });
+            attributes.push(KeyVal {
+                key: "hit_count".to_string(),
+                val: f.hit_count.to_string(),
+            });
+        }
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.command,
+                resource: f.key,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.status_code.map(|c| c as i32),
+                exception: f.error_message,
+                result,
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: f.opaque,
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemcachedLog {
+    info: MemcachedInfo,
+    perf_stats: Option<PerfStats>,
+    // <opaque,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for MemcachedLog {
+    fn default() -> Self {
+        Self {
+            info: MemcachedInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+        }
+    }
+}
+
+perf_impl!(MemcachedLog);
+
+impl L7ProtocolParserInterface for MemcachedLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        // 协议识别时只接受请求
+        // ====================
+        // only requests are accepted on protocol check
+        if payload.first() == Some(&BINARY_MAGIC_REQUEST) {
+            if payload.len() < BINARY_HEADER_LEN {
+                return false;
+            }
+            let Some((_, extras_len)) = binary_command(payload[1]) else {
+                return false;
+            };
+            let key_len = read_u16_be(&payload[2..]) as usize;
+            let body_len = read_u32_be(&payload[8..]) as usize;
+            // data type is reserved
+            return payload[5] == 0
+                && (payload[4] == extras_len
+                    || (matches!(payload[1], 0x08 | 0x18) && payload[4] == 0))
+                && payload[4] as usize + key_len <= body_len
+                && key_len <= MAX_KEY_LEN
+                && payload.len() >= BINARY_HEADER_LEN + body_len;
+        }
+        let mut info = MemcachedInfo::default();
+        Self::parse_text_request(&mut info, payload).is_ok()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        self.parse(payload, param.direction)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::MemcachedInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Memcached
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = MemcachedInfo::default();
+    }
+}
+
+impl MemcachedLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let info = &mut self.info;
+        match payload.first() {
+            Some(&BINARY_MAGIC_REQUEST) | Some(&BINARY_MAGIC_RESPONSE) => {
+                Self::parse_binary(info, payload)?
+            }
+            _ => match direction {
+                PacketDirection::ClientToServer => Self::parse_text_request(info, payload)?,
+                PacketDirection::ServerToClient => Self::parse_text_response(info, payload)?,
+            },
+        }
+        match info.msg_type {
+            LogMessageType::Response => info.resp_len = Some(payload.len() as u32),
+            _ => info.req_len = Some(payload.len() as u32),
+        }
+        Ok(())
+    }
+
+    // 只解析第一个命令
+    // ================
+    // only the first command is parsed
+    fn parse_text_request(info: &mut MemcachedInfo, payload: &[u8]) -> Result<()> {
+        let Some((line, _)) = read_line(payload) else {
+            return Err(Error::MemcachedLogParseFailed);
+        };
+        let Ok(line) = std::str::from_utf8(line) else {
+            return Err(Error::MemcachedLogParseFailed);
+        };
+        let mut words = line.split(' ').filter(|w| !w.is_empty());
+        let Some(command) = words.next() else {
+            return Err(Error::MemcachedLogParseFailed);
+        };
+        let args = words.collect::<Vec<_>>();
+        let Some(keys) = text_request_keys(command, &args) else {
+            return Err(Error::MemcachedLogParseFailed);
+        };
+        info.command = command.to_string();
+        info.key = keys.first().map(|k| k.to_string()).unwrap_or_default();
+        info.key_count = keys.len() as u32;
+        // noreply 和 quit 没有响应
+        // =======================
+        // noreply and quit have no response
+        info.msg_type = if command == "quit" || args.last() == Some(&"noreply") {
+            LogMessageType::Session
+        } else {
+            LogMessageType::Request
+        };
+        Ok(())
+    }
+
+    /*
+        读取命令的响应为零或多个 VALUE 后跟 END，没有 VALUE 即未命中:
+        VALUE <key> <flags> <bytes> [<cas unique>]\r\n<data block>\r\n
+        ...
+        END\r\n
+        meta get 命中返回 VA <size> <flags>*\r\n<data block>\r\n 或 HD，未命中返回 EN
+        ===============================================================================
+        response of retrieval commands is zero or more VALUE followed by END, no VALUE means miss:
+        VALUE <key> <flags> <bytes> [<cas unique>]\r\n<data block>\r\n
+        ...
+        END\r\n
+        meta get returns VA <size> <flags>*\r\n<data block>\r\n or HD on hit, and EN on miss
+    */
+    fn parse_text_response(info: &mut MemcachedInfo, payload: &[u8]) -> Result<()> {
+        info.msg_type = LogMessageType::Response;
+        let mut data = payload;
+        let mut values = 0;
+        while let Some((line, rest)) = read_line(data) {
+            data = rest;
+            let line = String::from_utf8_lossy(line);
+            let (word, message) = line.split_once(' ').unwrap_or((&*line, ""));
+            if info.response.is_empty() {
+                match word {
+                    "VALUE" | "END" | "STAT" | "STORED" | "NOT_STORED" | "EXISTS" | "NOT_FOUND"
+                    | "DELETED" | "TOUCHED" | "OK" | "RESET" | "VERSION" | "ERROR"
+                    | "CLIENT_ERROR" | "SERVER_ERROR" | "VA" | "HD" | "EN" | "NF" | "NS" | "EX"
+                    | "MN" => {}
+                    // incr/decr 返回新值
+                    // =================
+                    // incr/decr returns the new value
+                    _ if is_number(word) => {}
+                    _ => return Err(Error::MemcachedLogParseFailed),
+                }
+                info.response = word.to_string();
+            }
+            match word {
+                "VALUE" | "VA" => {
+                    values += 1;
+                    // VALUE <key> <flags> <bytes>, VA <size>
+                    let size = if word == "VALUE" {
+                        message.split(' ').nth(2)
+                    } else {
+                        message.split(' ').next()
+                    };
+                    // 数据块之后是 \r\n
+                    let Some(size) = size
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .and_then(|s| s.checked_add(2))
+                    else {
+                        return Err(Error::MemcachedLogParseFailed);
+                    };
+                    if data.len() < size {
+                        break;
+                    }
+                    data = &data[size..];
+                }
+                "STAT" => {}
+                "ERROR" => {
+                    info.status = L7ResponseStatus::ClientError;
+                    info.error_message = word.to_string();
+                    break;
+                }
+                "CLIENT_ERROR" => {
+                    info.status = L7ResponseStatus::ClientError;
+                    info.error_message = message.to_string();
+                    break;
+                }
+                "SERVER_ERROR" => {
+                    info.status = L7ResponseStatus::ServerError;
+                    info.error_message = message.to_string();
+                    break;
+                }
+                _ => break,
+            }
+        }
+        if info.response.is_empty() {
+            return Err(Error::MemcachedLogParseFailed);
+        }
+        if values > 0 {
+            info.cache_hit = Some(true);
+            info.hit_count = values;
+        } else if info.response == "END" || info.response == "EN" {
+            info.cache_hit = Some(false);
+        }
+        Ok(())
+    }
+
+    /*
+        二进制协议头部（大端）:
+        | magic 1B | opcode 1B | key length 2B | extras length 1B | data type 1B |
+        | vbucket id (request) or status (response) 2B | total body length 4B |
+        | opaque 4B | CAS 8B | extras | key | value |
+        =========================================================================
+        binary protocol header (big endian):
+        | magic 1B | opcode 1B | key length 2B | extras length 1B | data type 1B |
+        | vbucket id (request) or status (response) 2B | total body length 4B |
+        | opaque 4B | CAS 8B | extras | key | value |
+    */
+    fn parse_binary(info: &mut MemcachedInfo, payload: &[u8]) -> Result<()> {
+        if payload.len() < BINARY_HEADER_LEN {
+            return Err(Error::MemcachedLogParseFailed);
+        }
+        let opcode = payload[1];
+        let Some((name, _)) = binary_command(opcode) else {
+            return Err(Error::MemcachedLogParseFailed);
+        };
+        let key_len = read_u16_be(&payload[2..]) as usize;
+        let extras_len = payload[4] as usize;
+        let body_len = read_u32_be(&payload[8..]) as usize;
+        if extras_len + key_len > body_len {
+            return Err(Error::MemcachedLogParseFailed);
+        }
+        info.opcode = Some(opcode);
+        info.command = name.to_string();
+        info.opaque = Some(read_u32_be(&payload[12..]));
+
+        let body = &payload[BINARY_HEADER_LEN..payload.len().min(BINARY_HEADER_LEN + body_len)];
+        if let Some(key) = body.get(extras_len..extras_len + key_len) {
+            info.key = String::from_utf8_lossy(key).into_owned();
+        }
+        if key_len > 0 {
+            info.key_count = 1;
+        }
+
+        if payload[0] == BINARY_MAGIC_REQUEST {
+            info.msg_type = if binary_is_quiet(opcode) {
+                LogMessageType::Session
+            } else {
+                LogMessageType::Request
+            };
+            return Ok(());
+        }
+
+        info.msg_type = LogMessageType::Response;
+        let status = read_u16_be(&payload[6..]);
+        info.status_code = Some(status);
+        info.status = binary_status(status);
+        if binary_is_retrieval(opcode) {
+            match status {
+                BINARY_STATUS_NO_ERROR => {
+                    info.cache_hit = Some(true);
+                    info.hit_count = 1;
+                }
+                BINARY_STATUS_KEY_NOT_FOUND => info.cache_hit = Some(false),
+                _ => {}
+            }
+        }
+        // 出错时响应的值为错误信息
+        // ========================
+        // value of response is the error message on error
+        if status != BINARY_STATUS_NO_ERROR {
+            if let Some(value) = body.get(extras_len + key_len..) {
+                info.error_message = String::from_utf8_lossy(value).into_owned();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl L7FlowPerf for MemcachedLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::Memcached,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/memcached";
+
+    fn run(name: &str) -> Vec<(bool, MemcachedInfo)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let is_memcached =
+                MemcachedLog::new().check_payload(payload, &ParseParam::from(&*packet));
+            let mut parser = MemcachedLog::new();
+            let _ = parser.parse(payload, packet.lookup_key.direction);
+            output.push((is_memcached, parser.info));
+        }
+        output
+    }
+
+    fn merge(output: &[(bool, MemcachedInfo)], req: usize) -> MemcachedInfo {
+        let mut info = output[req].1.clone();
+        info.merge_log(L7ProtocolInfo::MemcachedInfo(output[req + 1].1.clone()))
+            .unwrap();
+        info
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("memcached_text.pcap", "memcached_text.result"),
+            ("memcached_binary.pcap", "memcached_binary.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output: String = run(item.0)
+                .iter()
+                .map(|(is_memcached, info)| {
+                    format!("{:?} is_memcached: {}\r\n", info, is_memcached)
+                })
+                .collect();
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cache_result() {
+        let output = run("memcached_text.pcap");
+        assert_eq!(merge(&output, 0).cache_result(), None);
+        assert_eq!(merge(&output, 2).cache_result(), Some("hit"));
+        let miss = merge(&output, 4);
+        assert_eq!(miss.cache_result(), Some("miss"));
+        assert_eq!(L7ProtocolSendLog::from(miss).resp.result, "miss");
+        assert_eq!(merge(&output, 6).cache_result(), Some("partial_hit"));
+        assert_eq!(merge(&output, 10).cache_result(), Some("hit"));
+        assert_eq!(merge(&output, 12).cache_result(), Some("miss"));
+
+        let output = run("memcached_binary.pcap");
+        assert_eq!(merge(&output, 2).cache_result(), Some("hit"));
+        assert_eq!(merge(&output, 4).cache_result(), Some("miss"));
+    }
+
+    #[test]
+    fn value_size_overflow() {
+        let mut info = MemcachedInfo::default();
+        assert!(MemcachedLog::parse_text_response(
+            &mut info,
+            format!("VALUE k 0 {}\r\nabc\r\nEND\r\n", usize::MAX).as_bytes(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("memcached_binary.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = MemcachedLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 4);
+        assert_eq!(stats.response_count, 4);
+        assert_eq!(stats.err_client_count, 1);
+        assert_eq!(stats.rrt_count, 4);
+    }
+}
@@ -14,12 +14,20 @@
  * limitations under the License.
  */
 
+mod cassandra;
+mod memcached;
+mod mongo;
 mod mysql;
 mod postgre_convert;
 mod postgresql;
 mod redis;
 mod sql_check;
+mod tds;
 
+pub use cassandra::{CassandraInfo, CassandraLog};
+pub use memcached::{MemcachedInfo, MemcachedLog};
+pub use mongo::{MongoDBInfo, MongoDBLog};
 pub use mysql::{MysqlHeader, MysqlInfo, MysqlLog};
 pub use postgresql::{PostgreInfo, PostgresqlLog};
 pub use redis::{decode, RedisInfo, RedisLog};
+pub use tds::{TdsInfo, TdsLog};
@@ -0,0 +1,887 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use lru::LruCache;
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::{read_u32_le, read_u64_le},
+};
+
+// https://www.mongodb.com/docs/manual/reference/mongodb-wire-protocol/
+const HEADER_LEN: usize = 16;
+// 48MB, maxMessageSizeBytes of mongod
+const MAX_MESSAGE_LEN: u32 = 48000000;
+// 只需要命令文档的开头部分，解压的数据不超过 4KB
+// ==================================================================
+// only the beginning of the command document is needed, at most 4KB is decompressed
+const MAX_DECOMPRESSED_LEN: usize = 4096;
+
+const OP_REPLY: u32 = 1;
+const OP_UPDATE: u32 = 2001;
+const OP_INSERT: u32 = 2002;
+const OP_QUERY: u32 = 2004;
+const OP_GET_MORE: u32 = 2005;
+const OP_DELETE: u32 = 2006;
+const OP_KILL_CURSORS: u32 = 2007;
+const OP_COMPRESSED: u32 = 2012;
+const OP_MSG: u32 = 2013;
+
+const MSG_FLAG_CHECKSUM_PRESENT: u32 = 1 << 0;
+const MSG_SECTION_BODY: u8 = 0;
+const MSG_SECTION_DOCUMENT_SEQUENCE: u8 = 1;
+
+const REPLY_FLAG_QUERY_FAILURE: u32 = 1 << 1;
+
+const COMPRESSOR_NOOP: u8 = 0;
+const COMPRESSOR_ZLIB: u8 = 2;
+
+fn op_code_name(op_code: u32) -> Option<&'static str> {
+    match op_code {
+        OP_REPLY => Some("OP_REPLY"),
+        OP_UPDATE => Some("OP_UPDATE"),
+        OP_INSERT => Some("OP_INSERT"),
+        OP_QUERY => Some("OP_QUERY"),
+        OP_GET_MORE => Some("OP_GET_MORE"),
+        OP_DELETE => Some("OP_DELETE"),
+        OP_KILL_CURSORS => Some("OP_KILL_CURSORS"),
+        OP_COMPRESSED => Some("OP_COMPRESSED"),
+        OP_MSG => Some("OP_MSG"),
+        _ => None,
+    }
+}
+
+// BSON 值，只保留解析命令和错误信息需要的类型
+// ===============================================
+// BSON value, only types required for command and error parsing are kept
+#[derive(Debug, PartialEq)]
+enum BsonValue<'a> {
+    Double(f64),
+    String(&'a str),
+    Document(&'a [u8]),
+    Bool(bool),
+    Int32(i32),
+    Int64(i64),
+    Other,
+}
+
+impl BsonValue<'_> {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            BsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match self {
+            BsonValue::Double(d) => Some(*d as i64),
+            BsonValue::Bool(b) => Some(*b as i64),
+            BsonValue::Int32(i) => Some(*i as i64),
+            BsonValue::Int64(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+// 最小化的 BSON 读取器，文档被截断时返回已读取的元素
+// =====================================================
+// a minimal BSON reader, elements already read are returned when the document is truncated
+struct BsonIter<'a>(&'a [u8]);
+
+impl<'a> BsonIter<'a> {
+    fn new(doc: &'a [u8]) -> Self {
+        if doc.len() < 4 {
+            return Self(&[]);
+        }
+        let size = (read_u32_le(doc) as usize).clamp(4, doc.len());
+        Self(&doc[4..size])
+    }
+
+    fn value_of(mut self, key: &str) -> Option<BsonValue<'a>> {
+        self.find_map(|(k, v)| if k == key { Some(v) } else { None })
+    }
+
+    fn cstring(&mut self) -> Option<&'a str> {
+        let end = self.0.iter().position(|b| *b == 0)?;
+        let s = std::str::from_utf8(&self.0[..end]).ok()?;
+        self.0 = &self.0[end + 1..];
+        Some(s)
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(v)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let len = read_u32_le(self.take(4)?) as usize;
+        let s = self.take(len)?;
+        if len == 0 || s[len - 1] != 0 {
+            return None;
+        }
+        std::str::from_utf8(&s[..len - 1]).ok()
+    }
+}
+
+impl<'a> Iterator for BsonIter<'a> {
+    type Item = (&'a str, BsonValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&typ, rest) = self.0.split_first()?;
+        // 0x00 为文档结束符
+        // ================
+        // 0x00 is the end of document
+        if typ == 0 {
+            self.0 = &[];
+            return None;
+        }
+        self.0 = rest;
+        let key = self.cstring()?;
+        let value = match typ {
+            0x01 => BsonValue::Double(f64::from_bits(read_u64_le(self.take(8)?))),
+            0x02 => BsonValue::String(self.string()?),
+            0x03 | 0x04 => {
+                let len = read_u32_le(self.0.get(..4)?) as usize;
+                BsonValue::Document(self.take(len)?)
+            }
+            0x05 => {
+                let len = read_u32_le(self.take(4)?) as usize;
+                self.take(len + 1)?;
+                BsonValue::Other
+            }
+            // undefined, null, min key, max key
+            0x06 | 0x0a | 0x7f | 0xff => BsonValue::Other,
+            // ObjectId
+            0x07 => {
+                self.take(12)?;
+                BsonValue::Other
+            }
+            0x08 => BsonValue::Bool(*self.take(1)?.first()? != 0),
+            // UTC datetime, timestamp
+            0x09 | 0x11 => {
+                self.take(8)?;
+                BsonValue::Other
+            }
+            // regex: pattern and options
+            0x0b => {
+                self.cstring()?;
+                self.cstring()?;
+                BsonValue::Other
+            }
+            // DBPointer
+            0x0c => {
+                self.string()?;
+                self.take(12)?;
+                BsonValue::Other
+            }
+            // JavaScript code, symbol
+            0x0d | 0x0e => {
+                self.string()?;
+                BsonValue::Other
+            }
+            // JavaScript code with scope
+            0x0f => {
+                let len = read_u32_le(self.0.get(..4)?) as usize;
+                self.take(len)?;
+                BsonValue::Other
+            }
+            0x10 => BsonValue::Int32(read_u32_le(self.take(4)?) as i32),
+            0x12 => BsonValue::Int64(read_u64_le(self.take(8)?) as i64),
+            // decimal128
+            0x13 => {
+                self.take(16)?;
+                BsonValue::Other
+            }
+            _ => return None,
+        };
+        Some((key, value))
+    }
+}
+
+fn read_cstring(data: &[u8]) -> Option<(&str, &[u8])> {
+    let end = data.iter().position(|b| *b == 0)?;
+    Some((std::str::from_utf8(&data[..end]).ok()?, &data[end + 1..]))
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct MongoDBInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub request_id: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub response_to: u32,
+    #[serde(skip)]
+    pub op_code: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub op_code_name: &'static str,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub command: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub database: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub collection: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for MongoDBInfo {
+    // 请求使用 requestID，响应使用 responseTo 关联
+    // ============================================
+    // requests are matched by requestID, responses by responseTo
+    fn session_id(&self) -> Option<u32> {
+        match self.msg_type {
+            LogMessageType::Response => Some(self.response_to),
+            _ => Some(self.request_id),
+        }
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, MongoDBInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::MongoDB,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl MongoDBInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.response_to = other.response_to;
+        self.resp_len = other.resp_len;
+        self.error_code = other.error_code;
+        self.error_message = other.error_message;
+        self.status = other.status;
+    }
+}
+
+impl From<MongoDBInfo> for L7ProtocolSendLog {
+    fn from(f: MongoDBInfo) -> Self {
+        let endpoint = if f.database.is_empty() || f.collection.is_empty() {
+            String::new()
+        } else {
+            format!("{}.{}", f.database, f.collection)
+        };
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.command,
+                domain: f.database,
+                resource: f.collection,
+                endpoint,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code,
+                exception: f.error_message,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.request_id),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MongoDBLog {
+    info: MongoDBInfo,
+    perf_stats: Option<PerfStats>,
+    // <request_id,(type,time)>, use for calculate perf
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for MongoDBLog {
+    fn default() -> Self {
+        Self {
+            info: MongoDBInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+        }
+    }
+}
+
+perf_impl!(MongoDBLog);
+
+impl L7ProtocolParserInterface for MongoDBLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        if payload.len() < HEADER_LEN || read_u32_le(payload) as usize != payload.len() {
+            return false;
+        }
+        // 协议识别时只接受客户端发送的命令
+        // ================================
+        // only commands sent by client are accepted on protocol check
+        let mut parser = MongoDBLog::new();
+        parser.parse(payload).is_ok()
+            && parser.info.msg_type == LogMessageType::Request
+            && !parser.info.command.is_empty()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        self.parse(payload)?;
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        if self.info.status == L7ResponseStatus::ServerError {
+            self.perf_inc_resp_err();
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::MongoDBInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::MongoDB
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = MongoDBInfo::default();
+    }
+}
+
+impl MongoDBLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        消息头（小端）:
+        | messageLength 4B | requestID 4B | responseTo 4B | opCode 4B |
+        ===============================================================
+        message header (little endian):
+        | messageLength 4B | requestID 4B | responseTo 4B | opCode 4B |
+    */
+    fn parse(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.len() < HEADER_LEN {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let length = read_u32_le(payload);
+        if (length as usize) < HEADER_LEN || length > MAX_MESSAGE_LEN {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let op_code = read_u32_le(&payload[12..]);
+        let Some(name) = op_code_name(op_code) else {
+            return Err(Error::MongoDBLogParseFailed);
+        };
+
+        let info = &mut self.info;
+        info.request_id = read_u32_le(&payload[4..]);
+        info.response_to = read_u32_le(&payload[8..]);
+        info.op_code = op_code;
+        info.op_code_name = name;
+        info.msg_type = match op_code {
+            OP_REPLY => LogMessageType::Response,
+            OP_MSG | OP_COMPRESSED if info.response_to != 0 => LogMessageType::Response,
+            OP_MSG | OP_COMPRESSED | OP_QUERY | OP_GET_MORE => LogMessageType::Request,
+            // 旧版写操作没有响应
+            // ==================
+            // legacy write operations have no reply
+            _ => LogMessageType::Session,
+        };
+        match info.msg_type {
+            LogMessageType::Response => info.resp_len = Some(length),
+            _ => info.req_len = Some(length),
+        }
+
+        let body = &payload[HEADER_LEN..payload.len().min(length as usize)];
+        if op_code == OP_COMPRESSED {
+            Self::parse_compressed(info, body)
+        } else {
+            Self::parse_op(info, op_code, body)
+        }
+    }
+
+    /*
+        OP_COMPRESSED:
+        | originalOpcode 4B | uncompressedSize 4B | compressorId 1B | compressedMessage |
+
+        只解压 zlib，snappy 和 zstd 只记录原始 opCode
+        =============================================
+        only zlib is decompressed, only the original opCode is recorded for snappy and zstd
+    */
+    fn parse_compressed(info: &mut MongoDBInfo, body: &[u8]) -> Result<()> {
+        if body.len() < 9 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let original_op_code = read_u32_le(body);
+        let Some(name) = op_code_name(original_op_code) else {
+            return Err(Error::MongoDBLogParseFailed);
+        };
+        if original_op_code == OP_COMPRESSED {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        info.op_code_name = name;
+        let uncompressed_size = (read_u32_le(&body[4..]) as usize).min(MAX_DECOMPRESSED_LEN);
+        let compressed = &body[9..];
+        match body[8] {
+            COMPRESSOR_NOOP => Self::parse_op(info, original_op_code, compressed),
+            COMPRESSOR_ZLIB => {
+                // 报文可能被截断，保留已解压的部分
+                // ================================
+                // the payload may be truncated, keep the decompressed part
+                let mut decoder = ZlibDecoder::new(compressed).take(uncompressed_size as u64);
+                let mut data = Vec::with_capacity(uncompressed_size);
+                let mut buf = [0u8; 1024];
+                loop {
+                    match decoder.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => data.extend_from_slice(&buf[..n]),
+                    }
+                }
+                Self::parse_op(info, original_op_code, &data)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn parse_op(info: &mut MongoDBInfo, op_code: u32, body: &[u8]) -> Result<()> {
+        match op_code {
+            OP_MSG => Self::parse_msg(info, body),
+            OP_QUERY => Self::parse_query(info, body),
+            OP_REPLY => Self::parse_reply(info, body),
+            OP_UPDATE | OP_INSERT | OP_GET_MORE | OP_DELETE => {
+                // | ZERO or flags 4B | fullCollectionName cstring | ...
+                let Some((name, _)) = body.get(4..).and_then(read_cstring) else {
+                    return Err(Error::MongoDBLogParseFailed);
+                };
+                info.command = match op_code {
+                    OP_UPDATE => "update",
+                    OP_INSERT => "insert",
+                    OP_GET_MORE => "getMore",
+                    _ => "delete",
+                }
+                .to_string();
+                Self::set_namespace(info, name);
+                Ok(())
+            }
+            OP_KILL_CURSORS => {
+                info.command = "killCursors".to_string();
+                Ok(())
+            }
+            _ => Err(Error::MongoDBLogParseFailed),
+        }
+    }
+
+    /*
+        OP_MSG:
+        | flagBits 4B | sections | optional checksum 4B |
+        section kind 0: | 0 1B | document |
+        section kind 1: | 1 1B | size 4B | identifier cstring | documents |
+    */
+    fn parse_msg(info: &mut MongoDBInfo, body: &[u8]) -> Result<()> {
+        if body.len() < 5 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let flags = read_u32_le(body);
+        let mut sections = &body[4..];
+        if flags & MSG_FLAG_CHECKSUM_PRESENT != 0 && sections.len() >= 4 {
+            sections = &sections[..sections.len() - 4];
+        }
+        let mut body_doc = None;
+        while let Some((&kind, rest)) = sections.split_first() {
+            if rest.len() < 4 {
+                break;
+            }
+            let size = read_u32_le(rest) as usize;
+            match kind {
+                MSG_SECTION_BODY => {
+                    body_doc = Some(&rest[..size.min(rest.len())]);
+                }
+                MSG_SECTION_DOCUMENT_SEQUENCE => {
+                    if size < 4 {
+                        return Err(Error::MongoDBLogParseFailed);
+                    }
+                }
+                _ => return Err(Error::MongoDBLogParseFailed),
+            }
+            if size > rest.len() {
+                break;
+            }
+            sections = &rest[size..];
+        }
+        let Some(doc) = body_doc else {
+            return Err(Error::MongoDBLogParseFailed);
+        };
+        match info.msg_type {
+            LogMessageType::Response => Self::parse_reply_doc(info, doc),
+            _ => Self::parse_command(info, doc),
+        }
+        Ok(())
+    }
+
+    /*
+        OP_QUERY:
+        | flags 4B | fullCollectionName cstring | numberToSkip 4B | numberToReturn 4B | query |
+    */
+    fn parse_query(info: &mut MongoDBInfo, body: &[u8]) -> Result<()> {
+        let Some((name, rest)) = body.get(4..).and_then(read_cstring) else {
+            return Err(Error::MongoDBLogParseFailed);
+        };
+        let Some(query) = rest.get(8..) else {
+            return Err(Error::MongoDBLogParseFailed);
+        };
+        match name.split_once('.') {
+            // 命令通过 OP_QUERY 发往 <db>.$cmd 集合
+            // ====================================
+            // commands are sent to the <db>.$cmd collection by OP_QUERY
+            Some((db, "$cmd")) => {
+                let mut doc = query;
+                // 命令可能被包装在 $query 中
+                // =========================
+                // the command may be wrapped in $query
+                if let Some(BsonValue::Document(inner)) = BsonIter::new(query).value_of("$query") {
+                    doc = inner;
+                }
+                Self::parse_command(info, doc);
+                info.database = db.to_string();
+            }
+            _ => {
+                info.command = "find".to_string();
+                Self::set_namespace(info, name);
+            }
+        }
+        if info.command.is_empty() {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        Ok(())
+    }
+
+    /*
+        OP_REPLY:
+        | responseFlags 4B | cursorID 8B | startingFrom 4B | numberReturned 4B | documents |
+    */
+    fn parse_reply(info: &mut MongoDBInfo, body: &[u8]) -> Result<()> {
+        if body.len() < 20 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let flags = read_u32_le(body);
+        let doc = &body[20..];
+        if flags & REPLY_FLAG_QUERY_FAILURE != 0 {
+            info.status = L7ResponseStatus::ServerError;
+            for (key, value) in BsonIter::new(doc) {
+                match key {
+                    "$err" => info.error_message = value.as_str().unwrap_or_default().to_string(),
+                    "code" => info.error_code = value.as_i64().map(|c| c as i32),
+                    _ => {}
+                }
+            }
+            return Ok(());
+        }
+        if doc.len() >= 4 {
+            Self::parse_reply_doc(info, doc);
+        }
+        Ok(())
+    }
+
+    // 命令名为文档的第一个键，其值为字符串时是目标集合
+    // ================================================
+    // the command name is the first key of the document, and its value is the target
+    // collection when it is a string
+    fn parse_command(info: &mut MongoDBInfo, doc: &[u8]) {
+        let mut iter = BsonIter::new(doc);
+        let Some((command, value)) = iter.next() else {
+            return;
+        };
+        info.command = command.to_string();
+        if let Some(collection) = value.as_str() {
+            info.collection = collection.to_string();
+        }
+        for (key, value) in iter {
+            match (key, value) {
+                ("$db", BsonValue::String(db)) => info.database = db.to_string(),
+                // getMore 的值为 cursorId
+                // =======================
+                // the value of getMore is cursorId
+                ("collection", BsonValue::String(c)) if info.collection.is_empty() => {
+                    info.collection = c.to_string()
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_reply_doc(info: &mut MongoDBInfo, doc: &[u8]) {
+        let mut ok = None;
+        for (key, value) in BsonIter::new(doc) {
+            match key {
+                "ok" => ok = value.as_i64(),
+                "errmsg" => info.error_message = value.as_str().unwrap_or_default().to_string(),
+                "code" => info.error_code = value.as_i64().map(|c| c as i32),
+                _ => {}
+            }
+        }
+        if ok == Some(0) {
+            info.status = L7ResponseStatus::ServerError;
+        }
+    }
+
+    fn set_namespace(info: &mut MongoDBInfo, namespace: &str) {
+        match namespace.split_once('.') {
+            Some((db, collection)) => {
+                info.database = db.to_string();
+                info.collection = collection.to_string();
+            }
+            None => info.collection = namespace.to_string(),
+        }
+    }
+}
+
+impl L7FlowPerf for MongoDBLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::MongoDB,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/mongo";
+
+    fn run(name: &str) -> Vec<(bool, MongoDBInfo)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let is_mongo = MongoDBLog::new().check_payload(payload, &ParseParam::from(&*packet));
+            let mut parser = MongoDBLog::new();
+            let _ = parser.parse(payload);
+            output.push((is_mongo, parser.info));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![("mongo.pcap", "mongo.result")];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output: String = run(item.0)
+                .iter()
+                .map(|(is_mongo, info)| format!("{:?} is_mongo: {}\r\n", info, is_mongo))
+                .collect();
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_large_document() {
+        use std::io::Write;
+
+        use flate2::{write::ZlibEncoder, Compression};
+
+        fn string(doc: &mut Vec<u8>, key: &str, value: &str) {
+            doc.push(0x02);
+            doc.extend_from_slice(key.as_bytes());
+            doc.push(0);
+            doc.extend_from_slice(&(value.len() as u32 + 1).to_le_bytes());
+            doc.extend_from_slice(value.as_bytes());
+            doc.push(0);
+        }
+
+        let mut elements = vec![];
+        string(&mut elements, "count", "users");
+        string(&mut elements, "$db", "test");
+        string(&mut elements, "comment", &"x".repeat(1 << 20));
+        let mut msg = 0u32.to_le_bytes().to_vec();
+        msg.push(MSG_SECTION_BODY);
+        msg.extend_from_slice(&(elements.len() as u32 + 5).to_le_bytes());
+        msg.extend(elements);
+        msg.push(0);
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&msg).unwrap();
+        let mut body = OP_MSG.to_le_bytes().to_vec();
+        body.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+        body.push(COMPRESSOR_ZLIB);
+        body.extend(encoder.finish().unwrap());
+
+        // 只解压文档开头的部分
+        let mut info = MongoDBInfo::default();
+        MongoDBLog::parse_compressed(&mut info, &body).unwrap();
+        assert_eq!(info.op_code_name, "OP_MSG");
+        assert_eq!(info.command, "count");
+        assert_eq!(info.collection, "users");
+        assert_eq!(info.database, "test");
+    }
+
+    #[test]
+    fn merge() {
+        let output = run("mongo.pcap");
+        let mut drop_req = output[6].1.clone();
+        drop_req
+            .merge_log(L7ProtocolInfo::MongoDBInfo(output[7].1.clone()))
+            .unwrap();
+        let log = L7ProtocolSendLog::from(drop_req);
+        assert_eq!(log.req.req_type, "drop");
+        assert_eq!(log.req.endpoint, "test.missing");
+        assert_eq!(log.resp.status, L7ResponseStatus::ServerError);
+        assert_eq!(log.resp.code, Some(26));
+        assert_eq!(log.resp.exception, "ns not found");
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("mongo.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = MongoDBLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 5);
+        assert_eq!(stats.response_count, 5);
+        assert_eq!(stats.err_server_count, 1);
+        assert_eq!(stats.rrt_count, 5);
+    }
+}
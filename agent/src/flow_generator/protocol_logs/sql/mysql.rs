@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use lru::LruCache;
 use serde::Serialize;
 
 use super::super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
@@ -29,7 +30,9 @@ use crate::{
     },
     flow_generator::{
         error::{Error, Result},
-        protocol_logs::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+        protocol_logs::pb_adapter::{
+            ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+        },
     },
     utils::bytes,
 };
@@ -57,12 +60,20 @@ pub struct MysqlInfo {
     pub command: u8,
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub context: String,
+    #[serde(skip)]
+    pub statement_id: u32,
     // response
     pub response_code: u8,
     #[serde(skip)]
     pub error_code: Option<i32>,
+    #[serde(skip)]
+    pub sql_state: String,
     #[serde(rename = "sql_affected_rows", skip_serializing_if = "value_is_default")]
     pub affected_rows: u64,
+    #[serde(skip)]
+    pub last_insert_id: u64,
+    #[serde(skip)]
+    pub warning_count: u16,
     #[serde(
         rename = "response_execption",
         skip_serializing_if = "value_is_default"
@@ -110,15 +121,29 @@ impl MysqlInfo {
             LogMessageType::Request => {
                 self.command = other.command;
                 self.context = other.context;
+                if other.statement_id != 0 {
+                    self.statement_id = other.statement_id;
+                }
             }
             LogMessageType::Response => {
                 self.response_code = other.response_code;
                 self.affected_rows = other.affected_rows;
+                self.last_insert_id = other.last_insert_id;
+                self.warning_count = other.warning_count;
                 self.error_message = other.error_message;
                 self.status = other.status;
                 if self.error_code.is_none() {
                     self.error_code = other.error_code;
                 }
+                if self.sql_state.is_empty() {
+                    self.sql_state = other.sql_state;
+                }
+                // COM_STMT_PREPARE的语句ID由应答分配
+                // =================================================
+                // The statement id of COM_STMT_PREPARE is assigned by the response
+                if self.statement_id == 0 {
+                    self.statement_id = other.statement_id;
+                }
             }
             _ => {}
         }
@@ -168,6 +193,31 @@ impl MysqlInfo {
 
 impl From<MysqlInfo> for L7ProtocolSendLog {
     fn from(f: MysqlInfo) -> Self {
+        let mut attributes = vec![];
+        if f.statement_id != 0 {
+            attributes.push(KeyVal {
+                key: "statement_id".to_string(),
+                val: f.statement_id.to_string(),
+            });
+        }
+        if !f.sql_state.is_empty() {
+            attributes.push(KeyVal {
+                key: "sql_state".to_string(),
+                val: f.sql_state.clone(),
+            });
+        }
+        if f.last_insert_id != 0 {
+            attributes.push(KeyVal {
+                key: "last_insert_id".to_string(),
+                val: f.last_insert_id.to_string(),
+            });
+        }
+        if f.warning_count != 0 {
+            attributes.push(KeyVal {
+                key: "warning_count".to_string(),
+                val: f.warning_count.to_string(),
+            });
+        }
         let log = L7ProtocolSendLog {
             version: if f.protocol_version == 0 {
                 None
@@ -187,6 +237,11 @@ impl From<MysqlInfo> for L7ProtocolSendLog {
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -195,11 +250,33 @@ impl From<MysqlInfo> for L7ProtocolSendLog {
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Debug, Serialize)]
 pub struct MysqlLog {
     info: MysqlInfo,
     l7_proto: L7Protocol,
     command: u8,
+    // 等待PREPARE_OK应答的COM_STMT_PREPARE语句
+    // =================================================
+    // SQL of the COM_STMT_PREPARE waiting for its PREPARE_OK response
+    #[serde(skip)]
+    prepare_sql: Option<String>,
+    // <statement_id, sql>，用于补全COM_STMT_EXECUTE的语句
+    // =================================================
+    // <statement_id, sql>, used to fill in the statement of COM_STMT_EXECUTE
+    #[serde(skip)]
+    statements: LruCache<u32, String>,
+}
+
+impl Default for MysqlLog {
+    fn default() -> Self {
+        Self {
+            info: MysqlInfo::default(),
+            l7_proto: L7Protocol::default(),
+            command: 0,
+            prepare_sql: None,
+            statements: LruCache::new(STATEMENT_CACHE_SIZE),
+        }
+    }
 }
 
 impl L7ProtocolParserInterface for MysqlLog {
@@ -227,11 +304,7 @@ impl L7ProtocolParserInterface for MysqlLog {
     }
 
     fn reset(&mut self) {
-        *self = Self {
-            l7_proto: self.l7_proto,
-            command: self.command,
-            ..Default::default()
-        };
+        self.info = MysqlInfo::default();
     }
 }
 
@@ -291,9 +364,27 @@ impl MysqlLog {
         self.info.command = payload[COMMAND_OFFSET];
         self.command = payload[COMMAND_OFFSET];
         match self.info.command {
-            COM_QUIT | COM_FIELD_LIST | COM_STMT_EXECUTE | COM_STMT_CLOSE | COM_STMT_FETCH => (),
-            COM_INIT_DB | COM_QUERY | COM_STMT_PREPARE => {
+            COM_QUIT | COM_FIELD_LIST => (),
+            COM_STMT_EXECUTE | COM_STMT_CLOSE | COM_STMT_FETCH => {
+                if payload.len() >= STATEMENT_ID_OFFSET + STATEMENT_ID_LEN {
+                    let statement_id = bytes::read_u32_le(&payload[STATEMENT_ID_OFFSET..]);
+                    self.info.statement_id = statement_id;
+                    let sql = if self.info.command == COM_STMT_CLOSE {
+                        self.statements.pop(&statement_id)
+                    } else {
+                        self.statements.get(&statement_id).cloned()
+                    };
+                    if let Some(sql) = sql {
+                        self.info.context = sql;
+                    }
+                }
+            }
+            COM_INIT_DB | COM_QUERY => {
+                self.request_string(&payload[COMMAND_OFFSET + COMMAND_LEN..]);
+            }
+            COM_STMT_PREPARE => {
                 self.request_string(&payload[COMMAND_OFFSET + COMMAND_LEN..]);
+                self.prepare_sql = Some(self.info.context.clone());
             }
             _ => return Err(Error::MysqlLogParseFailed),
         }
@@ -301,24 +392,75 @@ impl MysqlLog {
         Ok(())
     }
 
-    fn decode_compress_int(payload: &[u8]) -> u64 {
+    // 返回长度编码整数的值和所占字节数
+    // =================================================
+    // Returns the value of a length-encoded integer and the number of bytes it takes
+    fn decode_compress_int(payload: &[u8]) -> Option<(u64, usize)> {
         let remain = payload.len();
         if remain == 0 {
-            return 0;
+            return None;
         }
         let value = payload[0];
         match value {
-            INT_FLAGS_2 if remain > INT_BASE_LEN + 2 => {
+            INT_FLAGS_2 if remain >= INT_BASE_LEN + 2 => Some((
+                bytes::read_u16_le(&payload[INT_BASE_LEN..]) as u64,
+                INT_BASE_LEN + 2,
+            )),
+            INT_FLAGS_3 if remain >= INT_BASE_LEN + 3 => Some((
                 bytes::read_u16_le(&payload[INT_BASE_LEN..]) as u64
-            }
-            INT_FLAGS_3 if remain > INT_BASE_LEN + 3 => {
-                bytes::read_u16_le(&payload[INT_BASE_LEN..]) as u64
-                    | ((payload[INT_BASE_LEN + 2] as u64) << 16)
-            }
-            INT_FLAGS_8 if remain > INT_BASE_LEN + 8 => {
-                bytes::read_u64_le(&payload[INT_BASE_LEN..])
-            }
-            _ => value as u64,
+                    | ((payload[INT_BASE_LEN + 2] as u64) << 16),
+                INT_BASE_LEN + 3,
+            )),
+            INT_FLAGS_8 if remain >= INT_BASE_LEN + 8 => Some((
+                bytes::read_u64_le(&payload[INT_BASE_LEN..]),
+                INT_BASE_LEN + 8,
+            )),
+            INT_FLAGS_2 | INT_FLAGS_3 | INT_FLAGS_8 => None,
+            _ => Some((value as u64, INT_BASE_LEN)),
+        }
+    }
+
+    // OK包格式：
+    // 0x00 | affected_rows(lenenc) | last_insert_id(lenenc) | status_flags(2) | warnings(2) | info
+    // =================================================
+    // OK packet layout:
+    // 0x00 | affected_rows(lenenc) | last_insert_id(lenenc) | status_flags(2) | warnings(2) | info
+    fn ok_packet(&mut self, payload: &[u8]) {
+        let mut offset = AFFECTED_ROWS_OFFSET;
+        if let Some((affected_rows, len)) = Self::decode_compress_int(&payload[offset..]) {
+            self.info.affected_rows = affected_rows;
+            offset += len;
+        } else {
+            return;
+        }
+        if let Some((last_insert_id, len)) = Self::decode_compress_int(&payload[offset..]) {
+            self.info.last_insert_id = last_insert_id;
+            offset += len;
+        } else {
+            return;
+        }
+        offset += STATUS_FLAGS_LEN;
+        if offset + WARNINGS_LEN <= payload.len() {
+            self.info.warning_count = bytes::read_u16_le(&payload[offset..]);
+        }
+    }
+
+    // PREPARE_OK包格式：
+    // 0x00 | statement_id(4) | num_columns(2) | num_params(2) | filler(1) | warnings(2)
+    // =================================================
+    // PREPARE_OK packet layout:
+    // 0x00 | statement_id(4) | num_columns(2) | num_params(2) | filler(1) | warnings(2)
+    fn prepare_ok_packet(&mut self, payload: &[u8]) {
+        if payload.len() < STATEMENT_ID_OFFSET + STATEMENT_ID_LEN {
+            return;
+        }
+        let statement_id = bytes::read_u32_le(&payload[STATEMENT_ID_OFFSET..]);
+        self.info.statement_id = statement_id;
+        if let Some(sql) = self.prepare_sql.take() {
+            self.statements.put(statement_id, sql);
+        }
+        if payload.len() >= PREPARE_OK_WARNINGS_OFFSET + WARNINGS_LEN {
+            self.info.warning_count = bytes::read_u16_le(&payload[PREPARE_OK_WARNINGS_OFFSET..]);
         }
     }
 
@@ -334,7 +476,7 @@ impl MysqlLog {
         }
     }
 
-    fn response(&mut self, payload: &[u8]) -> Result<()> {
+    fn response(&mut self, payload: &[u8], number: u8) -> Result<()> {
         let mut remain = payload.len();
         if remain < RESPONSE_CODE_LEN {
             return Err(Error::MysqlLogParseFailed);
@@ -351,6 +493,10 @@ impl MysqlLog {
                 }
                 let error_message_offset =
                     if remain > SQL_STATE_LEN && payload[SQL_STATE_OFFSET] == SQL_STATE_MARKER {
+                        self.info.sql_state = String::from_utf8_lossy(
+                            &payload[SQL_STATE_OFFSET + 1..SQL_STATE_OFFSET + SQL_STATE_LEN],
+                        )
+                        .into_owned();
                         SQL_STATE_OFFSET + SQL_STATE_LEN
                     } else {
                         SQL_STATE_OFFSET
@@ -362,13 +508,23 @@ impl MysqlLog {
             }
             MYSQL_RESPONSE_CODE_OK => {
                 self.info.status = L7ResponseStatus::Ok;
-                if self.command == COM_QUERY {
-                    self.info.affected_rows =
-                        MysqlLog::decode_compress_int(&payload[AFFECTED_ROWS_OFFSET..]);
+                // 只有应答的第一个包是OK包，结果集中的二进制行同样以0x00开头
+                // =================================================
+                // Only the first packet of a response is an OK packet, binary
+                // rows in a result set also start with 0x00
+                if number == 1 {
+                    if self.command == COM_STMT_PREPARE {
+                        self.prepare_ok_packet(payload);
+                    } else {
+                        self.ok_packet(payload);
+                    }
                 }
             }
             _ => (),
         }
+        if self.command == COM_STMT_PREPARE {
+            self.prepare_sql = None;
+        }
         self.command = 0;
         Ok(())
     }
@@ -425,7 +581,7 @@ impl MysqlLog {
 
         match msg_type {
             LogMessageType::Request => self.request(&payload[offset..])?,
-            LogMessageType::Response => self.response(&payload[offset..])?,
+            LogMessageType::Response => self.response(&payload[offset..], header.number)?,
             LogMessageType::Other => self.greeting(&payload[offset..])?,
             _ => return Err(Error::MysqlLogParseFailed),
         };
@@ -566,6 +722,7 @@ mod tests {
             ("mysql-table-destroy.pcap", "mysql-table-destroy.result"),
             ("mysql-table-alter.pcap", "mysql-table-alter.result"),
             ("mysql-database.pcap", "mysql-database.result"),
+            ("mysql-prepare.pcap", "mysql-prepare.result"),
         ];
 
         for item in files.iter() {
@@ -32,7 +32,7 @@ use crate::{
     flow_generator::{
         perf::{L7FlowPerf, PerfStats},
         protocol_logs::{
-            pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
             L7ResponseStatus,
         },
         AppProtoHead, Error, LogMessageType, Result,
@@ -47,6 +47,9 @@ use super::{
 };
 
 const SSL_REQ: u64 = 34440615471; // 00000008(len) 04d2162f(const 80877103)
+const GSSENC_REQ: u64 = 34440615472; // 00000008(len) 04d21630(const 80877104)
+
+const PREPARED_STATEMENT_CACHE_SIZE: usize = 64;
 
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct PostgreInfo {
@@ -95,6 +98,15 @@ pub struct PostgreInfo {
         skip_serializing_if = "value_is_default"
     )]
     pub error_message: String,
+    // ErrorResponse/NoticeResponse的字段S(V)、M、D
+    // =================================================
+    // Fields S(V), M and D of ErrorResponse/NoticeResponse
+    #[serde(skip)]
+    pub severity: String,
+    #[serde(skip)]
+    pub message: String,
+    #[serde(skip)]
+    pub detail: String,
     pub status: L7ResponseStatus,
 }
 
@@ -116,6 +128,9 @@ impl L7ProtocolInfoInterface for PostgreInfo {
                     self.error_message = pg.error_message;
                     self.status = pg.status;
                     self.affected_rows = pg.affected_rows;
+                    self.severity = pg.severity;
+                    self.message = pg.message;
+                    self.detail = pg.detail;
                 }
                 _ => {}
             }
@@ -138,6 +153,19 @@ impl L7ProtocolInfoInterface for PostgreInfo {
 
 impl From<PostgreInfo> for L7ProtocolSendLog {
     fn from(p: PostgreInfo) -> L7ProtocolSendLog {
+        let mut attributes = vec![];
+        if !p.severity.is_empty() {
+            attributes.push(KeyVal {
+                key: "severity".to_string(),
+                val: p.severity,
+            });
+        }
+        if !p.detail.is_empty() {
+            attributes.push(KeyVal {
+                key: "detail".to_string(),
+                val: p.detail,
+            });
+        }
         L7ProtocolSendLog {
             req_len: None,
             resp_len: None,
@@ -150,10 +178,22 @@ impl From<PostgreInfo> for L7ProtocolSendLog {
             resp: L7Response {
                 status: p.status,
                 result: p.result,
-                exception: p.error_message,
+                // 优先使用服务端返回的错误信息，没有时使用错误码的描述
+                // =================================================
+                // Prefer the message from the server, fall back to the description of the error code
+                exception: if p.message.is_empty() {
+                    p.error_message
+                } else {
+                    p.message
+                },
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -169,6 +209,25 @@ pub struct PostgresqlLog {
     #[serde(skip)]
     previous_log_info: LruCache<u32, (LogMessageType, u64)>,
     parsed: bool,
+
+    // <statement name, sql>，由Parse消息建立，用于Execute时还原SQL
+    // =================================================
+    // <statement name, sql>, built from Parse messages to recover the SQL of Execute
+    #[serde(skip)]
+    statements: LruCache<String, String>,
+    // <portal name, sql>，由Bind消息建立
+    // =================================================
+    // <portal name, sql>, built from Bind messages
+    #[serde(skip)]
+    portals: LruCache<String, String>,
+    // 客户端发送了SSLRequest/GSSENCRequest，等待服务端应答
+    // =================================================
+    // The client has sent SSLRequest/GSSENCRequest and waits for the server reply
+    encryption_requested: bool,
+    // 服务端接受了加密请求，之后的数据无法解析
+    // =================================================
+    // The server accepted the encryption request, the following data is not parsable
+    encrypted: bool,
 }
 
 impl Default for PostgresqlLog {
@@ -178,6 +237,10 @@ impl Default for PostgresqlLog {
             info: PostgreInfo::default(),
             perf_stats: None,
             parsed: false,
+            statements: LruCache::new(PREPARED_STATEMENT_CACHE_SIZE),
+            portals: LruCache::new(PREPARED_STATEMENT_CACHE_SIZE),
+            encryption_requested: false,
+            encrypted: false,
         }
     }
 }
@@ -210,13 +273,20 @@ impl L7ProtocolParserInterface for PostgresqlLog {
             return Ok(r);
         }
         self.info.is_tls = param.is_tls();
+        if self.encrypted {
+            return Ok(vec![]);
+        }
+        self.set_msg_type(param.direction);
         if self.check_is_ssl_req(payload) {
+            self.encryption_requested = true;
+            return Ok(vec![]);
+        }
+        if self.check_encryption_resp(payload) {
             return Ok(vec![]);
         }
 
         self.info.start_time = param.time;
         self.info.end_time = param.time;
-        self.set_msg_type(param.direction);
         self.parse(payload, param)?;
         self.revert_info_time(param.direction, param.time);
         Ok(if self.info.ignore {
@@ -341,7 +411,28 @@ impl PostgresqlLog {
     fn check_is_ssl_req(&self, payload: &[u8]) -> bool {
         payload.len() == 8
             && self.info.msg_type == LogMessageType::Request
-            && read_u64_be(payload) == SSL_REQ
+            && (read_u64_be(payload) == SSL_REQ || read_u64_be(payload) == GSSENC_REQ)
+    }
+
+    // SSLRequest/GSSENCRequest的应答只有一个字节，'S'/'G'表示接受，'N'表示拒绝
+    // =================================================
+    // The reply to SSLRequest/GSSENCRequest is a single byte, 'S'/'G' for accepted and 'N' for refused
+    fn check_encryption_resp(&mut self, payload: &[u8]) -> bool {
+        if !self.encryption_requested
+            || payload.len() != 1
+            || self.info.msg_type != LogMessageType::Response
+        {
+            return false;
+        }
+        self.encryption_requested = false;
+        match payload[0] {
+            b'S' | b'G' => {
+                self.encrypted = true;
+                true
+            }
+            b'N' => true,
+            _ => false,
+        }
     }
 
     fn on_req_block(&mut self, tag: char, data: &[u8], time: u64) -> Result<()> {
@@ -361,13 +452,14 @@ impl PostgresqlLog {
 
                 // | statement str, end with 0x0 | query str, end with 0x0 | param |
                 if let Some(idx) = data.iter().position(|x| *x == 0x0) {
-                    // skip statement
+                    let statement = String::from_utf8_lossy(&data[..idx]).to_string();
                     data = &data[idx + 1..];
 
                     // parse query
                     if let Some(idx) = data.iter().position(|x| *x == 0x0) {
                         self.info.context = String::from_utf8_lossy(&data[..idx]).to_string();
                         if is_postgresql(&self.info.context) {
+                            self.statements.put(statement, self.info.context.clone());
                             self.perf_inc_req(time);
                             return Ok(());
                         }
@@ -375,7 +467,51 @@ impl PostgresqlLog {
                 }
                 Err(Error::L7ProtocolUnknown)
             }
-            'B' | 'F' | 'C' | 'D' | 'H' | 'S' | 'X' | 'd' | 'c' | 'f' => Ok(()),
+            'B' => {
+                // | portal str, end with 0x0 | statement str, end with 0x0 | param |
+                let (portal, data) = read_cstring(data)?;
+                let (statement, _) = read_cstring(data)?;
+                if let Some(sql) = self.statements.get(&statement) {
+                    let sql = sql.clone();
+                    self.portals.put(portal, sql);
+                }
+                Ok(())
+            }
+            'E' => {
+                // | portal str, end with 0x0 | max rows 4B |
+                let (portal, _) = read_cstring(data)?;
+                // 同一个包中已经有Parse或Query时以其为准
+                // =================================================
+                // A Parse or Query in the same payload takes precedence
+                if self.info.req_type != char::default() {
+                    return Ok(());
+                }
+                if let Some(sql) = self.portals.get(&portal) {
+                    self.info.req_type = tag;
+                    self.info.context = sql.clone();
+                    self.info.ignore = false;
+                    self.perf_inc_req(time);
+                }
+                Ok(())
+            }
+            'C' => {
+                // | 'S' statement or 'P' portal 1B | name str, end with 0x0 |
+                if data.is_empty() {
+                    return Err(Error::L7ProtocolUnknown);
+                }
+                let (name, _) = read_cstring(&data[1..])?;
+                match data[0] {
+                    b'S' => {
+                        self.statements.pop(&name);
+                    }
+                    b'P' => {
+                        self.portals.pop(&name);
+                    }
+                    _ => return Err(Error::L7ProtocolUnknown),
+                }
+                Ok(())
+            }
+            'F' | 'D' | 'H' | 'S' | 'X' | 'd' | 'c' | 'f' => Ok(()),
             _ => Err(Error::L7ProtocolUnknown),
         }
     }
@@ -392,6 +528,11 @@ impl PostgresqlLog {
                 // DELETE xxx0x0
                 // UPDATE xxx0x0
                 // SELECT xxx0x0
+                // MERGE/MOVE/FETCH/COPY xxx0x0
+                // 一个包中有多个CommandComplete时(如批量执行)累加影响行数
+                // =================================================
+                // Affected rows are summed when there are multiple CommandComplete
+                // in a payload, such as a batch execution
                 if let Some(idx) = data.iter().position(|x| *x == 0x20) {
                     let op = &data[..idx];
                     data = &data[idx + 1..];
@@ -404,7 +545,11 @@ impl PostgresqlLog {
                     } else {
                         if !(op.eq("DELETE".as_bytes())
                             || op.eq("UPDATE".as_bytes())
-                            || op.eq("SELECT".as_bytes()))
+                            || op.eq("SELECT".as_bytes())
+                            || op.eq("MERGE".as_bytes())
+                            || op.eq("MOVE".as_bytes())
+                            || op.eq("FETCH".as_bytes())
+                            || op.eq("COPY".as_bytes()))
                         {
                             return Ok(());
                         }
@@ -413,7 +558,7 @@ impl PostgresqlLog {
 
                 if let Some(idx) = data.iter().position(|x| *x == 0x0) {
                     let row_eff = String::from_utf8_lossy(&data[..idx]).to_string();
-                    self.info.affected_rows = row_eff.parse().unwrap_or(0);
+                    self.info.affected_rows += row_eff.parse().unwrap_or(0);
                 }
                 self.perf_inc_resp(time);
                 Ok(())
@@ -423,26 +568,34 @@ impl PostgresqlLog {
                 self.info.resp_type = tag;
                 self.info.ignore = false;
                 /*
-                Severity: string end with 0x0
-                Text:     string end with 0x0
-                code:     string end with 0x0
-                message:  string end with 0x0
-                ...
+                | field type 1B | field value, end with 0x0 | ... | 0x0 |
 
+                S: severity
+                V: severity, not localized
+                C: code, such as `42601`
+                M: message
+                D: detail
+                ...
                 */
-                for _ in 0..2 {
-                    if let Some(idx) = data.iter().position(|x| *x == 0) {
-                        data = &data[idx + 1..];
-                    } else {
-                        return Ok(());
+                // 清除之前通知中的字段
+                // =================================================
+                // Clear the fields set by a preceding notice
+                self.info.severity.clear();
+                self.info.message.clear();
+                self.info.detail.clear();
+                let mut code = None;
+                for (typ, val) in read_fields(data) {
+                    match typ {
+                        b'S' if self.info.severity.is_empty() => self.info.severity = val,
+                        b'V' => self.info.severity = val,
+                        b'C' => code = Some(val),
+                        b'M' => self.info.message = val,
+                        b'D' => self.info.detail = val,
+                        _ => {}
                     }
                 }
-                // code, such as `C42601`
-                if let Some(idx) = data.iter().position(|x| *x == 0) {
-                    if data[0] != b'C' {
-                        return Err(Error::L7ProtocolUnknown);
-                    }
-                    self.info.result = String::from_utf8_lossy(&data[1..idx]).to_string();
+                if let Some(code) = code {
+                    self.info.result = code;
                     let (err_desc, status) = get_code_desc(self.info.result.as_str());
                     self.info.error_message = String::from(err_desc);
                     self.info.status = status;
@@ -458,8 +611,27 @@ impl PostgresqlLog {
                 Err(Error::L7ProtocolUnknown)
             }
 
-            'Z' | 'I' | '1' | '2' | '3' | 'S' | 'K' | 'T' | 'n' | 'N' | 't' | 'D' | 'G' | 'H'
-            | 'W' | 'd' | 'c' => Ok(()),
+            'N' => {
+                // 通知和错误的格式相同，不覆盖错误信息，也不影响状态
+                // =================================================
+                // Notice has the same layout as error, it does not override
+                // the error fields nor affect the status
+                if self.info.resp_type == 'E' {
+                    return Ok(());
+                }
+                for (typ, val) in read_fields(data) {
+                    match typ {
+                        b'S' if self.info.severity.is_empty() => self.info.severity = val,
+                        b'V' => self.info.severity = val,
+                        b'M' => self.info.message = val,
+                        b'D' => self.info.detail = val,
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            'Z' | 'I' | '1' | '2' | '3' | 'S' | 'K' | 'T' | 'n' | 't' | 'D' | 'G' | 'H' | 'W'
+            | 'd' | 'c' | 's' => Ok(()),
             _ => Err(Error::L7ProtocolUnknown),
         }
     }
@@ -483,6 +655,33 @@ fn read_block(payload: &[u8]) -> Option<(char, usize)> {
     Some((tag, len - 4))
 }
 
+// read a string end with 0x0, return the string and the remaining data
+fn read_cstring(data: &[u8]) -> Result<(String, &[u8])> {
+    if let Some(idx) = data.iter().position(|x| *x == 0x0) {
+        return Ok((
+            String::from_utf8_lossy(&data[..idx]).to_string(),
+            &data[idx + 1..],
+        ));
+    }
+    Err(Error::L7ProtocolUnknown)
+}
+
+// read the fields of ErrorResponse/NoticeResponse, stop at the terminator 0x0
+fn read_fields(mut data: &[u8]) -> Vec<(u8, String)> {
+    let mut fields = vec![];
+    while !data.is_empty() && data[0] != 0 {
+        let typ = data[0];
+        match read_cstring(&data[1..]) {
+            Ok((val, remain)) => {
+                fields.push((typ, val));
+                data = remain;
+            }
+            Err(_) => break,
+        }
+    }
+    fields
+}
+
 // strip the latest 0x0 in string
 // if not end with 0x0, presume it is not pg protocol
 fn strip_string_end_with_zero(data: &[u8]) -> Result<String> {
@@ -503,6 +702,7 @@ mod test {
             l7_protocol_log::L7ProtocolParserInterface,
             l7_protocol_log::ParseParam,
         },
+        flow_generator::protocol_logs::L7ResponseStatus,
         flow_generator::protocol_logs::PostgreInfo,
         flow_generator::protocol_logs::PostgresqlLog,
         utils::test::Capture,
@@ -541,6 +741,83 @@ mod test {
         assert_eq!(info.error_message.as_str(), "syntax_error",);
     }
 
+    #[test]
+    fn test_extended_query() {
+        let infos = parse_flow("extended_query.pcap");
+        assert_eq!(infos.len(), 8);
+        let sql = "UPDATE test SET name=$1 WHERE id=$2";
+
+        // Parse/Bind/Describe/Execute/Sync
+        let req = infos[0].as_ref().unwrap();
+        assert_eq!(req.req_type, 'P');
+        assert_eq!(req.context.as_str(), sql);
+        assert_eq!(infos[1].as_ref().unwrap().affected_rows, 3);
+
+        // Bind/Execute/Sync with the named statement
+        let req = infos[2].as_ref().unwrap();
+        assert_eq!(req.req_type, 'E');
+        assert_eq!(req.context.as_str(), sql);
+        assert_eq!(infos[3].as_ref().unwrap().affected_rows, 1);
+
+        // batch execution
+        let req = infos[4].as_ref().unwrap();
+        assert_eq!(req.req_type, 'E');
+        assert_eq!(req.context.as_str(), sql);
+        assert_eq!(infos[5].as_ref().unwrap().affected_rows, 3);
+
+        // NoticeResponse followed by ErrorResponse
+        let resp = infos[7].as_ref().unwrap();
+        assert_eq!(resp.resp_type, 'E');
+        assert_eq!(resp.result.as_str(), "23505");
+        assert_eq!(resp.error_message.as_str(), "unique_violation");
+        assert_eq!(resp.status, L7ResponseStatus::ClientError);
+        assert_eq!(resp.severity.as_str(), "ERROR");
+        assert_eq!(
+            resp.message.as_str(),
+            "duplicate key value violates unique constraint \"test_pkey\""
+        );
+        assert_eq!(resp.detail.as_str(), "Key (id)=(1) already exists.");
+    }
+
+    #[test]
+    fn test_encryption_request() {
+        // SSLRequest accepted, the following data is encrypted
+        let infos = parse_flow("ssl_request.pcap");
+        assert!(infos.iter().all(|i| i.is_none()));
+
+        // GSSENCRequest refused, the session continues in plain text
+        let infos = parse_flow("gssenc_refused.pcap");
+        assert!(infos[0].is_none() && infos[1].is_none());
+        assert_eq!(infos[2].as_ref().unwrap().context.as_str(), "SELECT 1;");
+        assert_eq!(infos[3].as_ref().unwrap().affected_rows, 1);
+    }
+
+    fn parse_flow(file_name: &str) -> Vec<Option<PostgreInfo>> {
+        let pcap_file = Path::new(FILE_DIR).join(file_name);
+        let capture = Capture::load_pcap(pcap_file, None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let mut parser = PostgresqlLog::new();
+        let mut infos = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let param = &ParseParam::from(&*packet);
+            let payload = packet.get_l4_payload().unwrap();
+            let info = match parser.parse_payload(payload, param).unwrap().pop() {
+                Some(L7ProtocolInfo::PostgreInfo(info)) => Some(info),
+                _ => None,
+            };
+            parser.reset();
+            infos.push(info);
+        }
+        infos
+    }
+
     fn check_and_parse(file_name: &str) -> PostgreInfo {
         let pcap_file = Path::new(FILE_DIR).join(file_name);
         let capture = Capture::load_pcap(pcap_file, None);
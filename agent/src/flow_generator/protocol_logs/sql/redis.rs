@@ -16,7 +16,7 @@
 
 use serde::{Serialize, Serializer};
 
-use std::{fmt, str};
+use std::{collections::VecDeque, fmt, str};
 
 use super::super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 
@@ -30,16 +30,22 @@ use crate::{
     },
     flow_generator::{
         error::{Error, Result},
-        protocol_logs::pb_adapter::{L7ProtocolSendLog, L7Request, L7Response},
+        protocol_logs::pb_adapter::{
+            ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+        },
     },
     parse_common,
 };
 
 const SEPARATOR_SIZE: usize = 2;
+// 等待应答的请求数上限，超过时丢弃最早的请求
+// =================================================
+// The maximum number of requests waiting for replies, the oldest is dropped when exceeded
+const MAX_PENDING_REPLIES: usize = 1024;
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct RedisInfo {
-    msg_type: LogMessageType,
+    pub(crate) msg_type: LogMessageType,
     #[serde(skip)]
     start_time: u64,
     #[serde(skip)]
@@ -74,13 +80,23 @@ pub struct RedisInfo {
     pub error: Vec<u8>, // '-'
     #[serde(rename = "response_status")]
     pub resp_status: L7ResponseStatus,
+    // 集群重定向MOVED/ASK，不作为错误
+    // =================================================
+    // Cluster redirect MOVED/ASK, not treated as an error
+    #[serde(skip)]
+    pub redirect: &'static str,
 
     cap_seq: Option<u64>,
+    // 请求在连接中的序号，用于流水线中请求和应答的配对
+    // =================================================
+    // Sequence of the request in the connection, used to pair pipelined requests and replies
+    #[serde(skip)]
+    pub(crate) seq: Option<u32>,
 }
 
 impl L7ProtocolInfoInterface for RedisInfo {
     fn session_id(&self) -> Option<u32> {
-        None
+        self.seq
     }
 
     fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
@@ -127,9 +143,60 @@ impl RedisInfo {
         self.status = other.status;
         self.error = other.error;
         self.resp_status = other.resp_status;
+        self.redirect = other.redirect;
         Ok(())
     }
 
+    fn fill_response(&mut self, context: Vec<u8>, error_response: bool) {
+        self.msg_type = LogMessageType::Response;
+        if context.is_empty() {
+            return;
+        }
+
+        self.resp_status = L7ResponseStatus::Ok;
+        match context[0] {
+            b'+' => self.status = context,
+            _ if error_response => {
+                // "-MOVED 3999 127.0.0.1:6381"
+                // "-ASK 3999 127.0.0.1:6381"
+                let error = context.strip_prefix(b"-").unwrap_or(&context);
+                if error.starts_with(b"MOVED ") {
+                    self.redirect = "MOVED";
+                } else if error.starts_with(b"ASK ") {
+                    self.redirect = "ASK";
+                }
+                if self.redirect.is_empty() {
+                    self.error = context;
+                    self.resp_status = L7ResponseStatus::ServerError;
+                } else {
+                    self.response = context;
+                }
+            }
+            _ => self.response = context,
+        }
+    }
+
+    // 事务中各命令的结果以"; "分隔，有错误时记录第一个错误
+    // =================================================
+    // Results of the commands in a transaction are separated by "; ",
+    // the first error is recorded if any
+    fn fill_exec_response(&mut self, results: Vec<(Vec<u8>, bool)>) {
+        self.msg_type = LogMessageType::Response;
+        self.resp_status = L7ResponseStatus::Ok;
+        let mut response = vec![];
+        for (result, is_error) in results {
+            if !response.is_empty() {
+                response.extend_from_slice(b"; ");
+            }
+            response.extend_from_slice(&result);
+            if is_error && self.error.is_empty() {
+                self.error = result;
+                self.resp_status = L7ResponseStatus::ServerError;
+            }
+        }
+        self.response = response;
+    }
+
     pub fn set_packet_seq(&mut self, param: &ParseParam) {
         if let Some(p) = param.ebpf_param {
             self.cap_seq = Some(p.cap_seq);
@@ -137,6 +204,10 @@ impl RedisInfo {
     }
 
     pub fn can_merge(&self, resp: &Self) -> bool {
+        // 流水线的请求和应答已按顺序配对
+        if self.seq.is_some() && self.seq == resp.seq {
+            return true;
+        }
         if let (Some(req_seq), Some(resp_seq)) = (self.cap_seq, resp.cap_seq) {
             return resp_seq > req_seq && resp_seq - req_seq == 1;
         }
@@ -176,6 +247,13 @@ impl fmt::Display for RedisInfo {
 
 impl From<RedisInfo> for L7ProtocolSendLog {
     fn from(f: RedisInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.redirect.is_empty() {
+            attributes.push(KeyVal {
+                key: "redirect".to_string(),
+                val: f.redirect.to_string(),
+            });
+        }
         let log = L7ProtocolSendLog {
             req: L7Request {
                 req_type: String::from_utf8_lossy(f.request_type.as_slice()).to_string(),
@@ -188,15 +266,54 @@ impl From<RedisInfo> for L7ProtocolSendLog {
                 result: String::from_utf8_lossy(f.response.as_slice()).to_string(),
                 ..Default::default()
             },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
             ..Default::default()
         };
         return log;
     }
 }
 
+// 等待应答的请求
+// =================================================
+// Request waiting for its reply
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PendingReply {
+    // 普通命令，应答使用与请求相同的序号
+    // =================================================
+    // Normal command, the reply uses the same sequence as the request
+    Command(u32),
+    // MULTI和事务中排队的命令，应答为+OK/+QUEUED，不单独记录
+    // =================================================
+    // MULTI and the commands queued in a transaction, replied with +OK/+QUEUED which are not logged
+    Queued,
+    // EXEC，应答为事务中每个命令的结果
+    // =================================================
+    // EXEC, replied with the result of every command in the transaction
+    Exec(u32),
+}
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct RedisLog {
     info: RedisInfo,
+    #[serde(skip)]
+    seq: u32,
+    // 按发送顺序排列的等待应答的请求
+    // =================================================
+    // Requests waiting for replies in the order they were sent
+    #[serde(skip)]
+    pending: VecDeque<PendingReply>,
+    // MULTI之后排队的命令，EXEC/DISCARD时合并为一个请求
+    // =================================================
+    // Commands queued after MULTI, combined into one request on EXEC/DISCARD
+    #[serde(skip)]
+    transaction: Option<Vec<Vec<u8>>>,
 }
 
 impl L7ProtocolParserInterface for RedisLog {
@@ -213,8 +330,13 @@ impl L7ProtocolParserInterface for RedisLog {
         parse_common!(self, param);
         self.info.is_tls = param.is_tls();
         self.info.set_packet_seq(param);
-        self.parse(payload, param.l4_protocol, param.direction, None, None)?;
-        Ok(vec![L7ProtocolInfo::RedisInfo(self.info.clone())])
+        let infos = self.parse(
+            payload,
+            param.l4_protocol,
+            param.direction,
+            param.log_packet_size,
+        )?;
+        Ok(infos.into_iter().map(L7ProtocolInfo::RedisInfo).collect())
     }
 
     fn protocol(&self) -> L7Protocol {
@@ -225,41 +347,110 @@ impl L7ProtocolParserInterface for RedisLog {
         false
     }
 
+    // 截断的流水线会丢失请求，使后续的应答无法配对，超出 log_packet_size 的消息只用于配对
+    // =================================================
+    // A truncated pipeline loses requests so that the following replies can not be paired,
+    // messages beyond log_packet_size are only used for pairing
+    fn need_complete_payload(&self) -> bool {
+        true
+    }
+
     fn reset(&mut self) {
-        *self = Self::default();
+        self.info = RedisInfo::default();
     }
 }
 
-impl RedisLog {
-    fn reset(&mut self) {
-        *self = RedisLog::default();
+fn request_type(context: &[u8]) -> &[u8] {
+    match context.iter().position(|&x| x == b' ') {
+        Some(i) if i > 0 => &context[..i],
+        _ => context,
     }
+}
 
-    fn fill_request(&mut self, context: Vec<u8>) {
-        self.info.request_type = match (&context).iter().position(|&x| x == b' ') {
-            Some(i) if i > 0 => Vec::from(&context[..i]),
-            _ => context.clone(),
-        };
-        self.info.msg_type = LogMessageType::Request;
-        self.info.request = context;
+impl RedisLog {
+    fn next_seq(&mut self) -> u32 {
+        self.seq = self.seq.wrapping_add(1);
+        self.seq
     }
 
-    fn fill_response(&mut self, context: Vec<u8>, error_response: bool) {
-        self.info.msg_type = LogMessageType::Response;
-        if context.is_empty() {
-            return;
+    fn push_pending(&mut self, reply: PendingReply) {
+        if self.pending.len() >= MAX_PENDING_REPLIES {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(reply);
+    }
+
+    fn fill_request(&mut self, request_type: Vec<u8>, context: Vec<u8>, seq: u32) -> RedisInfo {
+        let mut info = self.info.clone();
+        info.msg_type = LogMessageType::Request;
+        info.request_type = request_type;
+        info.request = context;
+        info.seq = Some(seq);
+        info
+    }
+
+    fn on_request(&mut self, context: Vec<u8>) -> Option<RedisInfo> {
+        let command = request_type(&context);
+        let is_exec = command.eq_ignore_ascii_case(b"EXEC");
+        if let Some(mut commands) = self.transaction.take() {
+            if !is_exec && !command.eq_ignore_ascii_case(b"DISCARD") {
+                commands.push(context);
+                self.transaction = Some(commands);
+                self.push_pending(PendingReply::Queued);
+                return None;
+            }
+            // 请求为"MULTI; SET a 1; INCR b; EXEC"
+            // =================================================
+            // The request is "MULTI; SET a 1; INCR b; EXEC"
+            let request_type = request_type(&commands[0]).to_vec();
+            commands.push(context);
+            let seq = self.next_seq();
+            self.push_pending(if is_exec {
+                PendingReply::Exec(seq)
+            } else {
+                PendingReply::Command(seq)
+            });
+            return Some(self.fill_request(request_type, commands.join(&b"; "[..]), seq));
         }
 
-        self.info.resp_status = L7ResponseStatus::Ok;
-        match context[0] {
-            b'+' => self.info.status = context,
-            b'-' if error_response => {
-                self.info.error = context;
-                self.info.resp_status = L7ResponseStatus::ServerError;
+        if command.eq_ignore_ascii_case(b"MULTI") {
+            self.transaction = Some(vec![context]);
+            self.push_pending(PendingReply::Queued);
+            return None;
+        }
+        let request_type = command.to_vec();
+        let seq = self.next_seq();
+        self.push_pending(PendingReply::Command(seq));
+        Some(self.fill_request(request_type, context, seq))
+    }
+
+    fn on_response(
+        &mut self,
+        payload: &[u8],
+        context: Vec<u8>,
+        error_response: bool,
+    ) -> Option<RedisInfo> {
+        // RESP3的推送消息不是对请求的应答
+        // =================================================
+        // RESP3 push messages are not replies to requests
+        if payload[0] == b'>' {
+            return None;
+        }
+        let mut info = self.info.clone();
+        match self.pending.pop_front() {
+            Some(PendingReply::Queued) => return None,
+            Some(PendingReply::Exec(seq)) => {
+                info.seq = Some(seq);
+                if let Some(results) = decode_exec_results(payload) {
+                    info.fill_exec_response(results);
+                    return Some(info);
+                }
             }
-            b'-' if !error_response => self.info.response = context,
-            _ => self.info.response = context,
+            Some(PendingReply::Command(seq)) => info.seq = Some(seq),
+            None => {}
         }
+        info.fill_response(context, error_response);
+        Some(info)
     }
 
     pub fn redis_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
@@ -270,29 +461,49 @@ impl RedisLog {
         if payload[0] != b'*' {
             return false;
         }
-        decode_asterisk(payload, true).is_some()
+        decode_asterisk(payload, true, 1).is_some()
     }
 
-    fn parse(
+    pub(crate) fn parse(
         &mut self,
         payload: &[u8],
         proto: IpProtocol,
         direction: PacketDirection,
-        _is_req_end: Option<bool>,
-        _is_resp_end: Option<bool>,
-    ) -> Result<()> {
+        log_packet_size: usize,
+    ) -> Result<Vec<RedisInfo>> {
         if proto != IpProtocol::Tcp {
             return Err(Error::InvalidIpProtocol);
         }
 
-        let (context, _, error_response) =
-            decode(payload, direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisLogParseFailed)?;
-        match direction {
-            PacketDirection::ClientToServer => self.fill_request(context),
-            PacketDirection::ServerToClient => self.fill_response(context, error_response),
-        };
-        Ok(())
+        // 一个包中可能有多个流水线的请求或应答
+        // =================================================
+        // A payload may carry multiple pipelined requests or replies
+        let mut infos = vec![];
+        let mut offset = 0;
+        while offset < payload.len() {
+            let message = &payload[offset..];
+            let (context, size, error_response) =
+                match decode(message, direction == PacketDirection::ClientToServer) {
+                    Some(r) => r,
+                    None if offset == 0 => return Err(Error::RedisLogParseFailed),
+                    None => break,
+                };
+            let info = match direction {
+                PacketDirection::ClientToServer => self.on_request(context),
+                PacketDirection::ServerToClient => {
+                    self.on_response(message, context, error_response)
+                }
+            };
+            match info {
+                Some(info) if offset < log_packet_size => infos.push(info),
+                _ => {}
+            }
+            if size == 0 {
+                break;
+            }
+            offset += size;
+        }
+        Ok(infos)
     }
 }
 
@@ -333,10 +544,14 @@ fn decode_dollor(payload: &[u8], strict: bool) -> Option<(&[u8], usize)> {
 
     // $-1 $0时返回
     if next_data_len <= 0 {
-        return Some((
-            &payload[offset..offset + sub_offset - SEPARATOR_SIZE],
-            offset + sub_offset,
-        ));
+        let mut size = offset + sub_offset;
+        // "$0\r\n\r\n"的内容为空，但结尾仍有\r\n
+        // =================================================
+        // "$0\r\n\r\n" has empty content but still ends with \r\n
+        if next_data_len == 0 && payload[size..].starts_with(b"\r\n") {
+            size += SEPARATOR_SIZE;
+        }
+        return Some((&payload[offset..offset + sub_offset - SEPARATOR_SIZE], size));
     }
 
     offset += sub_offset;
@@ -361,7 +576,10 @@ fn decode_dollor(payload: &[u8], strict: bool) -> Option<(&[u8], usize)> {
 }
 
 // 命令为"set mykey myvalue"，实际封装为"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n"
-fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
+// RESP3的映射"%2\r\n..."的元素个数为键值对数的两倍，multiple为2
+// =================================================
+// The number of elements of RESP3 map "%2\r\n..." is twice the number of pairs, multiple is 2
+fn decode_asterisk(payload: &[u8], strict: bool, multiple: isize) -> Option<(Vec<u8>, usize)> {
     let mut offset = 1; // 开头的 *
 
     // 提取请求参数个数/批量回复个数
@@ -376,6 +594,10 @@ fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
         ));
     }
     offset += sub_offset;
+    // 元素个数来自报文，溢出时按解析失败处理
+    // =================================================
+    // The number of elements comes from the payload, overflow is treated as a parse failure
+    let next_data_num = next_data_num.checked_mul(multiple)?;
 
     let mut ret_vec = Vec::new();
     let len = payload.len();
@@ -398,24 +620,59 @@ fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
             if offset >= len {
                 return Some((ret_vec, len));
             }
+        } else {
+            break;
         }
     }
     Some((ret_vec, offset))
 }
 
+// EXEC的应答为数组，返回每个元素的内容及是否为错误
+// =================================================
+// The reply of EXEC is an array, returns the content of every element and whether it is an error
+fn decode_exec_results(payload: &[u8]) -> Option<Vec<(Vec<u8>, bool)>> {
+    if payload[0] != b'*' {
+        return None;
+    }
+    let (num, mut offset) = decode_integer(&payload[1..])?;
+    if num <= 0 {
+        return None;
+    }
+    offset += 1;
+
+    let mut results = vec![];
+    for _ in 0..num {
+        if offset >= payload.len() {
+            break;
+        }
+        match decode(&payload[offset..], false) {
+            Some((result, size, is_error)) if size > 0 => {
+                results.push((result, is_error));
+                offset += size;
+            }
+            _ => break,
+        }
+    }
+    Some(results)
+}
+
 fn decode_str(payload: &[u8], limit: usize) -> Option<(&[u8], usize)> {
     let len = payload.len();
     let separator_pos = find_separator(payload).unwrap_or(len);
+    // 解析的长度包括结尾的\r\n
+    // =================================================
+    // The decoded length includes the trailing \r\n
+    let size = (separator_pos + SEPARATOR_SIZE).min(len);
 
     if separator_pos > limit {
         return Some((
             // 截取数据后，并不会在末尾增加'...'提示
             &payload[..limit],
-            limit,
+            size,
         ));
     }
 
-    Some((&payload[..separator_pos], separator_pos))
+    Some((&payload[..separator_pos], size))
 }
 
 // 函数在入参为"$-1"或"-1"时都返回"-1", 使用第三个参数区分是否为错误回复
@@ -425,14 +682,36 @@ pub fn decode(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize, bool)> {
     }
 
     match payload[0] {
-        // 请求或多条批量回复
-        b'*' => decode_asterisk(payload, strict).map(|(v, s)| (v, s, false)),
-        // 状态回复,整数回复
-        b'+' | b':' => decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false)),
+        // 请求或多条批量回复，RESP3的集合和推送
+        b'*' | b'~' | b'>' => decode_asterisk(payload, strict, 1).map(|(v, s)| (v, s, false)),
+        // RESP3的映射
+        b'%' => decode_asterisk(payload, strict, 2).map(|(v, s)| (v, s, false)),
+        // RESP3的属性，其后紧跟实际的回复
+        b'|' => {
+            let (_, attribute_size) = decode_asterisk(payload, strict, 2)?;
+            if attribute_size >= payload.len() {
+                return None;
+            }
+            decode(&payload[attribute_size..], strict).map(|(v, s, e)| (v, attribute_size + s, e))
+        }
+        // 状态回复,整数回复，RESP3的空值、布尔值、浮点数和大数
+        b'+' | b':' | b'_' | b'#' | b',' | b'(' => {
+            decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false))
+        }
         // 错误回复
         b'-' => decode_str(payload, 256).map(|(v, s)| (v.to_vec(), s, true)),
         // 批量回复
         b'$' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, false)),
+        // RESP3的批量错误
+        b'!' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, true)),
+        // RESP3的逐字字符串，内容以3个字节的格式和':'开头，如"txt:"
+        b'=' => decode_dollor(payload, strict).map(|(v, s)| {
+            if v.len() >= 4 && v[3] == b':' {
+                (v[4..].to_vec(), s, false)
+            } else {
+                (v.to_vec(), s, false)
+            }
+        }),
         _ => None,
     }
 }
@@ -482,16 +761,26 @@ mod tests {
             };
 
             let mut redis = RedisLog::default();
-            let _ = redis.parse(
-                payload,
-                packet.lookup_key.proto,
-                packet.lookup_key.direction,
-                None,
-                None,
-            );
+            let infos = redis
+                .parse(
+                    payload,
+                    packet.lookup_key.proto,
+                    packet.lookup_key.direction,
+                    usize::MAX,
+                )
+                .unwrap_or_default();
             let is_redis =
                 RedisLog::redis_check_protocol(payload, &ParseParam::from(packet as &MetaPacket));
-            output.push_str(&format!("{} is_redis: {}\r\n", redis.info, is_redis));
+            if infos.is_empty() {
+                output.push_str(&format!(
+                    "{} is_redis: {}\r\n",
+                    RedisInfo::default(),
+                    is_redis
+                ));
+            }
+            for info in infos {
+                output.push_str(&format!("{} is_redis: {}\r\n", info, is_redis));
+            }
         }
         output
     }
@@ -562,7 +851,153 @@ mod tests {
         let payload = [b'-', b'1', b'\r', b'\n'];
         let (context, n, e) = decode(payload.as_slice(), true).unwrap();
         assert_eq!(context, "-1".as_bytes());
-        assert_eq!(n, 2);
+        assert_eq!(n, payload.len());
         assert_eq!(e, true);
     }
+
+    #[test]
+    fn test_decode_resp3() {
+        let (context, n, _) = decode(b"%1\r\n+key\r\n:1\r\n", false).unwrap();
+        assert_eq!(context, "+key :1".as_bytes());
+        assert_eq!(n, 14);
+
+        let (context, _, e) = decode(b"!10\r\nERR failed\r\n", false).unwrap();
+        assert_eq!(context, "ERR failed".as_bytes());
+        assert_eq!(e, true);
+
+        let (context, _, _) = decode(b"=9\r\ntxt:hello\r\n", false).unwrap();
+        assert_eq!(context, "hello".as_bytes());
+
+        let payload = b"|1\r\n+ttl\r\n:3600\r\n#t\r\n";
+        let (context, n, _) = decode(payload, false).unwrap();
+        assert_eq!(context, "#t".as_bytes());
+        assert_eq!(n, payload.len());
+
+        for payload in [&b"_\r\n"[..], b",3.14\r\n", b"(3492890328409238509324\r\n"] {
+            let (_, n, e) = decode(payload, false).unwrap();
+            assert_eq!(n, payload.len());
+            assert_eq!(e, false);
+        }
+
+        // 映射的元素个数溢出
+        let payload = format!("%{}\r\n+key\r\n:1\r\n", isize::MAX);
+        assert!(decode(payload.as_bytes(), false).is_none());
+        let mut redis = RedisLog::default();
+        assert!(matches!(
+            redis.parse(
+                payload.as_bytes(),
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                usize::MAX,
+            ),
+            Err(Error::RedisLogParseFailed)
+        ));
+    }
+
+    fn parse(redis: &mut RedisLog, payload: &[u8], direction: PacketDirection) -> Vec<RedisInfo> {
+        redis
+            .parse(payload, IpProtocol::Tcp, direction, usize::MAX)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let mut redis = RedisLog::default();
+        let requests = parse(
+            &mut redis,
+            b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$4\r\nINCR\r\n$1\r\nb\r\n",
+            PacketDirection::ClientToServer,
+        );
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request, b"GET a");
+        assert_eq!(requests[1].request, b"INCR b");
+
+        let responses = parse(
+            &mut redis,
+            b"$1\r\n1\r\n-WRONGTYPE Operation against a key\r\n",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].response, b"1");
+        assert_eq!(responses[0].session_id(), requests[0].session_id());
+        assert_eq!(responses[1].error, b"-WRONGTYPE Operation against a key");
+        assert_eq!(responses[1].resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(responses[1].session_id(), requests[1].session_id());
+
+        // 超出 log_packet_size 的请求不输出日志，但仍参与配对
+        let requests = redis
+            .parse(
+                b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer,
+                20,
+            )
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+        let responses = parse(
+            &mut redis,
+            b"$1\r\n1\r\n$1\r\n2\r\n",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].session_id(), requests[0].session_id());
+        assert_eq!(
+            responses[1].session_id(),
+            requests[0].session_id().map(|s| s + 1)
+        );
+    }
+
+    #[test]
+    fn test_transaction() {
+        let mut redis = RedisLog::default();
+        let mut requests = vec![];
+        for payload in [
+            &b"*1\r\n$5\r\nMULTI\r\n"[..],
+            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n",
+            b"*2\r\n$5\r\nLPUSH\r\n$1\r\na\r\n",
+            b"*1\r\n$4\r\nEXEC\r\n",
+        ] {
+            requests.extend(parse(&mut redis, payload, PacketDirection::ClientToServer));
+        }
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].request, b"MULTI; SET a 1; LPUSH a; EXEC");
+        assert_eq!(requests[0].request_type, b"MULTI");
+
+        let mut responses = vec![];
+        for payload in [
+            &b"+OK\r\n"[..],
+            b"+QUEUED\r\n+QUEUED\r\n",
+            b"*2\r\n+OK\r\n-WRONGTYPE Operation against a key\r\n",
+        ] {
+            responses.extend(parse(&mut redis, payload, PacketDirection::ServerToClient));
+        }
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].session_id(), requests[0].session_id());
+        assert_eq!(
+            responses[0].response,
+            b"+OK; -WRONGTYPE Operation against a key"
+        );
+        assert_eq!(responses[0].error, b"-WRONGTYPE Operation against a key");
+        assert_eq!(responses[0].resp_status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn test_redirect() {
+        let mut redis = RedisLog::default();
+        parse(
+            &mut redis,
+            b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n",
+            PacketDirection::ClientToServer,
+        );
+        let responses = parse(
+            &mut redis,
+            b"-MOVED 15495 127.0.0.1:7002\r\n",
+            PacketDirection::ServerToClient,
+        );
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].redirect, "MOVED");
+        assert_eq!(responses[0].response, b"-MOVED 15495 127.0.0.1:7002");
+        assert_eq!(responses[0].resp_status, L7ResponseStatus::Ok);
+        assert!(responses[0].error.is_empty());
+    }
 }
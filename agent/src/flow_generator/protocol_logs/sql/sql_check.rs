@@ -128,6 +128,53 @@ pub(super) fn is_mysql(sql: &String) -> bool {
     }
 }
 
+/*
+    t-sql start with(not all of the t-sql start first keyword):
+
+    USE
+    BEGIN
+    COMMIT
+    ROLLBACK
+    SAVE
+    EXEC
+    EXECUTE
+    IF
+    MERGE
+    TRUNCATE
+    WAITFOR
+    PRINT
+    DBCC
+    RAISERROR
+    THROW
+*/
+
+// not all of t-sql start first keyword. only log some necessary sql.
+const TDS_START: [&'static str; 15] = [
+    "USE",
+    "BEGIN",
+    "COMMIT",
+    "ROLLBACK",
+    "SAVE",
+    "EXEC",
+    "EXECUTE",
+    "IF",
+    "MERGE",
+    "TRUNCATE",
+    "WAITFOR",
+    "PRINT",
+    "DBCC",
+    "RAISERROR",
+    "THROW",
+];
+
+pub(super) fn is_tds(sql: &String) -> bool {
+    if let Some(first) = trim_head_comment_and_first_upper(sql.as_str(), 9) {
+        check_sql(first, &TDS_START)
+    } else {
+        false
+    }
+}
+
 /*
     strip the sql comment from head and return first word and upper it.
     sql comment include:
@@ -0,0 +1,917 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use serde::Serialize;
+
+use super::sql_check::is_tds;
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::{read_u16_be, read_u16_le, read_u32_le, read_u64_le},
+};
+
+// https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-tds
+const HEADER_LEN: usize = 8;
+
+const PACKET_SQL_BATCH: u8 = 0x01;
+const PACKET_RPC: u8 = 0x03;
+const PACKET_TABULAR_RESULT: u8 = 0x04;
+const PACKET_ATTENTION: u8 = 0x06;
+const PACKET_BULK_LOAD: u8 = 0x07;
+const PACKET_TRANSACTION_MANAGER: u8 = 0x0e;
+const PACKET_LOGIN7: u8 = 0x10;
+const PACKET_SSPI: u8 = 0x11;
+const PACKET_PRE_LOGIN: u8 = 0x12;
+
+const STATUS_EOM: u8 = 0x01;
+
+const TOKEN_RETURN_STATUS: u8 = 0x79;
+const TOKEN_ORDER: u8 = 0xa9;
+const TOKEN_ERROR: u8 = 0xaa;
+const TOKEN_INFO: u8 = 0xab;
+const TOKEN_LOGIN_ACK: u8 = 0xad;
+const TOKEN_ENV_CHANGE: u8 = 0xe3;
+const TOKEN_SSPI: u8 = 0xed;
+const TOKEN_DONE: u8 = 0xfd;
+const TOKEN_DONE_PROC: u8 = 0xfe;
+const TOKEN_DONE_IN_PROC: u8 = 0xff;
+
+// token type 1B, status 2B, current command 2B, row count 8B
+const DONE_TOKEN_LEN: usize = 13;
+const DONE_STATUS_COUNT: u16 = 0x0010;
+const DONE_STATUS_SRVERROR: u16 = 0x0100;
+
+const ENV_CHANGE_DATABASE: u8 = 1;
+
+const PRE_LOGIN_VERSION: u8 = 0x00;
+const PRE_LOGIN_TERMINATOR: u8 = 0xff;
+
+// Login7 中 HostName 的 offset/length 的位置，其后依次为 UserName、Password、AppName、
+// ServerName、Extension、CltIntName、Language、Database
+// ==================================================================================
+// position of offset/length of HostName in Login7, followed by UserName, Password, AppName,
+// ServerName, Extension, CltIntName, Language and Database
+const LOGIN7_HOST_NAME_OFFSET: usize = 36;
+const LOGIN7_USER_NAME: usize = 1;
+const LOGIN7_APP_NAME: usize = 3;
+const LOGIN7_DATABASE: usize = 8;
+
+fn packet_type_name(packet_type: u8) -> Option<&'static str> {
+    match packet_type {
+        PACKET_SQL_BATCH => Some("SQL Batch"),
+        PACKET_RPC => Some("RPC"),
+        PACKET_TABULAR_RESULT => Some("Tabular Result"),
+        PACKET_ATTENTION => Some("Attention"),
+        PACKET_BULK_LOAD => Some("Bulk Load"),
+        0x08 => Some("Federated Authentication Token"),
+        PACKET_TRANSACTION_MANAGER => Some("Transaction Manager"),
+        PACKET_LOGIN7 => Some("Login7"),
+        PACKET_SSPI => Some("SSPI"),
+        PACKET_PRE_LOGIN => Some("Pre-Login"),
+        _ => None,
+    }
+}
+
+// RPC 请求中 ProcIDSwitch 为 0xFFFF 时使用的存储过程编号
+// =====================================================
+// well-known stored procedure ids used when ProcIDSwitch in RPC request is 0xFFFF
+fn special_procedure_name(id: u16) -> Option<&'static str> {
+    match id {
+        1 => Some("sp_cursor"),
+        2 => Some("sp_cursoropen"),
+        3 => Some("sp_cursorprepare"),
+        4 => Some("sp_cursorexecute"),
+        5 => Some("sp_cursorprepexec"),
+        6 => Some("sp_cursorunprepare"),
+        7 => Some("sp_cursorfetch"),
+        8 => Some("sp_cursoroption"),
+        9 => Some("sp_cursorclose"),
+        10 => Some("sp_executesql"),
+        11 => Some("sp_prepare"),
+        12 => Some("sp_execute"),
+        13 => Some("sp_prepexec"),
+        14 => Some("sp_prepexecrpc"),
+        15 => Some("sp_unprepare"),
+        _ => None,
+    }
+}
+
+// 语句在存储过程字符串参数中的位置
+// ==============================
+// position of the statement in the string parameters of the stored procedure
+fn statement_param_index(procedure: &str) -> Option<usize> {
+    match procedure.to_ascii_lowercase().as_str() {
+        // sp_executesql @stmt, @params, ...
+        // sp_cursoropen @cursor OUTPUT, @stmt, ...
+        "sp_executesql" | "sp_cursoropen" => Some(0),
+        // sp_prepare @handle OUTPUT, @params, @stmt, ...
+        // sp_prepexec @handle OUTPUT, @params, @stmt, ...
+        // sp_cursorprepexec @handle OUTPUT, @cursor OUTPUT, @params, @stmt, ...
+        "sp_prepare" | "sp_prepexec" | "sp_cursorprepare" | "sp_cursorprepexec" => Some(1),
+        _ => None,
+    }
+}
+
+/*
+    ERROR token 的 Class 即错误的严重级别:
+    11-16 为用户可以纠正的错误，17-25 为资源不足、服务端内部错误或致命错误
+    ========================================================================
+    Class of ERROR token is the severity of the error:
+    11-16 are errors that can be corrected by user, 17-25 are insufficient resources,
+    internal errors of server or fatal errors
+*/
+fn severity_status(class: u8) -> L7ResponseStatus {
+    match class {
+        0..=16 => L7ResponseStatus::ClientError,
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+fn ucs2_to_string(data: &[u8]) -> String {
+    let chars = data.chunks_exact(2).map(read_u16_le).collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars)
+}
+
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(v)
+    }
+
+    // 数据被截断时返回已有部分
+    // ========================
+    // return the available part when data is truncated
+    fn take_partial(&mut self, n: usize) -> &'a [u8] {
+        let (v, rest) = self.0.split_at(n.min(self.0.len()));
+        self.0 = rest;
+        v
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(read_u16_le(self.take(2)?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(read_u32_le(self.take(4)?))
+    }
+
+    fn b_varchar(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        Some(ucs2_to_string(self.take(len * 2)?))
+    }
+
+    fn us_varchar(&mut self) -> Option<String> {
+        let len = self.u16()? as usize;
+        Some(ucs2_to_string(self.take(len * 2)?))
+    }
+}
+
+/*
+    TDS 7.2 及以上版本的 SQL Batch 和 RPC 请求以 ALL_HEADERS 开头:
+    | TotalLength 4B | HeaderLength 4B | HeaderType 2B | HeaderData | ... |
+    ==============================================================
+    SQL Batch and RPC requests of TDS 7.2 and above start with ALL_HEADERS:
+    | TotalLength 4B | HeaderLength 4B | HeaderType 2B | HeaderData | ... |
+*/
+fn skip_all_headers(data: &[u8]) -> &[u8] {
+    if data.len() < 10 {
+        return data;
+    }
+    let total = read_u32_le(data) as usize;
+    let first = read_u32_le(&data[4..]) as usize;
+    if total > data.len() || first < 6 || first > total - 4 {
+        return data;
+    }
+    &data[total..]
+}
+
+/*
+    RPC 参数:
+    | ParamName B_VARCHAR | StatusFlags 1B | TYPE_INFO | ParamLenData |
+    只支持常用的整数和字符串类型，字符串类型参数返回 Some(value)
+    ===============================================================
+    RPC parameter:
+    | ParamName B_VARCHAR | StatusFlags 1B | TYPE_INFO | ParamLenData |
+    only common integer and string types are supported, Some(value) is returned for string
+    parameters
+*/
+fn read_rpc_param(c: &mut Cursor) -> Option<Option<String>> {
+    c.b_varchar()?;
+    c.u8()?;
+    let typ = c.u8()?;
+    match typ {
+        // INTN, BITN, FLTN, MONEYN, DATETIMN: max length 1B, length 1B
+        0x26 | 0x68 | 0x6d | 0x6e | 0x6f => {
+            c.u8()?;
+            let len = c.u8()? as usize;
+            c.take(len)?;
+            Some(None)
+        }
+        // INT1, BIT
+        0x30 | 0x32 => c.take(1).map(|_| None),
+        // INT2
+        0x34 => c.take(2).map(|_| None),
+        // INT4, REAL, SMALLDATETIME, SMALLMONEY
+        0x38 | 0x3b | 0x3a | 0x7a => c.take(4).map(|_| None),
+        // INT8, FLT8, DATETIME, MONEY
+        0x7f | 0x3e | 0x3d | 0x3c => c.take(8).map(|_| None),
+        // NVARCHAR, BIGVARCHAR, NCHAR, BIGCHAR: max length 2B, collation 5B
+        0xe7 | 0xa7 | 0xef | 0xaf => {
+            let max_len = c.u16()?;
+            c.take(5)?;
+            let mut value = vec![];
+            if max_len == 0xffff {
+                // PLP: | total length 8B | chunk length 4B | chunk | ... | 0 4B |
+                if read_u64_le(c.take(8)?) != u64::MAX {
+                    loop {
+                        let len = c.u32()? as usize;
+                        if len == 0 {
+                            break;
+                        }
+                        let chunk = c.take_partial(len);
+                        value.extend_from_slice(chunk);
+                        if chunk.len() < len {
+                            break;
+                        }
+                    }
+                }
+            } else {
+                let len = c.u16()?;
+                if len != 0xffff {
+                    value.extend_from_slice(c.take_partial(len as usize));
+                }
+            }
+            if typ == 0xe7 || typ == 0xef {
+                Some(Some(ucs2_to_string(&value)))
+            } else {
+                Some(Some(String::from_utf8_lossy(&value).into_owned()))
+            }
+        }
+        _ => None,
+    }
+}
+
+// 返回 Pre-Login 的 VERSION 选项，如 15.0.2000
+// ==========================================
+// return VERSION option of Pre-Login, such as 15.0.2000
+fn parse_pre_login(data: &[u8]) -> Option<String> {
+    let mut options = Cursor(data);
+    let mut version = String::new();
+    loop {
+        let token = options.u8()?;
+        if token == PRE_LOGIN_TERMINATOR {
+            return Some(version);
+        }
+        let option = options.take(4)?;
+        let offset = read_u16_be(option) as usize;
+        let len = read_u16_be(&option[2..]) as usize;
+        let value = data.get(offset..offset + len)?;
+        if token == PRE_LOGIN_VERSION && len >= 4 {
+            version = format!("{}.{}.{}", value[0], value[1], read_u16_be(&value[2..]));
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct TdsInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(skip)]
+    pub packet_type: u8,
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub req_type: &'static str,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub context: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub procedure: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub database: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub user: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub app_name: String,
+    // Login7 为 TDS 版本，Pre-Login 响应为服务端版本
+    // ============================================
+    // TDS version for Login7, and server version for Pre-Login response
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "sql_affected_rows", skip_serializing_if = "value_is_default")]
+    pub affected_rows: u64,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_number: Option<i32>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub error_severity: u8,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub error_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for TdsInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, TdsInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::TDS,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl TdsInfo {
+    pub fn merge(&mut self, other: Self) {
+        self.resp_len = other.resp_len;
+        self.affected_rows = other.affected_rows;
+        self.error_number = other.error_number;
+        self.error_severity = other.error_severity;
+        self.error_message = other.error_message;
+        self.status = other.status;
+        if self.database.is_empty() {
+            self.database = other.database;
+        }
+        if self.version.is_empty() {
+            self.version = other.version;
+        }
+    }
+}
+
+impl From<TdsInfo> for L7ProtocolSendLog {
+    fn from(f: TdsInfo) -> Self {
+        let mut attributes = vec![];
+        if !f.user.is_empty() {
+            attributes.push(KeyVal {
+                key: "user".to_string(),
+                val: f.user,
+            });
+        }
+        if !f.app_name.is_empty() {
+            attributes.push(KeyVal {
+                key: "app_name".to_string(),
+                val: f.app_name,
+            });
+        }
+        if f.error_severity > 0 {
+            attributes.push(KeyVal {
+                key: "error_severity".to_string(),
+                val: f.error_severity.to_string(),
+            });
+        }
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            version: if f.version.is_empty() {
+                None
+            } else {
+                Some(f.version)
+            },
+            row_effect: f.affected_rows as u32,
+            req: L7Request {
+                req_type: String::from(f.req_type),
+                domain: f.database,
+                resource: f.context,
+                endpoint: f.procedure,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_number,
+                exception: f.error_message,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TdsLog {
+    info: TdsInfo,
+    perf_stats: Option<PerfStats>,
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+}
+
+impl Default for TdsLog {
+    fn default() -> Self {
+        Self {
+            info: TdsInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+        }
+    }
+}
+
+perf_impl!(TdsLog);
+
+impl L7ProtocolParserInterface for TdsLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        // 协议识别时只接受请求，window 字段固定为 0
+        // =========================================
+        // only requests are accepted on protocol check, and window is always 0
+        if payload.len() < HEADER_LEN || payload[7] != 0 {
+            return false;
+        }
+        let length = read_u16_be(&payload[2..]) as usize;
+        if length < HEADER_LEN || (payload[1] & STATUS_EOM != 0 && length != payload.len()) {
+            return false;
+        }
+        let mut info = TdsInfo::default();
+        let data = &payload[HEADER_LEN..payload.len().min(length)];
+        match payload[0] {
+            PACKET_PRE_LOGIN => parse_pre_login(data).is_some(),
+            PACKET_LOGIN7 => {
+                // TDSVersion 的最高字节为 0x7X
+                // ===========================
+                // the highest byte of TDSVersion is 0x7X
+                data.len() > LOGIN7_HOST_NAME_OFFSET
+                    && data[7] & 0xf0 == 0x70
+                    && Self::parse_login7(&mut info, data).is_some()
+            }
+            PACKET_SQL_BATCH => {
+                Self::parse_sql_batch(&mut info, data);
+                is_tds(&info.context)
+            }
+            PACKET_RPC => {
+                Self::parse_rpc(&mut info, data);
+                !info.procedure.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        if !self.parse(payload)? {
+            return Ok(vec![]);
+        }
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::TdsInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::TDS
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = TdsInfo::default();
+    }
+}
+
+impl TdsLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /*
+        包头部（长度和 SPID 为大端）:
+        | Type 1B | Status 1B | Length 2B | SPID 2B | PacketID 1B | Window 1B |
+        一个消息可以由多个包组成，最后一个包的 Status 带有 EOM 标记
+        返回 false 表示该包是响应的后续包，不单独输出日志
+        ====================================================================
+        packet header (length and SPID are big endian):
+        | Type 1B | Status 1B | Length 2B | SPID 2B | PacketID 1B | Window 1B |
+        a message may consist of multiple packets, and the Status of the last packet has
+        the EOM flag
+        false means the packet is a subsequent packet of response, which is not logged alone
+    */
+    fn parse(&mut self, payload: &[u8]) -> Result<bool> {
+        if payload.len() < HEADER_LEN {
+            return Err(Error::TdsLogParseFailed);
+        }
+        let packet_type = payload[0];
+        let Some(name) = packet_type_name(packet_type) else {
+            return Err(Error::TdsLogParseFailed);
+        };
+        let length = read_u16_be(&payload[2..]) as usize;
+        if length < HEADER_LEN {
+            return Err(Error::TdsLogParseFailed);
+        }
+        let data = &payload[HEADER_LEN..payload.len().min(length)];
+
+        let info = &mut self.info;
+        info.packet_type = packet_type;
+        if packet_type != PACKET_TABULAR_RESULT {
+            info.msg_type = LogMessageType::Request;
+            info.req_type = name;
+            info.req_len = Some(length as u32);
+            match packet_type {
+                PACKET_SQL_BATCH => Self::parse_sql_batch(info, data),
+                PACKET_RPC => Self::parse_rpc(info, data),
+                PACKET_LOGIN7 => {
+                    let _ = Self::parse_login7(info, data);
+                }
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        // 后续包中的 DONE token 不计入行数
+        // ================================
+        // DONE tokens in subsequent packets are not counted into row count
+        if payload[6] > 1 {
+            return Ok(false);
+        }
+        info.msg_type = LogMessageType::Response;
+        info.resp_len = Some(length as u32);
+        // Pre-Login 的响应也是 Tabular Result，以 VERSION 选项开头
+        // ======================================================
+        // response of Pre-Login is also Tabular Result, starting with VERSION option
+        if data.first() == Some(&PRE_LOGIN_VERSION) {
+            if let Some(version) = parse_pre_login(data) {
+                info.version = version;
+                return Ok(true);
+            }
+        }
+        Self::parse_tokens(info, data);
+        Ok(true)
+    }
+
+    fn parse_sql_batch(info: &mut TdsInfo, data: &[u8]) {
+        info.context = ucs2_to_string(skip_all_headers(data));
+    }
+
+    /*
+        RPC 请求:
+        | ALL_HEADERS | NameLenProcID | ProcName or ProcID | OptionFlags 2B | ParameterData |
+        NameLenProcID 为 0xFFFF 时后跟 2 字节的 ProcID
+        ===================================================================================
+        RPC request:
+        | ALL_HEADERS | NameLenProcID | ProcName or ProcID | OptionFlags 2B | ParameterData |
+        ProcID of 2 bytes follows when NameLenProcID is 0xFFFF
+    */
+    fn parse_rpc(info: &mut TdsInfo, data: &[u8]) {
+        let mut c = Cursor(skip_all_headers(data));
+        let Some(name_len) = c.u16() else {
+            return;
+        };
+        let procedure = if name_len == 0xffff {
+            c.u16().and_then(special_procedure_name).map(String::from)
+        } else {
+            c.take(name_len as usize * 2).map(ucs2_to_string)
+        };
+        let Some(procedure) = procedure else {
+            return;
+        };
+        if procedure.is_empty() || procedure.chars().any(|c| c.is_control()) {
+            return;
+        }
+        info.context = procedure.clone();
+        let index = statement_param_index(&procedure);
+        info.procedure = procedure;
+        let (Some(index), Some(_)) = (index, c.u16()) else {
+            return;
+        };
+        let mut n = 0;
+        while let Some(param) = read_rpc_param(&mut c) {
+            if let Some(value) = param {
+                if n == index {
+                    info.context = value;
+                    return;
+                }
+                n += 1;
+            }
+        }
+    }
+
+    fn parse_login7(info: &mut TdsInfo, data: &[u8]) -> Option<()> {
+        if data.len() < LOGIN7_HOST_NAME_OFFSET + (LOGIN7_DATABASE + 1) * 4 {
+            return None;
+        }
+        info.version = match read_u32_le(&data[4..]) {
+            0x70000000 => "7.0".to_string(),
+            0x71000001 => "7.1".to_string(),
+            0x72090002 => "7.2".to_string(),
+            0x730a0003 => "7.3A".to_string(),
+            0x730b0003 => "7.3B".to_string(),
+            0x74000004 => "7.4".to_string(),
+            v => format!("{:#x}", v),
+        };
+        let field = |index: usize| {
+            let pos = LOGIN7_HOST_NAME_OFFSET + index * 4;
+            let offset = read_u16_le(&data[pos..]) as usize;
+            let len = read_u16_le(&data[pos + 2..]) as usize;
+            data.get(offset..offset + len * 2).map(ucs2_to_string)
+        };
+        info.user = field(LOGIN7_USER_NAME)?;
+        info.app_name = field(LOGIN7_APP_NAME)?;
+        info.database = field(LOGIN7_DATABASE)?;
+        Some(())
+    }
+
+    /*
+        解析 ERROR、ENVCHANGE 和 DONE 等 token，遇到 COLMETADATA 和 ROW 等需要完整类型信息才能
+        跳过的 token 时，只检查结尾的 DONE token
+        ==================================================================================
+        parse tokens such as ERROR, ENVCHANGE and DONE, only the trailing DONE token is checked
+        when tokens such as COLMETADATA and ROW which require full type info to skip are met
+    */
+    fn parse_tokens(info: &mut TdsInfo, data: &[u8]) {
+        let mut c = Cursor(data);
+        while let Some(token) = c.u8() {
+            match token {
+                TOKEN_ERROR | TOKEN_INFO | TOKEN_LOGIN_ACK | TOKEN_ENV_CHANGE | TOKEN_ORDER
+                | TOKEN_SSPI => {
+                    let Some(len) = c.u16() else {
+                        return;
+                    };
+                    let Some(value) = c.take(len as usize) else {
+                        return;
+                    };
+                    match token {
+                        TOKEN_ERROR => Self::on_error(info, value),
+                        TOKEN_ENV_CHANGE if value.first() == Some(&ENV_CHANGE_DATABASE) => {
+                            if let Some(database) = Cursor(&value[1..]).b_varchar() {
+                                info.database = database;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                TOKEN_DONE | TOKEN_DONE_PROC | TOKEN_DONE_IN_PROC => {
+                    let Some(value) = c.take(DONE_TOKEN_LEN - 1) else {
+                        return;
+                    };
+                    Self::on_done(info, value);
+                }
+                TOKEN_RETURN_STATUS => {
+                    if c.take(4).is_none() {
+                        return;
+                    }
+                }
+                _ => {
+                    if data.len() > DONE_TOKEN_LEN {
+                        let tail = &data[data.len() - DONE_TOKEN_LEN..];
+                        if matches!(tail[0], TOKEN_DONE | TOKEN_DONE_PROC | TOKEN_DONE_IN_PROC) {
+                            Self::on_done(info, &tail[1..]);
+                        }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    // | Number 4B | State 1B | Class 1B | MsgText US_VARCHAR | ServerName | ProcName | LineNumber |
+    fn on_error(info: &mut TdsInfo, value: &[u8]) {
+        // 只记录第一个错误
+        // ================
+        // only the first error is recorded
+        if info.error_number.is_some() {
+            return;
+        }
+        let mut c = Cursor(value);
+        let (Some(number), Some(_), Some(class)) = (c.u32(), c.u8(), c.u8()) else {
+            return;
+        };
+        info.error_number = Some(number as i32);
+        info.error_severity = class;
+        info.status = severity_status(class);
+        info.error_message = c.us_varchar().unwrap_or_default();
+    }
+
+    // | Status 2B | CurCmd 2B | DoneRowCount 8B |
+    fn on_done(info: &mut TdsInfo, value: &[u8]) {
+        let status = read_u16_le(value);
+        if status & DONE_STATUS_COUNT != 0 {
+            info.affected_rows += read_u64_le(&value[4..]);
+        }
+        if status & DONE_STATUS_SRVERROR != 0 {
+            info.status = L7ResponseStatus::ServerError;
+        }
+    }
+}
+
+impl L7FlowPerf for TdsLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::TDS,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/tds";
+
+    fn run(name: &str) -> Vec<(bool, TdsInfo)> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut output = vec![];
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let is_tds = TdsLog::new().check_payload(payload, &ParseParam::from(&*packet));
+            let mut parser = TdsLog::new();
+            let _ = parser.parse(payload);
+            output.push((is_tds, parser.info));
+        }
+        output
+    }
+
+    fn merge(output: &[(bool, TdsInfo)], req: usize) -> TdsInfo {
+        let mut info = output[req].1.clone();
+        info.merge_log(L7ProtocolInfo::TdsInfo(output[req + 1].1.clone()))
+            .unwrap();
+        info
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![("tds.pcap", "tds.result")];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output: String = run(item.0)
+                .iter()
+                .map(|(is_tds, info)| format!("{:?} is_tds: {}\r\n", info, is_tds))
+                .collect();
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn send_log() {
+        let output = run("tds.pcap");
+        let login = merge(&output, 2);
+        assert_eq!(login.database, "orders");
+        assert_eq!(login.version, "7.4");
+        assert_eq!(merge(&output, 0).version, "15.0.2000");
+
+        let log = L7ProtocolSendLog::from(merge(&output, 8));
+        assert_eq!(log.req.req_type, "RPC");
+        assert_eq!(log.req.endpoint, "sp_prepexec");
+        assert_eq!(log.row_effect, 3);
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("tds.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = TdsLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 6);
+        assert_eq!(stats.response_count, 6);
+        assert_eq!(stats.err_client_count, 1);
+        assert_eq!(stats.rrt_count, 6);
+    }
+}
@@ -0,0 +1,1217 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use lru::LruCache;
+use md5::{Digest, Md5};
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common, perf_impl,
+    utils::bytes::read_u16_be,
+};
+
+// https://www.rfc-editor.org/rfc/rfc5246
+// https://www.rfc-editor.org/rfc/rfc8446
+const RECORD_HEADER_LEN: usize = 5;
+// 2^14 + 2048, the max length of TLSCiphertext
+const MAX_RECORD_LEN: usize = 18432;
+// 只解析证书链中的第一个证书，跨段缓存的证书消息不超过一个记录的长度
+// ===========================================================
+// only the first certificate of the chain is parsed, certificate messages
+// buffered across segments are limited to the length of one record
+const MAX_CERT_BUFFER_LEN: usize = MAX_RECORD_LEN;
+
+const CONTENT_TYPE_CHANGE_CIPHER_SPEC: u8 = 20;
+const CONTENT_TYPE_ALERT: u8 = 21;
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+const CONTENT_TYPE_APPLICATION_DATA: u8 = 23;
+
+pub(super) const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+pub(super) const HANDSHAKE_SERVER_HELLO: u8 = 2;
+const HANDSHAKE_CERTIFICATE: u8 = 11;
+
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_ALPN: u16 = 16;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+
+const SERVER_NAME_TYPE_HOST_NAME: u8 = 0;
+
+const ALERT_LEVEL_FATAL: u8 = 2;
+const ALERT_INTERNAL_ERROR: u8 = 80;
+
+const ASN1_INTEGER: u8 = 0x02;
+const ASN1_OID: u8 = 0x06;
+const ASN1_UTC_TIME: u8 = 0x17;
+const ASN1_GENERALIZED_TIME: u8 = 0x18;
+const ASN1_SEQUENCE: u8 = 0x30;
+const ASN1_SET: u8 = 0x31;
+const ASN1_CONTEXT_0: u8 = 0xa0;
+
+pub(super) fn version_name(version: u16) -> &'static str {
+    match version {
+        0x0300 => "SSL 3.0",
+        0x0301 => "TLS 1.0",
+        0x0302 => "TLS 1.1",
+        0x0303 => "TLS 1.2",
+        0x0304 => "TLS 1.3",
+        _ => "",
+    }
+}
+
+// GREASE 值（RFC 8701）形如 0x?a?a，计算 JA3 时需要去掉
+// =====================================================
+// GREASE values (RFC 8701) look like 0x?a?a and are removed when computing JA3
+fn is_grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+// 常见的加密套件显示名称，其余显示为十六进制
+// ==========================================
+// common cipher suites are shown by name, others in hex
+pub(super) fn cipher_suite_name(cipher: u16) -> String {
+    match cipher_suite_iana_name(cipher) {
+        Some(name) => String::from(name),
+        None => format!("0x{:04x}", cipher),
+    }
+}
+
+fn cipher_suite_iana_name(cipher: u16) -> Option<&'static str> {
+    let name = match cipher {
+        0x0005 => "TLS_RSA_WITH_RC4_128_SHA",
+        0x000a => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x003c => "TLS_RSA_WITH_AES_128_CBC_SHA256",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xc009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc00a => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0xc023 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256",
+        0xc027 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        _ => return None,
+    };
+    Some(name)
+}
+
+pub(super) fn alert_description_name(description: u8) -> &'static str {
+    match description {
+        0 => "close_notify",
+        10 => "unexpected_message",
+        20 => "bad_record_mac",
+        21 => "decryption_failed",
+        22 => "record_overflow",
+        30 => "decompression_failure",
+        40 => "handshake_failure",
+        41 => "no_certificate",
+        42 => "bad_certificate",
+        43 => "unsupported_certificate",
+        44 => "certificate_revoked",
+        45 => "certificate_expired",
+        46 => "certificate_unknown",
+        47 => "illegal_parameter",
+        48 => "unknown_ca",
+        49 => "access_denied",
+        50 => "decode_error",
+        51 => "decrypt_error",
+        70 => "protocol_version",
+        71 => "insufficient_security",
+        80 => "internal_error",
+        86 => "inappropriate_fallback",
+        90 => "user_canceled",
+        100 => "no_renegotiation",
+        109 => "missing_extension",
+        110 => "unsupported_extension",
+        112 => "unrecognized_name",
+        113 => "bad_certificate_status_response",
+        115 => "unknown_psk_identity",
+        116 => "certificate_required",
+        120 => "no_application_protocol",
+        _ => "unknown",
+    }
+}
+
+fn md5_hex(s: &str) -> String {
+    Md5::digest(s.as_bytes())
+        .into_iter()
+        .fold(String::new(), |s, c| s + &format!("{:02x}", c))
+}
+
+fn join_u16(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub(super) struct Reader<'a>(pub(super) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(super) fn u8(&mut self) -> Option<u8> {
+        let (v, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(*v)
+    }
+
+    pub(super) fn u16(&mut self) -> Option<u16> {
+        let v = read_u16_be(self.0.get(..2)?);
+        self.0 = &self.0[2..];
+        Some(v)
+    }
+
+    pub(super) fn u24(&mut self) -> Option<usize> {
+        let b = self.take(3)?;
+        Some((b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    pub(super) fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (v, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(v)
+    }
+
+    // 报文可能被截断，返回剩余可用的部分
+    // ================================
+    // the packet may be truncated, return what is available
+    pub(super) fn take_partial(&mut self, n: usize) -> &'a [u8] {
+        let (v, rest) = self.0.split_at(n.min(self.0.len()));
+        self.0 = rest;
+        v
+    }
+
+    pub(super) fn vec8(&mut self) -> Option<&'a [u8]> {
+        let n = self.u8()? as usize;
+        self.take(n)
+    }
+
+    pub(super) fn vec16(&mut self) -> Option<&'a [u8]> {
+        let n = self.u16()? as usize;
+        self.take(n)
+    }
+
+    fn u16_list(data: &[u8]) -> Vec<u16> {
+        data.chunks_exact(2).map(read_u16_be).collect()
+    }
+}
+
+struct Record<'a> {
+    content_type: u8,
+    fragment: &'a [u8],
+}
+
+/*
+    记录头部:
+    | content type 1B | legacy version 2B | length 2B | fragment |
+    最后一个记录可能被截断
+    ===========================================================
+    record header:
+    | content type 1B | legacy version 2B | length 2B | fragment |
+    the last record may be truncated
+*/
+fn read_records(payload: &[u8]) -> Option<Vec<Record>> {
+    let mut records = vec![];
+    let mut r = Reader(payload);
+    while r.0.len() >= RECORD_HEADER_LEN {
+        let content_type = r.u8()?;
+        let version = r.u16()?;
+        let length = r.u16()? as usize;
+        if !(CONTENT_TYPE_CHANGE_CIPHER_SPEC..=CONTENT_TYPE_APPLICATION_DATA)
+            .contains(&content_type)
+            || version >> 8 != 3
+            || length == 0
+            || length > MAX_RECORD_LEN
+        {
+            return None;
+        }
+        records.push(Record {
+            content_type,
+            fragment: r.take_partial(length),
+        });
+    }
+    if records.is_empty() {
+        return None;
+    }
+    Some(records)
+}
+
+#[derive(Default)]
+pub(super) struct ClientHello {
+    version: u16,
+    pub(super) server_name: String,
+    pub(super) alpn: Vec<String>,
+    supported_versions: Vec<u16>,
+    pub(super) ja3: String,
+}
+
+impl ClientHello {
+    // TLS 1.3 的客户端在 supported_versions 扩展中提供版本列表
+    // ======================================================
+    // TLS 1.3 clients offer the version list in the supported_versions extension
+    pub(super) fn max_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .copied()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(self.version)
+    }
+}
+
+pub(super) fn parse_client_hello(body: &[u8]) -> Option<ClientHello> {
+    let mut r = Reader(body);
+    let mut hello = ClientHello {
+        version: r.u16()?,
+        ..Default::default()
+    };
+    r.take(32)?;
+    r.vec8()?;
+    let mut ciphers = Reader::u16_list(r.vec16()?);
+    r.vec8()?;
+
+    let mut ext_types = vec![];
+    let mut groups: Vec<u16> = vec![];
+    let mut point_formats: Vec<u16> = vec![];
+    if let Some(extensions) = r.vec16() {
+        let mut exts = Reader(extensions);
+        while let (Some(ext_type), Some(data)) = (exts.u16(), exts.vec16()) {
+            if !is_grease(ext_type) {
+                ext_types.push(ext_type);
+            }
+            match ext_type {
+                EXT_SERVER_NAME => {
+                    let mut names = Reader(Reader(data).vec16()?);
+                    while let (Some(name_type), Some(name)) = (names.u8(), names.vec16()) {
+                        if name_type == SERVER_NAME_TYPE_HOST_NAME {
+                            hello.server_name = String::from_utf8_lossy(name).into_owned();
+                            break;
+                        }
+                    }
+                }
+                EXT_SUPPORTED_GROUPS => {
+                    groups = Reader::u16_list(Reader(data).vec16()?);
+                }
+                EXT_EC_POINT_FORMATS => {
+                    point_formats = Reader(data).vec8()?.iter().map(|f| *f as u16).collect();
+                }
+                EXT_ALPN => hello.alpn = parse_alpn(data)?,
+                EXT_SUPPORTED_VERSIONS => {
+                    let mut versions = Reader(data);
+                    hello.supported_versions = Reader::u16_list(versions.vec8()?);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    ciphers.retain(|c| !is_grease(*c));
+    groups.retain(|g| !is_grease(*g));
+    hello.ja3 = md5_hex(&format!(
+        "{},{},{},{},{}",
+        hello.version,
+        join_u16(&ciphers),
+        join_u16(&ext_types),
+        join_u16(&groups),
+        join_u16(&point_formats)
+    ));
+    Some(hello)
+}
+
+#[derive(Default)]
+pub(super) struct ServerHello {
+    pub(super) version: u16,
+    pub(super) cipher_suite: u16,
+    alpn: String,
+    ja3s: String,
+}
+
+pub(super) fn parse_server_hello(body: &[u8]) -> Option<ServerHello> {
+    let mut r = Reader(body);
+    let legacy_version = r.u16()?;
+    let mut hello = ServerHello {
+        version: legacy_version,
+        ..Default::default()
+    };
+    r.take(32)?;
+    r.vec8()?;
+    hello.cipher_suite = r.u16()?;
+    r.u8()?;
+
+    let mut ext_types = vec![];
+    if let Some(extensions) = r.vec16() {
+        let mut exts = Reader(extensions);
+        while let (Some(ext_type), Some(data)) = (exts.u16(), exts.vec16()) {
+            ext_types.push(ext_type);
+            match ext_type {
+                EXT_ALPN => {
+                    hello.alpn = parse_alpn(data)?.into_iter().next().unwrap_or_default();
+                }
+                // TLS 1.3 的协商版本在 supported_versions 扩展中
+                // ============================================
+                // the negotiated version of TLS 1.3 is in the supported_versions extension
+                EXT_SUPPORTED_VERSIONS => hello.version = Reader(data).u16()?,
+                _ => {}
+            }
+        }
+    }
+
+    hello.ja3s = md5_hex(&format!(
+        "{},{},{}",
+        legacy_version,
+        hello.cipher_suite,
+        join_u16(&ext_types)
+    ));
+    Some(hello)
+}
+
+fn parse_alpn(data: &[u8]) -> Option<Vec<String>> {
+    let mut protocols = Reader(Reader(data).vec16()?);
+    let mut alpn = vec![];
+    while let Some(p) = protocols.vec8() {
+        alpn.push(String::from_utf8_lossy(p).into_owned());
+    }
+    Some(alpn)
+}
+
+#[derive(Default)]
+struct Certificate {
+    subject: String,
+    issuer: String,
+    not_after: String,
+}
+
+// DER 编码的 TLV，证书可能被截断，长度超出时返回剩余可用的部分
+// ==========================================================
+// DER encoded TLV, the certificate may be truncated, return what is available
+// if the length exceeds the data
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn next(&mut self) -> Option<(u8, &'a [u8])> {
+        let mut r = Reader(self.0);
+        let tag = r.u8()?;
+        let first = r.u8()?;
+        let length = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || n > 3 {
+                return None;
+            }
+            r.take(n)?.iter().fold(0usize, |l, b| l << 8 | *b as usize)
+        };
+        let value = r.take_partial(length);
+        self.0 = r.0;
+        Some((tag, value))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.next()? {
+            (t, value) if t == tag => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn attribute_name(oid: &[u8]) -> Option<&'static str> {
+    // id-at, 2.5.4.x
+    let name = match oid {
+        [0x55, 0x04, 0x03] => "CN",
+        [0x55, 0x04, 0x06] => "C",
+        [0x55, 0x04, 0x07] => "L",
+        [0x55, 0x04, 0x08] => "ST",
+        [0x55, 0x04, 0x0a] => "O",
+        [0x55, 0x04, 0x0b] => "OU",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn format_name(name: &[u8]) -> String {
+    let mut parts = vec![];
+    let mut rdns = Der(name);
+    while let Some(rdn) = rdns.expect(ASN1_SET) {
+        let mut attributes = Der(rdn);
+        while let Some(attribute) = attributes.expect(ASN1_SEQUENCE) {
+            let mut attribute = Der(attribute);
+            let (Some(oid), Some((_, value))) = (attribute.expect(ASN1_OID), attribute.next())
+            else {
+                continue;
+            };
+            if let Some(key) = attribute_name(oid) {
+                parts.push(format!("{}={}", key, String::from_utf8_lossy(value)));
+            }
+        }
+    }
+    parts.join(", ")
+}
+
+// UTCTime: YYMMDDHHMMSSZ, GeneralizedTime: YYYYMMDDHHMMSSZ
+fn format_time(tag: u8, value: &[u8]) -> Option<String> {
+    let s = std::str::from_utf8(value).ok()?;
+    let (year, rest) = match tag {
+        ASN1_UTC_TIME => {
+            let yy: u16 = s.get(..2)?.parse().ok()?;
+            (if yy >= 50 { 1900 + yy } else { 2000 + yy }, s.get(2..)?)
+        }
+        ASN1_GENERALIZED_TIME => (s.get(..4)?.parse().ok()?, s.get(4..)?),
+        _ => return None,
+    };
+    let field = |i: usize| {
+        rest.get(i..i + 2)
+            .filter(|f| f.bytes().all(|b| b.is_ascii_digit()))
+    };
+    Some(format!(
+        "{:04}-{}-{}T{}:{}:{}Z",
+        year,
+        field(0)?,
+        field(2)?,
+        field(4)?,
+        field(6)?,
+        field(8)?
+    ))
+}
+
+/*
+    Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signatureValue }
+    TBSCertificate ::= SEQUENCE {
+        version [0] EXPLICIT OPTIONAL, serialNumber, signature, issuer, validity, subject, ... }
+    Validity ::= SEQUENCE { notBefore, notAfter }
+*/
+fn parse_certificate(der: &[u8]) -> Option<Certificate> {
+    let certificate = Der(der).expect(ASN1_SEQUENCE)?;
+    let mut tbs = Der(Der(certificate).expect(ASN1_SEQUENCE)?);
+    let (mut tag, _) = tbs.next()?;
+    if tag == ASN1_CONTEXT_0 {
+        (tag, _) = tbs.next()?;
+    }
+    if tag != ASN1_INTEGER {
+        return None;
+    }
+    tbs.expect(ASN1_SEQUENCE)?;
+    let mut cert = Certificate {
+        issuer: format_name(tbs.expect(ASN1_SEQUENCE)?),
+        ..Default::default()
+    };
+    if let Some(validity) = tbs.expect(ASN1_SEQUENCE) {
+        let mut validity = Der(validity);
+        validity.next();
+        if let Some((tag, value)) = validity.next() {
+            cert.not_after = format_time(tag, value).unwrap_or_default();
+        }
+        if let Some(subject) = tbs.expect(ASN1_SEQUENCE) {
+            cert.subject = format_name(subject);
+        }
+    }
+    Some(cert)
+}
+
+fn parse_certificate_message(body: &[u8]) -> Option<Certificate> {
+    let mut r = Reader(body);
+    r.u24()?;
+    let length = r.u24()?;
+    parse_certificate(r.take_partial(length))
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct TlsInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type")]
+    pub handshake_type: &'static str,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub server_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: &'static str,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub alpn: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cipher_suite: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub ja3s: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cert_subject: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cert_issuer: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub cert_not_after: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "value_is_negative")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub alert_description: Option<u8>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub alert: &'static str,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for TlsInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, TlsInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::TLS,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl TlsInfo {
+    pub fn merge(&mut self, other: Self) {
+        if !other.version.is_empty() {
+            self.version = other.version;
+        }
+        if !other.alpn.is_empty() {
+            self.alpn = other.alpn;
+        }
+        self.cipher_suite = other.cipher_suite;
+        self.ja3s = other.ja3s;
+        self.cert_subject = other.cert_subject;
+        self.cert_issuer = other.cert_issuer;
+        self.cert_not_after = other.cert_not_after;
+        self.resp_len = other.resp_len;
+        self.alert_description = other.alert_description;
+        self.alert = other.alert;
+        self.status = other.status;
+    }
+}
+
+impl From<TlsInfo> for L7ProtocolSendLog {
+    fn from(f: TlsInfo) -> Self {
+        let attributes = [
+            ("alpn", f.alpn),
+            ("cipher_suite", f.cipher_suite),
+            ("ja3", f.ja3),
+            ("ja3s", f.ja3s),
+            ("cert_subject", f.cert_subject),
+            ("cert_issuer", f.cert_issuer),
+            ("cert_not_after", f.cert_not_after),
+        ]
+        .into_iter()
+        .filter(|(_, val)| !val.is_empty())
+        .map(|(key, val)| KeyVal {
+            key: key.to_string(),
+            val,
+        })
+        .collect::<Vec<_>>();
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            version: if f.version.is_empty() {
+                None
+            } else {
+                Some(f.version.to_string())
+            },
+            req: L7Request {
+                req_type: String::from(f.handshake_type),
+                domain: f.server_name.clone(),
+                resource: f.server_name,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.alert_description.map(|d| d as i32),
+                exception: String::from(f.alert),
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TlsLog {
+    info: TlsInfo,
+    perf_stats: Option<PerfStats>,
+    #[serde(skip)]
+    previous_log_info: LruCache<u32, (LogMessageType, u64)>,
+    // ServerHello 之后的告警不再是握手的响应，作为会话日志上报
+    // ======================================================
+    // alerts after ServerHello are no longer responses of the handshake,
+    // they are reported as session logs
+    #[serde(skip)]
+    server_hello_seen: bool,
+    // ChangeCipherSpec 之后的握手消息是加密的，<client,server>
+    // ======================================================
+    // handshake messages after ChangeCipherSpec are encrypted, <client,server>
+    #[serde(skip)]
+    cipher_changed: (bool, bool),
+    #[serde(skip)]
+    client_hello_seen: bool,
+    // 跨越多个 TCP 段的证书消息，<方向，已收到的部分>
+    // ============================================
+    // certificate message spanning multiple TCP segments, <direction, received part>
+    #[serde(skip)]
+    cert_buffer: Option<(PacketDirection, Vec<u8>)>,
+}
+
+impl Default for TlsLog {
+    fn default() -> Self {
+        Self {
+            info: TlsInfo::default(),
+            perf_stats: None,
+            previous_log_info: LruCache::new(100),
+            server_hello_seen: false,
+            cipher_changed: (false, false),
+            client_hello_seen: false,
+            cert_buffer: None,
+        }
+    }
+}
+
+perf_impl!(TlsLog);
+
+impl L7ProtocolParserInterface for TlsLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if !param.ebpf_type.is_raw_protocol() || param.l4_protocol != IpProtocol::Tcp {
+            return false;
+        }
+        // 协议识别时只接受 ClientHello
+        // ==========================
+        // only ClientHello is accepted on protocol check
+        let Some(records) = read_records(payload) else {
+            return false;
+        };
+        let record = &records[0];
+        if record.content_type != CONTENT_TYPE_HANDSHAKE {
+            return false;
+        }
+        let mut r = Reader(record.fragment);
+        if r.u8() != Some(HANDSHAKE_CLIENT_HELLO) {
+            return false;
+        }
+        r.u24().is_some() && parse_client_hello(r.0).is_some()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        if !self.parse(payload, param.direction)? {
+            return Ok(vec![]);
+        }
+        match self.info.msg_type {
+            LogMessageType::Request => self.perf_inc_req(param.time),
+            LogMessageType::Response => self.perf_inc_resp(param.time),
+            _ => {}
+        }
+        match self.info.status {
+            L7ResponseStatus::ClientError => self.perf_inc_req_err(),
+            L7ResponseStatus::ServerError => self.perf_inc_resp_err(),
+            _ => {}
+        }
+        self.revert_info_time(param.direction, param.time);
+        Ok(vec![L7ProtocolInfo::TlsInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::TLS
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        self.save_info_time();
+        self.info = TlsInfo::default();
+    }
+}
+
+impl TlsLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn fill_certificate(&mut self, cert: Certificate, standalone: bool) {
+        self.info.cert_subject = cert.subject;
+        self.info.cert_issuer = cert.issuer;
+        self.info.cert_not_after = cert.not_after;
+        if standalone {
+            self.info.msg_type = LogMessageType::Session;
+            self.info.handshake_type = "Certificate";
+        }
+    }
+
+    // 将后续段追加到未完成的证书消息，足够解析出第一个证书时返回
+    // ======================================================
+    // append the following segment to the incomplete certificate message,
+    // return the first certificate once enough data is received
+    fn continue_certificate(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Option<Certificate> {
+        let (d, buffer) = self.cert_buffer.as_mut()?;
+        if *d != direction {
+            return None;
+        }
+        let len = payload.len().min(MAX_CERT_BUFFER_LEN - buffer.len());
+        buffer.extend_from_slice(&payload[..len]);
+        let cert = parse_certificate_message(buffer);
+        if cert.is_some() || buffer.len() >= MAX_CERT_BUFFER_LEN {
+            self.cert_buffer = None;
+        }
+        cert
+    }
+
+    // 返回 false 表示报文中没有需要上报的握手消息，例如加密的应用数据
+    // ============================================================
+    // return false if there is no handshake message to report in the packet,
+    // e.g. encrypted application data
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<bool> {
+        let Some(records) = read_records(payload) else {
+            // 跨越多个 TCP 段的记录（例如证书链），后续的段不以记录头部开始
+            // ==========================================================
+            // records spanning multiple TCP segments (e.g. certificate chains),
+            // the following segments do not start with a record header
+            if let Some(cert) = self.continue_certificate(payload, direction) {
+                self.fill_certificate(cert, true);
+                return Ok(true);
+            }
+            if self.client_hello_seen {
+                return Ok(false);
+            }
+            return Err(Error::TlsLogParseFailed);
+        };
+        // 同方向出现新的记录，之前的证书消息不会再有后续
+        // ============================================
+        // a new record in the same direction, the previous certificate message has no more data
+        if matches!(self.cert_buffer, Some((d, _)) if d == direction) {
+            self.cert_buffer = None;
+        }
+        let cipher_changed = match direction {
+            PacketDirection::ClientToServer => &mut self.cipher_changed.0,
+            PacketDirection::ServerToClient => &mut self.cipher_changed.1,
+        };
+        let mut alert = None;
+        let mut reported = false;
+        for record in records {
+            match record.content_type {
+                CONTENT_TYPE_CHANGE_CIPHER_SPEC => *cipher_changed = true,
+                CONTENT_TYPE_HANDSHAKE if !*cipher_changed => {
+                    let mut r = Reader(record.fragment);
+                    while let (Some(msg_type), Some(length)) = (r.u8(), r.u24()) {
+                        let body = r.take_partial(length);
+                        match msg_type {
+                            HANDSHAKE_CLIENT_HELLO => {
+                                let hello =
+                                    parse_client_hello(body).ok_or(Error::TlsLogParseFailed)?;
+                                self.info.msg_type = LogMessageType::Request;
+                                self.info.handshake_type = "ClientHello";
+                                self.info.version = version_name(hello.max_version());
+                                self.info.server_name = hello.server_name;
+                                self.info.alpn = hello.alpn.join(",");
+                                self.info.ja3 = hello.ja3;
+                                self.info.req_len = Some(payload.len() as u32);
+                                self.client_hello_seen = true;
+                                self.server_hello_seen = false;
+                                self.cert_buffer = None;
+                                reported = true;
+                            }
+                            HANDSHAKE_SERVER_HELLO => {
+                                let hello =
+                                    parse_server_hello(body).ok_or(Error::TlsLogParseFailed)?;
+                                self.info.msg_type = LogMessageType::Response;
+                                self.info.handshake_type = "ServerHello";
+                                self.info.version = version_name(hello.version);
+                                self.info.cipher_suite = cipher_suite_name(hello.cipher_suite);
+                                self.info.alpn = hello.alpn;
+                                self.info.ja3s = hello.ja3s;
+                                self.info.resp_len = Some(payload.len() as u32);
+                                self.server_hello_seen = true;
+                                reported = true;
+                            }
+                            // 与 ServerHello 不在同一个段中的证书单独上报
+                            // ==========================================
+                            // certificates not in the same segment as ServerHello
+                            // are reported on their own
+                            HANDSHAKE_CERTIFICATE => match parse_certificate_message(body) {
+                                Some(cert) => {
+                                    self.fill_certificate(cert, !reported);
+                                    reported = true;
+                                }
+                                None if body.len() < length => {
+                                    let len = body.len().min(MAX_CERT_BUFFER_LEN);
+                                    self.cert_buffer = Some((direction, body[..len].to_vec()));
+                                }
+                                None => {}
+                            },
+                            _ => {}
+                        }
+                        if body.len() < length {
+                            break;
+                        }
+                    }
+                }
+                // 加密的告警长度大于 2 字节，无法解析
+                // ================================
+                // encrypted alerts are longer than 2 bytes and can not be parsed
+                CONTENT_TYPE_ALERT if record.fragment.len() == 2 => {
+                    alert = Some((record.fragment[0], record.fragment[1]));
+                }
+                _ => {}
+            }
+        }
+
+        match alert {
+            Some((ALERT_LEVEL_FATAL, description)) => {
+                if !reported {
+                    self.info.handshake_type = "Alert";
+                    self.info.msg_type = if direction == PacketDirection::ServerToClient
+                        && !self.server_hello_seen
+                    {
+                        self.info.resp_len = Some(payload.len() as u32);
+                        LogMessageType::Response
+                    } else {
+                        LogMessageType::Session
+                    };
+                }
+                self.info.alert_description = Some(description);
+                self.info.alert = alert_description_name(description);
+                // 服务端发送的致命告警通常是因为客户端的请求无法被接受，例如不支持的版本或证书问题，
+                // 计为客户端错误；客户端发送的致命告警通常是因为服务端的证书或参数有问题，计为服务端错误
+                // ===================================================================================
+                // fatal alerts sent by the server usually mean the ClientHello is unacceptable,
+                // e.g. unsupported version or certificate, they are counted as client errors;
+                // fatal alerts sent by the client usually mean the certificate or parameters of
+                // the server are bad, they are counted as server errors
+                self.info.status = if direction == PacketDirection::ClientToServer
+                    || description == ALERT_INTERNAL_ERROR
+                {
+                    L7ResponseStatus::ServerError
+                } else {
+                    L7ResponseStatus::ClientError
+                };
+                Ok(true)
+            }
+            _ => Ok(reported),
+        }
+    }
+}
+
+impl L7FlowPerf for TlsLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::TLS,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/tls";
+
+    // 同一个流使用同一个解析器，以便区分 ServerHello 前后的告警，没有日志的报文输出 None
+    // =============================================================================
+    // use one parser for the flow to tell alerts before and after ServerHello,
+    // packets without logs output None
+    fn run(name: &str) -> String {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        if packets.is_empty() {
+            return "".to_string();
+        }
+
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = TlsLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from(&*packet);
+            let is_tls = TlsLog::new().check_payload(payload, &param);
+            let reported = parser
+                .parse(payload, packet.lookup_key.direction)
+                .unwrap_or_default();
+            let info = std::mem::take(&mut parser.info);
+            output.push_str(&format!(
+                "{:?} is_tls: {}\r\n",
+                reported.then_some(info),
+                is_tls
+            ));
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("tls12.pcap", "tls12.result"),
+            ("tls13.pcap", "tls13.result"),
+            ("tls_alert.pcap", "tls_alert.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let output = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn certificate_in_separate_segments() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("tls12.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        packets[0].lookup_key.direction = PacketDirection::ClientToServer;
+        packets[1].lookup_key.direction = PacketDirection::ServerToClient;
+        let client_param = ParseParam::from(&packets[0]);
+        let server_param = ParseParam::from(&packets[1]);
+
+        // 将 ServerHello 和 Certificate 拆为两个记录，证书记录再拆为两个段
+        // ==========================================================
+        // split ServerHello and Certificate into two records, and the certificate
+        // record into two segments
+        let fragment = &packets[1].get_l4_payload().unwrap()[RECORD_HEADER_LEN..];
+        let hello_len = 4 + Reader(&fragment[1..]).u24().unwrap();
+        let record = |body: &[u8]| {
+            let mut r = vec![CONTENT_TYPE_HANDSHAKE, 3, 3];
+            r.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            r.extend_from_slice(body);
+            r
+        };
+        let server_hello = record(&fragment[..hello_len]);
+        let certificate = record(&fragment[hello_len..]);
+        let segments = [&certificate[..], &certificate[..40], &certificate[40..]];
+
+        for (whole, cert_segments) in [(true, &segments[..1]), (false, &segments[1..])] {
+            let mut parser = TlsLog::new();
+            let client_payload = packets[0].get_l4_payload().unwrap();
+            assert_eq!(
+                parser
+                    .parse_payload(client_payload, &client_param)
+                    .unwrap()
+                    .len(),
+                1
+            );
+            parser.reset();
+            let info = match parser
+                .parse_payload(&server_hello, &server_param)
+                .unwrap()
+                .pop()
+            {
+                Some(L7ProtocolInfo::TlsInfo(i)) => i,
+                _ => unreachable!(),
+            };
+            assert_eq!(info.handshake_type, "ServerHello");
+            assert!(info.cert_subject.is_empty());
+            parser.reset();
+
+            let mut infos = vec![];
+            for payload in cert_segments {
+                infos.extend(parser.parse_payload(payload, &server_param).unwrap());
+                parser.reset();
+            }
+            assert_eq!(infos.len(), 1, "whole certificate record {}", whole);
+            let L7ProtocolInfo::TlsInfo(cert) = infos.pop().unwrap() else {
+                unreachable!();
+            };
+            assert_eq!(cert.msg_type, LogMessageType::Session);
+            assert_eq!(cert.handshake_type, "Certificate");
+            assert_eq!(cert.cert_subject, "C=US, O=Example, CN=www.example.com");
+            assert_eq!(cert.cert_issuer, "C=US, O=Example CA, CN=Example CA R1");
+            assert_eq!(cert.cert_not_after, "2025-06-30T23:59:59Z");
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("tls13.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        packets[0].lookup_key.direction = PacketDirection::ClientToServer;
+        packets[1].lookup_key.direction = PacketDirection::ServerToClient;
+
+        let mut parser = TlsLog::new();
+        let mut infos = vec![];
+        for packet in packets[..2].iter() {
+            let payload = packet.get_l4_payload().unwrap();
+            let info = match parser
+                .parse_payload(payload, &ParseParam::from(packet))
+                .unwrap()
+                .pop()
+            {
+                Some(L7ProtocolInfo::TlsInfo(i)) => i,
+                _ => unreachable!(),
+            };
+            parser.reset();
+            infos.push(info);
+        }
+        let server_hello = infos.pop().unwrap();
+        let mut client_hello = infos.pop().unwrap();
+
+        client_hello.merge(server_hello);
+        assert_eq!(client_hello.version, "TLS 1.3");
+        assert_eq!(client_hello.alpn, "h2");
+        let log = L7ProtocolSendLog::from(client_hello);
+        assert_eq!(log.version, Some("TLS 1.3".to_string()));
+        assert_eq!(log.req.req_type, "ClientHello");
+        assert_eq!(log.req.domain, "api.example.com");
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(
+            attributes
+                .iter()
+                .map(|kv| kv.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["alpn", "cipher_suite", "ja3", "ja3s"]
+        );
+    }
+
+    #[test]
+    fn perf() {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join("tls_alert.pcap"), None);
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut parser = TlsLog::new();
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            if L7FlowPerf::parse(&mut parser, None, packet, 0).is_ok() {
+                parser.reset();
+            }
+        }
+        let stats = parser.copy_and_reset_data(0).l7;
+        assert_eq!(stats.request_count, 1);
+        assert_eq!(stats.response_count, 1);
+        assert_eq!(stats.err_client_count, 1);
+        assert_eq!(stats.err_server_count, 0);
+        assert_eq!(stats.rrt_count, 1);
+        assert_eq!(stats.rrt_max, 1000);
+    }
+}
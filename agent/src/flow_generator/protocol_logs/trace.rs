@@ -0,0 +1,372 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::{decode_base64_to_string, decode_new_rpc_trace_context};
+
+use crate::config::handler::{L7LogDynamicConfig, TraceType};
+
+// B3 多头部格式中的父 span，跟随 X-B3-SpanId 使用
+const B3_PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+
+// 一个链路追踪头部中携带的 ID
+#[derive(Debug, Default, PartialEq)]
+pub struct TraceIds {
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub parent_span_id: Option<String>,
+}
+
+// 链路追踪头部的解码器，每种头部格式对应一个实现
+// ================================================================================
+// Decoder of a distributed tracing header, one implementation for each header format
+pub trait TraceDecoder: Sync {
+    fn decode(&self, value: &str) -> TraceIds;
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+// X-B3-TraceId, X-B3-SpanId, x-datadog-trace-id, x-datadog-parent-id 和自定义头部的值即为 ID
+struct RawDecoder;
+
+impl TraceDecoder for RawDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        TraceIds {
+            trace_id: non_empty(value),
+            span_id: non_empty(value),
+            parent_span_id: None,
+        }
+    }
+}
+
+// uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS
+struct UberDecoder;
+
+impl TraceDecoder for UberDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let mut segs = value.split(':');
+        let trace_id = segs.next().and_then(non_empty);
+        let span_id = segs.next().and_then(non_empty);
+        // 根 span 的 PARENTSPANID 为 0
+        let parent_span_id = segs.next().filter(|s| *s != "0").and_then(non_empty);
+        TraceIds {
+            trace_id,
+            span_id,
+            parent_span_id,
+        }
+    }
+}
+
+// sw6: 1-TRACEID-SEGMENTID-3-5-2-IPPORT-ENTRYURI-PARENTURI
+// sw8: 1-TRACEID-SEGMENTID-3-PARENT_SERVICE-PARENT_INSTANCE-PARENT_ENDPOINT-IPPORT
+// sw6和sw8的value全部使用'-'分隔，TRACEID前为SAMPLE字段取值范围仅有0或1
+// 提取`TRACEID`展示为HTTP日志中的`TraceID`字段
+// 提取`SEGMENTID-SPANID`展示为HTTP日志中的`SpanID`字段
+struct SkyWalkingDecoder;
+
+impl TraceDecoder for SkyWalkingDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let segs: Vec<&str> = value.split('-').collect();
+        TraceIds {
+            trace_id: (segs.len() > 2).then(|| decode_base64_to_string(segs[1])),
+            span_id: (segs.len() > 4)
+                .then(|| format!("{}-{}", decode_base64_to_string(segs[2]), segs[3])),
+            parent_span_id: None,
+        }
+    }
+}
+
+// OTel HTTP Trace format:
+// traceparent: 00-TRACEID-SPANID-01
+struct TraceParentDecoder;
+
+impl TraceDecoder for TraceParentDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let mut segs = value.split('-').skip(1);
+        TraceIds {
+            trace_id: segs.next().and_then(non_empty),
+            span_id: segs.next().and_then(non_empty),
+            parent_span_id: None,
+        }
+    }
+}
+
+// b3: TRACEID-SPANID-SAMPLED-PARENTSPANID，只有采样标志时没有 ID
+// 参考：https://github.com/openzipkin/b3-propagation#single-header
+struct B3Decoder;
+
+impl TraceDecoder for B3Decoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let segs: Vec<&str> = value.split('-').collect();
+        if segs.len() < 2 {
+            return TraceIds::default();
+        }
+        TraceIds {
+            trace_id: non_empty(segs[0]),
+            span_id: non_empty(segs[1]),
+            parent_span_id: segs.get(3).and_then(|s| non_empty(s)),
+        }
+    }
+}
+
+// X-Amzn-Trace-Id: Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1
+// 参考：https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader
+struct AwsXRayDecoder;
+
+impl TraceDecoder for AwsXRayDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let mut ids = TraceIds::default();
+        for field in value.split(';') {
+            match field.trim().split_once('=') {
+                Some(("Root", id)) => ids.trace_id = non_empty(id),
+                Some(("Parent", id)) => ids.span_id = non_empty(id),
+                _ => {}
+            }
+        }
+        ids
+    }
+}
+
+// tracestate 和 baggage 为逗号分隔的 key=value 列表，W3C 没有规定其中的 ID 字段，
+// trace_id、span_id 和 parent_span_id 是 deepflow 约定的成员名称，由业务在其中传递 ID
+// ================================================================================
+// tracestate and baggage are comma separated key=value lists without standard ID members,
+// trace_id, span_id and parent_span_id are member names defined by deepflow for
+// applications passing IDs in them
+// tracestate: trace_id=abc,span_id=def,parent_span_id=ghi
+// baggage: trace_id=abc;property,span_id=def
+struct ListMemberDecoder;
+
+impl TraceDecoder for ListMemberDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let mut ids = TraceIds::default();
+        for member in value.split(',') {
+            // baggage 的成员可以带有 ';' 分隔的属性
+            let member = member.split(';').next().unwrap_or_default();
+            let Some((key, val)) = member.split_once('=') else {
+                continue;
+            };
+            let val = non_empty(val.trim());
+            match key.trim() {
+                "trace_id" => ids.trace_id = val,
+                "span_id" => ids.span_id = val,
+                "parent_span_id" => ids.parent_span_id = val,
+                _ => {}
+            }
+        }
+        ids
+    }
+}
+
+// new_rpc_trace_context: tcid=ac11000116703149173111002125786&spid=0&pspid=1&sample=true&
+struct SofaRpcDecoder;
+
+impl TraceDecoder for SofaRpcDecoder {
+    fn decode(&self, value: &str) -> TraceIds {
+        let ctx = decode_new_rpc_trace_context(value.as_bytes());
+        TraceIds {
+            trace_id: non_empty(&ctx.trace_id),
+            span_id: non_empty(&ctx.span_id),
+            parent_span_id: non_empty(&ctx.parent_span_id),
+        }
+    }
+}
+
+pub fn trace_decoder(trace_type: &TraceType) -> &'static dyn TraceDecoder {
+    match trace_type {
+        TraceType::Disabled
+        | TraceType::XB3
+        | TraceType::XB3Span
+        | TraceType::DatadogTrace
+        | TraceType::DatadogParent
+        | TraceType::Customize(_) => &RawDecoder,
+        TraceType::Uber => &UberDecoder,
+        TraceType::Sw6 | TraceType::Sw8 => &SkyWalkingDecoder,
+        TraceType::TraceParent => &TraceParentDecoder,
+        TraceType::NewRpcTraceContext => &SofaRpcDecoder,
+        TraceType::B3 => &B3Decoder,
+        TraceType::AwsXRay => &AwsXRayDecoder,
+        TraceType::TraceState | TraceType::Baggage => &ListMemberDecoder,
+    }
+}
+
+// 按头部名称选择解码器
+pub fn decode_trace_header(key: &str, value: &str) -> TraceIds {
+    trace_decoder(&TraceType::from(key)).decode(value)
+}
+
+// 按配置的优先级合并一个消息中多个链路追踪头部得到的 ID
+// ================================================================================
+// Merge the IDs from several distributed tracing headers in a message in the
+// configured priority order
+#[derive(Clone, Debug, Default)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: String,
+    // 已得到的 ID 所在头部的优先级
+    trace_priority: Option<usize>,
+    span_priority: Option<usize>,
+    // X-B3-ParentSpanId 只属于 X-B3-SpanId，头部顺序不固定，先暂存
+    b3_parent_span_id: Option<String>,
+    span_from_b3: bool,
+}
+
+impl TraceContext {
+    // key 须为小写，ID 有更新时返回 true
+    pub fn on_header(&mut self, config: &L7LogDynamicConfig, key: &str, value: &str) -> bool {
+        let trace_priority = config
+            .trace_id_priority(key)
+            .filter(|p| self.trace_priority.map_or(true, |c| *p <= c));
+        let span_priority = config
+            .span_id_priority(key)
+            .filter(|p| self.span_priority.map_or(true, |c| *p <= c));
+        if trace_priority.is_none() && span_priority.is_none() {
+            if key == B3_PARENT_SPAN_ID && !value.is_empty() {
+                self.b3_parent_span_id = Some(value.to_owned());
+                if self.span_from_b3 {
+                    self.parent_span_id = value.to_owned();
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        let ids = decode_trace_header(key, value);
+        let mut updated = false;
+        if let (Some(p), Some(id)) = (trace_priority, ids.trace_id) {
+            self.trace_id = id;
+            self.trace_priority = Some(p);
+            updated = true;
+        }
+        if let (Some(p), Some(id)) = (span_priority, ids.span_id) {
+            self.span_id = id;
+            self.span_priority = Some(p);
+            // 父 span 只取自选中 span 所在的头部
+            self.span_from_b3 = TraceType::from(key) == TraceType::XB3Span;
+            let parent = if self.span_from_b3 {
+                self.b3_parent_span_id.clone()
+            } else {
+                ids.parent_span_id
+            };
+            self.parent_span_id = parent.unwrap_or_default();
+            updated = true;
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_headers() {
+        let ids = decode_trace_header(
+            "b3",
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90",
+        );
+        assert_eq!(
+            ids,
+            TraceIds {
+                trace_id: Some("80f198ee56343ba864fe8b2a57d3eff7".to_string()),
+                span_id: Some("e457b5a2e4d86bd1".to_string()),
+                parent_span_id: Some("05e3ac9a4f6e3b90".to_string()),
+            }
+        );
+        assert_eq!(decode_trace_header("b3", "d"), TraceIds::default());
+
+        let ids = decode_trace_header(
+            "x-amzn-trace-id",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1",
+        );
+        assert_eq!(
+            ids.trace_id.as_deref(),
+            Some("1-5759e988-bd862e3fe1be46a994272793")
+        );
+        assert_eq!(ids.span_id.as_deref(), Some("53995c3f42cd8ad8"));
+
+        let ids = decode_trace_header(
+            "uber-trace-id",
+            "5b8aa5a2d2c872e8:196cb4ec8d4d2ab6:3c2a7a9e0d1b4f21:1",
+        );
+        assert_eq!(ids.trace_id.as_deref(), Some("5b8aa5a2d2c872e8"));
+        assert_eq!(ids.span_id.as_deref(), Some("196cb4ec8d4d2ab6"));
+        assert_eq!(ids.parent_span_id.as_deref(), Some("3c2a7a9e0d1b4f21"));
+        let ids = decode_trace_header("uber-trace-id", "5b8aa5a2d2c872e8:196cb4ec8d4d2ab6:0:1");
+        assert_eq!(ids.parent_span_id, None);
+
+        let ids = decode_trace_header("baggage", "user=alice,trace_id=t1;p=1, span_id=s1");
+        assert_eq!(ids.trace_id.as_deref(), Some("t1"));
+        assert_eq!(ids.span_id.as_deref(), Some("s1"));
+
+        let ids = decode_trace_header("x-datadog-parent-id", "3541245215553398843");
+        assert_eq!(ids.span_id.as_deref(), Some("3541245215553398843"));
+    }
+
+    #[test]
+    fn priority() {
+        let config = L7LogDynamicConfig::new(
+            "".to_owned(),
+            "".to_owned(),
+            vec![TraceType::TraceParent, TraceType::XB3],
+            vec![TraceType::TraceParent, TraceType::XB3Span],
+        );
+        let mut ctx = TraceContext::default();
+        assert!(ctx.on_header(
+            &config,
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        ));
+        // 优先级低的头部不覆盖已有的 ID
+        assert!(!ctx.on_header(&config, "x-b3-traceid", "463ac35c9f6413ad"));
+        assert!(!ctx.on_header(&config, "x-b3-spanid", "a2fb4a1d1a96d312"));
+        // span 不是来自 X-B3-SpanId 时不使用 X-B3-ParentSpanId
+        assert!(!ctx.on_header(&config, "x-b3-parentspanid", "0020000000000001"));
+        assert!(!ctx.on_header(&config, "x-request-id", "1"));
+        assert_eq!(ctx.trace_id, "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(ctx.span_id, "b7ad6b7169203331");
+        assert_eq!(ctx.parent_span_id, "");
+
+        // X-B3-ParentSpanId 可以在 X-B3-SpanId 之前
+        let mut ctx = TraceContext::default();
+        assert!(!ctx.on_header(&config, "x-b3-parentspanid", "0020000000000001"));
+        assert!(ctx.on_header(&config, "x-b3-spanid", "a2fb4a1d1a96d312"));
+        assert_eq!(ctx.parent_span_id, "0020000000000001");
+        // 优先级更高的 span 替换 B3 的 span 和父 span
+        assert!(ctx.on_header(
+            &config,
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        ));
+        assert_eq!(ctx.span_id, "b7ad6b7169203331");
+        assert_eq!(ctx.parent_span_id, "");
+
+        let mut ctx = TraceContext::default();
+        ctx.on_header(&config, "x-b3-traceid", "463ac35c9f6413ad");
+        ctx.on_header(
+            &config,
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        );
+        assert_eq!(ctx.trace_id, "0af7651916cd43dd8448eb211c80319c");
+    }
+}
@@ -0,0 +1,898 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        MetaPacket,
+    },
+    config::handler::LogParserConfig,
+    flow_generator::{
+        error::{Error, Result},
+        perf::{L7FlowPerf, PerfStats},
+        protocol_logs::{
+            pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response},
+            value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType,
+        },
+    },
+    log_info_merge, parse_common,
+    utils::bytes::{read_u16_be, read_u64_be},
+};
+
+// https://www.rfc-editor.org/rfc/rfc6455#section-5.2
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-------+-+-------------+-------------------------------+
+// |F|R|R|R| opcode|M| Payload len |    Extended payload length    |
+// |I|S|S|S|  (4)  |A|     (7)     |             (16/64)           |
+// |N|V|V|V|       |S|             |   (if payload len==126/127)   |
+// | |1|2|3|       |K|             |                               |
+// +-+-+-+-+-------+-+-------------+ - - - - - - - - - - - - - - - +
+// |     Extended payload length continued, if payload len == 127  |
+// + - - - - - - - - - - - - - - - +-------------------------------+
+// |                               |Masking-key, if MASK set to 1  |
+// +-------------------------------+-------------------------------+
+// | Masking-key (continued)       |          Payload Data         |
+// +-------------------------------- - - - - - - - - - - - - - - - +
+const MAX_HEADER_LEN: usize = 14;
+const PAYLOAD_LEN_16: u8 = 126;
+const PAYLOAD_LEN_64: u8 = 127;
+const MAX_CONTROL_PAYLOAD_LEN: u64 = 125;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+// 1006 不会出现在 Close 帧中，但有些实现会错误地发送
+// ==============================================
+// 1006 is reserved and never sent in Close frames, but some implementations do
+const CLOSE_ABNORMAL_CLOSURE: u16 = 1006;
+const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FrameHeader {
+    fin: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    payload_len: u64,
+    header_len: usize,
+}
+
+impl FrameHeader {
+    // 帧头不完整时返回 None
+    // ====================
+    // return None if the frame header is not complete
+    fn parse(payload: &[u8]) -> Result<Option<Self>> {
+        if payload.len() < 2 {
+            return Ok(None);
+        }
+        let (fin, rsv, opcode) = (payload[0] & 0x80 != 0, payload[0] & 0x70, payload[0] & 0x0f);
+        let masked = payload[1] & 0x80 != 0;
+        let len = payload[1] & 0x7f;
+        match opcode {
+            OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {}
+            // 控制帧不能分片，载荷不超过 125 字节
+            // ================================
+            // control frames must not be fragmented and have a payload of at most 125 bytes
+            OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG
+                if fin && len as u64 <= MAX_CONTROL_PAYLOAD_LEN => {}
+            _ => return Err(Error::WebSocketLogParseFailed),
+        }
+        // 没有协商扩展时 RSV 必须为 0，permessage-deflate 只使用 RSV1
+        // ======================================================
+        // RSV must be 0 without negotiated extensions, permessage-deflate uses RSV1 only
+        if rsv & 0x30 != 0 {
+            return Err(Error::WebSocketLogParseFailed);
+        }
+
+        let (payload_len, mut header_len) = match len {
+            PAYLOAD_LEN_16 if payload.len() >= 4 => (read_u16_be(&payload[2..]) as u64, 4),
+            PAYLOAD_LEN_64 if payload.len() >= 10 => {
+                let len = read_u64_be(&payload[2..]);
+                if len >> 63 != 0 {
+                    return Err(Error::WebSocketLogParseFailed);
+                }
+                (len, 10)
+            }
+            PAYLOAD_LEN_16 | PAYLOAD_LEN_64 => return Ok(None),
+            _ => (len as u64, 2),
+        };
+        let mask = if masked {
+            if payload.len() < header_len + 4 {
+                return Ok(None);
+            }
+            let mut key = [0; 4];
+            key.copy_from_slice(&payload[header_len..header_len + 4]);
+            header_len += 4;
+            Some(key)
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            fin,
+            opcode,
+            mask,
+            payload_len,
+            header_len,
+        }))
+    }
+
+    fn is_control(&self) -> bool {
+        self.opcode & 0x08 != 0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    header: FrameHeader,
+    // 控制帧完整的载荷（已去掉掩码），载荷跨越报文时为 None
+    // ===================================================
+    // complete payload of control frames (unmasked), None if it spans packets
+    control_payload: Option<Vec<u8>>,
+    // 帧载荷在报文中的偏移
+    // ===================
+    // offset of the frame payload in the packet
+    payload_offset: usize,
+}
+
+// 连接一个方向上跨报文的帧状态
+// ==========================
+// frame state of one direction of the connection kept across packets
+#[derive(Debug, Default)]
+struct FrameReader {
+    // 当前帧剩余的载荷字节数
+    remain: u64,
+    // 跨越报文的帧头
+    header: Vec<u8>,
+}
+
+impl FrameReader {
+    // 返回报文中开始的帧
+    // ===================
+    // return frames started in the packet
+    fn read(&mut self, mut payload: &[u8], direction: PacketDirection) -> Result<Vec<Frame>> {
+        let packet_len = payload.len();
+        let mut frames = vec![];
+        loop {
+            let n = self.remain.min(payload.len() as u64) as usize;
+            self.remain -= n as u64;
+            payload = &payload[n..];
+            if payload.is_empty() {
+                return Ok(frames);
+            }
+
+            let header = if self.header.is_empty() {
+                FrameHeader::parse(payload).map(|h| h.map(|h| (h, h.header_len)))
+            } else {
+                let prefix = self.header.len();
+                let n = (MAX_HEADER_LEN - prefix).min(payload.len());
+                self.header.extend_from_slice(&payload[..n]);
+                FrameHeader::parse(&self.header).map(|h| h.map(|h| (h, h.header_len - prefix)))
+            };
+            let header = match header {
+                Ok(Some((header, consumed))) => {
+                    self.header.clear();
+                    payload = &payload[consumed..];
+                    header
+                }
+                Ok(None) => {
+                    if self.header.is_empty() {
+                        self.header.extend_from_slice(payload);
+                    }
+                    return Ok(frames);
+                }
+                Err(e) => {
+                    // 无法确定下一个帧的位置，丢弃状态等待重新同步
+                    // ==========================================
+                    // the next frame can not be located, drop the state to resync
+                    self.header.clear();
+                    if frames.is_empty() {
+                        return Err(e);
+                    }
+                    return Ok(frames);
+                }
+            };
+
+            // 客户端发送的帧必须有掩码，服务端发送的帧不能有掩码
+            // ===============================================
+            // frames sent by the client must be masked, frames sent by the server must not
+            if header.mask.is_some() != (direction == PacketDirection::ClientToServer) {
+                if frames.is_empty() {
+                    return Err(Error::WebSocketLogParseFailed);
+                }
+                return Ok(frames);
+            }
+
+            let control_payload =
+                if header.is_control() && payload.len() as u64 >= header.payload_len {
+                    let mut data = payload[..header.payload_len as usize].to_vec();
+                    if let Some(key) = header.mask {
+                        data.iter_mut()
+                            .enumerate()
+                            .for_each(|(i, b)| *b ^= key[i % 4]);
+                    }
+                    Some(data)
+                } else {
+                    None
+                };
+            self.remain = header.payload_len;
+            frames.push(Frame {
+                header,
+                control_payload,
+                payload_offset: packet_len - payload.len(),
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct WebSocketInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    #[serde(rename = "request_type")]
+    pub frame_type: &'static str,
+    // HTTP Upgrade 请求的路径
+    // =======================
+    // path of the HTTP Upgrade request
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub path: String,
+
+    // 连接建立以来每个方向的消息数，分片的消息只计一次
+    // ==============================================
+    // messages of each direction since the connection is established,
+    // fragmented messages are counted once
+    pub client_messages: u32,
+    pub server_messages: u32,
+    pub pong_count: u32,
+    // Ping 和 Pong 之间的时延，单位微秒
+    // ==============================
+    // latency between Ping and Pong in microseconds
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub pong_latency_avg: u64,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub pong_latency_max: u64,
+
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub closed_by: &'static str,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub close_code: Option<u16>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub close_reason: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl L7ProtocolInfoInterface for WebSocketInfo {
+    fn session_id(&self) -> Option<u32> {
+        None
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, WebSocketInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::WebSocket,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+}
+
+impl WebSocketInfo {
+    pub fn merge(&mut self, other: Self) {
+        if self.close_code.is_none() {
+            self.closed_by = other.closed_by;
+            self.close_code = other.close_code;
+            self.close_reason = other.close_reason;
+            self.status = other.status;
+        }
+    }
+}
+
+impl From<WebSocketInfo> for L7ProtocolSendLog {
+    fn from(f: WebSocketInfo) -> Self {
+        let mut attributes = vec![
+            ("client_messages", f.client_messages.to_string()),
+            ("server_messages", f.server_messages.to_string()),
+            ("pong_count", f.pong_count.to_string()),
+        ];
+        if f.pong_count > 0 {
+            attributes.push(("pong_latency_avg_us", f.pong_latency_avg.to_string()));
+            attributes.push(("pong_latency_max_us", f.pong_latency_max.to_string()));
+        }
+        if !f.closed_by.is_empty() {
+            attributes.push(("closed_by", f.closed_by.to_string()));
+        }
+        L7ProtocolSendLog {
+            req: L7Request {
+                req_type: String::from(f.frame_type),
+                resource: f.path.clone(),
+                endpoint: f.path,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.close_code.map(|c| c as i32),
+                exception: f.close_reason,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                attributes: Some(
+                    attributes
+                        .into_iter()
+                        .map(|(key, val)| KeyVal {
+                            key: key.to_string(),
+                            val,
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+// 只由 HttpLog 在 101 Switching Protocols 之后创建，不通过 check_payload 识别
+// =====================================================================
+// only created by HttpLog after 101 Switching Protocols, not recognized by check_payload
+#[derive(Debug, Default, Serialize)]
+pub struct WebSocketLog {
+    info: WebSocketInfo,
+    perf_stats: Option<PerfStats>,
+
+    // 升级时间，作为会话日志的开始时间
+    // =============================
+    // upgrade time, used as the start time of the session log
+    #[serde(skip)]
+    upgrade_time: u64,
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    client: FrameReader,
+    #[serde(skip)]
+    server: FrameReader,
+    // 每个方向的消息数，<client,server>
+    // ================================
+    // messages of each direction, <client,server>
+    #[serde(skip)]
+    messages: (u32, u32),
+    // 等待 Pong 的 Ping 的时间，<client,server>
+    // =======================================
+    // time of the Ping waiting for Pong, <client,server>
+    #[serde(skip)]
+    ping_time: (Option<u64>, Option<u64>),
+    #[serde(skip)]
+    pong_count: u32,
+    #[serde(skip)]
+    pong_latency_sum: u64,
+    #[serde(skip)]
+    pong_latency_max: u64,
+    #[serde(skip)]
+    closed: bool,
+}
+
+impl L7ProtocolParserInterface for WebSocketLog {
+    fn check_payload(&mut self, _: &[u8], _: &ParseParam) -> bool {
+        false
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if param.l4_protocol != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+        parse_common!(self, param);
+        if self.upgrade_time == 0 {
+            self.upgrade_time = param.time;
+        }
+        let reader = match param.direction {
+            PacketDirection::ClientToServer => &mut self.client,
+            PacketDirection::ServerToClient => &mut self.server,
+        };
+        let frames = reader.read(payload, param.direction)?;
+
+        let mut reported = false;
+        for frame in frames {
+            if self.closed {
+                break;
+            }
+            reported |= self.on_frame(frame, param);
+        }
+        if reported {
+            return Ok(vec![L7ProtocolInfo::WebSocketInfo(self.info.clone())]);
+        }
+        Ok(vec![])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::WebSocket
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    // 跨报文的帧需要完整的载荷才能找到下一个帧头，只读取帧头和控制帧的载荷，数据帧的内容不解析
+    // =========================================================================================
+    // the complete payload is needed for locating the next frame header across packets, only
+    // frame headers and control frame payloads are read, data frames are never decoded
+    fn need_complete_payload(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.info = WebSocketInfo::default();
+    }
+
+    // 连接没有交换 Close 帧就结束或超时，按 1006 Abnormal Closure 上报会话
+    // =================================================================
+    // the connection ends or times out without exchanging Close frames,
+    // the session is reported as 1006 Abnormal Closure
+    fn flow_end(&mut self, time: u64) -> Vec<L7ProtocolInfo> {
+        if self.closed {
+            return vec![];
+        }
+        self.closed = true;
+        self.fill_session(time);
+        self.info.close_code = Some(CLOSE_ABNORMAL_CLOSURE);
+        self.info.status = L7ResponseStatus::ServerError;
+        vec![L7ProtocolInfo::WebSocketInfo(std::mem::take(
+            &mut self.info,
+        ))]
+    }
+}
+
+impl WebSocketLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn upgraded(path: String, upgrade_time: u64) -> Self {
+        Self {
+            path,
+            upgrade_time,
+            ..Default::default()
+        }
+    }
+
+    // 返回 true 表示需要上报会话日志
+    // ============================
+    // return true if a session log should be reported
+    fn on_frame(&mut self, frame: Frame, param: &ParseParam) -> bool {
+        let (direction, time) = (param.direction, param.time);
+        let (messages, ping_time, peer_ping_time) = match direction {
+            PacketDirection::ClientToServer => (
+                &mut self.messages.0,
+                &mut self.ping_time.0,
+                &mut self.ping_time.1,
+            ),
+            PacketDirection::ServerToClient => (
+                &mut self.messages.1,
+                &mut self.ping_time.1,
+                &mut self.ping_time.0,
+            ),
+        };
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        match frame.header.opcode {
+            // 分片的消息在最后一个帧计数
+            // ========================
+            // fragmented messages are counted on the final frame
+            OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                if frame.header.fin {
+                    *messages += 1;
+                    match direction {
+                        PacketDirection::ClientToServer => perf_stats.req_count += 1,
+                        PacketDirection::ServerToClient => perf_stats.resp_count += 1,
+                    }
+                }
+                false
+            }
+            OPCODE_PING => {
+                *ping_time = Some(time);
+                false
+            }
+            // 对端没有发送 Ping 时的 Pong 是单向心跳
+            // ====================================
+            // Pong without Ping from the peer is a unidirectional heartbeat
+            OPCODE_PONG => {
+                if let Some(ping_time) = peer_ping_time.take() {
+                    let latency = time.saturating_sub(ping_time);
+                    self.pong_count += 1;
+                    self.pong_latency_sum += latency;
+                    self.pong_latency_max = self.pong_latency_max.max(latency);
+
+                    let rrt = Duration::from_micros(latency);
+                    perf_stats.rrt_count += 1;
+                    perf_stats.rrt_sum += rrt;
+                    perf_stats.rrt_last = rrt;
+                    perf_stats.rrt_max = perf_stats.rrt_max.max(rrt);
+                }
+                false
+            }
+            OPCODE_CLOSE => {
+                self.closed = true;
+                // 状态码属于连接状态，只有 l7_log_packet_size 以内的关闭原因作为日志内容
+                // ===================================================================
+                // the status code is connection state, only the close reason within
+                // l7_log_packet_size is log content
+                let reason_len = param
+                    .log_packet_size
+                    .saturating_sub(frame.payload_offset + 2);
+                self.on_close(frame.control_payload, reason_len, direction, time);
+                if self.info.status == L7ResponseStatus::ServerError {
+                    self.perf_stats
+                        .get_or_insert(PerfStats::default())
+                        .resp_err_count += 1;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn on_close(
+        &mut self,
+        payload: Option<Vec<u8>>,
+        reason_len: usize,
+        direction: PacketDirection,
+        time: u64,
+    ) {
+        self.fill_session(time);
+        let info = &mut self.info;
+        info.closed_by = match direction {
+            PacketDirection::ClientToServer => "client",
+            PacketDirection::ServerToClient => "server",
+        };
+        // 没有状态码的 Close 帧相当于 1005 No Status Received
+        // =================================================
+        // Close frames without status code are treated as 1005 No Status Received
+        if let Some(payload) = payload.filter(|p| p.len() >= 2) {
+            let code = read_u16_be(&payload);
+            info.close_code = Some(code);
+            let reason = &payload[2..];
+            info.close_reason =
+                String::from_utf8_lossy(&reason[..reason.len().min(reason_len)]).into_owned();
+            info.status = match code {
+                CLOSE_ABNORMAL_CLOSURE | CLOSE_INTERNAL_ERROR => L7ResponseStatus::ServerError,
+                _ => L7ResponseStatus::Ok,
+            };
+        }
+    }
+
+    fn fill_session(&mut self, time: u64) {
+        let info = &mut self.info;
+        info.msg_type = LogMessageType::Session;
+        info.frame_type = "Close";
+        info.start_time = self.upgrade_time.min(time);
+        info.end_time = time;
+        info.path = self.path.clone();
+        info.client_messages = self.messages.0;
+        info.server_messages = self.messages.1;
+        info.pong_count = self.pong_count;
+        if self.pong_count > 0 {
+            info.pong_latency_avg = self.pong_latency_sum / self.pong_count as u64;
+            info.pong_latency_max = self.pong_latency_max;
+        }
+    }
+}
+
+impl L7FlowPerf for WebSocketLog {
+    fn parse(&mut self, _: Option<&LogParserConfig>, packet: &MetaPacket, _: u64) -> Result<()> {
+        if let Some(payload) = packet.get_l4_payload() {
+            self.parse_payload(payload, &ParseParam::from(packet))?;
+            return Ok(());
+        }
+        Err(Error::L7ProtocolUnknown)
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        FlowPerfStats {
+            l7_protocol: L7Protocol::WebSocket,
+            l7: if let Some(perf) = self.perf_stats.take() {
+                L7PerfStats {
+                    request_count: perf.req_count,
+                    response_count: perf.resp_count,
+                    err_client_count: perf.req_err_count,
+                    err_server_count: perf.resp_err_count,
+                    err_timeout: timeout_count,
+                    rrt_count: perf.rrt_count,
+                    rrt_sum: perf.rrt_sum.as_micros() as u64,
+                    rrt_max: perf.rrt_max.as_micros() as u32,
+                }
+            } else {
+                L7PerfStats::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
+            return Some((h, 0));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::common::{ebpf::EbpfType, l7_protocol_log::L7ProtocolParser};
+    use crate::config::handler::L7LogDynamicConfig;
+    use crate::flow_generator::protocol_logs::HttpLog;
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/websocket";
+
+    fn param(direction: PacketDirection, time: u64) -> ParseParam<'static> {
+        ParseParam {
+            l4_protocol: IpProtocol::Tcp,
+            ip_src: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            ip_dst: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port_src: 0,
+            port_dst: 0,
+            direction,
+            ebpf_type: EbpfType::None,
+            ebpf_param: None,
+            time,
+            parse_config: None,
+            log_packet_size: usize::MAX,
+            tcp_seq: 0,
+            tcp_ack: 0,
+            payload_len: 0,
+        }
+    }
+
+    fn frame(fin: bool, opcode: u8, mask: Option<[u8; 4]>, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![((fin as u8) << 7) | opcode];
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        match payload.len() {
+            0..=125 => frame.push(mask_bit | payload.len() as u8),
+            126..=0xffff => {
+                frame.push(mask_bit | PAYLOAD_LEN_16);
+                frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            }
+            _ => {
+                frame.push(mask_bit | PAYLOAD_LEN_64);
+                frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            }
+        }
+        match mask {
+            Some(key) => {
+                frame.extend_from_slice(&key);
+                frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+            }
+            None => frame.extend_from_slice(payload),
+        }
+        frame
+    }
+
+    const KEY: Option<[u8; 4]> = Some([0x12, 0x34, 0x56, 0x78]);
+
+    #[test]
+    fn frame_header() {
+        let header = FrameHeader::parse(&frame(true, OPCODE_TEXT, KEY, &[0; 200]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            header,
+            FrameHeader {
+                fin: true,
+                opcode: OPCODE_TEXT,
+                mask: KEY,
+                payload_len: 200,
+                header_len: 8,
+            }
+        );
+        let header = FrameHeader::parse(&frame(false, OPCODE_BINARY, None, &[0; 70000]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((header.payload_len, header.header_len), (70000, 10));
+
+        assert_eq!(FrameHeader::parse(&[0x81, 0xfe, 0]).unwrap(), None);
+        // 分片的控制帧，保留的操作码
+        assert!(FrameHeader::parse(&[0x09, 0x00]).is_err());
+        assert!(FrameHeader::parse(&[0x83, 0x00]).is_err());
+    }
+
+    #[test]
+    fn frames_across_packets() {
+        let mut reader = FrameReader::default();
+        let direction = PacketDirection::ServerToClient;
+        let mut payload = frame(true, OPCODE_TEXT, None, b"hello");
+        payload.extend(frame(true, OPCODE_BINARY, None, &[0; 300]));
+
+        // 第二个帧的帧头跨越报文
+        let frames = reader.read(&payload[..8], direction).unwrap();
+        assert_eq!(frames.len(), 1);
+        let frames = reader.read(&payload[8..100], direction).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].header.payload_len, 300);
+        let mut payload = payload[100..].to_vec();
+        payload.extend(frame(true, OPCODE_CLOSE, None, &[0x03, 0xe8]));
+        let frames = reader.read(&payload, direction).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].control_payload, Some(vec![0x03, 0xe8]));
+
+        // 客户端发送的帧必须有掩码
+        assert!(reader
+            .read(
+                &frame(true, OPCODE_TEXT, None, b"a"),
+                PacketDirection::ClientToServer
+            )
+            .is_err());
+    }
+
+    fn config() -> LogParserConfig {
+        LogParserConfig {
+            l7_log_collect_nps_threshold: 0,
+            l7_log_session_aggr_timeout: Duration::from_secs(120),
+            l7_log_dynamic: L7LogDynamicConfig::default(),
+        }
+    }
+
+    // 升级握手由 HttpLog 解析，101 之后的报文交给它返回的 WebSocketLog，没有日志的报文输出 None，
+    // 最后输出流结束时上报的会话
+    // ======================================================================================
+    // the upgrade handshake is parsed by HttpLog, packets after 101 are handed to the
+    // WebSocketLog it returns, packets without logs output None, and sessions reported on
+    // flow end are output last
+    fn run(name: &str) -> (String, Option<WebSocketLog>) {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), None);
+        let mut packets = capture.as_meta_packets();
+        if packets.is_empty() {
+            return ("".to_string(), None);
+        }
+
+        let config = config();
+        let mut output = String::new();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        let mut http = HttpLog::new_v1();
+        let mut websocket = None;
+        let mut last_time = 0;
+        for packet in packets.iter_mut() {
+            packet.lookup_key.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let Some(payload) = packet.get_l4_payload() else {
+                continue;
+            };
+            let param = ParseParam::from((&*packet, &config));
+            last_time = param.time;
+            let Some(parser) = websocket.as_mut() else {
+                let _ = http.parse_payload(payload, &param);
+                http.reset();
+                if let Some(L7ProtocolParser::WebSocketParser(p)) = http.upgraded_parser() {
+                    websocket = Some(p);
+                }
+                continue;
+            };
+            let info = match parser.parse_payload(payload, &param) {
+                Ok(mut infos) => infos.pop(),
+                Err(_) => None,
+            };
+            parser.reset();
+            output.push_str(&format!("{:?}\r\n", info.map(session_info)));
+        }
+        if let Some(parser) = websocket.as_mut() {
+            for info in parser.flow_end(last_time) {
+                output.push_str(&format!("{:?}\r\n", Some(session_info(info))));
+            }
+        }
+        (output, websocket)
+    }
+
+    fn session_info(info: L7ProtocolInfo) -> WebSocketInfo {
+        match info {
+            L7ProtocolInfo::WebSocketInfo(i) => i,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn check() {
+        let files = vec![
+            ("websocket.pcap", "websocket.result"),
+            ("websocket_abnormal.pcap", "websocket_abnormal.result"),
+        ];
+
+        for item in files.iter() {
+            let expected = fs::read_to_string(&Path::new(FILE_DIR).join(item.1)).unwrap();
+            let (output, _) = run(item.0);
+
+            if output != expected {
+                let output_path = Path::new("actual.txt");
+                fs::write(&output_path, &output).unwrap();
+                assert!(
+                    output == expected,
+                    "output different from expected {}, written to {:?}",
+                    item.1,
+                    output_path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn perf() {
+        let (_, parser) = run("websocket.pcap");
+        let perf = parser.unwrap().copy_and_reset_data(0).l7;
+        assert_eq!((perf.request_count, perf.response_count), (1, 1));
+        assert_eq!((perf.rrt_count, perf.rrt_sum), (1, 1000));
+        assert_eq!(perf.rrt_max, 1000);
+        assert_eq!(perf.err_server_count, 1);
+    }
+
+    #[test]
+    fn close_reason_log_packet_size() {
+        let mut parser = WebSocketLog::upgraded("/chat".to_string(), 1000);
+        let mut payload = frame(true, OPCODE_TEXT, None, &[0; 20]);
+        let mut close = 1001u16.to_be_bytes().to_vec();
+        close.extend_from_slice(b"going away");
+        payload.extend(frame(true, OPCODE_CLOSE, None, &close));
+        // 第二个帧的关闭原因从偏移 26 开始
+        let param = ParseParam {
+            log_packet_size: 31,
+            ..param(PacketDirection::ServerToClient, 2000)
+        };
+        let Some(L7ProtocolInfo::WebSocketInfo(info)) =
+            parser.parse_payload(&payload, &param).unwrap().pop()
+        else {
+            unreachable!();
+        };
+        assert_eq!(info.close_code, Some(1001));
+        assert_eq!(info.close_reason, "going");
+        assert_eq!(info.server_messages, 1);
+    }
+}
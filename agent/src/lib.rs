@@ -38,6 +38,7 @@ mod rpc;
 mod sender;
 pub mod trident;
 pub mod utils;
+mod xflow_collector;
 
 // for benchmarks
 #[doc(hidden)]
@@ -92,6 +92,7 @@ use crate::{
         npb_bandwidth_watcher::NpbBandwidthWatcher,
         stats::{self, ArcBatch, Countable, RefCountable, StatsOption},
     },
+    xflow_collector::{XflowCollector, XflowType},
 };
 #[cfg(target_os = "linux")]
 use crate::{
@@ -936,6 +937,7 @@ pub struct AgentComponents {
     pub debugger: Debugger,
     #[cfg(target_os = "linux")]
     pub ebpf_collector: Option<Box<EbpfCollector>>,
+    pub xflow_collectors: Vec<XflowCollector>,
     pub running: AtomicBool,
     pub stats_collector: Arc<stats::Collector>,
     pub external_metrics_server: MetricServer,
@@ -1435,15 +1437,29 @@ impl AgentComponents {
             config_handler.candidate_config.npb.socket_type == SocketType::RawUdp,
         ));
 
+        #[cfg(target_os = "linux")]
+        let pcap_replay_files: Vec<PathBuf> = yaml_config
+            .pcap_replay
+            .files
+            .iter()
+            .map(PathBuf::from)
+            .collect();
         let mut src_interfaces_and_namespaces = vec![];
         for src_if in yaml_config.src_interfaces.iter() {
             src_interfaces_and_namespaces.push((src_if.clone(), NsFile::Root));
         }
+        #[cfg(target_os = "linux")]
+        if !pcap_replay_files.is_empty() {
+            // pcap 回放只需要一个 dispatcher
+            // ===============================
+            // only one dispatcher is needed for pcap replay
+            src_interfaces_and_namespaces.truncate(1);
+        }
         if src_interfaces_and_namespaces.is_empty() {
             src_interfaces_and_namespaces.push(("".into(), NsFile::Root));
         }
         #[cfg(target_os = "linux")]
-        if candidate_config.dispatcher.extra_netns_regex != "" {
+        if candidate_config.dispatcher.extra_netns_regex != "" && pcap_replay_files.is_empty() {
             let re = Regex::new(&candidate_config.dispatcher.extra_netns_regex).unwrap();
             let mut nss = NetNs::find_ns_files_by_regex(&re);
             nss.sort_unstable();
@@ -1610,6 +1626,10 @@ impl AgentComponents {
                     af_packet_blocks: config_handler.candidate_config.dispatcher.af_packet_blocks,
                     #[cfg(target_os = "linux")]
                     af_packet_version: config_handler.candidate_config.dispatcher.af_packet_version,
+                    #[cfg(target_os = "linux")]
+                    pcap_replay_files: pcap_replay_files.clone(),
+                    #[cfg(target_os = "linux")]
+                    pcap_replay_speed: yaml_config.pcap_replay.speed,
                     #[cfg(target_os = "windows")]
                     win_packet_blocks: config_handler.candidate_config.dispatcher.af_packet_blocks,
                     tap_mode: candidate_config.tap_mode,
@@ -1781,6 +1801,109 @@ impl AgentComponents {
             }
         }
 
+        let mut xflow_collectors = vec![];
+        if candidate_config.tap_mode == TapMode::Analyzer {
+            let mut ports = vec![];
+            for port in yaml_config.xflow_collector.sflow_ports.iter() {
+                match port.parse::<u16>() {
+                    Ok(p) => ports.push((XflowType::Sflow, p)),
+                    Err(_) => warn!("invalid sflow port {}, ignored", port),
+                }
+            }
+            for port in yaml_config.xflow_collector.netflow_ports.iter() {
+                match port.parse::<u16>() {
+                    Ok(p) => ports.push((XflowType::Netflow, p)),
+                    Err(_) => warn!("invalid netflow port {}, ignored", port),
+                }
+            }
+            for (flow_type, port) in ports {
+                let xflow_id = dispatchers.len() + xflow_collectors.len();
+                let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug(
+                    yaml_config.flow_queue_size,
+                    "1-tagged-flow-to-quadruple-generator",
+                    &queue_debugger,
+                );
+                stats_collector.register_countable(
+                    "queue",
+                    Countable::Owned(Box::new(counter)),
+                    vec![
+                        StatsOption::Tag(
+                            "module",
+                            "1-tagged-flow-to-quadruple-generator".to_string(),
+                        ),
+                        StatsOption::Tag("index", xflow_id.to_string()),
+                    ],
+                );
+                let collector = Self::new_collector(
+                    xflow_id,
+                    stats_collector.clone(),
+                    flow_receiver,
+                    toa_sender.clone(),
+                    Some(l4_flow_aggr_sender.clone()),
+                    metrics_sender.clone(),
+                    MetricsType::SECOND | MetricsType::MINUTE,
+                    config_handler,
+                    &queue_debugger,
+                    &synchronizer,
+                );
+                collectors.push(collector);
+                let (log_sender, log_receiver, counter) = queue::bounded_with_debug(
+                    yaml_config.flow_queue_size,
+                    "1-tagged-flow-to-app-protocol-logs",
+                    &queue_debugger,
+                );
+                stats_collector.register_countable(
+                    "queue",
+                    Countable::Owned(Box::new(counter)),
+                    vec![
+                        StatsOption::Tag(
+                            "module",
+                            "1-tagged-flow-to-app-protocol-logs".to_string(),
+                        ),
+                        StatsOption::Tag("index", xflow_id.to_string()),
+                    ],
+                );
+                let (app_proto_log_parser, counter) = AppProtoLogsParser::new(
+                    log_receiver,
+                    proto_log_sender.clone(),
+                    xflow_id as u32,
+                    config_handler.log_parser(),
+                    l7_log_rate.clone(),
+                );
+                stats_collector.register_countable(
+                    "l7_session_aggr",
+                    Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                    vec![StatsOption::Tag("index", xflow_id.to_string())],
+                );
+                log_parsers.push(app_proto_log_parser);
+
+                let (xflow_collector, counter) = XflowCollector::new(
+                    xflow_id,
+                    flow_type,
+                    port,
+                    yaml_config.flow_queue_size,
+                    synchronizer.ntp_diff(),
+                    tap_typer.clone(),
+                    policy_getter,
+                    config_handler.flow(),
+                    config_handler.log_parser(),
+                    flow_sender,
+                    log_sender,
+                    &queue_debugger,
+                    stats_collector.clone(),
+                );
+                stats_collector.register_countable(
+                    "xflow_collector",
+                    Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                    vec![
+                        StatsOption::Tag("type", flow_type.to_string()),
+                        StatsOption::Tag("port", port.to_string()),
+                    ],
+                );
+                xflow_collectors.push(xflow_collector);
+            }
+        }
+
         let sender_id = get_sender_id() as usize;
         let otel_queue_name = "1-otel-to-sender";
         let (otel_sender, otel_receiver, counter) = queue::bounded_with_debug(
@@ -1947,6 +2070,7 @@ impl AgentComponents {
             log_parsers,
             #[cfg(target_os = "linux")]
             ebpf_collector,
+            xflow_collectors,
             stats_collector,
             running: AtomicBool::new(false),
             external_metrics_server,
@@ -2031,6 +2155,9 @@ impl AgentComponents {
         if let Some(ebpf_collector) = self.ebpf_collector.as_mut() {
             ebpf_collector.start();
         }
+        for xflow_collector in self.xflow_collectors.iter() {
+            xflow_collector.start();
+        }
         if matches!(self.agent_mode, RunningMode::Managed) {
             self.otel_uniform_sender.start();
             self.compressed_otel_uniform_sender.start();
@@ -2064,6 +2191,10 @@ impl AgentComponents {
             d.stop();
         }
 
+        for x in self.xflow_collectors.iter() {
+            x.stop();
+        }
+
         #[cfg(target_os = "linux")]
         {
             self.platform_synchronizer.stop_kubernetes_poller();
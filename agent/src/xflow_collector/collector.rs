@@ -0,0 +1,823 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{
+    atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    Arc, Mutex, Weak,
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+
+use super::{
+    netflow::{FlowRecord, NetflowDecoder},
+    sflow::{self, Datagram, FlowSample, GenericInterfaceCounters, Sample},
+    Error, Result, XflowType,
+};
+use crate::common::{
+    enums::{EthernetType, IpProtocol, TapType, TcpFlags},
+    flow::{CloseType, Flow, FlowKey, FlowMetricsPeer, SignalSource},
+    MetaPacket, TaggedFlow, TapPort, TapTyper, XflowKey,
+};
+use crate::config::handler::LogParserAccess;
+use crate::config::FlowAccess;
+use crate::flow_generator::{
+    FlowMap, MetaAppProto, COUNTER_FLOW_ID_MASK, THREAD_FLOW_ID_MASK, TIMER_FLOW_ID_MASK, TIME_UNIT,
+};
+use crate::policy::PolicyGetter;
+use crate::rpc::get_timestamp;
+use crate::utils::stats::{
+    self, Countable, Counter, CounterType, CounterValue, RefCountable, StatsOption,
+};
+use public::{
+    debug::QueueDebugger,
+    queue::{self, bounded_with_debug, DebugSender, Receiver},
+    utils::net::MacAddr,
+};
+
+const RCV_TIMEOUT: Duration = Duration::from_secs(1);
+const QUEUE_BATCH_SIZE: usize = 1024;
+const UDP_BUFFER_SIZE: usize = 65536;
+// 接口统计按 (agent, if_index) 注册，限制数量并清理长时间没有计数器样本的接口
+// ====================================================================
+// interface stats are registered per (agent, if_index), the number is bounded and
+// interfaces without counter samples for a long time are removed
+const MAX_INTERFACES_PER_AGENT: usize = 1024;
+const MAX_INTERFACES: usize = 16384;
+const INTERFACE_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+const INTERFACE_AGING_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct XflowPacket {
+    timestamp: Duration,
+    src: SocketAddr,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct XflowCounter {
+    rx: AtomicU64,
+    rx_bytes: AtomicU64,
+    invalid: AtomicU64,
+    flow_samples: AtomicU64,
+    counter_samples: AtomicU64,
+    flow_records: AtomicU64,
+    // 没有以太网原始报文头的流样本
+    // ============================
+    // flow samples without ethernet raw packet header
+    unsupported: AtomicU64,
+    drop_in_queue: AtomicU64,
+    // 超过接口数量限制而丢弃的计数器样本
+    // ================================
+    // counter samples dropped for exceeding the interface limits
+    interface_overflow: AtomicU64,
+}
+
+impl RefCountable for XflowCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "rx",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "rx_bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "invalid",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.invalid.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "flow_samples",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.flow_samples.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "counter_samples",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.counter_samples.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "flow_records",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.flow_records.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "unsupported",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.unsupported.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "drop_in_queue",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.drop_in_queue.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "interface_overflow",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.interface_overflow.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+// sFlow 计数器样本中的累计值，转换为两次上报之间的增量
+// ====================================================
+// cumulative values in sFlow counter samples, reported as deltas between two samples
+#[derive(Default)]
+struct InterfaceCounter {
+    last: Mutex<Option<GenericInterfaceCounters>>,
+
+    if_speed: AtomicU64,
+    if_status: AtomicU64,
+    in_octets: AtomicU64,
+    in_packets: AtomicU64,
+    in_discards: AtomicU64,
+    in_errors: AtomicU64,
+    out_octets: AtomicU64,
+    out_packets: AtomicU64,
+    out_discards: AtomicU64,
+    out_errors: AtomicU64,
+}
+
+impl InterfaceCounter {
+    fn update(&self, current: GenericInterfaceCounters) {
+        // 计数器回绕或设备重启时，直接使用当前值
+        // ======================================
+        // use current value directly when counter wraps or device reboots
+        fn delta(current: u64, last: u64) -> u64 {
+            if current >= last {
+                current - last
+            } else {
+                current
+            }
+        }
+
+        self.if_speed.store(current.if_speed, Ordering::Relaxed);
+        self.if_status
+            .store(current.if_status as u64, Ordering::Relaxed);
+
+        let mut last = self.last.lock().unwrap();
+        if let Some(l) = last.as_ref() {
+            let in_packets = |c: &GenericInterfaceCounters| {
+                c.in_ucast_pkts as u64 + c.in_multicast_pkts as u64 + c.in_broadcast_pkts as u64
+            };
+            let out_packets = |c: &GenericInterfaceCounters| {
+                c.out_ucast_pkts as u64 + c.out_multicast_pkts as u64 + c.out_broadcast_pkts as u64
+            };
+            self.in_octets
+                .fetch_add(delta(current.in_octets, l.in_octets), Ordering::Relaxed);
+            self.in_packets.fetch_add(
+                delta(in_packets(&current), in_packets(l)),
+                Ordering::Relaxed,
+            );
+            self.in_discards.fetch_add(
+                delta(current.in_discards as u64, l.in_discards as u64),
+                Ordering::Relaxed,
+            );
+            self.in_errors.fetch_add(
+                delta(current.in_errors as u64, l.in_errors as u64),
+                Ordering::Relaxed,
+            );
+            self.out_octets
+                .fetch_add(delta(current.out_octets, l.out_octets), Ordering::Relaxed);
+            self.out_packets.fetch_add(
+                delta(out_packets(&current), out_packets(l)),
+                Ordering::Relaxed,
+            );
+            self.out_discards.fetch_add(
+                delta(current.out_discards as u64, l.out_discards as u64),
+                Ordering::Relaxed,
+            );
+            self.out_errors.fetch_add(
+                delta(current.out_errors as u64, l.out_errors as u64),
+                Ordering::Relaxed,
+            );
+        }
+        last.replace(current);
+    }
+}
+
+impl RefCountable for InterfaceCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "if_speed",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.if_speed.load(Ordering::Relaxed)),
+            ),
+            (
+                "if_status",
+                CounterType::Gauged,
+                CounterValue::Unsigned(self.if_status.load(Ordering::Relaxed)),
+            ),
+            (
+                "in_octets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.in_octets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "in_packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.in_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "in_discards",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.in_discards.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "in_errors",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.in_errors.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "out_octets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.out_octets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "out_packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.out_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "out_discards",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.out_discards.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "out_errors",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.out_errors.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+struct FlowGenerator {
+    id: usize,
+    flow_type: XflowType,
+    ntp_diff: Arc<AtomicI64>,
+    tap_typer: Arc<TapTyper>,
+    policy_getter: PolicyGetter,
+    flow_map_config: FlowAccess,
+    log_parser_config: LogParserAccess,
+    flow_output: DebugSender<Box<TaggedFlow>>,
+    log_output: DebugSender<Box<MetaAppProto>>,
+    counter: Arc<XflowCounter>,
+    stats_collector: Arc<stats::Collector>,
+    // <(agent, if_index), (counter, last sample time)>
+    interfaces: HashMap<(IpAddr, u32), (Arc<InterfaceCounter>, Duration)>,
+    // <agent, interface count>
+    agent_interfaces: HashMap<IpAddr, usize>,
+    last_aging: Duration,
+
+    netflow_decoder: NetflowDecoder,
+    output_buffer: Vec<Box<TaggedFlow>>,
+    total_flow: u64,
+}
+
+impl FlowGenerator {
+    // TapPort 中只能保存 32 位，IPv6 地址取低 32 位
+    // =============================================
+    // only 32 bits can be saved in TapPort, use the lower 32 bits of IPv6 address
+    fn ip_to_u32(ip: &IpAddr) -> u32 {
+        match ip {
+            IpAddr::V4(ip) => u32::from(*ip),
+            IpAddr::V6(ip) => {
+                let octets = ip.octets();
+                u32::from_be_bytes(octets[12..].try_into().unwrap())
+            }
+        }
+    }
+
+    fn tap_type(&self, agent: &IpAddr, if_index: u32) -> TapType {
+        match agent {
+            IpAddr::V4(ip) if if_index > 0 => self
+                .tap_typer
+                .get_tap_type_by_xflow_key(&XflowKey::new(*ip, if_index))
+                .unwrap_or(TapType::Cloud),
+            _ => TapType::Cloud,
+        }
+    }
+
+    fn handle_sflow(&mut self, flow_map: &mut FlowMap, packet: &XflowPacket) {
+        let datagram = match Datagram::decode(&packet.data) {
+            Ok(d) => d,
+            Err(e) => {
+                self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+                debug!("invalid sflow datagram from {}: {}", packet.src, e);
+                return;
+            }
+        };
+        for sample in datagram.samples {
+            match sample {
+                Sample::Flow(flow) => {
+                    self.counter.flow_samples.fetch_add(1, Ordering::Relaxed);
+                    self.inject_flow_sample(
+                        flow_map,
+                        &datagram.agent_address,
+                        packet.timestamp,
+                        &flow,
+                    );
+                }
+                Sample::Counter(counter) => {
+                    self.counter.counter_samples.fetch_add(1, Ordering::Relaxed);
+                    if let Some(interface) = counter.interface {
+                        self.update_interface(datagram.agent_address, interface, packet.timestamp);
+                    }
+                }
+            }
+        }
+    }
+
+    fn inject_flow_sample(
+        &self,
+        flow_map: &mut FlowMap,
+        agent: &IpAddr,
+        timestamp: Duration,
+        sample: &FlowSample,
+    ) {
+        let Some(raw) = sample
+            .raw_header
+            .as_ref()
+            .filter(|r| r.protocol == sflow::HEADER_PROTOCOL_ETHERNET)
+        else {
+            self.counter.unsupported.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+
+        let mut meta_packet = MetaPacket::empty();
+        if let Err(e) = meta_packet.update(
+            raw.header,
+            false,
+            false,
+            timestamp,
+            raw.original_length() as usize,
+        ) {
+            self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+            debug!("invalid sflow raw packet header from {}: {}", agent, e);
+            return;
+        }
+        meta_packet.signal_source = SignalSource::XFlow;
+        meta_packet.tap_port = TapPort::from_sflow(Self::ip_to_u32(agent));
+        meta_packet.lookup_key.tap_type = self.tap_type(agent, sample.input_if_index);
+        meta_packet.packet_count = sample.sampling_rate.max(1) as u64;
+        if meta_packet.vlan == 0 {
+            if let Some(switch) = sample.switch.as_ref() {
+                meta_packet.vlan = switch.src_vlan as u16;
+            }
+        }
+
+        flow_map.inject_meta_packet(&mut meta_packet);
+    }
+
+    fn update_interface(
+        &mut self,
+        agent: IpAddr,
+        counters: GenericInterfaceCounters,
+        timestamp: Duration,
+    ) {
+        let key = (agent, counters.if_index);
+        if !self.interfaces.contains_key(&key) {
+            let agent_interfaces = self.agent_interfaces.get(&agent).copied().unwrap_or(0);
+            if self.interfaces.len() >= MAX_INTERFACES
+                || agent_interfaces >= MAX_INTERFACES_PER_AGENT
+            {
+                self.counter
+                    .interface_overflow
+                    .fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            let counter = Arc::new(InterfaceCounter::default());
+            self.stats_collector.register_countable(
+                "xflow_interface",
+                Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                vec![
+                    StatsOption::Tag("type", self.flow_type.to_string()),
+                    StatsOption::Tag("agent", agent.to_string()),
+                    StatsOption::Tag("if_index", counters.if_index.to_string()),
+                ],
+            );
+            self.interfaces.insert(key, (counter, timestamp));
+            self.agent_interfaces.insert(agent, agent_interfaces + 1);
+        }
+        let (counter, last_seen) = self.interfaces.get_mut(&key).unwrap();
+        counter.update(counters);
+        *last_seen = timestamp;
+    }
+
+    // 丢弃 InterfaceCounter 后其统计随之注销
+    // ====================================
+    // stats of an InterfaceCounter are deregistered once it is dropped
+    fn age_interfaces(&mut self, now: Duration) {
+        if now < self.last_aging + INTERFACE_AGING_INTERVAL {
+            return;
+        }
+        self.last_aging = now;
+        let agent_interfaces = &mut self.agent_interfaces;
+        self.interfaces.retain(|(agent, _), (_, last_seen)| {
+            if now < *last_seen + INTERFACE_IDLE_TIMEOUT {
+                return true;
+            }
+            if let Some(n) = agent_interfaces.get_mut(agent) {
+                *n -= 1;
+                if *n == 0 {
+                    agent_interfaces.remove(agent);
+                }
+            }
+            false
+        });
+    }
+
+    fn handle_netflow(&mut self, packet: &XflowPacket) {
+        let exporter = packet.src.ip();
+        let records = match self.netflow_decoder.decode(exporter, &packet.data) {
+            Ok(r) => r,
+            Err(e) => {
+                self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+                debug!("invalid netflow datagram from {}: {}", packet.src, e);
+                return;
+            }
+        };
+        for record in records {
+            self.counter.flow_records.fetch_add(1, Ordering::Relaxed);
+            let tagged_flow = self.record_to_tagged_flow(&exporter, packet.timestamp, &record);
+            self.output_buffer.push(Box::new(tagged_flow));
+            if self.output_buffer.len() >= QUEUE_BATCH_SIZE {
+                self.flush_output();
+            }
+        }
+    }
+
+    fn generate_flow_id(&mut self, timestamp: Duration) -> u64 {
+        self.total_flow += 1;
+        (timestamp.as_nanos() as u64 >> 30 & TIMER_FLOW_ID_MASK) << 32
+            | self.id as u64 & THREAD_FLOW_ID_MASK << 24
+            | self.total_flow & COUNTER_FLOW_ID_MASK
+    }
+
+    // NetFlow 记录已是聚合后的单向流，直接生成 TaggedFlow，不经过 FlowMap
+    // ==================================================================
+    // NetFlow records are already aggregated unidirectional flows, so TaggedFlows
+    // are built directly without FlowMap
+    fn record_to_tagged_flow(
+        &mut self,
+        exporter: &IpAddr,
+        timestamp: Duration,
+        record: &FlowRecord,
+    ) -> TaggedFlow {
+        let config = self.flow_map_config.load();
+        let eth_type = if record.src_ip.is_ipv4() {
+            EthernetType::Ipv4
+        } else {
+            EthernetType::Ipv6
+        };
+        let tap_type = self.tap_type(exporter, record.input_if_index);
+        let tcp_flags = TcpFlags::from_bits_truncate(record.tcp_flags);
+        let close_type = if tcp_flags.contains(TcpFlags::RST) {
+            CloseType::TcpClientRst
+        } else if tcp_flags.contains(TcpFlags::FIN) {
+            CloseType::TcpFin
+        } else {
+            CloseType::ForcedReport
+        };
+
+        let mut tagged_flow = TaggedFlow::default();
+        tagged_flow.flow = Flow {
+            flow_key: FlowKey {
+                vtap_id: config.vtap_id,
+                mac_src: MacAddr::from(record.src_mac),
+                mac_dst: MacAddr::from(record.dst_mac),
+                ip_src: record.src_ip,
+                ip_dst: record.dst_ip,
+                port_src: record.src_port,
+                port_dst: record.dst_port,
+                proto: IpProtocol::from(record.protocol),
+                tap_type,
+                tap_port: TapPort::from_netflow(Self::ip_to_u32(exporter)),
+            },
+            flow_id: self.generate_flow_id(timestamp),
+            start_time: record.start_time,
+            end_time: record.end_time,
+            duration: record.end_time.saturating_sub(record.start_time),
+            flow_stat_time: Duration::from_nanos(
+                (timestamp.as_nanos() / TIME_UNIT.as_nanos() * TIME_UNIT.as_nanos()) as u64,
+            ),
+            vlan: record.vlan,
+            eth_type,
+            is_new_flow: true,
+            close_type,
+            flow_metrics_peers: [
+                FlowMetricsPeer {
+                    total_packet_count: record.packets,
+                    packet_count: record.packets,
+                    total_byte_count: record.bytes,
+                    byte_count: record.bytes,
+                    first: record.start_time,
+                    last: record.end_time,
+                    tcp_flags,
+                    ..Default::default()
+                },
+                FlowMetricsPeer::default(),
+            ],
+            signal_source: SignalSource::XFlow,
+            ..Default::default()
+        };
+
+        // 构造仅含查询键的 MetaPacket 查询策略和端点信息
+        // ============================================
+        // lookup policy and endpoints with a MetaPacket containing only the lookup key
+        let mut meta_packet = MetaPacket::empty();
+        meta_packet.signal_source = SignalSource::XFlow;
+        let key = &mut meta_packet.lookup_key;
+        key.timestamp = timestamp;
+        key.src_mac = tagged_flow.flow.flow_key.mac_src;
+        key.dst_mac = tagged_flow.flow.flow_key.mac_dst;
+        key.src_ip = record.src_ip;
+        key.dst_ip = record.dst_ip;
+        key.src_port = record.src_port;
+        key.dst_port = record.dst_port;
+        key.proto = tagged_flow.flow.flow_key.proto;
+        key.eth_type = eth_type;
+        key.tap_type = tap_type;
+        self.policy_getter.lookup(&mut meta_packet, self.id, 0);
+
+        if let Some(endpoints) = meta_packet.endpoint_data.as_ref() {
+            let peers = &mut tagged_flow.flow.flow_metrics_peers;
+            for (peer, info) in peers
+                .iter_mut()
+                .zip([&endpoints.src_info, &endpoints.dst_info])
+            {
+                peer.is_device = info.is_device;
+                peer.is_vip_interface = info.is_vip_interface;
+                peer.is_l2_end = info.l2_end;
+                peer.is_l3_end = info.l3_end;
+                peer.l3_epc_id = info.l3_epc_id;
+                peer.is_vip = info.is_vip;
+                peer.is_local_mac = info.is_local_mac;
+                peer.is_local_ip = info.is_local_ip;
+            }
+        }
+        if let Some(policy) = meta_packet.policy_data.as_ref() {
+            tagged_flow.tag.policy_data[0] = policy.as_ref().clone();
+        }
+        tagged_flow
+            .flow
+            .set_tap_side(config.trident_type, config.cloud_gateway_traffic);
+        tagged_flow
+    }
+
+    fn flush_output(&mut self) {
+        if self.output_buffer.is_empty() {
+            return;
+        }
+        if self.flow_output.send_all(&mut self.output_buffer).is_err() {
+            debug!("{} flow generator queue terminated", self.flow_type);
+            self.output_buffer.clear();
+        }
+    }
+
+    fn run(&mut self, running: Arc<AtomicBool>, receiver: Arc<Receiver<Box<XflowPacket>>>) {
+        let mut flow_map = FlowMap::new(
+            self.id as u32,
+            self.flow_output.clone(),
+            self.policy_getter,
+            self.log_output.clone(),
+            self.ntp_diff.clone(),
+            self.flow_map_config.clone(),
+            self.log_parser_config.clone(),
+            #[cfg(target_os = "linux")]
+            None,
+            None, // Enterprise Edition Feature: packet-sequence
+            &self.stats_collector,
+            false, // from_ebpf
+        );
+        let mut batch = Vec::with_capacity(QUEUE_BATCH_SIZE);
+        while running.load(Ordering::Relaxed) {
+            match receiver.recv_all(&mut batch, Some(RCV_TIMEOUT)) {
+                Ok(_) => {
+                    for packet in batch.drain(..) {
+                        match self.flow_type {
+                            XflowType::Sflow => self.handle_sflow(&mut flow_map, &packet),
+                            XflowType::Netflow => self.handle_netflow(&packet),
+                        }
+                    }
+                    self.flush_output();
+                }
+                Err(queue::Error::Timeout) => {
+                    flow_map.inject_flush_ticker(Duration::ZERO);
+                    self.flush_output();
+                }
+                Err(queue::Error::Terminated(..)) => break,
+            }
+            self.age_interfaces(get_timestamp(self.ntp_diff.load(Ordering::Relaxed)));
+        }
+        info!("{} flow generator (id={}) exited", self.flow_type, self.id);
+    }
+}
+
+/*
+    在 UDP 端口上接收 sFlow 数据，将采样的报文头还原为 MetaPacket（按采样率放大统计量）后注入
+    独立的 FlowMap，生成的流与分发器采集的流进入相同的后续流程。
+    每个端口两个线程：接收线程只负责收包入队，避免 FlowMap 处理慢时 socket 缓冲区溢出。
+    ===========================================================================
+    Receives sFlow datagrams on a UDP port, rebuilds MetaPackets from the sampled packet
+    headers (with counters scaled by the sampling rate) and injects them into a dedicated
+    FlowMap, so the generated flows follow the same path as flows from dispatchers.
+    Each port has two threads, the receiving thread only enqueues datagrams so that the
+    socket buffer won't overflow when FlowMap is slow.
+*/
+pub struct XflowCollector {
+    id: usize,
+    flow_type: XflowType,
+    port: u16,
+
+    running: Arc<AtomicBool>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    counter: Arc<XflowCounter>,
+
+    sender: DebugSender<Box<XflowPacket>>,
+    receiver: Arc<Receiver<Box<XflowPacket>>>,
+
+    ntp_diff: Arc<AtomicI64>,
+    tap_typer: Arc<TapTyper>,
+    policy_getter: PolicyGetter,
+    flow_map_config: FlowAccess,
+    log_parser_config: LogParserAccess,
+    flow_output: DebugSender<Box<TaggedFlow>>,
+    log_output: DebugSender<Box<MetaAppProto>>,
+    stats_collector: Arc<stats::Collector>,
+}
+
+impl XflowCollector {
+    pub fn new(
+        id: usize,
+        flow_type: XflowType,
+        port: u16,
+        queue_size: usize,
+        ntp_diff: Arc<AtomicI64>,
+        tap_typer: Arc<TapTyper>,
+        policy_getter: PolicyGetter,
+        flow_map_config: FlowAccess,
+        log_parser_config: LogParserAccess,
+        flow_output: DebugSender<Box<TaggedFlow>>,
+        log_output: DebugSender<Box<MetaAppProto>>,
+        queue_debugger: &QueueDebugger,
+        stats_collector: Arc<stats::Collector>,
+    ) -> (Self, Arc<XflowCounter>) {
+        let queue_name = flow_type.queue_name();
+        let (sender, receiver, counter) =
+            bounded_with_debug(queue_size, queue_name, queue_debugger);
+        stats_collector.register_countable(
+            "queue",
+            Countable::Owned(Box::new(counter)),
+            vec![
+                StatsOption::Tag("module", queue_name.to_string()),
+                StatsOption::Tag("index", id.to_string()),
+            ],
+        );
+        let counter: Arc<XflowCounter> = Default::default();
+        (
+            Self {
+                id,
+                flow_type,
+                port,
+                running: Default::default(),
+                threads: Mutex::new(vec![]),
+                counter: counter.clone(),
+                sender,
+                receiver: Arc::new(receiver),
+                ntp_diff,
+                tap_typer,
+                policy_getter,
+                flow_map_config,
+                log_parser_config,
+                flow_output,
+                log_output,
+                stats_collector,
+            },
+            counter,
+        )
+    }
+
+    fn bind(&self) -> Result<UdpSocket> {
+        let socket = UdpSocket::bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            self.port,
+        ))
+        .map_err(|e| Error::BindFailed(self.port, e.to_string()))?;
+        socket
+            .set_read_timeout(Some(RCV_TIMEOUT))
+            .map_err(|e| Error::BindFailed(self.port, e.to_string()))?;
+        Ok(socket)
+    }
+
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let socket = match self.bind() {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("{}", e);
+                self.running.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let running = self.running.clone();
+        let counter = self.counter.clone();
+        let sender = self.sender.clone();
+        let ntp_diff = self.ntp_diff.clone();
+        let receiver_thread = thread::Builder::new()
+            .name(format!("{}-receiver", self.flow_type))
+            .spawn(move || {
+                let mut buffer = vec![0u8; UDP_BUFFER_SIZE];
+                while running.load(Ordering::Relaxed) {
+                    let (n, src) = match socket.recv_from(&mut buffer) {
+                        Ok(r) => r,
+                        // timeout
+                        Err(_) => continue,
+                    };
+                    counter.rx.fetch_add(1, Ordering::Relaxed);
+                    counter.rx_bytes.fetch_add(n as u64, Ordering::Relaxed);
+                    let packet = XflowPacket {
+                        timestamp: get_timestamp(ntp_diff.load(Ordering::Relaxed)),
+                        src,
+                        data: buffer[..n].to_vec(),
+                    };
+                    if sender.send(Box::new(packet)).is_err() {
+                        counter.drop_in_queue.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .unwrap();
+
+        let mut generator = FlowGenerator {
+            id: self.id,
+            flow_type: self.flow_type,
+            ntp_diff: self.ntp_diff.clone(),
+            tap_typer: self.tap_typer.clone(),
+            policy_getter: self.policy_getter,
+            flow_map_config: self.flow_map_config.clone(),
+            log_parser_config: self.log_parser_config.clone(),
+            flow_output: self.flow_output.clone(),
+            log_output: self.log_output.clone(),
+            counter: self.counter.clone(),
+            stats_collector: self.stats_collector.clone(),
+            interfaces: HashMap::new(),
+            agent_interfaces: HashMap::new(),
+            last_aging: Duration::ZERO,
+            netflow_decoder: NetflowDecoder::default(),
+            output_buffer: Vec::with_capacity(QUEUE_BATCH_SIZE),
+            total_flow: 0,
+        };
+        let running = self.running.clone();
+        let receiver = self.receiver.clone();
+        let generator_thread = thread::Builder::new()
+            .name(format!("{}-flow-generator", self.flow_type))
+            .spawn(move || generator.run(running, receiver))
+            .unwrap();
+
+        self.threads
+            .lock()
+            .unwrap()
+            .extend([receiver_thread, generator_thread]);
+        info!(
+            "{} collector (id={}) started on port {}",
+            self.flow_type, self.id, self.port
+        );
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        for thread in self.threads.lock().unwrap().drain(..) {
+            let _ = thread.join();
+        }
+        info!("{} collector (id={}) stopped", self.flow_type, self.id);
+    }
+}
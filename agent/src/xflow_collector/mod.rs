@@ -0,0 +1,61 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod collector;
+pub mod netflow;
+pub mod sflow;
+
+use std::fmt;
+
+use thiserror::Error;
+
+pub use collector::{XflowCollector, XflowCounter};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("xflow datagram invalid: {0}")]
+    DatagramInvalid(String),
+    #[error("xflow version {0} unsupported")]
+    UnsupportedVersion(u32),
+    #[error("xflow collector bind port {0} failed: {1}")]
+    BindFailed(u16, String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XflowType {
+    Sflow,
+    Netflow,
+}
+
+impl XflowType {
+    pub fn queue_name(&self) -> &'static str {
+        match self {
+            XflowType::Sflow => "0-sflow-packet-to-tagged-flow",
+            XflowType::Netflow => "0-netflow-packet-to-tagged-flow",
+        }
+    }
+}
+
+impl fmt::Display for XflowType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XflowType::Sflow => write!(f, "sflow"),
+            XflowType::Netflow => write!(f, "netflow"),
+        }
+    }
+}
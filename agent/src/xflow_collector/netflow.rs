@@ -0,0 +1,993 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// NetFlow v5/v9 and IPFIX decoder
+// v5:    https://www.cisco.com/c/en/us/td/docs/net_mgmt/netflow_collection_engine/3-6/user/guide/format.html
+// v9:    https://www.rfc-editor.org/rfc/rfc3954
+// IPFIX: https://www.rfc-editor.org/rfc/rfc7011
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use lru::LruCache;
+use public::bytes::{read_u16_be, read_u32_be};
+
+use super::{Error, Result};
+
+pub const NETFLOW_V5: u16 = 5;
+pub const NETFLOW_V9: u16 = 9;
+pub const IPFIX: u16 = 10;
+
+const V5_HEADER_SIZE: usize = 24;
+const V5_RECORD_SIZE: usize = 48;
+const V9_HEADER_SIZE: usize = 20;
+const IPFIX_HEADER_SIZE: usize = 16;
+const SET_HEADER_SIZE: usize = 4;
+
+const V9_TEMPLATE_SET: u16 = 0;
+const V9_OPTIONS_TEMPLATE_SET: u16 = 1;
+const IPFIX_TEMPLATE_SET: u16 = 2;
+const IPFIX_OPTIONS_TEMPLATE_SET: u16 = 3;
+const MIN_DATA_SET_ID: u16 = 256;
+
+const IPFIX_ENTERPRISE_BIT: u16 = 0x8000;
+const IPFIX_VARIABLE_LENGTH: u16 = 0xffff;
+
+// v5 头部的 sampling_interval 高 2 位为采样模式，低 14 位为采样间隔
+// ==============================================================
+// the top 2 bits of sampling_interval in v5 header is mode and the lower 14 bits is interval
+const V5_SAMPLING_INTERVAL_MASK: u16 = 0x3fff;
+
+// 一个 exporter 的模板数量上限，避免异常报文耗尽内存
+// ==================================================
+// max templates of one exporter, in case of abnormal datagrams exhausting memory
+const TEMPLATE_MAX_PER_EXPORTER: usize = 1024;
+// 所有 exporter 的模板作用域和模板总数上限，超过时淘汰最久未使用的作用域
+// ==================================================================
+// max template scopes and total templates of all exporters, the least recently
+// used scopes are evicted when exceeded
+const DOMAIN_MAX: usize = 4096;
+const TEMPLATE_MAX: usize = 65536;
+
+// v9 and IPFIX information elements
+const IN_BYTES: u16 = 1;
+const IN_PKTS: u16 = 2;
+const PROTOCOL: u16 = 4;
+const TCP_FLAGS: u16 = 6;
+const L4_SRC_PORT: u16 = 7;
+const IPV4_SRC_ADDR: u16 = 8;
+const INPUT_SNMP: u16 = 10;
+const L4_DST_PORT: u16 = 11;
+const IPV4_DST_ADDR: u16 = 12;
+const OUTPUT_SNMP: u16 = 14;
+const LAST_SWITCHED: u16 = 21;
+const FIRST_SWITCHED: u16 = 22;
+const OUT_BYTES: u16 = 23;
+const OUT_PKTS: u16 = 24;
+const IPV6_SRC_ADDR: u16 = 27;
+const IPV6_DST_ADDR: u16 = 28;
+const SAMPLING_INTERVAL: u16 = 34;
+const FLOW_SAMPLER_RANDOM_INTERVAL: u16 = 50;
+const IN_SRC_MAC: u16 = 56;
+const SRC_VLAN: u16 = 58;
+const IN_DST_MAC: u16 = 80;
+const OCTET_TOTAL_COUNT: u16 = 85;
+const PACKET_TOTAL_COUNT: u16 = 86;
+const FLOW_START_SECONDS: u16 = 150;
+const FLOW_END_SECONDS: u16 = 151;
+const FLOW_START_MILLISECONDS: u16 = 152;
+const FLOW_END_MILLISECONDS: u16 = 153;
+const FLOW_START_DELTA_MICROSECONDS: u16 = 158;
+const FLOW_END_DELTA_MICROSECONDS: u16 = 159;
+const SYSTEM_INIT_TIME_MILLISECONDS: u16 = 160;
+const SAMPLING_PACKET_INTERVAL: u16 = 305;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowRecord {
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8,
+    pub tcp_flags: u8,
+    pub src_mac: [u8; 6],
+    pub dst_mac: [u8; 6],
+    pub vlan: u16,
+    pub input_if_index: u32,
+    pub output_if_index: u32,
+    // 已按采样间隔放大
+    // ================
+    // already scaled by sampling interval
+    pub packets: u64,
+    pub bytes: u64,
+    // unix 时间
+    // =========
+    // unix time
+    pub start_time: Duration,
+    pub end_time: Duration,
+}
+
+impl Default for FlowRecord {
+    fn default() -> Self {
+        Self {
+            src_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            dst_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            src_port: 0,
+            dst_port: 0,
+            protocol: 0,
+            tcp_flags: 0,
+            src_mac: [0; 6],
+            dst_mac: [0; 6],
+            vlan: 0,
+            input_if_index: 0,
+            output_if_index: 0,
+            packets: 0,
+            bytes: 0,
+            start_time: Duration::ZERO,
+            end_time: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    id: u16,
+    length: u16,
+    // IPFIX 私有字段不解析，仅用于跳过
+    // ===============================
+    // IPFIX enterprise specific fields are skipped
+    enterprise: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Template {
+    fields: Vec<Field>,
+    // 选项模板的 scope 字段数量，数据模板为 0
+    // ======================================
+    // number of scope fields of options template, 0 for data template
+    scope_field_count: usize,
+}
+
+impl Template {
+    // 变长字段按最小 1 字节计算
+    // ========================
+    // variable length fields count as 1 byte
+    fn min_record_size(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|f| {
+                if f.length == IPFIX_VARIABLE_LENGTH {
+                    1
+                } else {
+                    f.length as usize
+                }
+            })
+            .sum()
+    }
+}
+
+// 模板与采样间隔以 (exporter, 版本, source id/observation domain) 区分
+// ===================================================================
+// templates and sampling intervals are scoped by (exporter, version, source id/observation domain)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DomainKey {
+    exporter: IpAddr,
+    version: u16,
+    domain: u32,
+}
+
+// 报文头部中用于计算记录时间的信息
+// ================================
+// information from header to calculate record time
+#[derive(Clone, Copy)]
+struct ExportTime {
+    export_time: Duration,
+    // 设备启动时刻，v9 使用 sysUpTime 计算，IPFIX 由 systemInitTimeMilliseconds 给出
+    // ===========================================================================
+    // device boot time, calculated from sysUpTime for v9, given by systemInitTimeMilliseconds for IPFIX
+    boot_time: Option<Duration>,
+}
+
+pub struct NetflowDecoder {
+    templates: LruCache<DomainKey, HashMap<u16, Template>>,
+    template_count: usize,
+    sampling_intervals: LruCache<DomainKey, u32>,
+}
+
+impl Default for NetflowDecoder {
+    fn default() -> Self {
+        Self {
+            templates: LruCache::new(DOMAIN_MAX),
+            template_count: 0,
+            sampling_intervals: LruCache::new(DOMAIN_MAX),
+        }
+    }
+}
+
+impl NetflowDecoder {
+    pub fn template_count(&self) -> usize {
+        self.template_count
+    }
+
+    pub fn decode(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>> {
+        if data.len() < 2 {
+            return Err(Error::DatagramInvalid("netflow datagram too short".into()));
+        }
+        match read_u16_be(data) {
+            NETFLOW_V5 => Self::decode_v5(data),
+            NETFLOW_V9 => self.decode_v9(exporter, data),
+            IPFIX => self.decode_ipfix(exporter, data),
+            v => Err(Error::UnsupportedVersion(v as u32)),
+        }
+    }
+
+    fn decode_v5(data: &[u8]) -> Result<Vec<FlowRecord>> {
+        if data.len() < V5_HEADER_SIZE {
+            return Err(Error::DatagramInvalid("netflow v5 header too short".into()));
+        }
+        let count = read_u16_be(&data[2..]) as usize;
+        let sys_uptime = Duration::from_millis(read_u32_be(&data[4..]) as u64);
+        let unix_time = Duration::new(read_u32_be(&data[8..]) as u64, read_u32_be(&data[12..]));
+        let boot_time = unix_time.saturating_sub(sys_uptime);
+        let sampling_interval =
+            (read_u16_be(&data[22..]) & V5_SAMPLING_INTERVAL_MASK).max(1) as u64;
+        if data.len() < V5_HEADER_SIZE + count * V5_RECORD_SIZE {
+            return Err(Error::DatagramInvalid(format!(
+                "netflow v5 with {} records but only {} bytes",
+                count,
+                data.len()
+            )));
+        }
+
+        let mut records = Vec::with_capacity(count);
+        for r in data[V5_HEADER_SIZE..]
+            .chunks_exact(V5_RECORD_SIZE)
+            .take(count)
+        {
+            let end_time = boot_time + Duration::from_millis(read_u32_be(&r[28..]) as u64);
+            let start_time = boot_time + Duration::from_millis(read_u32_be(&r[24..]) as u64);
+            records.push(FlowRecord {
+                src_ip: IpAddr::V4(Ipv4Addr::from(read_u32_be(r))),
+                dst_ip: IpAddr::V4(Ipv4Addr::from(read_u32_be(&r[4..]))),
+                input_if_index: read_u16_be(&r[12..]) as u32,
+                output_if_index: read_u16_be(&r[14..]) as u32,
+                packets: (read_u32_be(&r[16..]) as u64).saturating_mul(sampling_interval),
+                bytes: (read_u32_be(&r[20..]) as u64).saturating_mul(sampling_interval),
+                start_time: start_time.min(end_time),
+                end_time,
+                src_port: read_u16_be(&r[32..]),
+                dst_port: read_u16_be(&r[34..]),
+                tcp_flags: r[37],
+                protocol: r[38],
+                ..Default::default()
+            });
+        }
+        Ok(records)
+    }
+
+    fn decode_v9(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>> {
+        if data.len() < V9_HEADER_SIZE {
+            return Err(Error::DatagramInvalid("netflow v9 header too short".into()));
+        }
+        let sys_uptime = Duration::from_millis(read_u32_be(&data[4..]) as u64);
+        let export_time = Duration::from_secs(read_u32_be(&data[8..]) as u64);
+        let key = DomainKey {
+            exporter,
+            version: NETFLOW_V9,
+            domain: read_u32_be(&data[16..]),
+        };
+        let time = ExportTime {
+            export_time,
+            boot_time: Some(export_time.saturating_sub(sys_uptime)),
+        };
+        self.decode_sets(key, time, &data[V9_HEADER_SIZE..])
+    }
+
+    fn decode_ipfix(&mut self, exporter: IpAddr, data: &[u8]) -> Result<Vec<FlowRecord>> {
+        if data.len() < IPFIX_HEADER_SIZE {
+            return Err(Error::DatagramInvalid("ipfix header too short".into()));
+        }
+        let length = (read_u16_be(&data[2..]) as usize).min(data.len());
+        if length < IPFIX_HEADER_SIZE {
+            return Err(Error::DatagramInvalid(format!(
+                "invalid ipfix message length {}",
+                length
+            )));
+        }
+        let key = DomainKey {
+            exporter,
+            version: IPFIX,
+            domain: read_u32_be(&data[12..]),
+        };
+        let time = ExportTime {
+            export_time: Duration::from_secs(read_u32_be(&data[4..]) as u64),
+            boot_time: None,
+        };
+        self.decode_sets(key, time, &data[IPFIX_HEADER_SIZE..length])
+    }
+
+    fn decode_sets(
+        &mut self,
+        key: DomainKey,
+        time: ExportTime,
+        mut data: &[u8],
+    ) -> Result<Vec<FlowRecord>> {
+        let mut records = vec![];
+        // 模板可能在数据之后才到达，缺少模板的数据集直接跳过
+        // ================================================
+        // templates may arrive after data, data sets without templates are skipped
+        while data.len() >= SET_HEADER_SIZE {
+            let set_id = read_u16_be(data);
+            let set_length = read_u16_be(&data[2..]) as usize;
+            if set_length < SET_HEADER_SIZE || set_length > data.len() {
+                return Err(Error::DatagramInvalid(format!(
+                    "invalid set length {} with {} bytes remaining",
+                    set_length,
+                    data.len()
+                )));
+            }
+            let set = &data[SET_HEADER_SIZE..set_length];
+            data = &data[set_length..];
+
+            match set_id {
+                V9_TEMPLATE_SET if key.version == NETFLOW_V9 => {
+                    self.decode_templates(key, set, false)?
+                }
+                V9_OPTIONS_TEMPLATE_SET if key.version == NETFLOW_V9 => {
+                    self.decode_v9_options_templates(key, set)?
+                }
+                IPFIX_TEMPLATE_SET if key.version == IPFIX => {
+                    self.decode_templates(key, set, false)?
+                }
+                IPFIX_OPTIONS_TEMPLATE_SET if key.version == IPFIX => {
+                    self.decode_templates(key, set, true)?
+                }
+                id if id >= MIN_DATA_SET_ID => {
+                    let Some(template) = self.templates.get(&key).and_then(|t| t.get(&id)) else {
+                        continue;
+                    };
+                    let template = template.clone();
+                    self.decode_data_set(key, time, &template, set, &mut records)?;
+                }
+                _ => (),
+            }
+        }
+        Ok(records)
+    }
+
+    // 记录长度为 0 的模板无法推进数据集的解析，直接拒绝
+    // ================================================
+    // templates with zero record length cannot advance data set decoding and are rejected
+    fn insert_template(&mut self, key: DomainKey, id: u16, template: Template) -> Result<()> {
+        if template.min_record_size() == 0 {
+            return Err(Error::DatagramInvalid(format!(
+                "template {} has zero record length",
+                id
+            )));
+        }
+        match self.templates.get_mut(&key) {
+            Some(t) if t.contains_key(&id) => {
+                t.insert(id, template);
+                return Ok(());
+            }
+            Some(t) if t.len() >= TEMPLATE_MAX_PER_EXPORTER => return Ok(()),
+            Some(_) => (),
+            None => {
+                if self.templates.len() >= self.templates.cap() {
+                    self.evict_domain();
+                }
+                self.templates.put(key, HashMap::new());
+            }
+        }
+        // 当前作用域刚被访问过，不会先于其他作用域被淘汰
+        // =============================================
+        // the current scope was just accessed and is not evicted before other scopes
+        while self.template_count >= TEMPLATE_MAX && self.templates.len() > 1 {
+            self.evict_domain();
+        }
+        self.templates.get_mut(&key).unwrap().insert(id, template);
+        self.template_count += 1;
+        Ok(())
+    }
+
+    fn evict_domain(&mut self) {
+        if let Some((key, templates)) = self.templates.pop_lru() {
+            self.template_count -= templates.len();
+            self.sampling_intervals.pop(&key);
+        }
+    }
+
+    fn read_fields(data: &mut &[u8], count: usize, ipfix: bool) -> Result<Vec<Field>> {
+        let mut fields = Vec::with_capacity(count.min(data.len() / 4));
+        for _ in 0..count {
+            if data.len() < 4 {
+                return Err(Error::DatagramInvalid("template field truncated".into()));
+            }
+            let mut id = read_u16_be(data);
+            let length = read_u16_be(&data[2..]);
+            *data = &data[4..];
+            let mut enterprise = false;
+            if ipfix && id & IPFIX_ENTERPRISE_BIT != 0 {
+                if data.len() < 4 {
+                    return Err(Error::DatagramInvalid(
+                        "template enterprise number truncated".into(),
+                    ));
+                }
+                *data = &data[4..];
+                id &= !IPFIX_ENTERPRISE_BIT;
+                enterprise = true;
+            }
+            fields.push(Field {
+                id,
+                length,
+                enterprise,
+            });
+        }
+        Ok(fields)
+    }
+
+    // v9 模板集与 IPFIX 模板集/选项模板集
+    // =================================
+    // v9 template set and IPFIX template/options template set
+    fn decode_templates(&mut self, key: DomainKey, mut set: &[u8], options: bool) -> Result<()> {
+        let ipfix = key.version == IPFIX;
+        let header_size = if options { 6 } else { 4 };
+        while set.len() >= header_size {
+            let id = read_u16_be(set);
+            let field_count = read_u16_be(&set[2..]) as usize;
+            let scope_field_count = if options {
+                read_u16_be(&set[4..]) as usize
+            } else {
+                0
+            };
+            set = &set[header_size..];
+            if id < MIN_DATA_SET_ID {
+                // padding
+                break;
+            }
+            // IPFIX 中 field_count 为 0 表示撤销模板
+            // =====================================
+            // field_count 0 withdraws the template in IPFIX
+            if field_count == 0 {
+                if let Some(t) = self.templates.get_mut(&key) {
+                    if t.remove(&id).is_some() {
+                        self.template_count -= 1;
+                    }
+                }
+                continue;
+            }
+            let fields = Self::read_fields(&mut set, field_count, ipfix)?;
+            self.insert_template(
+                key,
+                id,
+                Template {
+                    fields,
+                    scope_field_count: scope_field_count.min(field_count),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn decode_v9_options_templates(&mut self, key: DomainKey, mut set: &[u8]) -> Result<()> {
+        while set.len() >= 6 {
+            let id = read_u16_be(set);
+            // v9 选项模板中给出的是字节长度而不是字段数量
+            // ==========================================
+            // v9 options template gives lengths in bytes instead of field counts
+            let scope_length = read_u16_be(&set[2..]) as usize;
+            let option_length = read_u16_be(&set[4..]) as usize;
+            set = &set[6..];
+            if id < MIN_DATA_SET_ID {
+                break;
+            }
+            let scope_fields = Self::read_fields(&mut set, scope_length / 4, false)?;
+            let mut fields = Self::read_fields(&mut set, option_length / 4, false)?;
+            let scope_field_count = scope_fields.len();
+            fields.splice(0..0, scope_fields);
+            self.insert_template(
+                key,
+                id,
+                Template {
+                    fields,
+                    scope_field_count,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn decode_data_set(
+        &mut self,
+        key: DomainKey,
+        time: ExportTime,
+        template: &Template,
+        mut set: &[u8],
+        records: &mut Vec<FlowRecord>,
+    ) -> Result<()> {
+        let min_size = template.min_record_size();
+        // 剩余不足一条记录的数据为 padding
+        // ================================
+        // remaining bytes less than a record are padding
+        while set.len() >= min_size {
+            let values = Self::read_record(template, &mut set)?;
+            if template.scope_field_count > 0 {
+                self.update_sampling_interval(key, template, &values);
+            } else {
+                let sampling_interval = self.sampling_intervals.get(&key).copied().unwrap_or(1);
+                records.push(Self::build_record(
+                    template,
+                    &values,
+                    time,
+                    sampling_interval,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_record<'a>(template: &Template, set: &mut &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        let mut values = Vec::with_capacity(template.fields.len());
+        for field in template.fields.iter() {
+            let mut length = field.length as usize;
+            if field.length == IPFIX_VARIABLE_LENGTH {
+                if set.is_empty() {
+                    return Err(Error::DatagramInvalid("variable length truncated".into()));
+                }
+                length = set[0] as usize;
+                *set = &set[1..];
+                if length == 255 {
+                    if set.len() < 2 {
+                        return Err(Error::DatagramInvalid("variable length truncated".into()));
+                    }
+                    length = read_u16_be(set) as usize;
+                    *set = &set[2..];
+                }
+            }
+            if set.len() < length {
+                return Err(Error::DatagramInvalid(format!(
+                    "field {} needs {} bytes but only {} remaining",
+                    field.id,
+                    length,
+                    set.len()
+                )));
+            }
+            values.push(&set[..length]);
+            *set = &set[length..];
+        }
+        Ok(values)
+    }
+
+    // 按网络序读取 1~8 字节的无符号整数（IPFIX reduced size encoding）
+    // ==============================================================
+    // read 1 to 8 bytes unsigned integer in network order (IPFIX reduced size encoding)
+    fn read_uint(value: &[u8]) -> u64 {
+        value
+            .iter()
+            .take(8)
+            .fold(0u64, |acc, b| acc << 8 | *b as u64)
+    }
+
+    fn update_sampling_interval(&mut self, key: DomainKey, template: &Template, values: &[&[u8]]) {
+        for (field, value) in template.fields.iter().zip(values.iter()) {
+            if field.enterprise {
+                continue;
+            }
+            match field.id {
+                SAMPLING_INTERVAL | FLOW_SAMPLER_RANDOM_INTERVAL | SAMPLING_PACKET_INTERVAL => {
+                    let interval = Self::read_uint(value) as u32;
+                    if interval > 0 {
+                        self.sampling_intervals.put(key, interval);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn build_record(
+        template: &Template,
+        values: &[&[u8]],
+        time: ExportTime,
+        mut sampling_interval: u32,
+    ) -> FlowRecord {
+        let mut record = FlowRecord::default();
+        let (mut start_uptime, mut end_uptime) = (None, None);
+        let mut boot_time = time.boot_time;
+        let (mut start_time, mut end_time) = (None, None);
+
+        for (field, value) in template.fields.iter().zip(values.iter()) {
+            if field.enterprise {
+                continue;
+            }
+            match (field.id, value.len()) {
+                (IPV4_SRC_ADDR, 4) => {
+                    record.src_ip = IpAddr::V4(Ipv4Addr::from(read_u32_be(value)))
+                }
+                (IPV4_DST_ADDR, 4) => {
+                    record.dst_ip = IpAddr::V4(Ipv4Addr::from(read_u32_be(value)))
+                }
+                (IPV6_SRC_ADDR, 16) => {
+                    let bs: [u8; 16] = (*value).try_into().unwrap();
+                    record.src_ip = IpAddr::V6(Ipv6Addr::from(bs));
+                }
+                (IPV6_DST_ADDR, 16) => {
+                    let bs: [u8; 16] = (*value).try_into().unwrap();
+                    record.dst_ip = IpAddr::V6(Ipv6Addr::from(bs));
+                }
+                (IN_SRC_MAC, 6) => record.src_mac.copy_from_slice(value),
+                (IN_DST_MAC, 6) => record.dst_mac.copy_from_slice(value),
+                (L4_SRC_PORT, _) => record.src_port = Self::read_uint(value) as u16,
+                (L4_DST_PORT, _) => record.dst_port = Self::read_uint(value) as u16,
+                (PROTOCOL, _) => record.protocol = Self::read_uint(value) as u8,
+                (TCP_FLAGS, _) => record.tcp_flags = Self::read_uint(value) as u8,
+                (SRC_VLAN, _) => record.vlan = Self::read_uint(value) as u16,
+                (INPUT_SNMP, _) => record.input_if_index = Self::read_uint(value) as u32,
+                (OUTPUT_SNMP, _) => record.output_if_index = Self::read_uint(value) as u32,
+                // 同一记录中不会同时出现入向和出向计数
+                // ==================================
+                // ingress and egress counters won't appear in the same record
+                (IN_BYTES | OUT_BYTES | OCTET_TOTAL_COUNT, _) => {
+                    record.bytes = record.bytes.max(Self::read_uint(value))
+                }
+                (IN_PKTS | OUT_PKTS | PACKET_TOTAL_COUNT, _) => {
+                    record.packets = record.packets.max(Self::read_uint(value))
+                }
+                (FIRST_SWITCHED, _) => {
+                    start_uptime = Some(Duration::from_millis(Self::read_uint(value)))
+                }
+                (LAST_SWITCHED, _) => {
+                    end_uptime = Some(Duration::from_millis(Self::read_uint(value)))
+                }
+                (SYSTEM_INIT_TIME_MILLISECONDS, _) => {
+                    boot_time = Some(Duration::from_millis(Self::read_uint(value)))
+                }
+                (FLOW_START_SECONDS, _) => {
+                    start_time = Some(Duration::from_secs(Self::read_uint(value)))
+                }
+                (FLOW_END_SECONDS, _) => {
+                    end_time = Some(Duration::from_secs(Self::read_uint(value)))
+                }
+                (FLOW_START_MILLISECONDS, _) => {
+                    start_time = Some(Duration::from_millis(Self::read_uint(value)))
+                }
+                (FLOW_END_MILLISECONDS, _) => {
+                    end_time = Some(Duration::from_millis(Self::read_uint(value)))
+                }
+                (FLOW_START_DELTA_MICROSECONDS, _) => {
+                    start_time = Some(
+                        time.export_time
+                            .saturating_sub(Duration::from_micros(Self::read_uint(value))),
+                    )
+                }
+                (FLOW_END_DELTA_MICROSECONDS, _) => {
+                    end_time = Some(
+                        time.export_time
+                            .saturating_sub(Duration::from_micros(Self::read_uint(value))),
+                    )
+                }
+                (
+                    SAMPLING_INTERVAL | FLOW_SAMPLER_RANDOM_INTERVAL | SAMPLING_PACKET_INTERVAL,
+                    _,
+                ) => {
+                    let interval = Self::read_uint(value) as u32;
+                    if interval > 0 {
+                        sampling_interval = interval;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(boot_time) = boot_time {
+            if start_time.is_none() {
+                start_time = start_uptime.map(|t| boot_time + t);
+            }
+            if end_time.is_none() {
+                end_time = end_uptime.map(|t| boot_time + t);
+            }
+        }
+        record.end_time = end_time.unwrap_or(time.export_time);
+        record.start_time = start_time.unwrap_or(record.end_time).min(record.end_time);
+
+        let sampling_interval = sampling_interval.max(1) as u64;
+        record.packets = record.packets.saturating_mul(sampling_interval);
+        record.bytes = record.bytes.saturating_mul(sampling_interval);
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORTER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 1, 1, 1));
+
+    fn v9_header(count: u16, sys_uptime: u32, unix_secs: u32, source_id: u32) -> Vec<u8> {
+        let mut bs = vec![];
+        bs.extend_from_slice(&NETFLOW_V9.to_be_bytes());
+        bs.extend_from_slice(&count.to_be_bytes());
+        bs.extend_from_slice(&sys_uptime.to_be_bytes());
+        bs.extend_from_slice(&unix_secs.to_be_bytes());
+        bs.extend_from_slice(&1u32.to_be_bytes());
+        bs.extend_from_slice(&source_id.to_be_bytes());
+        bs
+    }
+
+    fn set(id: u16, body: &[u8]) -> Vec<u8> {
+        let mut bs = vec![];
+        bs.extend_from_slice(&id.to_be_bytes());
+        bs.extend_from_slice(&(body.len() as u16 + 4).to_be_bytes());
+        bs.extend_from_slice(body);
+        bs
+    }
+
+    fn u16s(vs: &[u16]) -> Vec<u8> {
+        vs.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn decode_v5() {
+        let mut bs = vec![];
+        bs.extend_from_slice(&NETFLOW_V5.to_be_bytes());
+        bs.extend_from_slice(&1u16.to_be_bytes());
+        bs.extend_from_slice(&60_000u32.to_be_bytes()); // sys_uptime
+        bs.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+        bs.extend_from_slice(&0u32.to_be_bytes());
+        bs.extend_from_slice(&0u32.to_be_bytes());
+        bs.extend_from_slice(&[0, 0]);
+        bs.extend_from_slice(&(0x4000u16 | 100).to_be_bytes()); // sampling 1/100
+
+        bs.extend_from_slice(&[192, 168, 0, 1, 192, 168, 0, 2, 0, 0, 0, 0]);
+        bs.extend_from_slice(&u16s(&[3, 4]));
+        bs.extend_from_slice(&10u32.to_be_bytes());
+        bs.extend_from_slice(&1000u32.to_be_bytes());
+        bs.extend_from_slice(&50_000u32.to_be_bytes());
+        bs.extend_from_slice(&59_000u32.to_be_bytes());
+        bs.extend_from_slice(&u16s(&[12345, 80]));
+        bs.extend_from_slice(&[0, 0x1b, 6, 0]);
+        bs.extend_from_slice(&[0; 8]);
+
+        let records = NetflowDecoder::default().decode(EXPORTER, &bs).unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.src_ip, IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)));
+        assert_eq!(r.dst_port, 80);
+        assert_eq!(r.protocol, 6);
+        assert_eq!(r.tcp_flags, 0x1b);
+        assert_eq!(r.input_if_index, 3);
+        assert_eq!(r.packets, 1000);
+        assert_eq!(r.bytes, 100_000);
+        assert_eq!(r.start_time, Duration::from_secs(1_700_000_000 - 10));
+        assert_eq!(r.end_time, Duration::from_secs(1_700_000_000 - 1));
+    }
+
+    #[test]
+    fn decode_v9_with_options() {
+        let mut decoder = NetflowDecoder::default();
+
+        // 模板未到达时数据被跳过
+        let mut record = vec![];
+        record.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        record.extend_from_slice(&u16s(&[1024, 443]));
+        record.push(17);
+        record.extend_from_slice(&5u32.to_be_bytes());
+        record.extend_from_slice(&500u32.to_be_bytes());
+        record.extend_from_slice(&9_000u32.to_be_bytes());
+        record.extend_from_slice(&10_000u32.to_be_bytes());
+        record.extend_from_slice(&[0, 0, 0]); // padding
+        let mut data = v9_header(1, 10_000, 1_700_000_000, 7);
+        data.extend(set(256, &record));
+        assert!(decoder.decode(EXPORTER, &data).unwrap().is_empty());
+
+        let template = u16s(&[
+            256,
+            9,
+            IPV4_SRC_ADDR,
+            4,
+            IPV4_DST_ADDR,
+            4,
+            L4_SRC_PORT,
+            2,
+            L4_DST_PORT,
+            2,
+            PROTOCOL,
+            1,
+            IN_PKTS,
+            4,
+            IN_BYTES,
+            4,
+            FIRST_SWITCHED,
+            4,
+            LAST_SWITCHED,
+            4,
+        ]);
+        let options_template = u16s(&[
+            257,
+            4,
+            8,
+            1, // scope: system
+            4,
+            SAMPLING_INTERVAL,
+            4,
+            35, // SAMPLING_ALGORITHM
+            1,
+        ]);
+        let mut options = vec![];
+        options.extend_from_slice(&0u32.to_be_bytes());
+        options.extend_from_slice(&10u32.to_be_bytes());
+        options.push(2);
+        options.extend_from_slice(&[0, 0, 0]); // padding
+
+        let mut data = v9_header(4, 10_000, 1_700_000_000, 7);
+        data.extend(set(V9_TEMPLATE_SET, &template));
+        data.extend(set(V9_OPTIONS_TEMPLATE_SET, &options_template));
+        data.extend(set(257, &options));
+        data.extend(set(256, &record));
+        let records = decoder.decode(EXPORTER, &data).unwrap();
+        assert_eq!(decoder.template_count(), 2);
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.src_ip, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(r.src_port, 1024);
+        assert_eq!(r.dst_port, 443);
+        assert_eq!(r.protocol, 17);
+        assert_eq!(r.packets, 50);
+        assert_eq!(r.bytes, 5000);
+        assert_eq!(r.start_time, Duration::from_secs(1_700_000_000 - 1));
+        assert_eq!(r.end_time, Duration::from_secs(1_700_000_000));
+
+        // 其他 source id 的模板相互独立
+        let mut data = v9_header(1, 10_000, 1_700_000_000, 8);
+        data.extend(set(256, &record));
+        assert!(decoder.decode(EXPORTER, &data).unwrap().is_empty());
+    }
+
+    #[test]
+    fn decode_ipfix() {
+        let template = u16s(&[
+            300,
+            6,
+            IPV6_SRC_ADDR,
+            16,
+            IPV6_DST_ADDR,
+            16,
+            PROTOCOL,
+            1,
+            OCTET_TOTAL_COUNT,
+            8,
+            FLOW_START_MILLISECONDS,
+            8,
+            // enterprise field with variable length
+            IPFIX_ENTERPRISE_BIT | 1,
+            IPFIX_VARIABLE_LENGTH,
+            0,
+            9,
+        ]);
+        let mut record = vec![];
+        record.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        record.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        record.push(58);
+        record.extend_from_slice(&3000u64.to_be_bytes());
+        record.extend_from_slice(&1_699_999_999_500u64.to_be_bytes());
+        record.extend_from_slice(&[3, b'a', b'b', b'c']);
+
+        let mut body = set(IPFIX_TEMPLATE_SET, &template);
+        body.extend(set(300, &record));
+        let mut data = vec![];
+        data.extend_from_slice(&IPFIX.to_be_bytes());
+        data.extend_from_slice(&(body.len() as u16 + 16).to_be_bytes());
+        data.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend(body);
+
+        let records = NetflowDecoder::default().decode(EXPORTER, &data).unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.src_ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(r.protocol, 58);
+        assert_eq!(r.bytes, 3000);
+        assert_eq!(r.start_time, Duration::from_millis(1_699_999_999_500));
+        assert_eq!(r.end_time, Duration::from_secs(1_700_000_000));
+    }
+
+    #[test]
+    fn template_limits() {
+        let mut decoder = NetflowDecoder::default();
+        let template = |id: u16| set(V9_TEMPLATE_SET, &u16s(&[id, 1, PROTOCOL, 1]));
+        let record = set(256, &[6]);
+
+        // 作用域数量超过上限时淘汰最久未使用的作用域
+        for domain in 0..=DOMAIN_MAX as u32 {
+            let mut data = v9_header(1, 0, 0, domain);
+            data.extend(template(256));
+            decoder.decode(EXPORTER, &data).unwrap();
+        }
+        assert_eq!(decoder.template_count(), DOMAIN_MAX);
+        let mut data = v9_header(1, 0, 0, 0);
+        data.extend(&record);
+        assert!(decoder.decode(EXPORTER, &data).unwrap().is_empty());
+        let mut data = v9_header(1, 0, 0, DOMAIN_MAX as u32);
+        data.extend(&record);
+        assert_eq!(decoder.decode(EXPORTER, &data).unwrap().len(), 1);
+
+        // 一个作用域的模板数量有上限，更新已有模板不受影响
+        let mut decoder = NetflowDecoder::default();
+        let mut data = v9_header(0, 0, 0, 0);
+        for id in 0..=TEMPLATE_MAX_PER_EXPORTER as u16 {
+            data.extend(template(MIN_DATA_SET_ID + id));
+        }
+        decoder.decode(EXPORTER, &data).unwrap();
+        assert_eq!(decoder.template_count(), TEMPLATE_MAX_PER_EXPORTER);
+        let mut data = v9_header(1, 0, 0, 0);
+        data.extend(template(256));
+        data.extend(&record);
+        assert_eq!(decoder.decode(EXPORTER, &data).unwrap().len(), 1);
+        assert_eq!(decoder.template_count(), TEMPLATE_MAX_PER_EXPORTER);
+
+        // 撤销模板
+        let ipfix = |body: &[u8]| {
+            let mut data = IPFIX.to_be_bytes().to_vec();
+            data.extend_from_slice(&((IPFIX_HEADER_SIZE + body.len()) as u16).to_be_bytes());
+            data.extend_from_slice(&[0; 12]);
+            data.extend_from_slice(body);
+            data
+        };
+        let data = ipfix(&set(IPFIX_TEMPLATE_SET, &u16s(&[256, 1, PROTOCOL, 1])));
+        decoder.decode(EXPORTER, &data).unwrap();
+        assert_eq!(decoder.template_count(), TEMPLATE_MAX_PER_EXPORTER + 1);
+        let data = ipfix(&set(IPFIX_TEMPLATE_SET, &u16s(&[256, 0])));
+        decoder.decode(EXPORTER, &data).unwrap();
+        assert_eq!(decoder.template_count(), TEMPLATE_MAX_PER_EXPORTER);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let mut decoder = NetflowDecoder::default();
+        assert!(matches!(
+            decoder.decode(EXPORTER, &[0, 1, 0, 0]),
+            Err(Error::UnsupportedVersion(1))
+        ));
+        let mut data = v9_header(1, 0, 0, 0);
+        data.extend_from_slice(&u16s(&[256, 100]));
+        assert!(matches!(
+            decoder.decode(EXPORTER, &data),
+            Err(Error::DatagramInvalid(_))
+        ));
+
+        // 字段长度全为 0 的模板会使数据集解析无法推进
+        let mut data = v9_header(2, 0, 0, 0);
+        data.extend(set(
+            V9_TEMPLATE_SET,
+            &u16s(&[256, 2, PROTOCOL, 0, IN_PKTS, 0]),
+        ));
+        data.extend(set(256, &[0; 8]));
+        assert!(matches!(
+            decoder.decode(EXPORTER, &data),
+            Err(Error::DatagramInvalid(_))
+        ));
+        let mut data = v9_header(2, 0, 0, 0);
+        data.extend(set(V9_OPTIONS_TEMPLATE_SET, &u16s(&[257, 4, 0, 1, 0])));
+        data.extend(set(257, &[0; 8]));
+        assert!(matches!(
+            decoder.decode(EXPORTER, &data),
+            Err(Error::DatagramInvalid(_))
+        ));
+        assert_eq!(decoder.template_count(), 0);
+    }
+}
@@ -0,0 +1,589 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// sFlow v5 datagram decoder, see https://sflow.org/sflow_version_5.txt
+// All fields are XDR encoded (big endian, 4 bytes aligned).
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use public::bytes::{read_u32_be, read_u64_be};
+
+use super::{Error, Result};
+
+pub const SFLOW_VERSION_5: u32 = 5;
+
+const ADDRESS_TYPE_IPV4: u32 = 1;
+const ADDRESS_TYPE_IPV6: u32 = 2;
+
+// sample formats, enterprise 0
+const SAMPLE_FLOW: u32 = 1;
+const SAMPLE_COUNTER: u32 = 2;
+const SAMPLE_FLOW_EXPANDED: u32 = 3;
+const SAMPLE_COUNTER_EXPANDED: u32 = 4;
+
+// flow record formats, enterprise 0
+const FLOW_RAW_PACKET_HEADER: u32 = 1;
+const FLOW_EXTENDED_SWITCH: u32 = 1001;
+const FLOW_EXTENDED_ROUTER: u32 = 1002;
+
+// counter record formats, enterprise 0
+const COUNTER_GENERIC_INTERFACE: u32 = 1;
+
+pub const HEADER_PROTOCOL_ETHERNET: u32 = 1;
+
+// 紧凑格式中 input/output 的高 2 位为格式，低 30 位为值
+// ======================================================
+// in compact format, the top 2 bits of input/output is format and the lower 30 bits is value
+const INTERFACE_FORMAT_OFFSET: u32 = 30;
+const INTERFACE_VALUE_MASK: u32 = (1 << INTERFACE_FORMAT_OFFSET) - 1;
+// source_id 的高 8 位为类型，低 24 位为索引
+// =========================================
+// the top 8 bits of source_id is type and the lower 24 bits is index
+const SOURCE_ID_TYPE_OFFSET: u32 = 24;
+const SOURCE_ID_INDEX_MASK: u32 = (1 << SOURCE_ID_TYPE_OFFSET) - 1;
+
+struct XdrReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> XdrReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        // opaque 数据按 4 字节对齐
+        let padded = (n + 3) & !3;
+        if self.remaining() < padded {
+            return Err(Error::DatagramInvalid(format!(
+                "need {} bytes at offset {} but only {} remaining",
+                padded,
+                self.offset,
+                self.remaining()
+            )));
+        }
+        let bs = &self.data[self.offset..self.offset + n];
+        self.offset += padded;
+        Ok(bs)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(read_u32_be(self.bytes(4)?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(read_u64_be(self.bytes(8)?))
+    }
+
+    fn ip_addr(&mut self) -> Result<IpAddr> {
+        match self.u32()? {
+            ADDRESS_TYPE_IPV4 => {
+                let bs: [u8; 4] = self.bytes(4)?.try_into().unwrap();
+                Ok(IpAddr::V4(Ipv4Addr::from(bs)))
+            }
+            ADDRESS_TYPE_IPV6 => {
+                let bs: [u8; 16] = self.bytes(16)?.try_into().unwrap();
+                Ok(IpAddr::V6(Ipv6Addr::from(bs)))
+            }
+            t => Err(Error::DatagramInvalid(format!(
+                "unknown address type {}",
+                t
+            ))),
+        }
+    }
+
+    // 读取 (format, length, data)，返回 format 与一个只包含 data 的 reader
+    // ===================================================================
+    // read (format, length, data), returns the format and a reader of data
+    fn record(&mut self) -> Result<(u32, XdrReader<'a>)> {
+        let format = self.u32()?;
+        let length = self.u32()? as usize;
+        Ok((format, XdrReader::new(self.bytes(length)?)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawPacketHeader<'a> {
+    pub protocol: u32,
+    // 原始报文长度，包含被剥离的部分（如 FCS）
+    // =========================================
+    // original length of the packet, including stripped bytes (e.g. FCS)
+    pub frame_length: u32,
+    pub stripped: u32,
+    pub header: &'a [u8],
+}
+
+impl<'a> RawPacketHeader<'a> {
+    fn decode(r: &mut XdrReader<'a>) -> Result<Self> {
+        let protocol = r.u32()?;
+        let frame_length = r.u32()?;
+        let stripped = r.u32()?;
+        let header_length = r.u32()? as usize;
+        Ok(Self {
+            protocol,
+            frame_length,
+            stripped,
+            header: r.bytes(header_length)?,
+        })
+    }
+
+    pub fn original_length(&self) -> u32 {
+        self.frame_length.saturating_sub(self.stripped)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ExtendedSwitch {
+    pub src_vlan: u32,
+    pub src_priority: u32,
+    pub dst_vlan: u32,
+    pub dst_priority: u32,
+}
+
+impl ExtendedSwitch {
+    fn decode(r: &mut XdrReader) -> Result<Self> {
+        Ok(Self {
+            src_vlan: r.u32()?,
+            src_priority: r.u32()?,
+            dst_vlan: r.u32()?,
+            dst_priority: r.u32()?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExtendedRouter {
+    pub next_hop: IpAddr,
+    pub src_mask_len: u32,
+    pub dst_mask_len: u32,
+}
+
+impl ExtendedRouter {
+    fn decode(r: &mut XdrReader) -> Result<Self> {
+        Ok(Self {
+            next_hop: r.ip_addr()?,
+            src_mask_len: r.u32()?,
+            dst_mask_len: r.u32()?,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FlowSample<'a> {
+    pub sequence: u32,
+    pub source_id_type: u32,
+    pub source_id_index: u32,
+    pub sampling_rate: u32,
+    pub sample_pool: u32,
+    pub drops: u32,
+    // 0 表示接口未知或报文被丢弃/多播到多个接口
+    // ==========================================
+    // 0 means unknown interface, discarded or sent to multiple interfaces
+    pub input_if_index: u32,
+    pub output_if_index: u32,
+
+    pub raw_header: Option<RawPacketHeader<'a>>,
+    pub switch: Option<ExtendedSwitch>,
+    pub router: Option<ExtendedRouter>,
+}
+
+impl<'a> FlowSample<'a> {
+    fn decode(r: &mut XdrReader<'a>, expanded: bool) -> Result<Self> {
+        let mut sample = FlowSample {
+            sequence: r.u32()?,
+            ..Default::default()
+        };
+        if expanded {
+            sample.source_id_type = r.u32()?;
+            sample.source_id_index = r.u32()?;
+        } else {
+            let source_id = r.u32()?;
+            sample.source_id_type = source_id >> SOURCE_ID_TYPE_OFFSET;
+            sample.source_id_index = source_id & SOURCE_ID_INDEX_MASK;
+        }
+        sample.sampling_rate = r.u32()?;
+        sample.sample_pool = r.u32()?;
+        sample.drops = r.u32()?;
+        if expanded {
+            let (input_format, input) = (r.u32()?, r.u32()?);
+            let (output_format, output) = (r.u32()?, r.u32()?);
+            sample.input_if_index = if input_format == 0 { input } else { 0 };
+            sample.output_if_index = if output_format == 0 { output } else { 0 };
+        } else {
+            let (input, output) = (r.u32()?, r.u32()?);
+            if input >> INTERFACE_FORMAT_OFFSET == 0 {
+                sample.input_if_index = input & INTERFACE_VALUE_MASK;
+            }
+            if output >> INTERFACE_FORMAT_OFFSET == 0 {
+                sample.output_if_index = output & INTERFACE_VALUE_MASK;
+            }
+        }
+
+        let num_records = r.u32()?;
+        for _ in 0..num_records {
+            let (format, mut record) = r.record()?;
+            match format {
+                FLOW_RAW_PACKET_HEADER => {
+                    sample.raw_header = Some(RawPacketHeader::decode(&mut record)?)
+                }
+                FLOW_EXTENDED_SWITCH => sample.switch = Some(ExtendedSwitch::decode(&mut record)?),
+                FLOW_EXTENDED_ROUTER => sample.router = Some(ExtendedRouter::decode(&mut record)?),
+                _ => (),
+            }
+        }
+        Ok(sample)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GenericInterfaceCounters {
+    pub if_index: u32,
+    pub if_type: u32,
+    pub if_speed: u64,
+    pub if_direction: u32,
+    pub if_status: u32,
+    pub in_octets: u64,
+    pub in_ucast_pkts: u32,
+    pub in_multicast_pkts: u32,
+    pub in_broadcast_pkts: u32,
+    pub in_discards: u32,
+    pub in_errors: u32,
+    pub in_unknown_protos: u32,
+    pub out_octets: u64,
+    pub out_ucast_pkts: u32,
+    pub out_multicast_pkts: u32,
+    pub out_broadcast_pkts: u32,
+    pub out_discards: u32,
+    pub out_errors: u32,
+    pub promiscuous_mode: u32,
+}
+
+impl GenericInterfaceCounters {
+    fn decode(r: &mut XdrReader) -> Result<Self> {
+        Ok(Self {
+            if_index: r.u32()?,
+            if_type: r.u32()?,
+            if_speed: r.u64()?,
+            if_direction: r.u32()?,
+            if_status: r.u32()?,
+            in_octets: r.u64()?,
+            in_ucast_pkts: r.u32()?,
+            in_multicast_pkts: r.u32()?,
+            in_broadcast_pkts: r.u32()?,
+            in_discards: r.u32()?,
+            in_errors: r.u32()?,
+            in_unknown_protos: r.u32()?,
+            out_octets: r.u64()?,
+            out_ucast_pkts: r.u32()?,
+            out_multicast_pkts: r.u32()?,
+            out_broadcast_pkts: r.u32()?,
+            out_discards: r.u32()?,
+            out_errors: r.u32()?,
+            promiscuous_mode: r.u32()?,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CounterSample {
+    pub sequence: u32,
+    pub source_id_type: u32,
+    pub source_id_index: u32,
+
+    pub interface: Option<GenericInterfaceCounters>,
+}
+
+impl CounterSample {
+    fn decode(r: &mut XdrReader, expanded: bool) -> Result<Self> {
+        let mut sample = CounterSample {
+            sequence: r.u32()?,
+            ..Default::default()
+        };
+        if expanded {
+            sample.source_id_type = r.u32()?;
+            sample.source_id_index = r.u32()?;
+        } else {
+            let source_id = r.u32()?;
+            sample.source_id_type = source_id >> SOURCE_ID_TYPE_OFFSET;
+            sample.source_id_index = source_id & SOURCE_ID_INDEX_MASK;
+        }
+
+        let num_records = r.u32()?;
+        for _ in 0..num_records {
+            let (format, mut record) = r.record()?;
+            if format == COUNTER_GENERIC_INTERFACE {
+                sample.interface = Some(GenericInterfaceCounters::decode(&mut record)?);
+            }
+        }
+        Ok(sample)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Sample<'a> {
+    Flow(FlowSample<'a>),
+    Counter(CounterSample),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Datagram<'a> {
+    pub agent_address: IpAddr,
+    pub sub_agent_id: u32,
+    pub sequence: u32,
+    // 设备启动后经过的毫秒数
+    // ========================
+    // milliseconds since the device booted
+    pub uptime: u32,
+    pub samples: Vec<Sample<'a>>,
+}
+
+impl<'a> Datagram<'a> {
+    pub fn decode(data: &'a [u8]) -> Result<Self> {
+        let mut r = XdrReader::new(data);
+        let version = r.u32()?;
+        if version != SFLOW_VERSION_5 {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let agent_address = r.ip_addr()?;
+        let sub_agent_id = r.u32()?;
+        let sequence = r.u32()?;
+        let uptime = r.u32()?;
+        let num_samples = r.u32()?;
+
+        // 每个 sample 至少 8 字节，避免按错误的数量预分配内存
+        // ====================================================
+        // each sample takes at least 8 bytes, don't trust num_samples for preallocation
+        let mut samples = Vec::with_capacity((num_samples as usize).min(r.remaining() / 8));
+        for _ in 0..num_samples {
+            let (format, mut sample) = r.record()?;
+            // 仅解析标准格式 (enterprise 0)
+            // ============================
+            // only standard formats (enterprise 0) are decoded
+            match format {
+                SAMPLE_FLOW => samples.push(Sample::Flow(FlowSample::decode(&mut sample, false)?)),
+                SAMPLE_FLOW_EXPANDED => {
+                    samples.push(Sample::Flow(FlowSample::decode(&mut sample, true)?))
+                }
+                SAMPLE_COUNTER => {
+                    samples.push(Sample::Counter(CounterSample::decode(&mut sample, false)?))
+                }
+                SAMPLE_COUNTER_EXPANDED => {
+                    samples.push(Sample::Counter(CounterSample::decode(&mut sample, true)?))
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            agent_address,
+            sub_agent_id,
+            sequence,
+            uptime,
+            samples,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct XdrWriter(Vec<u8>);
+
+    impl XdrWriter {
+        fn u32(&mut self, v: u32) -> &mut Self {
+            self.0.extend_from_slice(&v.to_be_bytes());
+            self
+        }
+
+        fn u64(&mut self, v: u64) -> &mut Self {
+            self.0.extend_from_slice(&v.to_be_bytes());
+            self
+        }
+
+        fn opaque(&mut self, bs: &[u8]) -> &mut Self {
+            self.0.extend_from_slice(bs);
+            self.0.resize((self.0.len() + 3) & !3, 0);
+            self
+        }
+
+        fn record(&mut self, format: u32, data: &XdrWriter) -> &mut Self {
+            self.u32(format).u32(data.0.len() as u32).opaque(&data.0)
+        }
+    }
+
+    fn datagram_header(w: &mut XdrWriter, num_samples: u32) {
+        w.u32(SFLOW_VERSION_5)
+            .u32(ADDRESS_TYPE_IPV4)
+            .opaque(&[10, 0, 0, 1])
+            .u32(0)
+            .u32(100)
+            .u32(3600000)
+            .u32(num_samples);
+    }
+
+    #[test]
+    fn decode_flow_sample() {
+        let header = [0xaau8; 42];
+        let mut raw = XdrWriter::default();
+        raw.u32(HEADER_PROTOCOL_ETHERNET)
+            .u32(1518)
+            .u32(4)
+            .u32(header.len() as u32)
+            .opaque(&header);
+        let mut switch = XdrWriter::default();
+        switch.u32(100).u32(0).u32(200).u32(0);
+        let mut router = XdrWriter::default();
+        router
+            .u32(ADDRESS_TYPE_IPV4)
+            .opaque(&[192, 168, 1, 254])
+            .u32(24)
+            .u32(16);
+        let mut unknown = XdrWriter::default();
+        unknown.u32(0xdeadbeef);
+
+        let mut sample = XdrWriter::default();
+        sample
+            .u32(7)
+            .u32(3)
+            .u32(1024)
+            .u32(1024 * 7)
+            .u32(0)
+            .u32(3)
+            .u32(0x80000002)
+            .u32(4)
+            .record(FLOW_RAW_PACKET_HEADER, &raw)
+            .record(FLOW_EXTENDED_SWITCH, &switch)
+            .record(FLOW_EXTENDED_ROUTER, &router)
+            .record(2000, &unknown);
+
+        let mut w = XdrWriter::default();
+        datagram_header(&mut w, 1);
+        w.record(SAMPLE_FLOW, &sample);
+
+        let datagram = Datagram::decode(&w.0).unwrap();
+        assert_eq!(
+            datagram.agent_address,
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+        );
+        assert_eq!(datagram.sequence, 100);
+        assert_eq!(datagram.samples.len(), 1);
+        let Sample::Flow(flow) = &datagram.samples[0] else {
+            panic!("expect flow sample");
+        };
+        assert_eq!(flow.source_id_type, 0);
+        assert_eq!(flow.source_id_index, 3);
+        assert_eq!(flow.sampling_rate, 1024);
+        assert_eq!(flow.input_if_index, 3);
+        // 0x80000002 为多播到 2 个接口
+        assert_eq!(flow.output_if_index, 0);
+        let raw = flow.raw_header.as_ref().unwrap();
+        assert_eq!(raw.protocol, HEADER_PROTOCOL_ETHERNET);
+        assert_eq!(raw.original_length(), 1514);
+        assert_eq!(raw.header, &header[..]);
+        assert_eq!(
+            flow.switch,
+            Some(ExtendedSwitch {
+                src_vlan: 100,
+                dst_vlan: 200,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            flow.router,
+            Some(ExtendedRouter {
+                next_hop: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 254)),
+                src_mask_len: 24,
+                dst_mask_len: 16,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_counter_sample() {
+        let mut generic = XdrWriter::default();
+        generic
+            .u32(5)
+            .u32(6)
+            .u64(10_000_000_000)
+            .u32(1)
+            .u32(3)
+            .u64(123456)
+            .u32(100)
+            .u32(2)
+            .u32(1)
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u64(654321)
+            .u32(200)
+            .u32(0)
+            .u32(0)
+            .u32(1)
+            .u32(2)
+            .u32(0);
+
+        let mut sample = XdrWriter::default();
+        sample
+            .u32(9)
+            .u32(0)
+            .u32(5)
+            .u32(1)
+            .record(COUNTER_GENERIC_INTERFACE, &generic);
+
+        let mut w = XdrWriter::default();
+        datagram_header(&mut w, 1);
+        w.record(SAMPLE_COUNTER_EXPANDED, &sample);
+
+        let datagram = Datagram::decode(&w.0).unwrap();
+        let Sample::Counter(counter) = &datagram.samples[0] else {
+            panic!("expect counter sample");
+        };
+        assert_eq!(counter.source_id_index, 5);
+        let interface = counter.interface.as_ref().unwrap();
+        assert_eq!(interface.if_index, 5);
+        assert_eq!(interface.if_speed, 10_000_000_000);
+        assert_eq!(interface.in_octets, 123456);
+        assert_eq!(interface.out_octets, 654321);
+        assert_eq!(interface.out_discards, 1);
+        assert_eq!(interface.out_errors, 2);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let mut w = XdrWriter::default();
+        w.u32(4);
+        assert!(matches!(
+            Datagram::decode(&w.0),
+            Err(Error::UnsupportedVersion(4))
+        ));
+
+        let mut w = XdrWriter::default();
+        datagram_header(&mut w, 1);
+        // sample 长度超出 datagram
+        w.u32(SAMPLE_FLOW).u32(1024);
+        assert!(matches!(
+            Datagram::decode(&w.0),
+            Err(Error::DatagramInvalid(_))
+        ));
+    }
+}
@@ -46,7 +46,7 @@ var log = logging.MustGetLogger("flow_log.decoder")
 
 const (
 	BUFFER_SIZE  = 1024
-	L7_PROTO_MAX = datatype.L7_PROTOCOL_DNS + 1
+	L7_PROTO_MAX = datatype.L7_PROTOCOL_QUIC + 1
 )
 
 type Counter struct {
@@ -63,6 +63,8 @@ type Counter struct {
 	L7RPCDropCount   int64 `statsd:"l7-rpc-drop-count"`
 	L7MQCount        int64 `statsd:"l7-mq-count"`
 	L7MQDropCount    int64 `statsd:"l7-mq-drop-count"`
+	L7TLSCount       int64 `statsd:"l7-tls-count"`
+	L7TLSDropCount   int64 `statsd:"l7-tls-drop-count"`
 	ErrorCount       int64 `statsd:"err-count"`
 	Count            int64 `statsd:"count"`
 	DropCount        int64 `statsd:"drop-count"`
@@ -296,26 +298,29 @@ func (d *Decoder) sendProto(proto *pb.AppProtoLogsData) {
 	proto.Release()
 
 	switch datatype.L7Protocol(proto.Base.Head.Proto) {
-	case datatype.L7_PROTOCOL_HTTP_1, datatype.L7_PROTOCOL_HTTP_2, datatype.L7_PROTOCOL_HTTP_1_TLS, datatype.L7_PROTOCOL_HTTP_2_TLS:
+	case datatype.L7_PROTOCOL_HTTP_1, datatype.L7_PROTOCOL_HTTP_2, datatype.L7_PROTOCOL_HTTP_1_TLS, datatype.L7_PROTOCOL_HTTP_2_TLS, datatype.L7_PROTOCOL_WEBSOCKET:
 		d.counter.L7HTTPCount++
 		d.counter.L7HTTPDropCount += drop
 	case datatype.L7_PROTOCOL_DNS:
 		d.counter.L7DNSCount++
 		d.counter.L7DNSDropCount += drop
-	case datatype.L7_PROTOCOL_MYSQL, datatype.L7_PROTOCOL_POSTGRE:
+	case datatype.L7_PROTOCOL_MYSQL, datatype.L7_PROTOCOL_POSTGRE, datatype.L7_PROTOCOL_TDS:
 		d.counter.L7SQLCount++
 		d.counter.L7SQLDropCount += drop
-	case datatype.L7_PROTOCOL_REDIS:
+	case datatype.L7_PROTOCOL_REDIS, datatype.L7_PROTOCOL_MONGODB, datatype.L7_PROTOCOL_MEMCACHED, datatype.L7_PROTOCOL_CASSANDRA:
 		d.counter.L7NoSQLCount++
 		d.counter.L7NoSQLDropCount += drop
-	case datatype.L7_PROTOCOL_DUBBO:
+	case datatype.L7_PROTOCOL_DUBBO, datatype.L7_PROTOCOL_THRIFT:
 		d.counter.L7RPCCount++
 		d.counter.L7RPCDropCount += drop
 	case datatype.L7_PROTOCOL_KAFKA:
 		fallthrough
-	case datatype.L7_PROTOCOL_MQTT:
+	case datatype.L7_PROTOCOL_MQTT, datatype.L7_PROTOCOL_AMQP:
 		d.counter.L7MQCount++
 		d.counter.L7MQDropCount += drop
+	case datatype.L7_PROTOCOL_TLS, datatype.L7_PROTOCOL_QUIC:
+		d.counter.L7TLSCount++
+		d.counter.L7TLSDropCount += drop
 	}
 }
 
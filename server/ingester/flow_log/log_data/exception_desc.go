@@ -393,6 +393,9 @@ var mqttV3ExceptionDescs = []string{
 	5: "not authorized",
 }
 
+// 与 agent 上报的 MQTT 版本字符串一致
+const MQTT_VERSION_5 = "5.0"
+
 var mqttV5ExceptionDescs = []string{
 	0:   "Success",
 	4:   "Dissconnect with Will Message",
@@ -405,8 +405,8 @@ func (h *L7FlowLog) fillL7FlowLog(l *pb.AppProtoLogsData) {
 			}
 			h.RequestId = &h.requestId
 		}
-	case datatype.L7_PROTOCOL_PROTOBUF_RPC, datatype.L7_PROTOCOL_SOFARPC:
-		// assume protobuf and sofa rpc Always have request_id and maybe equal to 0
+	case datatype.L7_PROTOCOL_PROTOBUF_RPC, datatype.L7_PROTOCOL_SOFARPC, datatype.L7_PROTOCOL_THRIFT:
+		// assume protobuf, sofa rpc and thrift Always have request_id and maybe equal to 0
 		h.RequestId = &h.requestId
 	}
 }
@@ -427,10 +427,13 @@ func (h *L7FlowLog) fillExceptionDesc(l *pb.AppProtoLogsData) {
 	case datatype.L7_PROTOCOL_KAFKA:
 		h.ResponseException = GetKafkaExceptionDesc(int16(code))
 	case datatype.L7_PROTOCOL_MQTT:
-		if l.Version != "5" {
-			h.ResponseException = GetMQTTV3ExceptionDesc(uint16(code))
-		} else {
+		// agent 上报的 MQTT 5.0 Reason String 优先
+		if l.Resp.Exception != "" {
+			h.ResponseException = l.Resp.Exception
+		} else if l.Version == MQTT_VERSION_5 {
 			h.ResponseException = GetMQTTV5ExceptionDesc(uint16(code))
+		} else {
+			h.ResponseException = GetMQTTV3ExceptionDesc(uint16(code))
 		}
 	case datatype.L7_PROTOCOL_MYSQL, datatype.L7_PROTOCOL_REDIS:
 		fallthrough
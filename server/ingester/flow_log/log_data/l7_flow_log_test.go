@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+package log_data
+
+import (
+	"testing"
+
+	"github.com/deepflowys/deepflow/server/libs/datatype"
+	"github.com/deepflowys/deepflow/server/libs/datatype/pb"
+)
+
+func TestMQTTExceptionDesc(t *testing.T) {
+	testCases := []struct {
+		version   string
+		code      int32
+		exception string
+		expected  string
+	}{
+		{"5.0", 0x87, "", "Not authorized"},
+		{"5.0", 0x87, "token expired", "token expired"},
+		{"3.1.1", 5, "", "not authorized"},
+		{"3.1", 4, "", "bad user name or password"},
+	}
+	for _, c := range testCases {
+		h := L7FlowLog{}
+		h.L7Protocol = uint8(datatype.L7_PROTOCOL_MQTT)
+		h.ResponseStatus = datatype.STATUS_CLIENT_ERROR
+		h.fillExceptionDesc(&pb.AppProtoLogsData{
+			Version: c.version,
+			Resp:    &pb.L7Response{Code: c.code, Exception: c.exception},
+		})
+		if h.ResponseException != c.expected {
+			t.Errorf("version %s code %d: expected %q, got %q", c.version, c.code, c.expected, h.ResponseException)
+		}
+	}
+}
@@ -152,16 +152,25 @@ const (
 	L7_PROTOCOL_HTTP_2       L7Protocol = 21
 	L7_PROTOCOL_HTTP_1_TLS   L7Protocol = 22
 	L7_PROTOCOL_HTTP_2_TLS   L7Protocol = 23
+	L7_PROTOCOL_WEBSOCKET    L7Protocol = 24
 	L7_PROTOCOL_DUBBO        L7Protocol = 40
 	L7_PROTOCOL_GRPC         L7Protocol = 41
 	L7_PROTOCOL_PROTOBUF_RPC L7Protocol = 42
 	L7_PROTOCOL_SOFARPC      L7Protocol = 43
+	L7_PROTOCOL_THRIFT       L7Protocol = 44
 	L7_PROTOCOL_MYSQL        L7Protocol = 60
 	L7_PROTOCOL_POSTGRE      L7Protocol = 61
+	L7_PROTOCOL_TDS          L7Protocol = 62
 	L7_PROTOCOL_REDIS        L7Protocol = 80
+	L7_PROTOCOL_MONGODB      L7Protocol = 81
+	L7_PROTOCOL_MEMCACHED    L7Protocol = 82
+	L7_PROTOCOL_CASSANDRA    L7Protocol = 83
 	L7_PROTOCOL_KAFKA        L7Protocol = 100
 	L7_PROTOCOL_MQTT         L7Protocol = 101
+	L7_PROTOCOL_AMQP         L7Protocol = 102
 	L7_PROTOCOL_DNS          L7Protocol = 120
+	L7_PROTOCOL_TLS          L7Protocol = 121
+	L7_PROTOCOL_QUIC         L7Protocol = 122
 )
 
 // size = 9 * 4B = 36B
@@ -616,14 +625,28 @@ func (p L7Protocol) String() string {
 		formatted = "HTTP1_TLS"
 	case L7_PROTOCOL_HTTP_2_TLS:
 		formatted = "HTTP2_TLS"
+	case L7_PROTOCOL_WEBSOCKET:
+		formatted = "WebSocket"
 	case L7_PROTOCOL_DNS:
 		formatted = "DNS"
+	case L7_PROTOCOL_TLS:
+		formatted = "TLS"
+	case L7_PROTOCOL_QUIC:
+		formatted = "QUIC"
 	case L7_PROTOCOL_MYSQL:
 		formatted = "MySQL"
 	case L7_PROTOCOL_POSTGRE:
 		formatted = "PostgreSQL"
+	case L7_PROTOCOL_TDS:
+		formatted = "TDS"
 	case L7_PROTOCOL_REDIS:
 		formatted = "Redis"
+	case L7_PROTOCOL_MONGODB:
+		formatted = "MongoDB"
+	case L7_PROTOCOL_MEMCACHED:
+		formatted = "Memcached"
+	case L7_PROTOCOL_CASSANDRA:
+		formatted = "Cassandra"
 	case L7_PROTOCOL_DUBBO:
 		formatted = "Dubbo"
 	case L7_PROTOCOL_GRPC:
@@ -632,10 +655,14 @@ func (p L7Protocol) String() string {
 		formatted = "ProtobufRPC"
 	case L7_PROTOCOL_SOFARPC:
 		formatted = "SofaRPC"
+	case L7_PROTOCOL_THRIFT:
+		formatted = "Thrift"
 	case L7_PROTOCOL_KAFKA:
 		formatted = "Kafka"
 	case L7_PROTOCOL_MQTT:
 		formatted = "MQTT"
+	case L7_PROTOCOL_AMQP:
+		formatted = "AMQP"
 	case L7_PROTOCOL_OTHER:
 		formatted = "Others"
 	default:
@@ -649,13 +676,22 @@ var L7ProtocolStringMap = map[string]L7Protocol{
 	L7_PROTOCOL_HTTP_2.String():     L7_PROTOCOL_HTTP_2,
 	L7_PROTOCOL_HTTP_1_TLS.String(): L7_PROTOCOL_HTTP_1_TLS,
 	L7_PROTOCOL_HTTP_2_TLS.String(): L7_PROTOCOL_HTTP_2_TLS,
+	L7_PROTOCOL_WEBSOCKET.String():  L7_PROTOCOL_WEBSOCKET,
 	L7_PROTOCOL_DNS.String():        L7_PROTOCOL_DNS,
+	L7_PROTOCOL_TLS.String():        L7_PROTOCOL_TLS,
+	L7_PROTOCOL_QUIC.String():       L7_PROTOCOL_QUIC,
 	L7_PROTOCOL_MYSQL.String():      L7_PROTOCOL_MYSQL,
+	L7_PROTOCOL_TDS.String():        L7_PROTOCOL_TDS,
 	L7_PROTOCOL_REDIS.String():      L7_PROTOCOL_REDIS,
+	L7_PROTOCOL_MONGODB.String():    L7_PROTOCOL_MONGODB,
+	L7_PROTOCOL_MEMCACHED.String():  L7_PROTOCOL_MEMCACHED,
+	L7_PROTOCOL_CASSANDRA.String():  L7_PROTOCOL_CASSANDRA,
 	L7_PROTOCOL_DUBBO.String():      L7_PROTOCOL_DUBBO,
 	L7_PROTOCOL_GRPC.String():       L7_PROTOCOL_GRPC,
+	L7_PROTOCOL_THRIFT.String():     L7_PROTOCOL_THRIFT,
 	L7_PROTOCOL_KAFKA.String():      L7_PROTOCOL_KAFKA,
 	L7_PROTOCOL_MQTT.String():       L7_PROTOCOL_MQTT,
+	L7_PROTOCOL_AMQP.String():       L7_PROTOCOL_AMQP,
 	L7_PROTOCOL_OTHER.String():      L7_PROTOCOL_OTHER,
 	L7_PROTOCOL_UNKNOWN.String():    L7_PROTOCOL_UNKNOWN,
 }